@@ -0,0 +1,168 @@
+//! Directory browser filling the path inputs, so that the long paths don't have to be typed by hand.
+
+use cursive::{
+    traits::{Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use log::*;
+use std::path::{Path, PathBuf};
+
+/// Called with the chosen path after it is put into the input, e.g. to check it.
+pub type OnChoose = fn(&mut Cursive, &str);
+
+/// Home directory and the filesystem roots (drives on Windows), to start browsing from.
+fn roots() -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    if cfg!(windows) {
+        roots.extend(
+            (b'A'..=b'Z')
+                .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+                .filter(|drive| drive.is_dir()),
+        );
+    } else {
+        roots.push("/".into());
+    }
+    roots
+}
+
+/// Subdirectories of the directory, then its files, if they can be chosen; hidden ones are skipped.
+fn entries(dir: &Path, files: bool) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && (files || path.is_dir())
+        })
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let label = if path.is_dir() {
+                format!("{}/", name)
+            } else {
+                name.into_owned()
+            };
+            (label, path)
+        })
+        .collect();
+    entries.sort_by_key(|(label, path)| (!path.is_dir(), label.to_lowercase()));
+    Ok(entries)
+}
+
+/// Directory to start from: the one in the input (or the one containing the file there), or the first root.
+fn start_dir(current: &str) -> PathBuf {
+    let current = Path::new(current.trim());
+    let from_input = if current.is_dir() {
+        Some(current)
+    } else {
+        current.parent().filter(|parent| parent.is_dir())
+    };
+    from_input
+        .map(Path::to_owned)
+        .or_else(|| roots().into_iter().next())
+        .unwrap_or_else(|| ".".into())
+}
+
+fn choose(cursive: &mut Cursive, path: &Path, target: &'static str, on_choose: OnChoose) {
+    let path = path.to_string_lossy().into_owned();
+    info!("[browse] Chosen {} for {}", path, target);
+    cursive.pop_layer();
+    cursive.call_on_name(target, |view: &mut EditView| view.set_content(path.clone()));
+    on_choose(cursive, &path);
+}
+
+fn show(
+    cursive: &mut Cursive,
+    dir: PathBuf,
+    target: &'static str,
+    files: bool,
+    on_choose: OnChoose,
+) {
+    let mut list = SelectView::new();
+    if let Some(parent) = dir.parent() {
+        list.add_item("../", parent.to_owned());
+    }
+    let status = match entries(&dir, files) {
+        Ok(entries) => {
+            list.add_all(entries);
+            String::new()
+        }
+        Err(err) => {
+            warn!("[browse] Unable to list {:?}: {}", dir, err);
+            format!("Unable to list the directory: {}", err)
+        }
+    };
+    for root in roots() {
+        list.add_item(format!("[{}]", root.to_string_lossy()), root);
+    }
+    list.set_on_submit(move |cursive, path: &PathBuf| {
+        if path.is_dir() {
+            cursive.pop_layer();
+            show(cursive, path.clone(), target, files, on_choose);
+        } else {
+            choose(cursive, path, target, on_choose);
+        }
+    });
+
+    let current = dir.clone();
+    let mut dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(TextView::new(dir.to_string_lossy()))
+            .child(TextView::new(status))
+            .child(list.scrollable().max_height(20)),
+    )
+    .title(if files {
+        "Choose file"
+    } else {
+        "Choose directory"
+    });
+    if !files {
+        dialog.add_button("Choose this directory", move |cursive| {
+            choose(cursive, &current, target, on_choose)
+        });
+    }
+    cursive.add_layer(dialog.dismiss_button("Cancel").min_width(60));
+}
+
+/// Opens the browser for the path input named `target`, starting from the path already there.
+///
+/// If `files` is set, files are chosen, otherwise directories.
+pub fn browse(cursive: &mut Cursive, target: &'static str, files: bool, on_choose: OnChoose) {
+    let current = cursive
+        .call_on_name(target, |view: &mut EditView| view.get_content())
+        .unwrap_or_default();
+    show(cursive, start_dir(&current), target, files, on_choose);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::temp_dir;
+
+    #[test]
+    fn directories_go_first() {
+        let root = temp_dir("browse");
+        std::fs::create_dir_all(root.join("mods")).unwrap();
+        std::fs::create_dir_all(root.join(".hidden")).unwrap();
+        std::fs::write(root.join("a_preset.json"), "{}").unwrap();
+
+        let labels = |files| {
+            entries(&root, files)
+                .unwrap()
+                .into_iter()
+                .map(|(label, _)| label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(true), ["mods/", "a_preset.json"]);
+        assert_eq!(labels(false), ["mods/"]);
+        assert_eq!(
+            start_dir(&root.join("a_preset.json").to_string_lossy()),
+            root
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,29 +1,357 @@
+mod camping;
+mod case;
+mod category;
+mod changes;
+mod coverage;
+mod csv;
+mod decisions;
 mod deploy;
 mod diff;
+mod dlc;
+mod dry_run;
+mod edits;
 mod error;
+mod executables;
+mod heatmap;
+mod highlight;
+mod language;
+mod lock;
+mod names;
+mod overrides;
+mod policy;
+mod preset;
+mod progress;
+#[cfg(test)]
+mod puppet;
+mod rename;
 mod resolve;
+#[cfg(all(test, feature = "sample-mods"))]
+mod samples;
+mod search;
+mod session;
+mod size;
+mod sprites;
+mod stats;
 mod structures;
+mod summary;
+mod target;
+mod timing;
+mod translations;
+mod validate;
 
 use crate::loader::GlobalData;
+use coverage::Coverage;
 use cursive::{
-    traits::{Finder, Nameable},
-    views::{Dialog, LinearLayout, TextView},
+    traits::{Nameable, Scrollable},
+    views::{Dialog, LinearLayout, ProgressBar, TextView},
     Cursive,
 };
-use diff::{DataNode, DataTree, DataTreeExt, DiffTreeExt, ModContent, ResultDiffTressExt};
+use diff::{
+    Conflicts, DataNode, DataTree, DataTreeExt, DiffTree, DiffTreeExt, ModContent,
+    ResultDiffTressExt,
+};
 use error::ExtractionError;
+use lock::{LockFile, LockedMod};
 use log::*;
 use std::{
+    collections::BTreeMap,
     fs::read_dir,
     path::{Path, PathBuf},
+    time::Instant,
 };
 use thiserror::Error;
+use timing::Timings;
+
+pub use deploy::CommentVerbosity;
+pub use dlc::screen as dlc_baseline;
+pub use edits::{screen as value_edits, ValueEdit};
+pub use policy::{
+    set_for_all as set_default_merge_policy, settings as merge_settings, MergePolicy,
+};
+pub use preset::screen as presets;
+pub use rename::screen as rename_ids;
+pub use search::screen as search_data;
+pub use size::SizeLimits;
+pub use stats::screen as usage_stats;
+pub use structures::localization::LANGUAGES;
+pub use summary::{Outcome, Summary};
+pub use target::TARGETS as DEPLOYMENT_TARGETS;
 
 #[derive(Debug, Error)]
 #[error("Background thread panicked, stopping: {0}")]
 struct PanicError(String);
 
+impl PanicError {
+    fn new(panic_info: Box<dyn std::any::Any + Send>) -> Self {
+        let msg = match panic_info.downcast_ref::<&'static str>() {
+            Some(s) => *s,
+            None => match panic_info.downcast_ref::<String>() {
+                Some(s) => &s[..],
+                None => "Box<Any>",
+            },
+        };
+        Self(msg.to_string())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("None of the mods listed in the lock file is in the library")]
+struct NoLockedMods;
+
+/// Bundle location, relative to the game directory.
+const BUNDLE_DIR: &str = "mods/generated_bundle";
+/// Compatibility patch location, relative to the game directory.
+const PATCH_DIR: &str = "mods/generated_compat_patch";
+
+/// Cleans up after the deployment interrupted in the previous run, if any.
+pub fn recover_deployment(base_path: &Path) {
+    for dir in [BUNDLE_DIR, PATCH_DIR] {
+        let mod_path = crate::paths::game(base_path).join(dir);
+        if let Err(err) = deploy::recover(&mod_path) {
+            warn!("Unable to recover after interrupted deployment: {}", err);
+        }
+    }
+}
+
+/// Restores the bundle and the compatibility patch as they were before the last deployment.
+pub fn rollback_deployment(cursive: &mut Cursive) {
+    let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+    let game = crate::paths::game(&global_data.base_path);
+    cursive.add_layer(
+        Dialog::text(
+            "Restore the bundle as it was before the last deployment?\n\
+             The current bundle will be removed, together with its lock file.",
+        )
+        .title("Rollback last deployment")
+        .button("Rollback", move |cursive| {
+            cursive.pop_layer();
+            let mut rolled_back = false;
+            for dir in [BUNDLE_DIR, PATCH_DIR] {
+                match deploy::rollback(&game.join(dir)) {
+                    Ok(done) => rolled_back |= done,
+                    Err(err) => return crate::error(cursive, &err),
+                }
+            }
+            info!("Rollback finished, anything restored: {}", rolled_back);
+            cursive.add_layer(Dialog::info(if rolled_back {
+                "Last deployment was rolled back."
+            } else {
+                "There is no previous deployment to roll back to."
+            }));
+        })
+        .dismiss_button("Cancel"),
+    );
+}
+
+/// Removes the parsed vanilla and DLC files kept between runs, e.g. after the game files were repaired.
+pub fn clear_parse_cache(cursive: &mut Cursive) {
+    let text = match structures::clear_parse_cache() {
+        Ok(true) => {
+            "Parse cache is cleared; the game files will be parsed anew on the next bundling."
+                .to_string()
+        }
+        Ok(false) => "There is no parse cache yet.".to_string(),
+        Err(err) => {
+            warn!("[cache] Unable to remove parse cache: {}", err);
+            format!("Unable to clear the parse cache: {}", err)
+        }
+    };
+    cursive.add_layer(Dialog::info(text));
+}
+
 pub fn bundle(cursive: &mut Cursive) {
+    start_bundle(cursive, None, false);
+}
+
+/// Merges the selected mods without asking anything and writes the report instead of deploying.
+pub fn dry_run(cursive: &mut Cursive) {
+    start_bundle(cursive, None, true);
+}
+
+/// Rebuilds the bundle from the mods and decisions recorded in its lock file.
+pub fn rebuild_from_lock(cursive: &mut Cursive) {
+    let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+    let lock_path = crate::paths::game(&global_data.base_path)
+        .join(BUNDLE_DIR)
+        .join(lock::LOCK_FILE);
+    let lock = match LockFile::read(&lock_path) {
+        Ok(lock) => lock,
+        Err(err) => {
+            warn!("Unable to read lock file: {}", err);
+            cursive.add_layer(Dialog::info(format!(
+                "Unable to read lock file of the existing bundle: {}",
+                err
+            )));
+            return;
+        }
+    };
+    info!("Rebuilding bundle from lock file {:?}", lock_path);
+    select_locked(global_data, &lock.mods);
+    start_bundle(cursive, Some(lock), false);
+}
+
+/// Rebuilds the bundle from the mods and decisions recorded in its lock file without the user, e.g. in CI.
+///
+/// Nothing is asked: the conflicts which the recorded decisions don't resolve are left unresolved, and then
+/// nothing is deployed. UI updates of the bundling are applied to the screen which is never shown.
+pub fn rebuild_headless(mut global_data: GlobalData) -> Summary {
+    crate::prompt::set_headless();
+    let mut summary = Summary::default();
+    recover_deployment(&global_data.base_path);
+    let lock_path = crate::paths::game(&global_data.base_path)
+        .join(BUNDLE_DIR)
+        .join(lock::LOCK_FILE);
+    let lock = match LockFile::read(&lock_path) {
+        Ok(lock) => lock,
+        Err(err) => {
+            error!("Unable to read lock file: {}", err);
+            summary.failed(summary::Outcome::NotBundled, &err);
+            return summary;
+        }
+    };
+    info!(
+        "Rebuilding bundle from lock file {:?} headlessly",
+        lock_path
+    );
+    select_locked(&mut global_data, &lock.mods);
+    if !global_data.mods.iter().any(|the_mod| the_mod.selected) {
+        error!("{}", NoLockedMods);
+        summary.failed(summary::Outcome::NotBundled, &NoLockedMods);
+        return summary;
+    }
+
+    let (mut sink, callbacks) = crossbeam_channel::unbounded();
+    let bundling = std::thread::spawn(move || {
+        let mut summary = Summary::default();
+        if let Err(err) = do_bundle(&mut sink, &mut summary, global_data, Some(lock), false) {
+            error!("Bundling failed: {}", err);
+            summary.failed(failure_outcome(&err), &err);
+        }
+        summary
+    });
+    // The bundling drops the sink when it's over, be it finished or panicked.
+    let mut cursive = Cursive::new(|| {
+        cursive::backends::puppet::Backend::init(Some(cursive::Vec2::new(100, 40)))
+    });
+    for callback in callbacks {
+        callback(&mut cursive);
+    }
+    bundling.join().unwrap_or_else(|panic_info| {
+        let err = PanicError::new(panic_info);
+        error!("{}", err);
+        let mut summary = Summary::default();
+        summary.failed(summary::Outcome::NotBundled, &err);
+        summary
+    })
+}
+
+fn failure_outcome(err: &error::BundlerError) -> summary::Outcome {
+    match err {
+        error::BundlerError::Deployment(_) => summary::Outcome::DeployErrors,
+        _ => summary::Outcome::NotBundled,
+    }
+}
+
+/// Selects exactly the locked mods, in the locked order.
+fn select_locked(global_data: &mut GlobalData, locked_mods: &[LockedMod]) {
+    for the_mod in &mut global_data.mods {
+        let directory = the_mod
+            .path
+            .file_name()
+            .map(std::ffi::OsStr::to_string_lossy);
+        the_mod.selected = locked_mods
+            .iter()
+            .any(|locked| directory.as_deref() == Some(locked.directory.as_str()));
+    }
+    // Selected mods are bundled in the order they are listed, which must be the locked one.
+    global_data.mods.sort_by_key(|the_mod| {
+        let directory = the_mod.directory();
+        locked_mods
+            .iter()
+            .position(|locked| locked.directory == directory)
+    });
+}
+
+/// Offers to continue the bundling which was interrupted or stopped before every conflict was resolved.
+///
+/// Resuming bundles the same mods again, skipping the conflicts which were resolved in the saved session.
+pub fn offer_resume(cursive: &mut Cursive) {
+    let session = match session::Session::stored() {
+        Some(session) => session,
+        None => return,
+    };
+    let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+    let missing: Vec<_> = session
+        .mods
+        .iter()
+        .filter(|locked| {
+            !global_data
+                .mods
+                .iter()
+                .any(|the_mod| the_mod.directory() == locked.directory)
+        })
+        .map(|locked| locked.title.clone())
+        .collect();
+    if !missing.is_empty() {
+        info!(
+            "[session] Saved session can't be resumed, mods are missing: {:?}",
+            missing
+        );
+        return;
+    }
+    info!("[session] Offering to resume the saved session");
+    let titles: Vec<_> = session
+        .mods
+        .iter()
+        .map(|locked| format!("- {}", locked.title))
+        .collect();
+    cursive.add_layer(
+        Dialog::text(format!(
+            "The previous bundling was stopped with {} files resolved by you.
+Resume it to bundle the same mods again and go straight to the remaining conflicts:
+{}
+
+Discard it to forget these resolutions for good.",
+            session.chosen.len(),
+            titles.join("\n")
+        ))
+        .title("Resume previous session")
+        .button("Resume previous session", move |cursive| {
+            cursive.pop_layer();
+            let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+            select_locked(global_data, &session.mods);
+            start_bundle(cursive, None, false);
+        })
+        .button("Not now", |cursive| {
+            cursive.pop_layer();
+        })
+        .button("Discard", |cursive| {
+            cursive.pop_layer();
+            session::Session::discard();
+        }),
+    );
+}
+
+/// Starts bundling in the background; if `report_only` is set, it stops after merging and writes the report.
+fn start_bundle(cursive: &mut Cursive, lock: Option<LockFile>, report_only: bool) {
+    let selected = cursive
+        .user_data::<GlobalData>()
+        .expect("No data was set")
+        .mods
+        .iter()
+        .filter(|the_mod| the_mod.selected)
+        .count();
+    if selected == 0 {
+        info!("Nothing to bundle: no mods are selected");
+        cursive.add_layer(
+            Dialog::info(
+                "No mods are selected, so there is nothing to bundle.\n\
+                 Select the mods in the list first; a single mod is copied into the bundle as it is.",
+            )
+            .title("Nothing to bundle"),
+        );
+        return;
+    }
     let global_data: GlobalData = cursive.take_user_data().expect("No data was set");
 
     crate::screen(
@@ -31,8 +359,13 @@ pub fn bundle(cursive: &mut Cursive) {
         Dialog::around(
             LinearLayout::vertical()
                 // Space added so that the view is always rendered, even when this is not specified.
-                .child(TextView::new(" ").with_name("Loading part"))
-                .child(TextView::new(" ").with_name("Loading filename")),
+                .child(
+                    TextView::new(" ")
+                        .with_name(progress::PHASE_LIST)
+                        .scrollable(),
+                )
+                .child(TextView::new(" ").with_name("Loading filename"))
+                .child(ProgressBar::new().with_name(progress::PROGRESS_BAR)),
         )
         .title("Loading vanilla game data...")
         .with_name("Loading dialog"),
@@ -43,41 +376,100 @@ pub fn bundle(cursive: &mut Cursive) {
     let mut on_error = on_file_read.clone();
     std::thread::spawn(move || {
         info!("Starting background thread");
-        let thread = std::thread::spawn(|| {
+        let thread = std::thread::spawn(move || {
             let mut on_file_read = on_file_read;
-            if let Err(err) = do_bundle(&mut on_file_read, global_data) {
+            let mut summary = Summary::default();
+            if let Err(err) = do_bundle(
+                &mut on_file_read,
+                &mut summary,
+                global_data,
+                lock,
+                report_only,
+            ) {
+                summary.failed(failure_outcome(&err), &err);
                 crate::run_update(&mut on_file_read, move |cursive| {
                     crate::error(cursive, &err);
                 });
                 std::thread::yield_now(); // to let cursive run update immediately
             };
+            summary
         });
         info!("Waiting on the background thread");
-        if let Err(panic_info) = thread.join() {
-            let msg = match panic_info.downcast_ref::<&'static str>() {
-                Some(s) => *s,
-                None => match panic_info.downcast_ref::<String>() {
-                    Some(s) => &s[..],
-                    None => "Box<Any>",
-                },
+        let summary = match thread.join() {
+            Ok(summary) => {
+                info!("Background thread exited successfully");
+                summary
             }
-            .to_string();
-            crate::run_update(&mut on_error, move |cursive| {
-                crate::error(cursive, &PanicError(msg));
-            });
-        } else {
-            info!("Background thread exited successfully");
-        }
+            Err(panic_info) => {
+                let err = PanicError::new(panic_info);
+                let mut summary = Summary::default();
+                summary.failed(summary::Outcome::NotBundled, &err);
+                crate::run_update(&mut on_error, move |cursive| {
+                    crate::error(cursive, &err);
+                });
+                summary
+            }
+        };
+        // Nothing else needs the user data once the bundling is over; the summary is taken from it on exit.
+        crate::run_update(&mut on_error, move |cursive| cursive.set_user_data(summary));
     });
 }
 
 fn do_bundle(
     on_file_read: &mut cursive::CbSink,
+    summary: &mut Summary,
     global_data: GlobalData,
+    lock: Option<LockFile>,
+    report_only: bool,
 ) -> Result<(), error::BundlerError> {
+    profile_span!("bundle");
+    let started = Instant::now();
+    let mut timings = Timings::default();
     let path = crate::paths::game(&global_data.base_path);
+    let config = crate::config::Config::load();
+    // Must be set before loading, since the files under these globs are read as text.
+    structures::set_line_set_globs(&config.line_set_globs);
+    // With one mod there's nothing to merge, unless user edits are bundled together with it.
+    let has_edits = config
+        .profile()
+        .is_some_and(|profile| !profile.value_edits.is_empty());
+    let selected: Vec<_> = global_data
+        .mods
+        .iter()
+        .filter(|the_mod| the_mod.selected)
+        .collect();
+    if let [single] = selected[..] {
+        if lock.is_none() && !report_only && !has_edits {
+            return repackage(on_file_read, summary, &path, single, &config);
+        }
+    }
+    let dlc_path = path.join("dlc");
+    let dlc_phases = dlc::installed(&dlc_path)
+        .into_iter()
+        .filter(|name| !config.dlc_excluded(name))
+        .map(progress::Phase::Dlc);
+    let mod_phases = selected
+        .iter()
+        .map(|the_mod| progress::Phase::Mod(the_mod.name().to_owned()));
+    progress::plan(
+        on_file_read,
+        std::iter::once(progress::Phase::Vanilla)
+            .chain(dlc_phases)
+            .chain(mod_phases)
+            .chain([progress::Phase::Merge, progress::Phase::Deploy])
+            .collect(),
+    );
+    structures::load_parse_cache();
     info!("Extracting data from game directory");
-    let mut original_data = extract_data(on_file_read, &path, &path, true)?;
+    let mut original_data = timings.measure("Loading vanilla game", || {
+        extract_data(
+            on_file_read,
+            &path,
+            &path,
+            true,
+            Some(progress::Phase::Vanilla),
+        )
+    })?;
     info!("Vanilla game data extracted");
 
     crate::run_update(on_file_read, |cursive| {
@@ -87,8 +479,20 @@ fn do_bundle(
     });
 
     info!("Extracting DLC data");
-    let dlc_path = path.join("dlc");
-    for entry in read_dir(&dlc_path).map_err(ExtractionError::from_io(&dlc_path))? {
+    let dlc_start = Instant::now();
+    // DLC which provides every file of the baseline, if it is not a vanilla one.
+    let mut origins = BTreeMap::new();
+    let mut dlc_changes = vec![];
+    let dlc_entries = dlc::entries(&dlc_path)?;
+    let mut dlc_issues = vec![];
+    let mut excluded_dlc = vec![];
+    if dlc_entries.is_none() {
+        dlc_issues.push(validate::Issue {
+            path: "dlc".into(),
+            message: "Directory not found, so the bundle is built without any DLC".into(),
+        });
+    }
+    for entry in dlc_entries.into_iter().flatten() {
         let entry = entry.map_err(ExtractionError::from_io(&dlc_path))?;
         let path = entry.path();
         if entry
@@ -105,49 +509,418 @@ fn do_bundle(
                     "<INVALID>".into()
                 })
                 .to_string();
-            crate::run_update(on_file_read, |cursive| {
-                cursive
-                    .call_on_name("Loading part", |text: &mut TextView| {
-                        text.set_content(dlc_dir_name);
-                    })
-                    .unwrap();
-            });
-            original_data.extend(extract_data(on_file_read, &path, &path, true)?);
+            if config.dlc_excluded(&dlc_dir_name) {
+                info!("DLC {} is excluded from the baseline", dlc_dir_name);
+                excluded_dlc.push(dlc_dir_name);
+                continue;
+            }
+            let dlc_data = extract_data(
+                on_file_read,
+                &path,
+                &path,
+                true,
+                Some(progress::Phase::Dlc(dlc_dir_name.clone())),
+            )?;
+            if config.dlc_report {
+                dlc_changes.push((dlc_dir_name.clone(), original_data.diff(dlc_data.clone())));
+            }
+            origins.extend(
+                dlc_data
+                    .keys()
+                    .map(|file| (file.clone(), dlc_dir_name.clone())),
+            );
+            original_data.extend(dlc_data);
         } else {
             warn!("Found non-directory item in DLC folder: {:?}", path);
         }
     }
+    timings.add("Loading DLC", dlc_start.elapsed());
     info!("DLC data extracted and merged into vanilla game");
+    if config.dlc_report {
+        let report_path = crate::paths::dlc_report();
+        match std::fs::write(&report_path, dlc::report(&dlc_changes)) {
+            Ok(()) => info!("[dlc] Report of DLC changes written to {:?}", report_path),
+            Err(err) => warn!("[dlc] Unable to write {:?}: {}", report_path, err),
+        }
+    }
 
     crate::run_update(on_file_read, |cursive| {
         cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
             dialog.set_title("Loading workshop data...");
-            dialog.call_on_name("Loading part", |text: &mut TextView| {
-                text.set_content(" ");
-            })
         });
     });
 
     info!("Reading selected mods");
-    let mut for_mods_extract = on_file_read.clone();
-    let mods = global_data
+    let selected: Vec<_> = global_data
         .mods
         .into_iter()
         .inspect(|the_mod| info!("Reading mod: {:?}", the_mod))
         .filter(|the_mod| the_mod.selected)
+        .collect();
+    let attributed = if config.include_attribution {
+        selected.clone()
+    } else {
+        vec![]
+    };
+    let compat_issues: Vec<_> = crate::compat::Database::load()
+        .warnings(&selected)
+        .into_iter()
+        .map(|(title, message)| {
+            warn!("[compat] {} {}", title, message);
+            validate::Issue {
+                path: title.into(),
+                message,
+            }
+        })
+        .collect();
+    let mut locked_mods = selected
+        .iter()
         .map(|the_mod| {
-            info!("Extracting data from selected mod: {}", the_mod.name());
-            extract_mod(&mut for_mods_extract, the_mod, &original_data)
+            lock::LockedMod::new(the_mod).map_err(ExtractionError::from_io(&the_mod.path))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // User edits are bundled as one more mod, so that their conflicts with the selected ones are resolved as usual.
+    let value_edits = config
+        .profile()
+        .map_or(&[][..], |profile| profile.value_edits.as_slice());
+    let (edits_patch, late_edits) = edits::split(&original_data, value_edits);
+    if !value_edits.is_empty() {
+        info!(
+            "Bundling {} value edits, {} of them after merging",
+            value_edits.len(),
+            late_edits.len()
+        );
+        locked_mods.push(edits::locked(value_edits));
+    }
+    *summary = Summary::new(
+        locked_mods
+            .iter()
+            .map(|the_mod| the_mod.title.clone())
+            .collect(),
+    );
+    let translations = translations::detect(&locked_mods);
+    for translation in &translations {
+        info!(
+            "[translations] {} translates {}",
+            translation.translation, translation.target
+        );
+    }
+    let misordered: Vec<_> = translations
+        .iter()
+        .filter(|translation| translation.misordered)
+        .map(|translation| {
+            format!(
+                "- \"{}\" should be placed after \"{}\"",
+                translation.translation, translation.target
+            )
+        })
+        .collect();
+    if !misordered.is_empty() {
+        show_and_wait(
+            on_file_read,
+            "Translations are bundled before their mods",
+            format!(
+                "These mods look like translations of other selected mods, but are bundled before them,
+so the files they both change are taken from the original mods:
+{}
+
+String tables are taken from the translations anyway; consider reordering the selected mods for everything else.",
+                misordered.join("\n")
+            ),
+            "Continue",
+        );
+    }
+    let sources = resolve::Sources::new(
+        selected
+            .iter()
+            .map(|the_mod| (the_mod.name().to_owned(), the_mod.path.clone())),
+    );
+    let mut coverage = vec![];
+    let mut protected_issues = vec![];
+    // Mods are loaded on several threads at once, so only the time of the whole loading is meaningful.
+    let loaded = timings.measure("Loading mods", || {
+        load_mods(on_file_read, &selected, &original_data, &config)
+    });
+    let mods = loaded
+        .into_iter()
+        .map(|(content, mod_coverage, dropped)| {
+            coverage.extend(mod_coverage);
+            protected_issues.extend(dropped);
+            content
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let mut touched = dry_run::Touched::new();
+    let mods = mods
+        .chain(
+            Some(Ok(ModContent::new(edits::USER_EDITS, edits_patch)))
+                .filter(|_| !value_edits.is_empty()),
+        )
+        .inspect(|content| {
+            if let Ok(content) = content {
+                touched.push((
+                    content.name().to_owned(),
+                    content.paths().cloned().collect(),
+                ));
+            }
         });
 
-    let (merged, conflicts) = mods.try_merge(Some(on_file_read))?;
+    let policies = policy::Policies::from_config(&config, &locked_mods);
+    let (merged, conflicts) =
+        timings.measure("Merging", || mods.try_merge(Some(on_file_read), &policies))?;
     info!("Merged mods data, got {} conflicts", conflicts.len());
+    summary.conflicts(conflicts.len());
+    if report_only {
+        let report_path = crate::paths::conflict_report();
+        std::fs::write(&report_path, dry_run::report(&touched, &merged, &conflicts))
+            .map_err(|err| error::BundlerError::Report(err, report_path.clone()))?;
+        info!("[dry_run] Report written to {:?}", report_path);
+        let csv_path = crate::paths::conflict_csv();
+        let mod_names: Vec<_> = touched.iter().map(|(name, _)| name.clone()).collect();
+        std::fs::write(
+            &csv_path,
+            csv::export(&original_data, &conflicts, &mod_names),
+        )
+        .map_err(|err| error::BundlerError::Report(err, csv_path.clone()))?;
+        info!("[dry_run] Conflict spreadsheet written to {:?}", csv_path);
+        let text = format!(
+            "Nothing was deployed. The report of the files every mod changes, the files changed by several mods\n\
+             and the way they would be merged ({} conflicts would be asked) is written to {}.\n\
+             The conflicts are also listed in {}; fill its Decision column and import it on the presets screen.",
+            conflicts.len(),
+            report_path.to_string_lossy(),
+            csv_path.to_string_lossy()
+        );
+        crate::run_update(on_file_read, move |cursive| {
+            crate::screen(
+                cursive,
+                Dialog::around(TextView::new(text))
+                    .title("Conflict report")
+                    .button("OK", Cursive::quit),
+            );
+        });
+        return Ok(());
+    }
+    let bundle_stats = stats::Bundle::of(&conflicts);
+
+    // When rebuilding, the lock being replayed is the previous one; otherwise, check the deployed bundle.
+    let deployed_lock = match &lock {
+        Some(_) => None,
+        None => LockFile::read(&path.join(BUNDLE_DIR).join(lock::LOCK_FILE)).ok(),
+    };
+    if let Some(previous) = lock.as_ref().or(deployed_lock.as_ref()) {
+        let changes = changes::report(previous, &locked_mods, &conflicts);
+        for line in &changes {
+            info!("[changes] {}", line);
+        }
+        if !changes.is_empty() {
+            show_and_wait(
+                on_file_read,
+                "Changes since the last bundle",
+                changes.join("\n"),
+                "Continue",
+            );
+        }
+    }
+
+    let inputs = resolve::all_inputs(&conflicts);
+    let (mut resolutions, conflicts) = match &lock {
+        Some(lock) => resolve::replay(&lock.resolutions, &lock.inputs, conflicts),
+        None => (Default::default(), conflicts),
+    };
+    // Decisions saved when the previous bundling was stopped with some conflicts left for later.
+    let resumed = match &lock {
+        Some(_) => None,
+        None => session::Session::load(&locked_mods),
+    };
+    let conflicts = match &resumed {
+        Some(session) => {
+            let (replayed, remaining) =
+                resolve::replay(&session.resolutions, &session.inputs, conflicts);
+            resolutions.extend(replayed);
+            remaining
+        }
+        None => conflicts,
+    };
+    // Decisions made by hand in the earlier bundlings, for the conflicts which haven't changed since.
+    let mut decisions = decisions::Decisions::load();
+    let (replayed, mut conflicts) = decisions.replay(conflicts);
+    resolutions.extend(replayed);
+    // Decisions shared by other users for the same mods, imported by the user.
+    if let Some(preset) =
+        preset::Preset::load_active(&crate::paths::workshop(&global_data.base_path))
+    {
+        let (replayed, remaining) = resolve::replay(&preset.resolutions, &preset.inputs, conflicts);
+        resolutions.extend(replayed);
+        let (chosen, remaining) = preset.choose(remaining);
+        resolutions.extend(chosen);
+        conflicts = remaining;
+    }
+    resolutions.extend(translations::prefer(&translations, &mut conflicts));
+    if config.auto_resolve_cosmetic {
+        resolutions.extend(category::resolve_cosmetic(&mut conflicts));
+    }
+    if crate::prompt::is_headless() && !conflicts.is_empty() {
+        warn!(
+            "{} conflicts are not resolved by the earlier decisions, stopping before deploy",
+            conflicts.len()
+        );
+        summary.postponed(conflicts.into_keys().collect());
+        return Ok(());
+    }
+    // Saved after every resolved file, so that a crash or quitting in the middle doesn't lose the decisions.
+    let mut saved = session::Session {
+        mods: locked_mods.clone(),
+        resolutions: resolutions.clone(),
+        inputs: inputs.clone(),
+        chosen: resumed.map(|session| session.chosen).unwrap_or_default(),
+    };
+    let (resolved, postponed) = timings.measure("Resolving conflicts", || {
+        if conflicts.is_empty() {
+            return (DiffTree::new(), Conflicts::new());
+        }
+        heatmap::show(on_file_read, &conflicts);
+        let names =
+            names::DisplayNames::new(&original_data, &merged, config.bundle_language.as_deref())
+                .with_origins(origins);
+        let chosen = session::choose_files(on_file_read, &conflicts, &names);
+        let (mut now, later): (Conflicts, Conflicts) = conflicts
+            .into_iter()
+            .partition(|(path, _)| chosen.contains(path));
+        let asked = now.clone();
+        let mut resolved = camping::resolve_together(on_file_read, &mut now, &names);
+        for (path, node) in &resolved {
+            saved.record(path, node);
+        }
+        resolved.extend(resolve::resolve(
+            on_file_read,
+            now,
+            &original_data,
+            &names,
+            &sources,
+            |path, node| saved.record(path, node),
+        ));
+        decisions.record(&asked, &resolved);
+        decisions.save();
+        (resolved, later)
+    });
+    resolutions.extend(resolved);
+    if !postponed.is_empty() {
+        session::Session {
+            mods: locked_mods,
+            resolutions,
+            inputs,
+            chosen: saved.chosen,
+        }
+        .save()?;
+        let mut files: Vec<_> = postponed
+            .keys()
+            .map(|path| format!("- {}", path.to_string_lossy()))
+            .collect();
+        files.sort();
+        info!(
+            "[session] {} files are left unresolved, stopping before deploy",
+            files.len()
+        );
+        summary.postponed(postponed.keys().cloned().collect());
+        let text = format!(
+            "{} files still have conflicts, so the bundle was not deployed.
+Decisions made so far are saved: resume the session on the next launch, or make the bundle again with the same mods, to resolve the rest.
 
-    let resolved = resolve::resolve(on_file_read, conflicts);
-    let merged = resolve::merge_resolved(merged, resolved);
+Unresolved files:
+{}",
+            files.len(),
+            files.join("\n")
+        );
+        crate::run_update(on_file_read, move |cursive| {
+            crate::screen(
+                cursive,
+                Dialog::around(TextView::new(text).scrollable()).button("OK", Cursive::quit),
+            );
+        });
+        return Ok(());
+    }
+    let merged = resolve::merge_resolved(merged, resolutions.clone());
 
     info!("Applying patches");
-    let modded = merged.apply_to(original_data);
+    let mut modded = timings.measure("Applying patches", || {
+        merged.apply_reporting(&original_data, |path, err| summary.parse_error(path, err))
+    });
+    // Every vanilla file was parsed by now, so the cache can be written without waiting for the rest.
+    structures::save_parse_cache();
+
+    let override_issues =
+        overrides::resolve(on_file_read, &original_data, &mut modded, &locked_mods);
+    // Edits of the values which only the mods have are applied last, on top of them.
+    let edit_issues = edits::apply(&original_data, &mut modded, &late_edits);
+    let case_issues = case::resolve(on_file_read, &original_data, &mut modded, &locked_mods);
+
+    info!("Validating merged data");
+    let mut issues = timings.measure("Validating", || {
+        validate::validate(&original_data, &modded, &locked_mods)
+    });
+    for issue in &issues {
+        warn!("[validate] {}", issue);
+    }
+    issues.extend(override_issues);
+    issues.extend(edit_issues);
+    issues.extend(case_issues);
+    issues.extend(protected_issues);
+    issues.extend(compat_issues);
+    issues.extend(dlc_issues);
+    if config.validate_sprites {
+        issues.extend(sprites::check(resolutions.keys(), &original_data, &modded));
+    }
+    record_parse_errors(summary, &issues);
+
+    // Done after validation, since the references are checked against the primary language.
+    if let Some(language) = &config.bundle_language {
+        info!("Leaving only {} in the string tables", language);
+        let language_issues = timings.measure("Restricting language", || {
+            language::restrict(&mut modded, language)
+        });
+        issues.extend(language_issues);
+    }
+
+    let game_build = lock::game_build(&path);
+    info!("Game build: {:?}", game_build);
+    deploy::annotate(&mut modded, config.deploy_comments, &locked_mods);
+    let new_lock = LockFile::new(locked_mods, resolutions, lock::hash_tree(&modded)?)
+        .with_inputs(inputs)
+        .with_game_build(game_build.clone())
+        .with_excluded_dlc(excluded_dlc);
+    if let Some(lock) = &lock {
+        let differences = lock.differences(&new_lock);
+        for difference in &differences {
+            warn!("[lock] {}", difference);
+        }
+        issues.extend(differences);
+    }
+
+    let bundle_size = size::BundleSize::of(&modded);
+    let size_warnings = bundle_size.warnings(&config.size_limits, &new_lock.mods);
+    if !size_warnings.is_empty() {
+        for line in &size_warnings {
+            warn!("[size] {}", line);
+        }
+        show_and_wait(
+            on_file_read,
+            "Bundle is unusually large",
+            size_warnings.join("\n"),
+            "Deploy anyway",
+        );
+    }
+    let mods_size: u64 = coverage.iter().map(|(_, coverage)| coverage.total()).sum();
+    let size_summary = format!(
+        "Bundle takes {} in {} files, selected mods take {} in total.",
+        coverage::format_size(bundle_size.total),
+        bundle_size.files.len(),
+        coverage::format_size(mods_size)
+    );
+    info!("{}", size_summary);
+    if config.bundle_executables {
+        executables::confirm(on_file_read, &modded, &new_lock.mods);
+    }
 
     crate::run_update(on_file_read, |cursive| {
         cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
@@ -156,35 +929,501 @@ fn do_bundle(
     });
 
     info!("Deploying generated mod to the \"mods\" directory");
-    let mod_path = path.join("mods/generated_bundle");
-    deploy::deploy(on_file_read, &mod_path, modded)?;
+    let mod_path = path.join(BUNDLE_DIR);
+    let patch = config
+        .compat_patch
+        .then(|| deploy::compat_patch(&modded, &new_lock.mods));
+    let staging = timings.measure("Deploying", || {
+        let staging = deploy::deploy(
+            on_file_read,
+            &mod_path,
+            modded,
+            config.workshop_upload,
+            game_build.as_deref(),
+            Some(&new_lock.output),
+        )?;
+        if !attributed.is_empty() {
+            deploy::write_attribution(&staging, &attributed)?;
+        }
+        Ok::<_, error::DeploymentError>(staging)
+    })?;
+    // The bundle itself is complete, so the lock file is not worth failing for.
+    if let Err(err) = timings.measure("Deploying", || new_lock.write(&staging)) {
+        warn!("[lock] Unable to write lock file: {}", err);
+        issues.push(validate::Issue {
+            path: lock::LOCK_FILE.into(),
+            message: format!("Unable to write lock file: {}", err),
+        });
+    }
+    timings.measure("Deploying", || deploy::finish(&staging, &mod_path))?;
+    let delivered = timings.measure("Deploying", || {
+        target::deliver(on_file_read, &mod_path, &config)
+    })?;
+    if let Some(patch) = patch {
+        info!("Deploying compatibility patch with {} files", patch.len());
+        let patch_path = path.join(PATCH_DIR);
+        timings.measure("Deploying", || {
+            let staging =
+                deploy::deploy_patch(on_file_read, &patch_path, patch, game_build.as_deref())?;
+            deploy::finish(&staging, &patch_path)
+        })?;
+    }
+    session::Session::remove();
+    summary.deployed(issues.iter().map(ToString::to_string).collect());
+    bundle_stats.record();
+
+    let mut upload_instructions = if config.workshop_upload {
+        format!("\n\n{}", deploy::upload_instructions(&path, &mod_path))
+    } else {
+        String::new()
+    };
+    if let Some(delivered) = delivered {
+        upload_instructions += &format!("\n\nBundle is also delivered to {}.", delivered.display());
+    }
+    let timing_report = timings.report(started.elapsed());
+    info!("Bundling finished, timings:\n{}", timing_report);
+    crate::run_update(on_file_read, move |cursive| {
+        let text = if issues.is_empty() {
+            "Bundle ready!".to_string()
+        } else {
+            format!(
+                "Bundle ready, but there are some things to check:\n{}",
+                issues
+                    .iter()
+                    .map(|issue| format!("- {}", issue))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        };
+        let coverage = coverage
+            .into_iter()
+            .map(|(name, coverage)| format!("- {}: {}", name, coverage))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = format!(
+            "{}\n\n{}\n\nMods content by the way it is merged:\n{}\n\nTime spent:\n{}{}",
+            text, size_summary, coverage, timing_report, upload_instructions
+        );
+        crate::screen(
+            cursive,
+            Dialog::around(TextView::new(text).scrollable()).button("OK", Cursive::quit),
+        );
+    });
+    Ok(())
+}
+
+/// Deploys the only selected mod as the bundle, without loading the game data or merging anything,
+/// e.g. to get the local copy of the Workshop mod which can be edited.
+fn repackage(
+    on_file_read: &mut cursive::CbSink,
+    summary: &mut Summary,
+    path: &Path,
+    the_mod: &crate::loader::Mod,
+    config: &crate::config::Config,
+) -> Result<(), error::BundlerError> {
+    info!("Only {:?} is selected, repackaging it", the_mod.name());
+    let started = Instant::now();
+    let mut timings = Timings::default();
+    *summary = Summary::new(vec![the_mod.name().to_owned()]);
+    progress::plan(
+        on_file_read,
+        vec![
+            progress::Phase::Mod(the_mod.name().to_owned()),
+            progress::Phase::Deploy,
+        ],
+    );
+    crate::run_update(on_file_read, |cursive| {
+        cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
+            dialog.set_title("Loading workshop data...");
+        });
+    });
+    let locked = lock::LockedMod::new(the_mod).map_err(ExtractionError::from_io(&the_mod.path))?;
+    let mut dropped = vec![];
+    let data = timings.measure("Loading mods", || {
+        repackaged(on_file_read, the_mod, &locked, config, &mut dropped)
+    })?;
+    record_parse_errors(summary, &dropped);
+    let mut issues: Vec<_> = dropped.iter().map(ToString::to_string).collect();
+    let game_build = lock::game_build(path);
+    let new_lock = LockFile::new(vec![locked], DiffTree::new(), lock::hash_tree(&data)?)
+        .with_game_build(game_build.clone());
+    if config.bundle_executables {
+        executables::confirm(on_file_read, &data, &new_lock.mods);
+    }
 
     crate::run_update(on_file_read, |cursive| {
+        cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
+            dialog.set_title("Deploying...");
+        });
+    });
+    let mod_path = path.join(BUNDLE_DIR);
+    let files = data.len();
+    let staging = timings.measure("Deploying", || {
+        deploy::deploy(
+            on_file_read,
+            &mod_path,
+            data,
+            config.workshop_upload,
+            game_build.as_deref(),
+            Some(&new_lock.output),
+        )
+    })?;
+    if let Err(err) = new_lock.write(&staging) {
+        warn!("[lock] Unable to write lock file: {}", err);
+        issues.push(format!("Unable to write lock file: {}", err));
+    }
+    timings.measure("Deploying", || deploy::finish(&staging, &mod_path))?;
+    let delivered = timings.measure("Deploying", || {
+        target::deliver(on_file_read, &mod_path, config)
+    })?;
+    summary.deployed(issues.clone());
+
+    let mut text = format!(
+        "Only \"{}\" is selected, so its {} files are copied into the bundle without merging.",
+        the_mod.name(),
+        files
+    );
+    for issue in issues {
+        text += &format!("\n- {}", issue);
+    }
+    if config.workshop_upload {
+        text += &format!("\n\n{}", deploy::upload_instructions(path, &mod_path));
+    }
+    if let Some(delivered) = delivered {
+        text += &format!("\n\nBundle is also delivered to {}.", delivered.display());
+    }
+    let timing_report = timings.report(started.elapsed());
+    info!("Repackaging finished, timings:\n{}", timing_report);
+    text += &format!("\n\nTime spent:\n{}", timing_report);
+    crate::run_update(on_file_read, move |cursive| {
         crate::screen(
             cursive,
-            Dialog::around(TextView::new("Bundle ready!")).button("OK", Cursive::quit),
+            Dialog::around(TextView::new(text).scrollable())
+                .title("Bundle ready!")
+                .button("OK", Cursive::quit),
         );
     });
     Ok(())
 }
 
+/// Files of the only selected mod as they are bundled: filtered the same way as the merged mods are,
+/// and annotated with the deployment comments.
+fn repackaged(
+    on_file_read: &mut cursive::CbSink,
+    the_mod: &crate::loader::Mod,
+    locked: &lock::LockedMod,
+    config: &crate::config::Config,
+    dropped: &mut Vec<validate::Issue>,
+) -> Result<DataTree, ExtractionError> {
+    let mut data = extract_data(
+        on_file_read,
+        &the_mod.path,
+        &the_mod.path,
+        true,
+        Some(progress::Phase::Mod(the_mod.name().to_owned())),
+    )?;
+    filter_files(the_mod, &mut data, config, dropped);
+    data.retain(|path, _| !is_protected(the_mod, path, config, dropped));
+    deploy::annotate(
+        &mut data,
+        config.deploy_comments,
+        std::slice::from_ref(locked),
+    );
+    Ok(data)
+}
+
+fn show_and_wait(
+    sink: &mut cursive::CbSink,
+    title: &'static str,
+    text: impl Into<cursive::utils::markup::StyledString> + Send + 'static,
+    button: &'static str,
+) {
+    let text = text.into();
+    crate::prompt::ask_or(sink, (), move |cursive, reply| {
+        crate::push_screen(
+            cursive,
+            Dialog::around(TextView::new(text).scrollable())
+                .title(title)
+                .button(button, move |cursive| {
+                    cursive.pop_layer();
+                    reply.send(());
+                }),
+        );
+    })
+}
+
+/// What to do with the mod which failed to load.
+enum Recovery {
+    /// Load the mod again, after it was fixed on disk.
+    Retry,
+    /// Bundle everything else without this mod.
+    Skip,
+    Stop,
+}
+
+/// Asks what to do with the failed mod, blocking until the user decides.
+fn ask_recovery(sink: &mut cursive::CbSink, name: &str, err: &ExtractionError) -> Recovery {
+    let mut text = format!("Unable to load mod \"{}\": {}", name, err);
+    if let Some(source) = std::error::Error::source(err) {
+        text.push_str(&format!("\n{}", source));
+    }
+    warn!("{}", text);
+    text.push_str(
+        "\n\nIf the file is broken, fix it on disk and retry: only this mod will be loaded again.",
+    );
+    crate::prompt::ask_or(sink, Recovery::Stop, move |cursive, reply| {
+        let mut dialog =
+            Dialog::around(TextView::new(text).scrollable()).title("Unable to load mod");
+        let retry = reply.clone();
+        dialog.add_button("Retry", move |cursive| {
+            cursive.pop_layer();
+            retry.send(Recovery::Retry);
+        });
+        let skip = reply.clone();
+        dialog.add_button("Skip this mod", move |cursive| {
+            cursive.pop_layer();
+            skip.send(Recovery::Skip);
+        });
+        dialog.add_button("Stop bundling", move |cursive| {
+            cursive.pop_layer();
+            reply.send(Recovery::Stop);
+        });
+        crate::push_screen(cursive, dialog);
+    })
+}
+
+/// Result of loading one mod: its changes, its content coverage and the changes dropped from it.
+type LoadedMod = (
+    Result<ModContent, ExtractionError>,
+    Option<(String, Coverage)>,
+    Vec<validate::Issue>,
+);
+
+/// Loads the selected mods on several threads, returning them in the order of selection.
+///
+/// Mods are independent of each other until they are merged, so only the progress is shared:
+/// one bar for the files of every mod, while every mod has its own phase in the list.
+fn load_mods(
+    sink: &cursive::CbSink,
+    selected: &[crate::loader::Mod],
+    original_data: &DataTree,
+    config: &crate::config::Config,
+) -> Vec<LoadedMod> {
+    let total_files = selected
+        .iter()
+        .map(|the_mod| progress::count_files(&the_mod.path, true))
+        .sum();
+    let progress = progress::Progress::start(&mut sink.clone(), total_files);
+    let threads = std::thread::available_parallelism()
+        .map_or(1, std::num::NonZeroUsize::get)
+        .min(selected.len().max(1));
+    let (queue, queued) = crossbeam_channel::unbounded();
+    for item in selected.iter().enumerate() {
+        queue.send(item).expect("Receiver is not dropped yet");
+    }
+    drop(queue);
+    let (done, finished) = crossbeam_channel::unbounded();
+    let mut loaded: Vec<Option<LoadedMod>> = selected.iter().map(|_| None).collect();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let queued = queued.clone();
+            let done = done.clone();
+            let mut sink = sink.clone();
+            let progress = &progress;
+            scope.spawn(move || {
+                for (index, the_mod) in queued {
+                    info!("Extracting data from selected mod: {}", the_mod.name());
+                    let mut coverage = None;
+                    let mut dropped = vec![];
+                    // The mod which failed to load can be fixed on disk and loaded again,
+                    // keeping everything loaded before.
+                    let content = loop {
+                        let phase = progress::Phase::Mod(the_mod.name().to_owned());
+                        let total = progress::count_files(&the_mod.path, true);
+                        let mod_progress = progress.part(&mut sink, phase, total);
+                        let err = match extract_mod(
+                            &mut sink,
+                            &mod_progress,
+                            the_mod,
+                            original_data,
+                            config,
+                            &mut dropped,
+                        ) {
+                            Ok((content, mod_coverage)) => {
+                                coverage = Some((the_mod.name().to_owned(), mod_coverage));
+                                break Ok(content);
+                            }
+                            Err(err) => err,
+                        };
+                        match ask_recovery(&mut sink, the_mod.name(), &err) {
+                            Recovery::Retry => {
+                                info!("Retrying to extract data from mod: {}", the_mod.name());
+                            }
+                            Recovery::Skip => {
+                                warn!("Skipping mod {}: {}", the_mod.name(), err);
+                                dropped.push(validate::Issue {
+                                    path: the_mod.path.clone(),
+                                    message: format!(
+                                        "Mod \"{}\" was skipped: {}",
+                                        the_mod.name(),
+                                        err
+                                    ),
+                                });
+                                break Ok(ModContent::new(the_mod.name(), DiffTree::new()));
+                            }
+                            Recovery::Stop => break Err(err),
+                        }
+                    };
+                    done.send((index, (content, coverage, dropped)))
+                        .expect("Receiver is not dropped yet");
+                }
+            });
+        }
+        drop(done);
+        // Results are gathered on this thread only, which is also the only one reporting the finished mods.
+        let mut sink = sink.clone();
+        for (index, result) in finished {
+            let phase = progress::Phase::Mod(selected[index].name().to_owned());
+            progress::finish(&mut sink, &phase);
+            loaded[index] = Some(result);
+        }
+    });
+    loaded
+        .into_iter()
+        .map(|result| result.expect("Every mod is loaded"))
+        .collect()
+}
+
 fn extract_mod(
     on_file_read: &mut cursive::CbSink,
-    the_mod: crate::loader::Mod,
+    progress: &progress::Progress,
+    the_mod: &crate::loader::Mod,
     original_data: &DataTree,
-) -> Result<ModContent, ExtractionError> {
-    let title = the_mod.name().to_owned();
-    crate::run_update(on_file_read, move |cursive| {
-        cursive.call_on_name("Loading part", |text: &mut TextView| {
-            text.set_content(title);
+    config: &crate::config::Config,
+    dropped: &mut Vec<validate::Issue>,
+) -> Result<(ModContent, Coverage), ExtractionError> {
+    profile_span!("load_mod", name = the_mod.name());
+    let mut content = extract_dir(on_file_read, progress, &the_mod.path, &the_mod.path, true)?;
+    filter_files(the_mod, &mut content, config, dropped);
+    // These files are still merged, line by line or as a whole, but user should check the result.
+    for (path, err) in malformed(original_data, &content) {
+        warn!(
+            "Mod {}: file {:?} is malformed: {}",
+            the_mod.name(),
+            path,
+            err
+        );
+        dropped.push(validate::Issue {
+            path,
+            message: match err {
+                structures::StructureError::NotText => format!(
+                    "File from mod \"{}\" is not text, unlike the game's own one, so it is taken as a whole",
+                    the_mod.name()
+                ),
+                err => format!(
+                    "{} the file from mod \"{}\" ({}), so it is merged line by line",
+                    validate::UNPARSED,
+                    the_mod.name(),
+                    err
+                ),
+            },
         });
-    });
-    let content = extract_data(on_file_read, &the_mod.path, &the_mod.path, true)?;
+    }
     info!(
         "Mod {}: Data successfully extracted, calculating patch",
         the_mod.name()
     );
-    Ok(ModContent::new(the_mod.name(), original_data.diff(content)))
+    let mod_coverage = Coverage::of(&content);
+    info!("Mod {}: Content coverage: {}", the_mod.name(), mod_coverage);
+    let mut diff = original_data.diff(content);
+    diff.retain(|path, _| !is_protected(the_mod, path, config, dropped));
+    Ok((ModContent::new(the_mod.name(), diff), mod_coverage))
+}
+
+/// Records the files which couldn't be parsed among the issues in the summary.
+fn record_parse_errors(summary: &mut Summary, issues: &[validate::Issue]) {
+    for issue in issues.iter().filter(|issue| issue.is_unparsed()) {
+        summary.parse_error(&issue.path, &issue.message);
+    }
+}
+
+/// Drops the mod's files excluded by user and, unless allowed, the executables, then renames the ids.
+fn filter_files(
+    the_mod: &crate::loader::Mod,
+    content: &mut DataTree,
+    config: &crate::config::Config,
+    dropped: &mut Vec<validate::Issue>,
+) {
+    let excluded = config.mod_excludes(the_mod);
+    content.retain(|path, _| {
+        let keep = !excluded.iter().any(|glob| crate::glob::matches(glob, path));
+        if !keep {
+            info!("Mod {}: file {:?} is excluded", the_mod.name(), path);
+        }
+        keep
+    });
+    if !config.bundle_executables {
+        dropped.extend(executables::drop_from(content, the_mod.name()));
+    }
+    if let Some(renames) = config.id_renames(the_mod) {
+        info!("Mod {}: Renaming ids {:?}", the_mod.name(), renames);
+        *content = rename::apply(std::mem::take(content), renames);
+    }
+}
+
+/// Whether the path is protected by user, so that the mod's changes to it must be dropped; reports them if so.
+fn is_protected(
+    the_mod: &crate::loader::Mod,
+    path: &Path,
+    config: &crate::config::Config,
+    dropped: &mut Vec<validate::Issue>,
+) -> bool {
+    if !config
+        .protected_paths
+        .iter()
+        .any(|glob| crate::glob::matches(glob, path))
+    {
+        return false;
+    }
+    warn!(
+        "Mod {}: dropping changes of the protected path {:?}",
+        the_mod.name(),
+        path
+    );
+    dropped.push(validate::Issue {
+        path: path.to_owned(),
+        message: format!(
+            "Changes by mod \"{}\" were dropped, since the path is protected",
+            the_mod.name()
+        ),
+    });
+    true
+}
+
+/// Mod's files which replace the game's ones, but can't be merged with them value by value:
+/// the structured files which fail to parse while the game's own ones don't, and the binary files
+/// in place of the text ones.
+fn malformed(
+    original_data: &DataTree,
+    content: &DataTree,
+) -> Vec<(PathBuf, structures::StructureError)> {
+    content
+        .iter()
+        .filter_map(|(path, node)| {
+            let original = original_data.get(path)?;
+            let err = match (original.text(), node.text()) {
+                (Some(_), None) => structures::StructureError::NotText,
+                (Some(original), Some(text)) if original != text => {
+                    let format = structures::data_type(path)?.format;
+                    // If the game's file is broken too, there's nothing the mod could do better.
+                    format.flatten(original).ok()?;
+                    format.flatten(text).err()?
+                }
+                _ => return None,
+            };
+            Some((path.clone(), err))
+        })
+        .collect()
 }
 
 fn extract_data(
@@ -192,6 +1431,22 @@ fn extract_data(
     base_path: &Path,
     cur_path: &Path,
     root: bool,
+    phase: Option<progress::Phase>,
+) -> Result<DataTree, ExtractionError> {
+    let total = progress::count_files(cur_path, root);
+    let progress = match phase {
+        Some(phase) => progress::Progress::phase(on_file_read, phase, total),
+        None => progress::Progress::start(on_file_read, total),
+    };
+    extract_dir(on_file_read, &progress, base_path, cur_path, root)
+}
+
+fn extract_dir(
+    on_file_read: &mut cursive::CbSink,
+    progress: &progress::Progress,
+    base_path: &Path,
+    cur_path: &Path,
+    root: bool,
 ) -> Result<DataTree, ExtractionError> {
     info!("Extracting data from: {:?}", cur_path);
     let items = read_dir(cur_path)
@@ -215,7 +1470,7 @@ fn extract_data(
                     Ok(vec![])
                 } else {
                     debug!("Descending into child directory {:?}", item_path);
-                    extract_data(on_file_read, base_path, &item_path, false)
+                    extract_dir(on_file_read, progress, base_path, &item_path, false)
                         .map(|data| data.into_iter().collect())
                 }
             } else if root {
@@ -223,6 +1478,7 @@ fn extract_data(
                 // Special case - don't extract anything from root folder (there is no data there)
                 Ok(vec![])
             } else {
+                progress.advance();
                 extract_from_file(on_file_read, base_path, &item_path)
                     .map(|(path, data)| vec![(path, data)])
                     .map_err(ExtractionError::from_io(&item_path))
@@ -280,39 +1536,226 @@ fn extract_from_file(
             ),
         )
     })?;
+    profile_span!("load_file", path = ?rel_path);
     let log_path = rel_path.to_string_lossy();
     set_file_updated(on_file_read, "Reading", log_path);
 
-    let content = match path.extension().and_then(std::ffi::OsStr::to_str) {
-        Some("js") | Some("darkest") | Some("xml") | Some("json") | Some("txt") => {
-            match std::fs::read_to_string(path).map(Some) {
-                Ok(s) => {
-                    debug!("Read successful: {:?}", path);
-                    s.as_ref().map(|s| {
-                        debug!(
-                            "Total {} lines, {} characters",
-                            s.lines().count(),
-                            s.chars().count()
-                        )
-                    });
-                    Ok(s)
-                }
-                Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+    let whitelisted = matches!(
+        path.extension().and_then(std::ffi::OsStr::to_str),
+        Some("js") | Some("darkest") | Some("xml") | Some("json") | Some("txt")
+    );
+    // Files merged as sets of lines might have any extension, but they are text nevertheless.
+    let content = if whitelisted || structures::data_type(rel_path).is_some() {
+        match std::fs::read_to_string(path).map(Some) {
+            Ok(s) => {
+                debug!("Read successful: {:?}", path);
+                if let Some(s) = &s {
                     debug!(
-                        "Read unsuccessful, non-UTF8 data; asserting that {:?} is a binary file",
-                        path
-                    );
-                    Ok(None)
+                        "Total {} lines, {} characters",
+                        s.lines().count(),
+                        s.chars().count()
+                    )
                 }
-                err => err,
-            }?
-        }
-        _ => {
-            debug!(
-                "File extension is not in white-list (js,json,xml,txt,darkest), loading as binary"
-            );
-            None
-        }
+                Ok(s)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
+                debug!(
+                    "Read unsuccessful, non-UTF8 data; asserting that {:?} is a binary file",
+                    path
+                );
+                Ok(None)
+            }
+            err => err,
+        }?
+    } else {
+        debug!("File extension is not in white-list (js,json,xml,txt,darkest), loading as binary");
+        None
     };
     Ok((rel_path.into(), DataNode::new(path, content)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{temp_dir, write_files};
+    use diff::DataNodeContent;
+
+    #[test]
+    fn extract_non_ascii_paths() {
+        let base = temp_dir("extract").join("Мод 模组");
+        write_files(
+            &base,
+            &[
+                ("project.xml", "<project/>"),
+                ("heroes/герой/герой.info.darkest", "display: .size 1"),
+            ],
+        );
+        std::fs::write(base.join("heroes/герой/立绘.png"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let (mut sink, _receiver) = crossbeam_channel::unbounded();
+        let data = extract_data(
+            &mut sink,
+            &base,
+            &base,
+            true,
+            Some(progress::Phase::Vanilla),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(base.parent().unwrap()).unwrap();
+
+        let paths: Vec<_> = data.keys().cloned().collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("heroes").join("герой").join("герой.info.darkest"),
+                Path::new("heroes").join("герой").join("立绘.png"),
+            ]
+        );
+        assert_eq!(data[&paths[0]].text(), Some("display: .size 1"));
+        assert_eq!(data[&paths[1]].text(), None);
+    }
+
+    #[test]
+    fn mods_are_loaded_in_selection_order() {
+        let base = temp_dir("load_mods");
+        let selected: Vec<_> = (0..6)
+            .map(|index| {
+                let path = base.join(format!("mod_{}", index));
+                write_files(
+                    &path,
+                    &[
+                        (
+                            "project.xml",
+                            &format!("<project><Title>Mod {}</Title></project>", index),
+                        ),
+                        ("heroes/hero.info.darkest", &format!("hero: .id {}", index)),
+                    ],
+                );
+                crate::loader::load_mod(path).unwrap()
+            })
+            .collect();
+
+        let (sink, _receiver) = crossbeam_channel::unbounded();
+        let loaded = load_mods(&sink, &selected, &DataTree::new(), &Default::default());
+        std::fs::remove_dir_all(&base).unwrap();
+
+        let names: Vec<_> = loaded
+            .iter()
+            .map(|(content, coverage, _)| {
+                assert!(coverage.is_some());
+                content.as_ref().unwrap().name().to_owned()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            (0..6)
+                .map(|index| format!("Mod {}", index))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn single_mod_is_filtered_like_the_merged_ones() {
+        let base = temp_dir("repackage");
+        let path = base.join("local_mod");
+        write_files(
+            &path,
+            &[
+                ("heroes/hag/hag.info.darkest", "hero: .id sun_ring"),
+                ("heroes/hag/hag.art.darkest", "art: .id 1"),
+                ("localization/hag.string_table.xml", "<root/>"),
+                ("notes/readme.txt", "sun_ring"),
+            ],
+        );
+        let the_mod = crate::loader::load_mod(path).unwrap();
+        let locked = lock::LockedMod::new(&the_mod).unwrap();
+        let mut config = crate::config::Config {
+            protected_paths: vec!["localization/**".into()],
+            deploy_comments: CommentVerbosity::Minimal,
+            ..Default::default()
+        };
+        let profile = config.profile_mut();
+        profile
+            .mod_excludes
+            .insert("local_mod".into(), vec!["notes/**".into()]);
+        profile.id_renames.insert(
+            "local_mod".into(),
+            vec![("sun_ring".into(), "sun_ring_1".into())]
+                .into_iter()
+                .collect(),
+        );
+
+        let (mut sink, _receiver) = crossbeam_channel::unbounded();
+        let mut dropped = vec![];
+        let data = repackaged(&mut sink, &the_mod, &locked, &config, &mut dropped).unwrap();
+        std::fs::remove_dir_all(&base).unwrap();
+
+        let paths: Vec<_> = data.keys().cloned().collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("heroes/hag/hag.art.darkest"),
+                PathBuf::from("heroes/hag/hag.info.darkest"),
+            ]
+        );
+        let info = data[&paths[1]].text().unwrap();
+        assert!(info.starts_with("//"), "{}", info);
+        assert!(info.ends_with("hero: .id sun_ring_1"), "{}", info);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(
+            dropped[0].path,
+            PathBuf::from("localization/hag.string_table.xml")
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_reported_and_merged() {
+        let info = PathBuf::from("heroes/hag/hag.info.darkest");
+        let original: DataTree = vec![(
+            info.clone(),
+            DataNode::new(
+                "/game/hag.info.darkest",
+                "hp: .id \"base\" .value 10\n".to_string(),
+            ),
+        )]
+        .into_iter()
+        .collect();
+        let text = |content: &str| -> DataTree {
+            vec![(
+                info.clone(),
+                DataNode::new("/mod/hag.info.darkest", content.to_string()),
+            )]
+            .into_iter()
+            .collect()
+        };
+        assert!(malformed(&original, &text("hp: .id \"base\" .value 12\n")).is_empty());
+        let broken = text("hp: .id \"base .value 12\n");
+        assert!(matches!(
+            &malformed(&original, &broken)[..],
+            [(path, structures::StructureError::Darkest(_))] if *path == info
+        ));
+        // Still merged, line by line.
+        assert!(matches!(
+            &original.diff(broken.clone())[&info],
+            diff::DiffNode::ModifiedText(_)
+        ));
+        // Nothing is reported if the game's own file can't be parsed either.
+        let broken_original = text("hp: .id \"base .value 10\n");
+        assert!(malformed(&broken_original, &broken).is_empty());
+        let binary: DataTree = vec![(
+            info.clone(),
+            DataNode::new("/mod/hag.info.darkest", DataNodeContent::Binary),
+        )]
+        .into_iter()
+        .collect();
+        assert!(matches!(
+            &malformed(&original, &binary)[..],
+            [(_, structures::StructureError::NotText)]
+        ));
+        // Taken as a whole, instead of failing the merge.
+        assert!(matches!(
+            &original.diff(binary)[&info],
+            diff::DiffNode::Binary(source) if source == Path::new("/mod/hag.info.darkest")
+        ));
+    }
+}
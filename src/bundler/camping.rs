@@ -0,0 +1,128 @@
+//! Camping skills are defined in the global camping file and assigned to the hero classes in the per-class files,
+//! which class mods usually change together. If both kinds of files conflict, they are resolved as one group,
+//! so that the assignments don't end up taken from one mod and the definitions from another.
+
+use super::{
+    diff::{Conflicts, DiffTree},
+    names::DisplayNames,
+    resolve::ask_for_resolve,
+    structures::{CAMPING_SKILLS, HERO_CAMPING_SKILLS},
+};
+use log::*;
+use std::path::PathBuf;
+
+/// Conflicting camping files, if both the definitions and the assignments conflict,
+/// and the mods which are involved in every one of these conflicts.
+fn group(conflicts: &Conflicts) -> Option<(Vec<PathBuf>, Vec<String>)> {
+    let mut paths: Vec<_> = conflicts
+        .keys()
+        .filter(|path| CAMPING_SKILLS.matches(path) || HERO_CAMPING_SKILLS.matches(path))
+        .cloned()
+        .collect();
+    let both = paths.iter().any(|path| CAMPING_SKILLS.matches(path))
+        && paths.iter().any(|path| HERO_CAMPING_SKILLS.matches(path));
+    if !both {
+        return None;
+    }
+    paths.sort();
+    let mods = conflicts[&paths[0]]
+        .iter()
+        .map(|(name, _)| name.clone())
+        .filter(|name| {
+            paths
+                .iter()
+                .all(|path| conflicts[path].iter().any(|(other, _)| other == name))
+        })
+        .collect();
+    Some((paths, mods))
+}
+
+/// Takes every grouped file from the same mod.
+fn take(conflicts: &mut Conflicts, paths: &[PathBuf], name: &str) -> DiffTree {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let conflict = conflicts.remove(path)?;
+            let node = conflict.into_iter().find(|(other, _)| other == name)?.1;
+            Some((path.clone(), node))
+        })
+        .collect()
+}
+
+/// Asks once for the conflicting camping definitions and assignments together.
+///
+/// Returns the files resolved this way; the rest of the conflicts, including these files if user chooses
+/// to resolve them one by one, are left in place.
+pub fn resolve_together(
+    sink: &mut cursive::CbSink,
+    conflicts: &mut Conflicts,
+    names: &DisplayNames,
+) -> DiffTree {
+    let (paths, mods) = match group(conflicts) {
+        Some(group) => group,
+        None => return DiffTree::new(),
+    };
+    info!("[camping] Camping skills conflict together in {:?}", paths);
+    let files: Vec<_> = paths
+        .iter()
+        .map(|path| format!("- {}", names.describe(path)))
+        .collect();
+    let options = mods
+        .into_iter()
+        .map(|name| (format!("Take every file from {}", name), Some(name)))
+        .chain(Some(("Resolve the files one by one".to_string(), None)));
+    let chosen = ask_for_resolve(
+        sink,
+        format!(
+            "Mods are changing both the camping skills and their assignments to the hero classes:\n{}\n\
+             Taking them from different mods may assign the skills which are not defined.",
+            files.join("\n")
+        ),
+        options,
+    );
+    match chosen {
+        Some(name) => {
+            info!("[camping] Taking camping files from {}", name);
+            take(conflicts, &paths, &name)
+        }
+        None => DiffTree::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::DiffNode;
+
+    fn conflict(mods: &[&str]) -> Vec<(String, DiffNode)> {
+        mods.iter()
+            .map(|name| (name.to_string(), DiffNode::AddedText(name.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn definitions_and_assignments_are_grouped() {
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            "raid/camping/default.camping_skills.json".into(),
+            conflict(&["Hag", "Rebalance", "Exorcist"]),
+        );
+        assert!(group(&conflicts).is_none());
+
+        conflicts.insert(
+            "heroes/hag/hag.camping_skills.json".into(),
+            conflict(&["Exorcist", "Hag"]),
+        );
+        conflicts.insert("heroes/hag/hag.info.darkest".into(), conflict(&["A", "B"]));
+        let (paths, mods) = group(&conflicts).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(mods, ["Exorcist", "Hag"]);
+
+        let taken = take(&mut conflicts, &paths, "Hag");
+        assert_eq!(taken.len(), 2);
+        assert!(taken
+            .values()
+            .all(|node| matches!(node, DiffNode::AddedText(text) if text == "Hag")));
+        assert_eq!(conflicts.len(), 1);
+    }
+}
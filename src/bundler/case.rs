@@ -0,0 +1,257 @@
+//! Paths which differ only in the letter case, e.g. `Heroes/hag.png` and `heroes/hag.png` from different mods.
+//!
+//! They are deployed as distinct files where the file system is case-sensitive, but on Windows they are the same
+//! file, and which one ends up there is up to chance. They are normalized to one spelling, preferring the one
+//! used by the game itself.
+
+use super::{
+    diff::{DataNode, DataTree},
+    lock::LockedMod,
+    resolve::ask_for_resolve,
+    validate::Issue,
+};
+use log::*;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+fn lowercase(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// Every spelling of the bundled directories and files which are spelled in several ways, by their lowercased path.
+fn spellings(modded: &DataTree) -> BTreeMap<String, BTreeSet<PathBuf>> {
+    let mut spellings = BTreeMap::<String, BTreeSet<PathBuf>>::new();
+    for path in modded.keys() {
+        for prefix in path
+            .ancestors()
+            .filter(|prefix| !prefix.as_os_str().is_empty())
+        {
+            spellings
+                .entry(lowercase(prefix))
+                .or_default()
+                .insert(prefix.to_owned());
+        }
+    }
+    spellings.retain(|_, spelled| spelled.len() > 1);
+    spellings
+}
+
+/// Normalized path for every bundled path which collides with another one, by the original path.
+///
+/// Colliding names are spelled as in the game itself, if it has them, otherwise as the first of them in order.
+fn normalized(original: &DataTree, modded: &DataTree) -> BTreeMap<PathBuf, PathBuf> {
+    let colliding = spellings(modded);
+    if colliding.is_empty() {
+        return BTreeMap::new();
+    }
+    let vanilla: BTreeMap<_, _> = original
+        .keys()
+        .flat_map(|path| path.ancestors())
+        .filter(|prefix| !prefix.as_os_str().is_empty())
+        .map(|prefix| (lowercase(prefix), prefix))
+        .collect();
+    let chosen = |prefix: &Path| -> PathBuf {
+        let key = lowercase(prefix);
+        match colliding.get(&key) {
+            Some(spelled) => vanilla
+                .get(&key)
+                .map(|prefix| prefix.to_path_buf())
+                .unwrap_or_else(|| spelled.iter().next().cloned().unwrap_or_default()),
+            None => prefix.to_owned(),
+        }
+    };
+    modded
+        .keys()
+        .filter_map(|path| {
+            // Every component is normalized separately, so that the files in the same directory keep together.
+            let mut normal = PathBuf::new();
+            let mut prefix = PathBuf::new();
+            for component in path.iter() {
+                prefix.push(component);
+                normal.push(chosen(&prefix).file_name().unwrap_or(component));
+            }
+            Some((path.clone(), normal)).filter(|(path, normal)| path != normal)
+        })
+        .collect()
+}
+
+fn sources(path: &Path, mods: &[LockedMod]) -> String {
+    let titles: Vec<_> = mods
+        .iter()
+        .filter(|the_mod| the_mod.files.contains_key(path))
+        .map(|the_mod| the_mod.title.as_str())
+        .collect();
+    if titles.is_empty() {
+        "merged".into()
+    } else {
+        titles.join(", ")
+    }
+}
+
+fn same_content(first: &DataNode, second: &DataNode) -> bool {
+    match (first.text(), second.text()) {
+        (Some(first), Some(second)) => first == second,
+        (None, None) => {
+            first.source() == second.source()
+                || matches!(
+                    (std::fs::read(first.source()), std::fs::read(second.source())),
+                    (Ok(first), Ok(second)) if first == second
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Asks whether to normalize the colliding paths, and which file to keep where several of them become one.
+pub fn resolve(
+    sink: &mut cursive::CbSink,
+    original: &DataTree,
+    modded: &mut DataTree,
+    mods: &[LockedMod],
+) -> Vec<Issue> {
+    let renames = normalized(original, modded);
+    if renames.is_empty() {
+        return vec![];
+    }
+    let listed: Vec<_> = renames
+        .iter()
+        .map(|(path, normal)| {
+            format!(
+                "- {} (from {}) -> {}",
+                path.to_string_lossy(),
+                sources(path, mods),
+                normal.to_string_lossy()
+            )
+        })
+        .collect();
+    info!(
+        "[case] Paths differing only in case:\n{}",
+        listed.join("\n")
+    );
+    // Nobody can check the renamed paths in the headless run, so they are only reported there.
+    let normalize = !crate::prompt::is_headless()
+        && ask_for_resolve(
+            sink,
+            format!(
+                "These files differ from the other bundled paths only in the letter case.
+On Windows they are the same files, so only one of them would be used:
+{}",
+                listed.join("\n")
+            ),
+            vec![
+                ("Rename them to one spelling".to_string(), true),
+                ("Keep them as they are".to_string(), false),
+            ],
+        );
+    if !normalize {
+        return renames
+            .into_iter()
+            .map(|(path, normal)| Issue {
+                path,
+                message: format!("Collides with {} on Windows", normal.to_string_lossy()),
+            })
+            .collect();
+    }
+
+    let mut targets = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+    for path in modded.keys() {
+        let normal = renames.get(path).unwrap_or(path).clone();
+        targets.entry(normal).or_default().push(path.clone());
+    }
+    let mut issues = vec![];
+    for (normal, paths) in targets {
+        if paths.len() == 1 && paths[0] == normal {
+            continue;
+        }
+        let nodes: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                (
+                    path.clone(),
+                    modded.remove(path).expect("Path is taken from the bundle"),
+                )
+            })
+            .collect();
+        let (kept, node) = if nodes
+            .iter()
+            .all(|(_, node)| same_content(&nodes[0].1, node))
+        {
+            nodes
+                .into_iter()
+                .next()
+                .expect("Every target has some paths")
+        } else {
+            let options = nodes
+                .iter()
+                .enumerate()
+                .map(|(index, (path, _))| {
+                    (
+                        format!(
+                            "Take {} (from {})",
+                            path.to_string_lossy(),
+                            sources(path, mods)
+                        ),
+                        index,
+                    )
+                })
+                .collect::<Vec<_>>();
+            let chosen = ask_for_resolve(
+                sink,
+                format!(
+                    "Several different files become {} on Windows; which one should be kept?",
+                    normal.to_string_lossy()
+                ),
+                options,
+            );
+            let mut nodes = nodes;
+            let (kept, node) = nodes.swap_remove(chosen);
+            issues.extend(nodes.into_iter().map(|(path, _)| Issue {
+                path,
+                message: format!("Dropped in favor of {}", kept.to_string_lossy()),
+            }));
+            (kept, node)
+        };
+        info!("[case] {:?} is deployed as {:?}", kept, normal);
+        modded.insert(normal, node);
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(paths: &[&str]) -> DataTree {
+        paths
+            .iter()
+            .map(|path| (PathBuf::from(path), DataNode::new("", path.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn colliding_paths_take_vanilla_spelling() {
+        let original = tree(&["heroes/hag/hag.info.darkest"]);
+        let modded = tree(&[
+            "Heroes/hag/Hag.png",
+            "heroes/hag/hag.info.darkest",
+            "heroes/hag/hag.png",
+            "Trinkets/hag.trinkets.json",
+        ]);
+        let renames = normalized(&original, &modded);
+        let renames: Vec<_> = renames
+            .iter()
+            .map(|(path, normal)| (path.to_str().unwrap(), normal.to_str().unwrap()))
+            .collect();
+        // File names not known to the game are spelled as the first of them in order.
+        assert_eq!(
+            renames,
+            [
+                ("Heroes/hag/Hag.png", "heroes/hag/Hag.png"),
+                ("heroes/hag/hag.png", "heroes/hag/Hag.png"),
+            ]
+        );
+        assert!(normalized(&original, &tree(&["Trinkets/a.json", "Heroes/b.png"])).is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! Classification of the conflicts into cosmetic (art, colours, fonts, strings) and gameplay ones,
+//! so that the cosmetic ones can be resolved without asking.
+
+use super::{
+    diff::{Conflict, Conflicts, DiffNode, DiffTree, StructuredChangeset},
+    structures::DataPath,
+    validate::is_string_table,
+};
+use log::*;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Only changes the look of the game.
+    Cosmetic,
+    Gameplay,
+}
+
+/// Extensions of the files which only hold art, animations and fonts.
+const COSMETIC_EXTENSIONS: &[&str] = &["png", "jpg", "tga", "atlas", "skel", "ttf", "fnt"];
+/// Words in the value names which refer to art, colours or fonts, e.g. `portrait_art` or `text_colour`.
+const COSMETIC_WORDS: &[&str] = &[
+    "art", "anim", "sprite", "icon", "image", "portrait", "color", "colour", "tint", "font",
+];
+
+/// Category of the whole file, if it can be told by its path alone.
+fn of_file(path: &Path) -> Option<Category> {
+    let cosmetic = is_string_table(path)
+        || path.starts_with("fonts")
+        || path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                COSMETIC_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+            });
+    cosmetic.then_some(Category::Cosmetic)
+}
+
+fn of_value(data_path: &DataPath) -> Category {
+    let cosmetic = data_path.iter().any(|segment| {
+        segment
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| COSMETIC_WORDS.contains(&word))
+    });
+    if cosmetic {
+        Category::Cosmetic
+    } else {
+        Category::Gameplay
+    }
+}
+
+/// Conflict is cosmetic if it is in a cosmetic file, or if every conflicting value is a cosmetic one.
+pub fn of_conflict(path: &Path, conflict: &Conflict) -> Category {
+    if let Some(category) = of_file(path) {
+        return category;
+    }
+    let all_cosmetic = conflict.iter().all(|(_, node)| match node {
+        DiffNode::ModifiedStructured(changeset) => changeset
+            .0
+            .keys()
+            .all(|data_path| of_value(data_path) == Category::Cosmetic),
+        _ => false,
+    });
+    if all_cosmetic {
+        Category::Cosmetic
+    } else {
+        Category::Gameplay
+    }
+}
+
+/// Resolves the cosmetic conflicts in favor of the mod selected last, leaving the gameplay ones to the user.
+pub fn resolve_cosmetic(conflicts: &mut Conflicts) -> DiffTree {
+    let mut resolved = DiffTree::new();
+    conflicts.retain(|path, conflict| {
+        if of_conflict(path, conflict) == Category::Gameplay {
+            return true;
+        }
+        info!(
+            "[category] {:?}: Cosmetic conflict, resolving by mods order",
+            path
+        );
+        resolved.insert(path.clone(), by_priority(std::mem::take(conflict)));
+        false
+    });
+    resolved
+}
+
+/// Takes every value from the last mod changing it, or the whole file from the last mod.
+fn by_priority(conflict: Conflict) -> DiffNode {
+    if conflict
+        .iter()
+        .all(|(_, node)| matches!(node, DiffNode::ModifiedStructured(_)))
+    {
+        let mut merged = StructuredChangeset(Default::default());
+        for (_, node) in conflict {
+            if let DiffNode::ModifiedStructured(changeset) = node {
+                merged.0.extend(changeset.0);
+            }
+        }
+        return DiffNode::ModifiedStructured(merged);
+    }
+    let (_, node) = conflict
+        .into_iter()
+        .last()
+        .expect("Conflict always has some changes");
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structured(name: &str, changes: &[(&[&str], &str)]) -> (String, DiffNode) {
+        let changeset = changes
+            .iter()
+            .map(|(data_path, value)| {
+                (
+                    data_path
+                        .iter()
+                        .map(|segment| segment.to_string())
+                        .collect(),
+                    Some(value.to_string()),
+                )
+            })
+            .collect();
+        (
+            name.into(),
+            DiffNode::ModifiedStructured(StructuredChangeset(changeset)),
+        )
+    }
+
+    #[test]
+    fn only_cosmetic_conflicts_are_resolved() {
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            "heroes/hag/hag.png".into(),
+            vec![
+                ("First".into(), DiffNode::Binary("1/hag.png".into())),
+                ("Second".into(), DiffNode::Binary("2/hag.png".into())),
+            ],
+        );
+        conflicts.insert(
+            "overlays/town.layout.json".into(),
+            vec![
+                structured("First", &[(&["banner", "text_colour"], "red")]),
+                structured("Second", &[(&["banner", "text_colour"], "blue")]),
+            ],
+        );
+        conflicts.insert(
+            "monsters/brigand/brigand.info.darkest".into(),
+            vec![
+                structured(
+                    "First",
+                    &[
+                        (&["stats", "0", "hp"], "10"),
+                        (&["display", "0", "art"], "a"),
+                    ],
+                ),
+                structured("Second", &[(&["stats", "0", "hp"], "12")]),
+            ],
+        );
+
+        let resolved = resolve_cosmetic(&mut conflicts);
+        assert_eq!(resolved.len(), 2);
+        assert!(matches!(
+            &resolved[Path::new("heroes/hag/hag.png")],
+            DiffNode::Binary(source) if source == Path::new("2/hag.png")
+        ));
+        assert!(matches!(
+            &resolved[Path::new("overlays/town.layout.json")],
+            DiffNode::ModifiedStructured(changeset)
+                if changeset.0.values().collect::<Vec<_>>() == [&Some("blue".to_string())]
+        ));
+        assert!(conflicts.contains_key(Path::new("monsters/brigand/brigand.info.darkest")));
+    }
+}
@@ -0,0 +1,165 @@
+use super::{
+    diff::Conflicts,
+    lock::{LockFile, LockedMod},
+    resolve,
+};
+use std::{collections::BTreeSet, path::PathBuf};
+
+/// Differences of one mod's files between the previous bundle and the current one.
+fn file_changes(previous: &LockedMod, current: &LockedMod) -> Vec<(PathBuf, &'static str)> {
+    let mut changes: Vec<_> = current
+        .files
+        .iter()
+        .filter_map(|(path, hash)| match previous.files.get(path) {
+            None => Some((path.clone(), "added")),
+            Some(old) if old != hash => Some((path.clone(), "modified")),
+            Some(_) => None,
+        })
+        .collect();
+    changes.extend(
+        previous
+            .files
+            .keys()
+            .filter(|path| !current.files.contains_key(*path))
+            .map(|path| (path.clone(), "removed")),
+    );
+    changes.sort();
+    changes
+}
+
+fn version(the_mod: &LockedMod) -> &str {
+    the_mod.version.as_deref().unwrap_or("unknown")
+}
+
+/// Human-readable description of what changed since the previous bundle, one line per item.
+///
+/// Empty if the same mods are bundled in the same versions.
+pub fn report(previous: &LockFile, current: &[LockedMod], conflicts: &Conflicts) -> Vec<String> {
+    let mut lines = vec![];
+    let mut changed_files = BTreeSet::new();
+    for the_mod in current {
+        match previous
+            .mods
+            .iter()
+            .find(|old| old.directory == the_mod.directory)
+        {
+            None => {
+                lines.push(format!("New mod: {}", the_mod.title));
+                changed_files.extend(the_mod.files.keys().cloned());
+            }
+            Some(old) if old.hash != the_mod.hash => {
+                lines.push(format!(
+                    "Changed mod: {} (version {} -> {})",
+                    the_mod.title,
+                    version(old),
+                    version(the_mod)
+                ));
+                if old.files.is_empty() {
+                    lines.push("  (files were not recorded for the previous bundle)".into());
+                }
+                for (path, change) in file_changes(old, the_mod) {
+                    lines.push(format!("  {}: {}", change, path.to_string_lossy()));
+                    changed_files.insert(path);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for old in &previous.mods {
+        if !current.iter().any(|m| m.directory == old.directory) {
+            lines.push(format!("Removed mod: {}", old.title));
+            changed_files.extend(old.files.keys().cloned());
+        }
+    }
+
+    let invalidated: Vec<_> = previous
+        .resolutions
+        .iter()
+        .filter_map(|(path, resolution)| {
+            let reason = match conflicts.get(path) {
+                None => "there is no conflict anymore",
+                Some(_) if changed_files.contains(path) => "conflicting file was changed",
+                Some(conflict)
+                    if !resolve::resolution_fits(
+                        resolution,
+                        previous.inputs.get(path),
+                        conflict,
+                    ) =>
+                {
+                    "conflict is different now"
+                }
+                Some(_) => return None,
+            };
+            Some(format!("  {}: {}", path.to_string_lossy(), reason))
+        })
+        .collect();
+    if !invalidated.is_empty() {
+        lines.push("Previous decisions which are no longer valid:".into());
+        lines.extend(invalidated);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::{DiffNode, DiffTree};
+
+    fn locked(directory: &str, hash: &str, files: &[(&str, &str)]) -> LockedMod {
+        LockedMod {
+            title: directory.to_uppercase(),
+            directory: directory.into(),
+            version: None,
+            hash: hash.into(),
+            files: files
+                .iter()
+                .map(|(path, hash)| (PathBuf::from(path), hash.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn changed_mod_and_resolution() {
+        let mut resolutions = DiffTree::new();
+        resolutions.insert(
+            "heroes/a.png".into(),
+            DiffNode::Binary("x/heroes/a.png".into()),
+        );
+        let previous = LockFile::new(
+            vec![
+                locked("x", "1", &[("heroes/a.png", "1"), ("old.txt", "1")]),
+                locked("y", "1", &[]),
+            ],
+            resolutions,
+            Default::default(),
+        );
+        let current = vec![
+            locked("x", "2", &[("heroes/a.png", "2"), ("new.txt", "1")]),
+            locked("y", "1", &[]),
+        ];
+        let mut conflicts = Conflicts::new();
+        conflicts.insert("heroes/a.png".into(), vec![]);
+        assert_eq!(
+            report(&previous, &current, &conflicts),
+            vec![
+                "Changed mod: X (version unknown -> unknown)",
+                "  modified: heroes/a.png",
+                "  added: new.txt",
+                "  removed: old.txt",
+                "Previous decisions which are no longer valid:",
+                "  heroes/a.png: conflicting file was changed",
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_changed() {
+        let previous = LockFile::new(
+            vec![locked("x", "1", &[])],
+            DiffTree::new(),
+            Default::default(),
+        );
+        let current = vec![locked("x", "1", &[])];
+        assert!(report(&previous, &current, &Conflicts::new()).is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use super::{
+    diff::{DataNode, DataNodeContent, DataTree},
+    structures,
+};
+use log::*;
+use std::collections::BTreeMap;
+
+/// How the bundler treats the file when merging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    /// Known data type, merged value-by-value.
+    Structured,
+    /// Unknown text file, merged line-by-line.
+    Text,
+    /// Opaque file, can only be taken as a whole.
+    Binary,
+}
+
+impl std::fmt::Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Category::Structured => "structured",
+            Category::Text => "text",
+            Category::Binary => "binary",
+        })
+    }
+}
+
+/// File and byte counts of the mod content for every category.
+#[derive(Debug, Default)]
+pub struct Coverage(BTreeMap<Category, (usize, u64)>);
+
+impl Coverage {
+    pub fn of(tree: &DataTree) -> Self {
+        let mut coverage = Self::default();
+        for (path, node) in tree {
+            let (category, size) = match node.content() {
+                DataNodeContent::Text(_) => match structures::data_type(path) {
+                    Some(_) => (Category::Structured, node_size(node)),
+                    None => (Category::Text, node_size(node)),
+                },
+                DataNodeContent::Binary => (Category::Binary, node_size(node)),
+            };
+            debug!("[coverage] {:?}: {}", path, category);
+            let entry = coverage.0.entry(category).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+        coverage
+    }
+
+    pub fn total(&self) -> u64 {
+        self.0.values().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+/// Size of the file in bytes; binary files which can't be accessed are counted as empty.
+pub fn node_size(node: &DataNode) -> u64 {
+    match node.content() {
+        DataNodeContent::Text(text) => text.len() as u64,
+        DataNodeContent::Binary => std::fs::metadata(node.source())
+            .map(|meta| meta.len())
+            .unwrap_or_else(|err| {
+                warn!("Unable to get size of {:?}: {}", node.source(), err);
+                0
+            }),
+    }
+}
+
+impl std::fmt::Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.total();
+        let parts: Vec<_> = self
+            .0
+            .iter()
+            .map(|(category, (files, bytes))| {
+                let percent = if total == 0 {
+                    0.0
+                } else {
+                    *bytes as f64 * 100.0 / total as f64
+                };
+                format!(
+                    "{} {} files, {} ({:.0}%)",
+                    category,
+                    files,
+                    format_size(*bytes),
+                    percent
+                )
+            })
+            .collect();
+        if parts.is_empty() {
+            f.write_str("no files")
+        } else {
+            f.write_str(&parts.join("; "))
+        }
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories() {
+        let tree: DataTree = vec![
+            (
+                "dungeons/crypts/crypts.props.darkest".into(),
+                DataNode::new("", "props: .id 1".to_string()),
+            ),
+            (
+                "dungeons/crypts/crypts.props.darkest.bak".into(),
+                DataNode::new("", "12".to_string()),
+            ),
+            ("heroes/a.txt".into(), DataNode::new("", "123".to_string())),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            Coverage::of(&tree).to_string(),
+            "structured 1 files, 12 B (71%); text 2 files, 5 B (29%)"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,226 @@
+//! Conflicts as a spreadsheet, so that the teams making modpacks can split the decisions between them.
+//!
+//! Every conflicting value is one row, with the value of every mod in its own column. The decisions are written
+//! into the last column as the title of the mod to take the file from, and imported back into the active preset.
+
+use super::{
+    diff::{Conflicts, DataTree, DiffNode},
+    structures::data_type,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+const FILE_COLUMN: &str = "File";
+const DATA_PATH_COLUMN: &str = "Data path";
+const VANILLA_COLUMN: &str = "Vanilla";
+const DECISION_COLUMN: &str = "Decision";
+/// Shown in place of the value which the mod removes.
+const REMOVED: &str = "<removed>";
+
+/// Quotes the field, if it contains anything which would break the row.
+pub(super) fn field(text: &str) -> String {
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn row<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+    let fields: Vec<_> = fields.into_iter().map(field).collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// Short description of the change which can't be split into values.
+fn summary(node: &DiffNode) -> String {
+    match node {
+        DiffNode::Binary(_) => "binary file".into(),
+        DiffNode::AddedText(_) => "added".into(),
+        DiffNode::ModifiedText(changeset) => format!(
+            "{} lines changed",
+            changeset.0.iter().filter(|change| change.is_some()).count()
+        ),
+        DiffNode::ModifiedStructured(changeset) => format!("{} values changed", changeset.0.len()),
+    }
+}
+
+/// Renders the conflicts, one row per conflicting value, with a column for each of the `mods`.
+pub fn export(original: &DataTree, conflicts: &Conflicts, mods: &[String]) -> String {
+    let involved: BTreeSet<_> = conflicts
+        .values()
+        .flat_map(|conflict| conflict.iter().map(|(name, _)| name))
+        .collect();
+    let columns: Vec<_> = mods.iter().filter(|name| involved.contains(name)).collect();
+    let mut text = row([FILE_COLUMN, DATA_PATH_COLUMN, VANILLA_COLUMN]
+        .iter()
+        .copied()
+        .chain(columns.iter().map(|name| name.as_str()))
+        .chain(Some(DECISION_COLUMN)));
+
+    let sorted: BTreeMap<_, _> = conflicts.iter().collect();
+    for (path, conflict) in sorted {
+        let file = path.to_string_lossy();
+        let value_of = |name: &String| {
+            conflict
+                .iter()
+                .find(|(other, _)| other == name)
+                .map(|(_, node)| node)
+        };
+        let structured: Option<Vec<_>> = conflict
+            .iter()
+            .map(|(_, node)| match node {
+                DiffNode::ModifiedStructured(changeset) => Some(changeset),
+                _ => None,
+            })
+            .collect();
+        let structured = match structured {
+            Some(structured) => structured,
+            None => {
+                let values: Vec<_> = columns
+                    .iter()
+                    .map(|name| value_of(name).map(summary).unwrap_or_default())
+                    .collect();
+                text.push_str(&row([&*file, "", ""]
+                    .iter()
+                    .copied()
+                    .chain(values.iter().map(String::as_str))
+                    .chain(Some(""))));
+                continue;
+            }
+        };
+        let vanilla = original
+            .get(path)
+            .and_then(|node| Some((data_type(path)?, node.text()?)))
+            .and_then(|(data_type, text)| data_type.format.flatten(text).ok())
+            .unwrap_or_default();
+        let data_paths: BTreeSet<_> = structured
+            .iter()
+            .flat_map(|changeset| changeset.0.keys())
+            .collect();
+        for data_path in data_paths {
+            let values: Vec<_> = columns
+                .iter()
+                .map(|name| match value_of(name) {
+                    Some(DiffNode::ModifiedStructured(changeset)) => {
+                        match changeset.0.get(data_path) {
+                            Some(Some(value)) => value.as_str(),
+                            Some(None) => REMOVED,
+                            None => "",
+                        }
+                    }
+                    _ => "",
+                })
+                .collect();
+            let vanilla = vanilla.get(data_path).map_or("", String::as_str);
+            let data_path = data_path.to_string();
+            text.push_str(&row([&*file, &data_path, vanilla]
+                .iter()
+                .copied()
+                .chain(values)
+                .chain(Some(""))));
+        }
+    }
+    text
+}
+
+/// Splits the text into rows of fields, unquoting them.
+pub(super) fn parse(text: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Mod chosen for every file in the filled spreadsheet.
+///
+/// Decisions are made for the whole files, so the first decision in the rows of the file is taken.
+pub fn decisions(text: &str) -> Result<BTreeMap<PathBuf, String>, String> {
+    let mut rows = parse(text).into_iter();
+    let header = rows.next().ok_or("File is empty")?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .ok_or(format!("There's no column \"{}\"", name))
+    };
+    let (file, decision) = (column(FILE_COLUMN)?, column(DECISION_COLUMN)?);
+    let mut decisions = BTreeMap::new();
+    for row in rows {
+        match (row.get(file), row.get(decision)) {
+            (Some(path), Some(name)) if !path.is_empty() && !name.trim().is_empty() => {
+                decisions
+                    .entry(PathBuf::from(path))
+                    .or_insert_with(|| name.trim().to_owned());
+            }
+            _ => {}
+        }
+    }
+    Ok(decisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::{DataNode, StructuredChangeset};
+
+    #[test]
+    fn decisions_are_read_back() {
+        let path = PathBuf::from("heroes/hag/hag.info.darkest");
+        let original: DataTree = vec![(
+            path.clone(),
+            DataNode::new("", "hp: .id \"base\" .value 10\n".to_string()),
+        )]
+        .into_iter()
+        .collect();
+        let changed = |value: &str| {
+            DiffNode::ModifiedStructured(StructuredChangeset::from(vec![(
+                ["hp", "base", "value"][..].into(),
+                Some(value.to_string()),
+            )]))
+        };
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            path.clone(),
+            vec![
+                ("Hag, Rebalanced".into(), changed("12")),
+                ("Hag".into(), changed("\"15\"")),
+            ],
+        );
+        let mods = ["Hag".to_string(), "Other".into(), "Hag, Rebalanced".into()];
+        let exported = export(&original, &conflicts, &mods);
+        assert_eq!(
+            exported,
+            "File,Data path,Vanilla,Hag,\"Hag, Rebalanced\",Decision\n\
+             heroes/hag/hag.info.darkest,hp/base/value,10,\"\"\"15\"\"\",12,\n"
+        );
+
+        let filled = exported.replace(",12,\n", ",12,\"Hag, Rebalanced\"\r\n");
+        let decisions = decisions(&filled).unwrap();
+        assert_eq!(decisions[&path], "Hag, Rebalanced");
+        assert!(super::decisions("File\nfoo\n").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! Conflict resolutions made by hand, kept between the bundlings, so that the same conflicts aren't asked again
+//! when the bundle is made anew, e.g. with one more mod selected.
+//!
+//! They are the third source of the resolutions: the lock file of the rebuilt bundle and the resumed session
+//! come first, and the active preset is used only for the conflicts which none of them resolves.
+//! User can forget them all on the presets screen.
+
+use super::{
+    diff::{Conflicts, DiffNode, DiffTree},
+    resolve::inputs,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Decision {
+    /// Mods which changed the file, in the bundling order.
+    pub mods: Vec<String>,
+    /// Hash of the conflicting changes; the decision is only replayed while they stay the same.
+    pub inputs: String,
+    pub resolution: DiffNode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Decisions(BTreeMap<PathBuf, Decision>);
+
+impl Decisions {
+    /// Loads the saved decisions; any failure results in no decisions.
+    pub fn load() -> Self {
+        let path = crate::paths::decisions();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|err| {
+                warn!("[decisions] {:?} is malformed, ignoring it: {}", path, err);
+                Self::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                warn!("[decisions] Unable to read {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Writes the decisions to disk. Failure only means they will be asked again, so it is only logged.
+    pub fn save(&self) {
+        let path = crate::paths::decisions();
+        let result = serde_json::to_string_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|text| std::fs::write(&path, text));
+        match result {
+            Ok(()) => info!("[decisions] Saved {} decisions to {:?}", self.0.len(), path),
+            Err(err) => warn!("[decisions] Unable to save {:?}: {}", path, err),
+        }
+    }
+
+    /// Removes the saved decisions, so that every conflict is asked again.
+    pub fn forget() {
+        let path = crate::paths::decisions();
+        if let Err(err) = std::fs::remove_file(&path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!("[decisions] Unable to remove {:?}: {}", path, err);
+            }
+            return;
+        }
+        info!("[decisions] Saved decisions are forgotten");
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Reuses the decisions made for exactly the same conflicts.
+    ///
+    /// Returns the replayed resolutions and the conflicts which are new or have changed since.
+    pub fn replay(&self, conflicts: Conflicts) -> (DiffTree, Conflicts) {
+        let mut replayed = DiffTree::new();
+        let mut remaining = Conflicts::new();
+        for (path, conflict) in conflicts {
+            match self.0.get(&path) {
+                Some(decision) if decision.inputs == inputs(&conflict) => {
+                    info!("[decisions] {:?}: Replaying saved decision", path);
+                    replayed.insert(path, decision.resolution.clone());
+                }
+                Some(_) => {
+                    info!(
+                        "[decisions] {:?}: Conflicting changes differ from the saved ones",
+                        path
+                    );
+                    remaining.insert(path, conflict);
+                }
+                None => {
+                    remaining.insert(path, conflict);
+                }
+            }
+        }
+        (replayed, remaining)
+    }
+
+    /// Remembers how the conflicts were resolved, replacing the older decisions for the same files.
+    pub fn record(&mut self, conflicts: &Conflicts, resolved: &DiffTree) {
+        for (path, resolution) in resolved {
+            if let Some(conflict) = conflicts.get(path) {
+                self.0.insert(
+                    path.clone(),
+                    Decision {
+                        mods: conflict.iter().map(|(name, _)| name.clone()).collect(),
+                        inputs: inputs(conflict),
+                        resolution: resolution.clone(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::Conflict;
+
+    fn conflict(second: &str) -> Conflict {
+        vec![
+            ("First".into(), DiffNode::AddedText("a".into())),
+            ("Second".into(), DiffNode::AddedText(second.into())),
+        ]
+    }
+
+    #[test]
+    fn only_unchanged_conflicts_are_replayed() {
+        let path = PathBuf::from("scripts/a.txt");
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(path.clone(), conflict("b"));
+        let mut resolved = DiffTree::new();
+        resolved.insert(path.clone(), DiffNode::AddedText("b".into()));
+        let mut decisions = Decisions::default();
+        decisions.record(&conflicts, &resolved);
+        assert_eq!(decisions.0[&path].mods, ["First", "Second"]);
+
+        let (replayed, remaining) = decisions.replay(conflicts);
+        assert!(replayed.contains_key(&path));
+        assert!(remaining.is_empty());
+
+        let mut changed = Conflicts::new();
+        changed.insert(path.clone(), conflict("c"));
+        let (replayed, remaining) = decisions.replay(changed);
+        assert!(replayed.is_empty());
+        assert!(remaining.contains_key(&path));
+    }
+}
@@ -1,35 +1,390 @@
 use super::{
-    diff::{DataNodeContent, DataTree},
+    diff::{DataNode, DataNodeContent, DataTree},
     error::DeploymentError,
+    lock::{LockFile, LockedMod, LOCK_FILE},
+    progress::{Phase, Progress},
+};
+use crate::{
+    loader::{Mod, Project},
+    prompt::Reply,
 };
-use crossbeam_channel::{bounded, Sender};
 use cursive::{
     views::{Dialog, TextView},
     Cursive,
 };
-use indoc::indoc;
 use log::*;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// First line of the comment put into the deployed files.
+const COMMENT_HEADER: &str = "// Deployed by Darkest Dungeon Mod Bundler";
+/// Extension of the files which support comments; the JSON ones, for example, don't.
+const COMMENTED_EXTENSION: &str = "darkest";
+
+/// Folder inside the bundle with the information about the bundled mods.
+const ATTRIBUTION_DIR: &str = "included_mods";
+/// Files copied from every bundled mod into the attribution folder, besides its preview.
+const ATTRIBUTION_FILES: &[&str] = &["project.xml"];
+/// Title of the bundle, unless it was published under another one.
+const BUNDLE_TITLE: &str = "Generated mods bundle";
+/// Title of the compatibility patch, unless it was published under another one.
+const PATCH_TITLE: &str = "Generated compatibility patch";
+/// Fields of the existing `project.xml` which are kept, so that the published bundle can still be updated.
+const PUBLISHING_FIELDS: &[&str] = &[
+    "PublishedFileId",
+    "ItemDescription",
+    "Visibility",
+    "UploadMode",
+    "PreviewIconFile",
+    "VersionMajor",
+    "VersionMinor",
+    "VersionBuild",
+    "ModDataPath",
+];
+/// Upload mode which makes the uploader shipped with the game actually submit the item.
+const UPLOAD_MODE: &str = "direct_upload";
+/// Values required by the uploader, used unless the bundle already has its own.
+const UPLOAD_DEFAULTS: &[(&str, &str)] = &[
+    ("Visibility", "private"),
+    ("PreviewIconFile", PLACEHOLDER_PREVIEW),
+];
+/// Preview written when the bundle has none, since the uploader refuses to work without it.
+const PLACEHOLDER_PREVIEW: &str = "preview_icon.png";
+const PLACEHOLDER_PREVIEW_CONTENT: &[u8] = include_bytes!("preview_placeholder.png");
+
+/// Start of the description line naming the game build, replaced every time the bundle is made.
+const GAME_BUILD_PREFIX: &str = "Built against the game build ";
+
+/// Suffix of the directory where the bundle is written before being moved into place.
+const STAGING_SUFFIX: &str = ".partial";
+/// Suffix of the directory where the previous bundle is kept while being replaced.
+const BACKUP_SUFFIX: &str = ".previous";
+/// Suffix of the directory where the bundle replaced by the last deployment is kept, to be restored on rollback.
+/// It is empty if there was no bundle before.
+const ROLLBACK_SUFFIX: &str = ".rollback";
+/// File in the rollback directory listing the files written by the update in place, one per line.
+/// If it's there, the directory holds only the files replaced or removed by the update.
+const UPDATED_FILES: &str = ".updated_in_place";
+
+fn sibling(mod_path: &Path, suffix: &str) -> PathBuf {
+    let mut name = mod_path.file_name().unwrap_or_default().to_owned();
+    name.push(suffix);
+    mod_path.with_file_name(name)
+}
+
+/// Cleans up after the deployment which was interrupted, so that the game never sees a half-written bundle.
+///
+/// The staging directory is incomplete by definition and is removed. The backup is restored if the process died
+/// between moving the old bundle away and moving the new one in, and is removed otherwise.
+pub fn recover(mod_path: &Path) -> Result<(), DeploymentError> {
+    let staging = sibling(mod_path, STAGING_SUFFIX);
+    if staging.exists() {
+        warn!("Found unfinished deployment in {:?}, removing it", staging);
+        std::fs::remove_dir_all(&staging).map_err(DeploymentError::from_io(&staging))?;
+    }
+    let backup = sibling(mod_path, BACKUP_SUFFIX);
+    if backup.exists() {
+        if mod_path.exists() {
+            info!("Removing backup of the replaced bundle {:?}", backup);
+            std::fs::remove_dir_all(&backup).map_err(DeploymentError::from_io(&backup))?;
+        } else {
+            warn!(
+                "Bundle was not replaced completely, restoring previous one from {:?}",
+                backup
+            );
+            std::fs::rename(&backup, mod_path).map_err(DeploymentError::from_io(&backup))?;
+        }
+    }
+    Ok(())
+}
+
+/// Publishing data of the previously deployed bundle, if it was uploaded to the Workshop.
+struct Published {
+    project: Project,
+    /// Preview file name and content, since the file will be removed with the old bundle.
+    preview: Option<(String, Vec<u8>)>,
+}
+
+impl Published {
+    fn read(mod_path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(mod_path.join("project.xml")).ok()?;
+        let mut project = Project::parse(&bytes)
+            .map_err(|err| warn!("Existing bundle has malformed project.xml: {}", err))
+            .ok()?;
+        let id = project.fields.get("PublishedFileId")?;
+        warn!(
+            "Existing bundle is published on the Workshop with id {}",
+            id
+        );
+        if let Some(file) = project.fields.get("PreviewIconFile") {
+            if !is_inside_mod(file) {
+                warn!(
+                    "Preview {:?} of the existing bundle is outside of it, ignoring it",
+                    file
+                );
+                project.fields.remove("PreviewIconFile");
+            }
+        }
+        let preview = project.fields.get("PreviewIconFile").and_then(|file| {
+            std::fs::read(mod_path.join(file))
+                .map_err(|err| warn!("Unable to read preview of the existing bundle: {}", err))
+                .ok()
+                .map(|content| (file.clone(), content))
+        });
+        Some(Self { project, preview })
+    }
+
+    fn id(&self) -> &str {
+        &self.project.fields["PublishedFileId"]
+    }
+}
+
+/// Whether the file named in `project.xml` is a plain relative path, which can't point outside of the mod.
+fn is_inside_mod(file: &str) -> bool {
+    !file.is_empty()
+        && Path::new(file)
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Description with the line naming the game build, replacing the one left by the previous bundling.
+fn tag_description(description: Option<&str>, game_build: &str) -> String {
+    let mut lines: Vec<_> = description
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.starts_with(GAME_BUILD_PREFIX))
+        .map(String::from)
+        .collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    if !lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines.push(format!("{}{}.", GAME_BUILD_PREFIX, game_build));
+    lines.join("\n")
+}
+
+/// Publishing fields of the new bundle: the ones kept from the published bundle,
+/// and, if it is prepared for upload, the ones required by the uploader.
+///
+/// If the game build is known, it is noted in the description.
+fn publishing_fields(
+    published: Option<&Published>,
+    upload: bool,
+    game_build: Option<&str>,
+) -> BTreeMap<&'static str, String> {
+    let mut fields: BTreeMap<_, _> = PUBLISHING_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let value = published?.project.fields.get(*field)?;
+            Some((*field, value.clone()))
+        })
+        .collect();
+    if upload {
+        fields.insert("UploadMode", UPLOAD_MODE.into());
+        fields.insert("ModDataPath", super::BUNDLE_DIR.into());
+        for (field, value) in UPLOAD_DEFAULTS {
+            fields.entry(*field).or_insert_with(|| (*value).into());
+        }
+    }
+    if let Some(game_build) = game_build {
+        let description = tag_description(
+            fields.get("ItemDescription").map(String::as_str),
+            game_build,
+        );
+        fields.insert("ItemDescription", description);
+    }
+    fields
+}
+
+fn render_project(
+    published: Option<&Published>,
+    title: &str,
+    fields: &BTreeMap<&str, String>,
+) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<project>\n");
+    let title = published.map_or(title, |published| &published.project.title);
+    xml.push_str(&format!("    <Title>{}</Title>\n", escape_xml(title)));
+    for field in PUBLISHING_FIELDS {
+        if let Some(value) = fields.get(*field) {
+            xml.push_str(&format!("    <{0}>{1}</{0}>\n", field, escape_xml(value)));
+        }
+    }
+    xml.push_str("</project>\n");
+    xml
+}
+
+/// Steps to upload the bundle prepared for it, with the paths on this machine.
+pub fn upload_instructions(game: &Path, mod_path: &Path) -> String {
+    format!(
+        "To upload the bundle to the Steam Workshop:
+1. Check the title and description in {project}; replace {preview} with your own square image, if you wish.
+2. While Steam is running, start the uploader shipped with the game from the game directory ({game}):
+   _windows\\steam_workshop_upload.exe {bundle}/project.xml
+   (on Linux and macOS, use the steam_workshop_upload script in the _linux or _osx folder).
+3. The uploader writes the Workshop id into project.xml. It is kept when the bundle is made again,
+   so the next uploads update the same Workshop item.
+4. The item is uploaded as private; change its visibility on the Workshop page when it is ready.",
+        project = mod_path.join("project.xml").to_string_lossy(),
+        preview = PLACEHOLDER_PREVIEW,
+        game = game.to_string_lossy(),
+        bundle = super::BUNDLE_DIR,
+    )
+}
 
 #[derive(Copy, Clone)]
 enum OverwriteChoice {
     Overwrite,
+    /// Rewrite only the changed files.
+    Update,
     Retry,
     Cancel,
 }
 
+/// Comments put at the beginning of the deployed files which support them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CommentVerbosity {
+    /// Files are written byte for byte as they were merged.
+    #[default]
+    None,
+    /// Only the line telling that the file was deployed by the bundler.
+    Minimal,
+    /// Also the bundler version and the mods which changed the file.
+    Verbose,
+}
+
+impl CommentVerbosity {
+    pub const ALL: &'static [CommentVerbosity] = &[
+        CommentVerbosity::None,
+        CommentVerbosity::Minimal,
+        CommentVerbosity::Verbose,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            CommentVerbosity::None => "None",
+            CommentVerbosity::Minimal => "Minimal",
+            CommentVerbosity::Verbose => "Verbose, with the mods changing the file",
+        }
+    }
+}
+
+/// Puts the comments of the chosen verbosity into the bundled files.
+///
+/// Done before the bundle is hashed for the lock file, so that the hashes match the deployed files.
+pub fn annotate(bundle: &mut DataTree, verbosity: CommentVerbosity, mods: &[LockedMod]) {
+    if verbosity == CommentVerbosity::None {
+        return;
+    }
+    for (path, node) in bundle.iter_mut() {
+        if path.extension().and_then(std::ffi::OsStr::to_str) != Some(COMMENTED_EXTENSION) {
+            continue;
+        }
+        let text = match node.text() {
+            Some(text) => text,
+            None => continue,
+        };
+        let mut comment = vec![COMMENT_HEADER.to_owned()];
+        if verbosity == CommentVerbosity::Verbose {
+            comment.push(format!("// Bundler version: {}", env!("CARGO_PKG_VERSION")));
+            let sources: Vec<_> = mods
+                .iter()
+                .filter(|the_mod| the_mod.files.contains_key(path))
+                .map(|the_mod| the_mod.title.as_str())
+                .collect();
+            if !sources.is_empty() {
+                comment.push(format!("// Changed by: {}", sources.join(", ")));
+            }
+        }
+        let text = format!("{}\n{}", comment.join("\n"), text);
+        *node = DataNode::new(node.source().to_owned(), text);
+    }
+}
+
+/// Writes the bundle into the staging directory next to `mod_path` and returns its path.
+///
+/// Nothing in `mod_path` is touched until [`finish`] is called, so the deployment can be interrupted at any moment.
+/// If `upload` is set, the bundle is prepared for the uploader shipped with the game.
+///
+/// If `manifest`, the hashes of the bundled files, is given and the deployed bundle has its lock file, user may
+/// choose to update the deployed bundle in place instead; then `mod_path` itself is returned.
 pub fn deploy(
     sink: &mut cursive::CbSink,
     mod_path: &Path,
     bundle: DataTree,
-) -> Result<(), DeploymentError> {
+    upload: bool,
+    game_build: Option<&str>,
+    manifest: Option<&BTreeMap<PathBuf, String>>,
+) -> Result<PathBuf, DeploymentError> {
+    deploy_titled(
+        sink,
+        mod_path,
+        BUNDLE_TITLE,
+        bundle,
+        upload,
+        game_build,
+        manifest,
+    )
+}
+
+/// Same as [`deploy`], for the compatibility patch.
+pub fn deploy_patch(
+    sink: &mut cursive::CbSink,
+    patch_path: &Path,
+    patch: DataTree,
+    game_build: Option<&str>,
+) -> Result<PathBuf, DeploymentError> {
+    deploy_titled(
+        sink,
+        patch_path,
+        PATCH_TITLE,
+        patch,
+        false,
+        game_build,
+        None,
+    )
+}
+
+fn deploy_titled(
+    sink: &mut cursive::CbSink,
+    mod_path: &Path,
+    title: &str,
+    bundle: DataTree,
+    upload: bool,
+    game_build: Option<&str>,
+    manifest: Option<&BTreeMap<PathBuf, String>>,
+) -> Result<PathBuf, DeploymentError> {
     info!("Mod is being deployed to {:?}", mod_path);
+    recover(mod_path)?;
+    let published = Published::read(mod_path);
     // This is possibly subject for TOCTOU attack, but in this case the user seems to have a problem somewhere else
     if mod_path.exists() {
-        match ask_for_overwrite(sink, mod_path) {
-            OverwriteChoice::Overwrite => {
-                info!("Overwriting existing mod bundle");
-                std::fs::remove_dir_all(mod_path).map_err(DeploymentError::from_io(&mod_path))?
+        // Lock file of the deployed bundle has the hashes of its files, so that only the changed ones can be written.
+        let previous = manifest.and_then(|_| LockFile::read(&mod_path.join(LOCK_FILE)).ok());
+        match ask_for_overwrite(
+            sink,
+            mod_path,
+            published.as_ref().map(Published::id),
+            previous.is_some(),
+        ) {
+            OverwriteChoice::Overwrite => info!("Existing mod bundle will be overwritten"),
+            OverwriteChoice::Update => {
+                if let (Some(previous), Some(current)) = (previous, manifest) {
+                    info!("Existing mod bundle will be updated in place");
+                    update_in_place(sink, mod_path, bundle, &previous.output, current)?;
+                    write_project(mod_path, published.as_ref(), title, upload, game_build)?;
+                    return Ok(mod_path.to_owned());
+                }
             }
             OverwriteChoice::Cancel => return Err(DeploymentError::AlreadyExists),
             OverwriteChoice::Retry => {
@@ -40,83 +395,749 @@ pub fn deploy(
         }
     }
 
-    std::fs::create_dir(mod_path).map_err(DeploymentError::from_io(mod_path))?;
+    let staging = sibling(mod_path, STAGING_SUFFIX);
+    std::fs::create_dir(&staging).map_err(DeploymentError::from_io(&staging))?;
+    write_project(&staging, published.as_ref(), title, upload, game_build)?;
 
-    let project_xml_path = mod_path.join("project.xml");
-    std::fs::write(
-        &project_xml_path,
-        indoc!(
-            r#"
-            <?xml version="1.0" encoding="utf-8"?>
-            <project>
-                <Title>Generated mods bundle</Title>
-            </project>
-            "#
-        ),
-    )
-    .map_err(DeploymentError::from_io(&project_xml_path))?;
+    let progress = Progress::phase(sink, Phase::Deploy, bundle.len());
+    for (path, item) in bundle {
+        progress.advance();
+        write_file(sink, &staging, &path, item)?;
+    }
+    Ok(staging)
+}
+
+/// Writes `project.xml` and the preview of the bundle into `dir`.
+fn write_project(
+    dir: &Path,
+    published: Option<&Published>,
+    title: &str,
+    upload: bool,
+    game_build: Option<&str>,
+) -> Result<(), DeploymentError> {
+    let project_xml_path = dir.join("project.xml");
+    let fields = publishing_fields(published, upload, game_build);
+    std::fs::write(&project_xml_path, render_project(published, title, &fields))
+        .map_err(DeploymentError::from_io(&project_xml_path))?;
     info!("Written project.xml");
+    match published.and_then(|published| published.preview.as_ref()) {
+        Some((file, content)) => {
+            let preview_path = dir.join(file);
+            std::fs::write(&preview_path, content)
+                .map_err(DeploymentError::from_io(&preview_path))?;
+            info!("Restored preview of the published bundle");
+        }
+        None if upload => {
+            let preview_path = dir.join(&fields["PreviewIconFile"]);
+            std::fs::write(&preview_path, PLACEHOLDER_PREVIEW_CONTENT)
+                .map_err(DeploymentError::from_io(&preview_path))?;
+            info!("Written placeholder preview for the upload");
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+fn write_file(
+    sink: &mut cursive::CbSink,
+    dir: &Path,
+    path: &Path,
+    item: DataNode,
+) -> Result<(), DeploymentError> {
+    profile_span!("deploy_file", path = ?path);
+    info!("Writing mod file to relative path {:?}", path);
+    super::set_file_updated(sink, "Deploying", path.to_string_lossy());
+    let (source, content) = item.into_parts();
+    let target = dir.join(path);
+    let parent = target.parent().unwrap_or(dir);
+    std::fs::create_dir_all(parent).map_err(DeploymentError::from_io(&parent))?;
+    match content {
+        DataNodeContent::Binary => {
+            info!("Copying binary file from {:?}", source);
+            let mut source =
+                std::fs::File::open(&source).map_err(DeploymentError::from_io(&source))?;
+            let mut target =
+                std::fs::File::create(&target).map_err(DeploymentError::from_io(&target))?;
+            std::io::copy(&mut source, &mut target).map(|_| {})
+        }
+        DataNodeContent::Text(text) => {
+            info!(
+                "Writing text file, first 100 chars = \"{}\"",
+                text.chars().take(100).collect::<String>()
+            );
+            std::fs::write(&target, text)
+        }
+    }
+    .map_err(DeploymentError::from_io(&target))
+}
+
+/// Files which must be written into the deployed bundle, since they are new, changed or missing,
+/// and the files of the deployed bundle which are no longer there.
+fn changed_files(
+    mod_path: &Path,
+    previous: &BTreeMap<PathBuf, String>,
+    current: &BTreeMap<PathBuf, String>,
+) -> (BTreeSet<PathBuf>, Vec<PathBuf>) {
+    let changed = current
+        .iter()
+        .filter(|(path, hash)| previous.get(*path) != Some(*hash) || !mod_path.join(path).is_file())
+        .map(|(path, _)| path.clone())
+        .collect();
+    let removed = previous
+        .keys()
+        .filter(|path| !current.contains_key(*path))
+        .cloned()
+        .collect();
+    (changed, removed)
+}
+
+/// Moves the file (or directory) of the bundle into the rollback directory, if it's there.
+///
+/// Links, e.g. into the Workshop mod, are moved themselves, so that nothing is written through them.
+fn keep_for_rollback(mod_path: &Path, rollback: &Path, path: &Path) -> Result<(), DeploymentError> {
+    let source = mod_path.join(path);
+    if std::fs::symlink_metadata(&source).is_err() {
+        return Ok(());
+    }
+    let kept = rollback.join(path);
+    let parent = kept.parent().unwrap_or(rollback);
+    std::fs::create_dir_all(parent).map_err(DeploymentError::from_io(parent))?;
+    std::fs::rename(&source, &kept).map_err(DeploymentError::from_io(&source))
+}
+
+/// Rewrites only the changed files of the deployed bundle, keeping the rest untouched.
+///
+/// Every file replaced or removed by the update is moved into the fresh rollback directory first, together with
+/// the list of the written files, so that the update can be rolled back like the full deployment.
+/// There's no staging here, so the lock file is moved away first: if the update is interrupted,
+/// the next deployment can't trust it and replaces the bundle as a whole.
+fn update_in_place(
+    sink: &mut cursive::CbSink,
+    mod_path: &Path,
+    bundle: DataTree,
+    previous: &BTreeMap<PathBuf, String>,
+    current: &BTreeMap<PathBuf, String>,
+) -> Result<(), DeploymentError> {
+    // The bundle replaced earlier is not the one before this update, so it's no use for the rollback.
+    let rollback = sibling(mod_path, ROLLBACK_SUFFIX);
+    if rollback.exists() {
+        std::fs::remove_dir_all(&rollback).map_err(DeploymentError::from_io(&rollback))?;
+    }
+    std::fs::create_dir(&rollback).map_err(DeploymentError::from_io(&rollback))?;
+    keep_for_rollback(mod_path, &rollback, Path::new(LOCK_FILE))?;
+    keep_for_rollback(mod_path, &rollback, Path::new(ATTRIBUTION_DIR))?;
+    // Project file is written anew after the update.
+    let project = mod_path.join("project.xml");
+    if project.is_file() {
+        std::fs::copy(&project, rollback.join("project.xml"))
+            .map_err(DeploymentError::from_io(&project))?;
+    }
+
+    let (changed, removed) = changed_files(mod_path, previous, current);
+    info!(
+        "Updating {} files of {}, removing {}",
+        changed.len(),
+        current.len(),
+        removed.len()
+    );
+    let updated = rollback.join(UPDATED_FILES);
+    let list: String = changed
+        .iter()
+        .map(|path| format!("{}\n", path.to_string_lossy()))
+        .collect();
+    std::fs::write(&updated, list).map_err(DeploymentError::from_io(&updated))?;
 
+    for path in removed {
+        keep_for_rollback(mod_path, &rollback, &path)?;
+        info!("Removed {:?}", path);
+    }
+    let progress = Progress::phase(sink, Phase::Deploy, changed.len());
     for (path, item) in bundle {
-        info!("Writing mod file to relative path {:?}", path);
-        super::set_file_updated(sink, "Deploying", path.to_string_lossy());
-        let (source, content) = item.into_parts();
+        if !changed.contains(&path) {
+            continue;
+        }
+        progress.advance();
+        keep_for_rollback(mod_path, &rollback, &path)?;
+        write_file(sink, mod_path, &path, item)?;
+    }
+    Ok(())
+}
+
+/// Undoes the update in place: removes the files it has written and moves the replaced ones back.
+fn restore_updated(mod_path: &Path, rollback: &Path) -> Result<(), DeploymentError> {
+    let updated = rollback.join(UPDATED_FILES);
+    let list = std::fs::read_to_string(&updated).map_err(DeploymentError::from_io(&updated))?;
+    std::fs::remove_file(&updated).map_err(DeploymentError::from_io(&updated))?;
+    let attribution = mod_path.join(ATTRIBUTION_DIR);
+    if attribution.exists() {
+        std::fs::remove_dir_all(&attribution).map_err(DeploymentError::from_io(&attribution))?;
+    }
+    for path in list.lines().filter(|line| !line.is_empty()) {
         let target = mod_path.join(path);
-        let dir = target.parent().unwrap();
-        std::fs::create_dir_all(dir).map_err(DeploymentError::from_io(&dir))?;
-        match content {
-            DataNodeContent::Binary => {
-                info!("Copying binary file from {:?}", source);
-                let mut source =
-                    std::fs::File::open(&source).map_err(DeploymentError::from_io(&source))?;
-                let mut target =
-                    std::fs::File::create(&target).map_err(DeploymentError::from_io(&target))?;
-                std::io::copy(&mut source, &mut target).map(|_| {})
+        match std::fs::remove_file(&target) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(DeploymentError::from_io(&target)(err))
             }
-            DataNodeContent::Text(text) => {
-                info!(
-                    "Writing text file, first 100 chars = \"{}\"",
-                    text.chars().take(100).collect::<String>()
-                );
-                std::fs::write(&target, text)
+            _ => {}
+        }
+    }
+    for path in files(rollback).map_err(DeploymentError::from_io(rollback))? {
+        let target = mod_path.join(&path);
+        let parent = target.parent().unwrap_or(mod_path);
+        std::fs::create_dir_all(parent).map_err(DeploymentError::from_io(parent))?;
+        let kept = rollback.join(&path);
+        std::fs::rename(&kept, &target).map_err(DeploymentError::from_io(&kept))?;
+    }
+    std::fs::remove_dir_all(rollback).map_err(DeploymentError::from_io(rollback))?;
+    info!(
+        "Bundle at {:?} restored as it was before the update",
+        mod_path
+    );
+    Ok(())
+}
+
+/// Relative paths of every file in the directory, sorted.
+pub(super) fn files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    fn walk(base: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(base, &path, files)?;
+            } else {
+                files.push(path.strip_prefix(base).unwrap_or(&path).to_owned());
             }
         }
-        .map_err(DeploymentError::from_io(&target))?;
+        Ok(())
+    }
+    let mut files = vec![];
+    walk(dir, dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Files of the bundle which are changed by several mods (or by the user edits), i.e. the ones where
+/// the game would only use the version from the mod loaded last, if the original mods were used.
+///
+/// Loaded after the original mods, these files are enough to make them work together.
+pub fn compat_patch(bundle: &DataTree, mods: &[LockedMod]) -> DataTree {
+    let mut changed_by: BTreeMap<&Path, usize> = BTreeMap::new();
+    for the_mod in mods {
+        // User edits are not in any of the original mods, so they must always be in the patch.
+        let weight = if the_mod.directory == super::edits::USER_EDITS_DIRECTORY {
+            2
+        } else {
+            1
+        };
+        for path in the_mod.files.keys() {
+            *changed_by.entry(path).or_default() += weight;
+        }
+    }
+    bundle
+        .iter()
+        .filter(|(path, _)| {
+            changed_by
+                .get(path.as_path())
+                .is_some_and(|count| *count > 1)
+        })
+        .map(|(path, node)| (path.clone(), node.clone()))
+        .collect()
+}
+
+/// Replaces the bundle in `mod_path` with the fully written staging directory.
+///
+/// If the bundle was updated in place, i.e. `staging` is `mod_path` itself, there's nothing to replace.
+pub fn finish(staging: &Path, mod_path: &Path) -> Result<(), DeploymentError> {
+    if staging == mod_path {
+        info!("Bundle was updated in place at {:?}", mod_path);
+        return Ok(());
+    }
+    let backup = sibling(mod_path, BACKUP_SUFFIX);
+    if mod_path.exists() {
+        std::fs::rename(mod_path, &backup).map_err(DeploymentError::from_io(mod_path))?;
+    }
+    std::fs::rename(staging, mod_path).map_err(DeploymentError::from_io(staging))?;
+    info!("Bundle moved into place at {:?}", mod_path);
+    // The new bundle is already in place, so failures here only lose the rollback;
+    // the leftover backup will be removed by the next recovery.
+    let rollback = sibling(mod_path, ROLLBACK_SUFFIX);
+    if rollback.exists() {
+        if let Err(err) = std::fs::remove_dir_all(&rollback) {
+            warn!("Unable to remove the older bundle {:?}: {}", rollback, err);
+        }
+    }
+    let kept = if backup.exists() {
+        std::fs::rename(&backup, &rollback)
+    } else {
+        std::fs::create_dir(&rollback)
+    };
+    if let Err(err) = kept {
+        warn!("Unable to keep the previous bundle for rollback: {}", err);
     }
     Ok(())
 }
 
-fn send_choice(sender: &Sender<OverwriteChoice>, choice: OverwriteChoice) -> impl Fn(&mut Cursive) {
-    let sender = sender.clone();
+/// Restores the bundle replaced by the last deployment, or removes the bundle if there was none before.
+/// The update in place is rolled back file by file.
+///
+/// Returns `false` if there's nothing to roll back to.
+pub fn rollback(mod_path: &Path) -> Result<bool, DeploymentError> {
+    let rollback = sibling(mod_path, ROLLBACK_SUFFIX);
+    if !rollback.is_dir() {
+        return Ok(false);
+    }
+    if rollback.join(UPDATED_FILES).is_file() {
+        restore_updated(mod_path, &rollback)?;
+        return Ok(true);
+    }
+    // If this is interrupted, recovery puts the current bundle back.
+    let backup = sibling(mod_path, BACKUP_SUFFIX);
+    if mod_path.exists() {
+        std::fs::rename(mod_path, &backup).map_err(DeploymentError::from_io(mod_path))?;
+    }
+    let empty = std::fs::read_dir(&rollback)
+        .map_err(DeploymentError::from_io(&rollback))?
+        .next()
+        .is_none();
+    if empty {
+        info!(
+            "There was no bundle at {:?} before, leaving it removed",
+            mod_path
+        );
+        std::fs::remove_dir(&rollback).map_err(DeploymentError::from_io(&rollback))?;
+    } else {
+        std::fs::rename(&rollback, mod_path).map_err(DeploymentError::from_io(&rollback))?;
+        info!("Previous bundle restored at {:?}", mod_path);
+    }
+    if backup.exists() {
+        std::fs::remove_dir_all(&backup).map_err(DeploymentError::from_io(&backup))?;
+    }
+    Ok(true)
+}
+
+/// Collects the metadata and previews of the bundled mods, so that their authors can be credited.
+pub fn write_attribution(mod_path: &Path, mods: &[Mod]) -> Result<(), DeploymentError> {
+    let dir = mod_path.join(ATTRIBUTION_DIR);
+    info!("Writing attribution for {} mods to {:?}", mods.len(), dir);
+    let mut text = String::from("This bundle includes the following mods:\n\n");
+    for the_mod in mods {
+        let dir_name = the_mod
+            .path
+            .file_name()
+            .map(std::ffi::OsStr::to_string_lossy)
+            .unwrap_or_else(|| the_mod.name().into());
+        let target = dir.join(&*dir_name);
+        std::fs::create_dir_all(&target).map_err(DeploymentError::from_io(&target))?;
+        let files =
+            ATTRIBUTION_FILES
+                .iter()
+                .copied()
+                .chain(the_mod.field("PreviewIconFile").filter(|file| {
+                    let inside = is_inside_mod(file);
+                    if !inside {
+                        warn!(
+                            "Preview {:?} is outside of the mod, skipping it in attribution",
+                            file
+                        );
+                    }
+                    inside
+                }));
+        for file in files {
+            let source = the_mod.path.join(file);
+            if !source.is_file() {
+                warn!("File {:?} not found, skipping it in attribution", source);
+                continue;
+            }
+            let file_target = target.join(file);
+            std::fs::copy(&source, &file_target).map_err(DeploymentError::from_io(&file_target))?;
+        }
+
+        text.push_str(&format!("- {}", the_mod.name()));
+        if let Some(version) = the_mod.version() {
+            text.push_str(&format!(", version {}", version));
+        }
+        if let Some(id) = the_mod.field("PublishedFileId") {
+            text.push_str(&format!(
+                " (https://steamcommunity.com/sharedfiles/filedetails/?id={})",
+                id
+            ));
+        }
+        text.push('\n');
+    }
+    let attribution_path = dir.join("ATTRIBUTION.txt");
+    std::fs::write(&attribution_path, text).map_err(DeploymentError::from_io(&attribution_path))
+}
+
+fn send_choice(reply: &Reply<OverwriteChoice>, choice: OverwriteChoice) -> impl Fn(&mut Cursive) {
+    let reply = reply.clone();
     move |cursive| {
         cursive.pop_layer();
-        let _ = sender.send(choice);
+        reply.send(choice);
     }
 }
 
-fn ask_for_overwrite(sink: &mut cursive::CbSink, path: &Path) -> OverwriteChoice {
+fn ask_for_overwrite(
+    sink: &mut cursive::CbSink,
+    path: &Path,
+    published_id: Option<&str>,
+    can_update: bool,
+) -> OverwriteChoice {
     use OverwriteChoice::*;
-    let (sender, receiver) = bounded(0);
     let path = path.to_owned();
-    crate::run_update(sink, move |cursive| {
-        crate::push_screen(
-            cursive,
-            Dialog::around(TextView::new(format!(
-                "Target directory {} already exists!
+    let published = match published_id {
+        Some(id) => format!(
+            "\nIt is published on the Workshop (id {}); its title, description and preview will be kept.",
+            id
+        ),
+        None => String::new(),
+    };
+    let update = if can_update {
+        "\n- update it in place, writing only the changed files;"
+    } else {
+        ""
+    };
+    // Rebuilt bundle replaces the deployed one, which can still be rolled back to.
+    let unattended = if can_update { Update } else { Overwrite };
+    crate::prompt::ask_or(sink, unattended, move |cursive, reply| {
+        let mut dialog = Dialog::around(TextView::new(format!(
+            "Target directory {} already exists!{}
 Choose your action:
-- overwrite existing folder;
+- overwrite existing folder;{}
 - rename/move it manually and retry deploying (it will fail if folder still exists);
 - cancel mod bundling process entirely.",
-                path.to_string_lossy()
-            )))
-            .button("Overwrite", send_choice(&sender, Overwrite))
-            .button("Retry", send_choice(&sender, Retry))
-            .button("Cancel", send_choice(&sender, Cancel))
-            .h_align(cursive::align::HAlign::Center),
+            path.to_string_lossy(),
+            published,
+            update
+        )))
+        .button("Overwrite", send_choice(&reply, Overwrite));
+        if can_update {
+            dialog.add_button("Update changed", send_choice(&reply, Update));
+        }
+        crate::push_screen(
+            cursive,
+            dialog
+                .button("Retry", send_choice(&reply, Retry))
+                .button("Cancel", send_choice(&reply, Cancel))
+                .h_align(cursive::align::HAlign::Center),
         )
-    });
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::edits::USER_EDITS_DIRECTORY;
+    use crate::fixtures::{temp_dir, write_files};
+
+    #[test]
+    fn interrupted_swap_is_rolled_back() {
+        let root = temp_dir("recover");
+        let mod_path = root.join("generated_bundle");
+        let staging = sibling(&mod_path, STAGING_SUFFIX);
+        let backup = sibling(&mod_path, BACKUP_SUFFIX);
+        std::fs::create_dir_all(&staging).unwrap();
+        write_files(&backup, &[("project.xml", "old")]);
 
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
+        recover(&mod_path).unwrap();
+        assert!(!staging.exists());
+        assert!(!backup.exists());
+        assert_eq!(
+            std::fs::read_to_string(mod_path.join("project.xml")).unwrap(),
+            "old"
+        );
+
+        std::fs::create_dir_all(&staging).unwrap();
+        std::fs::write(staging.join("project.xml"), "new").unwrap();
+        finish(&staging, &mod_path).unwrap();
+        assert!(!staging.exists());
+        assert!(!backup.exists());
+        assert_eq!(
+            std::fs::read_to_string(mod_path.join("project.xml")).unwrap(),
+            "new"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn last_deployment_is_rolled_back() {
+        let root = temp_dir("rollback");
+        let mod_path = root.join("generated_bundle");
+        let staging = sibling(&mod_path, STAGING_SUFFIX);
+        let deploy = |content: &str| {
+            std::fs::create_dir_all(&staging).unwrap();
+            std::fs::write(staging.join("project.xml"), content).unwrap();
+            finish(&staging, &mod_path).unwrap();
+        };
+        deploy("first");
+        deploy("second");
+        assert!(rollback(&mod_path).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(mod_path.join("project.xml")).unwrap(),
+            "first"
+        );
+        assert!(!rollback(&mod_path).unwrap());
+
+        // Rolling back the very first deployment leaves no bundle at all.
+        std::fs::remove_dir_all(&root).unwrap();
+        deploy("first");
+        assert!(rollback(&mod_path).unwrap());
+        assert!(!mod_path.exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn only_changed_files_are_updated() {
+        let root = temp_dir("update");
+        write_files(&root, &[("heroes/kept.png", "")]);
+        let hashes = |entries: &[(&str, &str)]| -> BTreeMap<PathBuf, String> {
+            entries
+                .iter()
+                .map(|(path, hash)| (PathBuf::from(path), hash.to_string()))
+                .collect()
+        };
+        let previous = hashes(&[
+            ("heroes/kept.png", "a"),
+            ("heroes/missing.png", "b"),
+            ("heroes/changed.json", "c"),
+            ("heroes/removed.json", "d"),
+        ]);
+        let current = hashes(&[
+            ("heroes/kept.png", "a"),
+            ("heroes/missing.png", "b"),
+            ("heroes/changed.json", "e"),
+            ("heroes/added.json", "f"),
+        ]);
+        let (changed, removed) = changed_files(&root, &previous, &current);
+        // The file deleted from the deployed bundle by hand is written again.
+        let changed: Vec<_> = changed.iter().map(|path| path.to_str().unwrap()).collect();
+        assert_eq!(
+            changed,
+            [
+                "heroes/added.json",
+                "heroes/changed.json",
+                "heroes/missing.png"
+            ]
+        );
+        assert_eq!(removed, [PathBuf::from("heroes/removed.json")]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn update_in_place_is_rolled_back() {
+        let root = temp_dir("update_rollback");
+        let mod_path = root.join("generated_bundle");
+        let deployed = [
+            ("project.xml", "<project/>"),
+            (LOCK_FILE, "previous lock"),
+            ("included_mods/credits.txt", "credits"),
+            ("heroes/kept.json", "kept"),
+            ("heroes/changed.json", "old"),
+            ("heroes/removed.json", "removed"),
+        ];
+        write_files(&mod_path, &deployed);
+        let hashes = |entries: &[(&str, &str)]| -> BTreeMap<PathBuf, String> {
+            entries
+                .iter()
+                .map(|(path, hash)| (PathBuf::from(path), hash.to_string()))
+                .collect()
+        };
+        let previous = hashes(&[
+            ("heroes/kept.json", "a"),
+            ("heroes/changed.json", "b"),
+            ("heroes/removed.json", "c"),
+        ]);
+        let current = hashes(&[
+            ("heroes/kept.json", "a"),
+            ("heroes/changed.json", "d"),
+            ("heroes/added.json", "e"),
+        ]);
+        let bundle: DataTree = [
+            ("heroes/kept.json", "kept"),
+            ("heroes/changed.json", "new"),
+            ("heroes/added.json", "added"),
+        ]
+        .iter()
+        .map(|(path, text)| (PathBuf::from(path), DataNode::new("", text.to_string())))
+        .collect();
+        let (mut sink, _receiver) = crossbeam_channel::unbounded();
+        update_in_place(&mut sink, &mod_path, bundle, &previous, &current).unwrap();
+        std::fs::write(mod_path.join("project.xml"), "<project>updated</project>").unwrap();
+        let read = |path: &str| std::fs::read_to_string(mod_path.join(path)).unwrap();
+        assert_eq!(read("heroes/changed.json"), "new");
+        assert_eq!(read("heroes/added.json"), "added");
+        assert!(!mod_path.join("heroes/removed.json").exists());
+
+        assert!(rollback(&mod_path).unwrap());
+        let mut expected: Vec<_> = deployed
+            .iter()
+            .map(|(path, _)| PathBuf::from(path))
+            .collect();
+        expected.sort();
+        assert_eq!(files(&mod_path).unwrap(), expected);
+        for (path, text) in &deployed {
+            assert_eq!(read(path), *text);
+        }
+        assert!(!rollback(&mod_path).unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn published_fields_are_kept() {
+        let xml = r#"<project><Title>My &amp; pack</Title><PublishedFileId>42</PublishedFileId>
+            <Language>english</Language></project>"#;
+        let published = Published {
+            project: Project::parse(xml.as_bytes()).unwrap(),
+            preview: None,
+        };
+        let rendered = render_project(
+            Some(&published),
+            BUNDLE_TITLE,
+            &publishing_fields(Some(&published), false, None),
+        );
+        assert!(rendered.contains("<Title>My &amp; pack</Title>"));
+        assert!(rendered.contains("<PublishedFileId>42</PublishedFileId>"));
+        assert!(!rendered.contains("Language"));
+        assert!(!rendered.contains("UploadMode"));
+        assert!(render_project(None, PATCH_TITLE, &BTreeMap::new()).contains(PATCH_TITLE));
+    }
+
+    #[test]
+    fn patch_contains_files_changed_by_several_mods() {
+        let locked = |directory: &str, files: &[&str]| LockedMod {
+            title: directory.into(),
+            directory: directory.into(),
+            version: None,
+            hash: String::new(),
+            files: files
+                .iter()
+                .map(|file| (file.into(), String::new()))
+                .collect(),
+        };
+        let mods = [
+            locked(
+                "1",
+                &["project.xml", "loot/loot.darkest", "heroes/hag/hag.png"],
+            ),
+            locked("2", &["project.xml", "loot/loot.darkest", "raid/raid.json"]),
+            locked(USER_EDITS_DIRECTORY, &["raid/torch.json"]),
+        ];
+        let bundle: DataTree = [
+            "loot/loot.darkest",
+            "heroes/hag/hag.png",
+            "raid/raid.json",
+            "raid/torch.json",
+        ]
+        .iter()
+        .map(|path| (PathBuf::from(path), DataNode::new(*path, String::new())))
+        .collect();
+        let patch = compat_patch(&bundle, &mods);
+        assert_eq!(
+            patch.keys().collect::<Vec<_>>(),
+            [Path::new("loot/loot.darkest"), Path::new("raid/torch.json")]
+        );
+    }
+
+    #[test]
+    fn upload_fields_are_filled() {
+        let xml = r#"<project><Title>Pack</Title><PublishedFileId>42</PublishedFileId>
+            <UploadMode>dont_submit</UploadMode><Visibility>public</Visibility></project>"#;
+        let published = Published {
+            project: Project::parse(xml.as_bytes()).unwrap(),
+            preview: None,
+        };
+        let fields = publishing_fields(Some(&published), true, None);
+        assert_eq!(fields["UploadMode"], UPLOAD_MODE);
+        assert_eq!(fields["Visibility"], "public");
+        assert_eq!(fields["PreviewIconFile"], PLACEHOLDER_PREVIEW);
+        assert_eq!(fields["ModDataPath"], "mods/generated_bundle");
+        assert_eq!(fields["PublishedFileId"], "42");
+    }
+
+    #[test]
+    fn preview_outside_of_bundle_is_ignored() {
+        let root = temp_dir("preview");
+        let mod_path = root.join("generated_bundle");
+        write_files(
+            &root,
+            &[
+                ("secret.png", "secret"),
+                ("generated_bundle/preview.png", "preview"),
+            ],
+        );
+        let project = |preview: &str| {
+            format!(
+                "<project><Title>Pack</Title><PublishedFileId>42</PublishedFileId>\
+                 <PreviewIconFile>{}</PreviewIconFile></project>",
+                preview
+            )
+        };
+        std::fs::write(mod_path.join("project.xml"), project("preview.png")).unwrap();
+        let published = Published::read(&mod_path).unwrap();
+        assert_eq!(
+            published.preview,
+            Some(("preview.png".into(), b"preview".to_vec()))
+        );
+
+        for preview in ["../secret.png", root.join("secret.png").to_str().unwrap()] {
+            std::fs::write(mod_path.join("project.xml"), project(preview)).unwrap();
+            let published = Published::read(&mod_path).unwrap();
+            assert_eq!(published.preview, None);
+            let fields = publishing_fields(Some(&published), true, None);
+            assert_eq!(fields["PreviewIconFile"], PLACEHOLDER_PREVIEW);
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn game_build_replaces_previous_one() {
+        let xml = r#"<project><Title>Pack</Title><ItemDescription>My mods.
+
+Built against the game build 24788.</ItemDescription></project>"#;
+        let published = Published {
+            project: Project::parse(xml.as_bytes()).unwrap(),
+            preview: None,
+        };
+        let fields = publishing_fields(Some(&published), false, Some("25000"));
+        assert_eq!(
+            fields["ItemDescription"],
+            "My mods.\n\nBuilt against the game build 25000."
+        );
+        let fields = publishing_fields(None, false, Some("25000"));
+        assert_eq!(
+            fields["ItemDescription"],
+            "Built against the game build 25000."
+        );
+    }
+
+    #[test]
+    fn comments_list_the_changing_mods() {
+        let mut bundle: DataTree = vec![
+            ("heroes/hag/hag.info.darkest", "hero: .id \"hag\"\n"),
+            ("heroes/hag/hag.camping_skills.json", "{}"),
+        ]
+        .into_iter()
+        .map(|(path, text)| (PathBuf::from(path), DataNode::new(path, text.to_string())))
+        .collect();
+        let mods = [LockedMod {
+            title: "Hag Class".into(),
+            directory: "123".into(),
+            version: None,
+            hash: String::new(),
+            files: vec![("heroes/hag/hag.info.darkest".into(), String::new())]
+                .into_iter()
+                .collect(),
+        }];
+        let text =
+            |bundle: &DataTree, path: &str| bundle[Path::new(path)].text().unwrap().to_owned();
+
+        annotate(&mut bundle, CommentVerbosity::None, &mods);
+        assert_eq!(
+            text(&bundle, "heroes/hag/hag.info.darkest"),
+            "hero: .id \"hag\"\n"
+        );
+        annotate(&mut bundle, CommentVerbosity::Verbose, &mods);
+        let info = text(&bundle, "heroes/hag/hag.info.darkest");
+        assert!(info.starts_with(COMMENT_HEADER));
+        assert!(info.ends_with("// Changed by: Hag Class\nhero: .id \"hag\"\n"));
+        assert_eq!(text(&bundle, "heroes/hag/hag.camping_skills.json"), "{}");
+    }
 }
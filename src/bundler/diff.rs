@@ -1,18 +1,20 @@
-use cursive::{
-    traits::Finder,
-    views::{Dialog, TextView},
-};
+use super::policy::Policies;
+use super::progress::{Phase, Progress};
+use super::structures::{self, DataPath, Format, StructureError};
+use cursive::views::Dialog;
 use difference::{Changeset, Difference};
 use log::*;
+use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
     collections::{BTreeMap, HashMap, HashSet},
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
 pub type DataTree = BTreeMap<PathBuf, DataNode>;
 
+#[derive(Clone)]
 pub struct DataNode {
     absolute: PathBuf,
     content: DataNodeContent,
@@ -31,9 +33,22 @@ impl DataNode {
     pub fn into_content(self) -> DataNodeContent {
         self.content
     }
+    /// Absolute path to the file this data was read from.
+    pub fn source(&self) -> &std::path::Path {
+        &self.absolute
+    }
+    pub fn content(&self) -> &DataNodeContent {
+        &self.content
+    }
+    pub fn text(&self) -> Option<&str> {
+        match &self.content {
+            DataNodeContent::Binary => None,
+            DataNodeContent::Text(text) => Some(text),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DataNodeContent {
     Binary,
     Text(String),
@@ -64,6 +79,13 @@ impl ModContent {
             diff,
         }
     }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Files changed by the mod.
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.diff.keys()
+    }
 }
 
 pub type DiffTree = BTreeMap<PathBuf, DiffNode>;
@@ -71,8 +93,27 @@ pub type DiffTree = BTreeMap<PathBuf, DiffNode>;
 pub type Conflict = Vec<(String, DiffNode)>;
 pub type Conflicts = HashMap<PathBuf, Conflict>;
 
-#[derive(Clone, Debug)]
-pub struct LinesChangeset(pub Vec<Option<LineChange>>);
+/// Change for every line of the original file, and the hash of that file, since the changes only make sense for it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "StoredLinesChangeset")]
+pub struct LinesChangeset(pub Vec<Option<LineChange>>, pub String);
+
+/// Changesets stored before the hash of the original file was added have none.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredLinesChangeset {
+    Hashed(Vec<Option<LineChange>>, String),
+    Unhashed(Vec<Option<LineChange>>),
+}
+impl From<StoredLinesChangeset> for LinesChangeset {
+    fn from(stored: StoredLinesChangeset) -> Self {
+        match stored {
+            StoredLinesChangeset::Hashed(changes, original) => Self(changes, original),
+            StoredLinesChangeset::Unhashed(changes) => Self(changes, String::new()),
+        }
+    }
+}
+
 impl LinesChangeset {
     fn diff(first: &str, second: &str) -> Self {
         let lines_count = first.split('\n').count();
@@ -97,7 +138,7 @@ impl LinesChangeset {
                         inner.push(Some(LineChange::Modified(modification)));
                     }
                     debug!("Pushed pending removals, length = {}", removed.len());
-                    inner.extend(removed.drain(..));
+                    inner.append(&mut removed);
                     inner.extend(unchanged);
                 }
                 Difference::Add(lines) => {
@@ -176,11 +217,81 @@ impl LinesChangeset {
         inner.extend(removed);
         debug_assert!(inner.len() == lines_count);
         info!("Calculated patches for every line");
-        Self(inner)
+        Self(inner, super::lock::hash_bytes(first.as_bytes()))
+    }
+}
+
+/// Changes to the flattened structured file: new value for every changed path, `None` for removed ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(
+    from = "Vec<(DataPath, Option<String>)>",
+    into = "Vec<(DataPath, Option<String>)>"
+)]
+pub struct StructuredChangeset(pub BTreeMap<DataPath, Option<String>>);
+impl From<Vec<(DataPath, Option<String>)>> for StructuredChangeset {
+    fn from(changes: Vec<(DataPath, Option<String>)>) -> Self {
+        Self(changes.into_iter().collect())
+    }
+}
+impl From<StructuredChangeset> for Vec<(DataPath, Option<String>)> {
+    fn from(changeset: StructuredChangeset) -> Self {
+        changeset.0.into_iter().collect()
+    }
+}
+impl StructuredChangeset {
+    fn diff(format: Format, first: &str, second: &str) -> Result<Self, StructureError> {
+        // The first one is the vanilla file, which is the same in every run.
+        let first = format.flatten_cached(first)?;
+        let second = format.flatten(second)?;
+        let mut changes: BTreeMap<_, _> = second
+            .iter()
+            .filter(|(path, value)| first.get(*path) != Some(value))
+            .map(|(path, value)| (path.clone(), Some(value.clone())))
+            .collect();
+        changes.extend(
+            first
+                .keys()
+                .filter(|path| !second.contains_key(*path))
+                .map(|path| (path.clone(), None)),
+        );
+        info!("Calculated {} changed values", changes.len());
+        Ok(Self(changes))
+    }
+
+    fn apply(self, format: Format, original: &str) -> Result<String, StructureError> {
+        let mut map = format.flatten_cached(original)?;
+        for (path, change) in self.0 {
+            match change {
+                Some(value) => map.insert(path, value),
+                None => map.remove(&path),
+            };
+        }
+        format.rebuild(original, map)
+    }
+
+    /// Applies the changes one by one, dropping the ones which can't be put together with those applied before,
+    /// e.g. the value added inside the one which another mod has replaced with a plain value.
+    /// Removals go first, so that they make room for the values replacing the removed ones.
+    fn apply_compatible(self, format: Format, original: &str) -> String {
+        let mut applied = BTreeMap::new();
+        let mut text = original.to_owned();
+        let (removals, insertions): (Vec<_>, Vec<_>) =
+            self.0.into_iter().partition(|(_, change)| change.is_none());
+        for (path, change) in removals.into_iter().chain(insertions) {
+            applied.insert(path.clone(), change);
+            match Self(applied.clone()).apply(format, original) {
+                Ok(rebuilt) => text = rebuilt,
+                Err(err) => {
+                    warn!("[apply] Dropping the change of {:?}: {}", path, err);
+                    applied.remove(&path);
+                }
+            }
+        }
+        text
     }
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LineModification {
     Replaced(String),
     Added(String),
@@ -201,23 +312,25 @@ impl LineModification {
         .count()
     }
 }
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LineChange {
     Removed,
     Modified(LineModification),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum DiffNode {
     Binary(PathBuf),
     AddedText(String),
     ModifiedText(LinesChangeset),
+    ModifiedStructured(StructuredChangeset),
 }
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DiffNodeKind {
     Binary,
     AddedText,
     ModifiedText,
+    ModifiedStructured,
 }
 impl DiffNode {
     pub fn kind(&self) -> DiffNodeKind {
@@ -225,6 +338,7 @@ impl DiffNode {
             DiffNode::Binary(_) => DiffNodeKind::Binary,
             DiffNode::AddedText(_) => DiffNodeKind::AddedText,
             DiffNode::ModifiedText(_) => DiffNodeKind::ModifiedText,
+            DiffNode::ModifiedStructured(_) => DiffNodeKind::ModifiedStructured,
         }
     }
 }
@@ -235,9 +349,14 @@ pub trait DataTreeExt {
 impl DataTreeExt for DataTree {
     fn diff(&self, other: DataTree) -> DiffTree {
         use DataNodeContent::*;
-        other.into_iter().map(|(path, modded)| {
+        other.into_iter().filter_map(|(path, modded)| {
+            profile_span!("diff_file", path = ?path);
             info!("Comparing data on path {:?}", path);
             let value = match self.get(&path) {
+                Some(orig) if is_unchanged(orig, &modded) => {
+                    info!("{:?} is identical to the original file - skipping", path);
+                    return None;
+                }
                 Some(orig) => {
                     info!("Mod is overwriting existing file {:?}", path);
                     match (&orig.content, &modded.content) {
@@ -245,16 +364,29 @@ impl DataTreeExt for DataTree {
                             info!("{:?} is a binary file - skipping diff", path);
                             DiffNode::Binary(modded.absolute)
                         }
-                        (Text(orig), Text(modded)) => {
-                            info!("{:?} is a text file - calculating diff", path);
-                            DiffNode::ModifiedText(LinesChangeset::diff(orig, modded))
-                        }
+                        (Text(orig), Text(modded)) => match structures::data_type(&path) {
+                            Some(data_type) => {
+                                info!("{:?} is a structured file ({}) - calculating diff", path, data_type.name);
+                                match StructuredChangeset::diff(data_type.format, orig, modded) {
+                                    Ok(changeset) => DiffNode::ModifiedStructured(changeset),
+                                    Err(err) => {
+                                        warn!("{:?}: {}; falling back to line-based diff", path, err);
+                                        DiffNode::ModifiedText(LinesChangeset::diff(orig, modded))
+                                    }
+                                }
+                            }
+                            None => {
+                                info!("{:?} is a text file - calculating diff", path);
+                                DiffNode::ModifiedText(LinesChangeset::diff(orig, modded))
+                            }
+                        },
                         _ => {
-                            panic!(
-                                "Unexpected mismatch: original file {:?} and modded file {:?} have different kinds",
+                            warn!(
+                                "Original file {:?} and modded file {:?} have different kinds - taking the whole modded file",
                                 orig.absolute,
                                 modded.absolute
-                            )
+                            );
+                            DiffNode::Binary(modded.absolute)
                         },
                     }
                 }
@@ -266,23 +398,49 @@ impl DataTreeExt for DataTree {
                     }
                 }
             };
-            (path, value)
+            Some((path, value))
         }).collect()
     }
 }
 
+/// Whether the mod's file is an unmodified copy of the original one.
+///
+/// Binary files are compared by size first, so that their content is read only if it's really necessary.
+fn is_unchanged(orig: &DataNode, modded: &DataNode) -> bool {
+    use DataNodeContent::*;
+    match (&orig.content, &modded.content) {
+        (Text(orig), Text(modded)) => orig == modded,
+        (Binary, Binary) => {
+            let size = |path: &Path| std::fs::metadata(path).map(|meta| meta.len()).ok();
+            match (size(&orig.absolute), size(&modded.absolute)) {
+                (Some(orig_size), Some(modded_size)) if orig_size == modded_size => {}
+                _ => return false,
+            }
+            match (
+                std::fs::read(&orig.absolute),
+                std::fs::read(&modded.absolute),
+            ) {
+                (Ok(orig), Ok(modded)) => orig == modded,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
 pub trait ResultDiffTressExt<E>: Iterator<Item = Result<ModContent, E>> + Sized {
     fn try_merge(
         self,
         on_progress: Option<&mut cursive::CbSink>,
+        policies: &Policies,
     ) -> Result<(DiffTree, Conflicts), E> {
-        Ok(merge(try_prepare_merge(self)?, on_progress))
+        Ok(merge(try_prepare_merge(self)?, on_progress, policies))
     }
 }
 impl<I, E> ResultDiffTressExt<E> for I where I: Iterator<Item = Result<ModContent, E>> + Sized {}
 pub trait DiffTreesExt: Iterator<Item = ModContent> + Sized {
     fn merge(self, on_progress: Option<&mut cursive::CbSink>) -> (DiffTree, Conflicts) {
-        merge(prepare_merge(self), on_progress)
+        merge(prepare_merge(self), on_progress, &Policies::default())
     }
 }
 impl<I> DiffTreesExt for I where I: Iterator<Item = ModContent> + Sized {}
@@ -325,6 +483,7 @@ fn prepare_merge(mods: impl IntoIterator<Item = ModContent>) -> UsagesMap {
 fn merge(
     usages: UsagesMap,
     mut on_progress: Option<&mut cursive::CbSink>,
+    policies: &Policies,
 ) -> (DiffTree, Conflicts) {
     let mut conflicts = Conflicts::new();
     let mut merged = DiffTree::new();
@@ -333,47 +492,22 @@ fn merge(
         crate::run_update(sink, |cursive| {
             cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
                 dialog.set_title("Merging fetched mods...");
-                dialog.call_on_name("Loading part", |text: &mut TextView| {
-                    text.set_content(" ");
-                });
-                dialog.call_on_name("Loading file", |text: &mut TextView| {
-                    text.set_content(" ");
-                });
             });
         })
     }
 
-    // Now, we'll iterate over files.
-    for (path, mut mods) in usages {
-        let string_path = path.to_string_lossy();
-        info!("[merge] {:?}: merging changes", path);
-        if let Some(sink) = on_progress.as_mut() {
-            super::set_file_updated(sink, "Merging", string_path)
-        }
-
-        // Sanity check: mods vec shouldn't be empty.
-        if mods.is_empty() {
-            warn!(
-                "[merge] {:?}: unexpected empty list of modifying mods",
-                path
-            );
-            continue;
-        }
-        // The simplest case: file is modified by exactly one mod.
-        else if mods.len() == 1 {
-            // We can remove entry from DiffTree, since it won't be ever touched later.
-            let the_mod = mods.remove(0);
-            info!(
-                "[merge] {:?}: no conflicts - file is changed only by mod {}",
-                path,
-                the_mod.borrow().name
-            );
-            let item = the_mod.borrow_mut().diff.remove(&path).unwrap();
-            merged.insert(path, item);
-        }
-        // Now, we should check what kind of changes are there.
-        else {
-            let kind = mods[0].borrow().diff.get(&path).unwrap().kind();
+    // Changes are taken out of the mods first, since the mods are shared between the files.
+    let files: Vec<_> = usages
+        .into_iter()
+        .filter_map(|(path, mods)| {
+            // Sanity check: mods vec shouldn't be empty.
+            if mods.is_empty() {
+                warn!(
+                    "[merge] {:?}: unexpected empty list of modifying mods",
+                    path
+                );
+                return None;
+            }
             let list = mods
                 .into_iter()
                 .map(|item| {
@@ -381,140 +515,333 @@ fn merge(
                     (item.name.clone(), item.diff.remove(&path).unwrap())
                 })
                 .collect::<Vec<_>>();
-            info!(
-                "[merge] {:?}: multiple mods are changing file: {:?}",
-                path,
-                list.iter().map(|(name, _)| name).collect::<Vec<_>>()
+            Some((path, list))
+        })
+        .collect();
+
+    let progress = on_progress
+        .as_mut()
+        .map(|sink| Progress::phase(sink, Phase::Merge, files.len()));
+    let progress = progress.as_ref();
+
+    // Files are merged independently of each other, so they are spread over the threads.
+    // Nothing is asked here: the conflicts are only collected, to be resolved later.
+    let threads = std::thread::available_parallelism()
+        .map_or(1, std::num::NonZeroUsize::get)
+        .min(files.len().max(1));
+    let (queue, queued) = crossbeam_channel::unbounded();
+    for file in files {
+        queue.send(file).expect("Receiver is not dropped yet");
+    }
+    drop(queue);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let queued = queued.clone();
+                let mut on_progress = on_progress.as_deref().cloned();
+                scope.spawn(move || {
+                    let mut merged = DiffTree::new();
+                    let mut conflicts = Conflicts::new();
+                    for (path, list) in queued {
+                        if let Some(sink) = on_progress.as_mut() {
+                            super::set_file_updated(sink, "Merging", path.to_string_lossy())
+                        }
+                        if let Some(progress) = progress {
+                            progress.advance();
+                        }
+                        let (file_merged, file_conflicts) = merge_file(path, list, policies);
+                        merged.extend(file_merged);
+                        conflicts.extend(file_conflicts);
+                    }
+                    (merged, conflicts)
+                })
+            })
+            .collect();
+        for worker in workers {
+            let (worker_merged, worker_conflicts) =
+                worker.join().expect("Merging thread panicked");
+            merged.extend(worker_merged);
+            conflicts.extend(worker_conflicts);
+        }
+    });
+
+    (merged, conflicts)
+}
+
+/// Merges the changes of one file, listed in the order of mods selection.
+///
+/// Returns the merged changes and the conflicts, if any, to be put into the overall results.
+fn merge_file(
+    path: PathBuf,
+    mut list: Vec<(String, DiffNode)>,
+    policies: &Policies,
+) -> (DiffTree, Conflicts) {
+    profile_span!("merge_file", path = ?path);
+    info!("[merge] {:?}: merging changes", path);
+    let mut conflicts = Conflicts::new();
+    let mut merged = DiffTree::new();
+
+    // The simplest case: file is modified by exactly one mod.
+    if list.len() == 1 {
+        let (name, item) = list.remove(0);
+        info!(
+            "[merge] {:?}: no conflicts - file is changed only by mod {}",
+            path, name
+        );
+        merged.insert(path, item);
+        return (merged, conflicts);
+    }
+    // Now, we should check what kind of changes are there.
+    let kind = list[0].1.kind();
+    info!(
+        "[merge] {:?}: multiple mods are changing file: {:?}",
+        path,
+        list.iter().map(|(name, _)| name).collect::<Vec<_>>()
+    );
+    let (list, kind) = match added_structured(&path, &list) {
+        Some(changesets) => (changesets, DiffNodeKind::ModifiedStructured),
+        None => (list, kind),
+    };
+    // This can happen if some mod's version of structured file couldn't be parsed.
+    // There's no way to merge such changes, so one of them must be chosen as a whole.
+    if list.iter().any(|(_, node)| node.kind() != kind) {
+        warn!(
+            "[merge] {:?}: Mods are changing file in incompatible ways - putting it to conflicts directly",
+            path
+        );
+        conflicts.insert(path, list);
+        return (merged, conflicts);
+    }
+    match kind {
+        // Another simple case is when multiple mods modify (or create) one binary file.
+        // For multiple mods adding the same text file (except the structured ones merged above),
+        // we want to ask user to choose one of them as "base",
+        // and then we'll run the diffing again, with "base" being the "vanilla" and all others being "mods".
+        // So, they are directly put into "conflicts", like the binaries.
+        kind @ DiffNodeKind::Binary | kind @ DiffNodeKind::AddedText => {
+            debug!(
+                "[merge] {:?}: Diff is of kind {:?} - putting it to conflicts directly",
+                path, kind
             );
-            match kind {
-                // Another simple case is when multiple mods modify (or create) one binary file.
-                // For multiple mods adding the same text file, we want to ask user to choose one of them as "base",
-                // and then we'll run the diffing again, with "base" being the "vanilla" and all others being "mods".
-                // So, they are directly put into "conflicts", like the binaries.
-                kind @ DiffNodeKind::Binary | kind @ DiffNodeKind::AddedText => {
+            conflicts.insert(path, list);
+        }
+        DiffNodeKind::ModifiedStructured => {
+            debug!("[merge] {:?}: Diff is modifying structured file - trying to merge value-by-value", path);
+            // Same as for the text below, but every value is identified by its path, not by line number.
+            let policy = policies.for_path(&path);
+            let mut value_changes: BTreeMap<DataPath, Vec<(String, Option<String>)>> =
+                BTreeMap::new();
+            for (name, node) in &list {
+                if let DiffNode::ModifiedStructured(changeset) = node {
+                    for (data_path, change) in &changeset.0 {
+                        value_changes
+                            .entry(data_path.clone())
+                            .or_default()
+                            .push((name.clone(), change.clone()));
+                    }
+                } else {
+                    unreachable!();
+                }
+            }
+            let mut merged_changes = BTreeMap::new();
+            let mut conflict_changes: HashMap<String, BTreeMap<_, _>> = HashMap::new();
+            for (data_path, changes) in value_changes {
+                let set: HashSet<_> = changes.iter().map(|(_, change)| change).collect();
+                if set.len() == 1 {
+                    let (name, change) = changes.into_iter().next().unwrap();
+                    debug!(
+                        "[merge] {:?}: Value {:?} changed by {}",
+                        path, data_path, name
+                    );
+                    merged_changes.insert(data_path, change);
+                } else if let Some(change) = policies.resolve(&path, &changes) {
+                    info!(
+                        "[merge] {:?}: Conflicting changes for value {:?} resolved to {:?} by policy {:?}",
+                        path, data_path, change, policy
+                    );
+                    merged_changes.insert(data_path, change);
+                } else {
                     debug!(
-                        "[merge] {:?}: Diff is of kind {:?} - putting it to conflicts directly",
-                        path, kind
+                        "[merge] {:?}: Conflicting changes for value {:?}, mods: {:?}",
+                        path,
+                        data_path,
+                        changes.iter().map(|(name, _)| name).collect::<Vec<_>>()
                     );
-                    conflicts.insert(path, list);
+                    for (name, change) in changes {
+                        conflict_changes
+                            .entry(name)
+                            .or_default()
+                            .insert(data_path.clone(), change);
+                    }
                 }
-                // Now that's getting tricky.
-                DiffNodeKind::ModifiedText => {
-                    debug!("[merge] {:?}: Diff is modifying existing text - trying to merge line-by-line", path);
-                    // We will treat as conflict any case when two mods modify the same line.
-                    // And we want to merge all non-conflicting cases.
-                    // So, we iterate over every changeset, to check which lines are
-                    // changed by it.
-                    let mut line_changes: Vec<HashMap<String, LineChange>> = vec![];
-                    let mut conflict_changes = HashMap::new();
-                    for changes in &list {
-                        if let (name, DiffNode::ModifiedText(changelist)) = changes {
-                            conflict_changes.insert(name.to_string(), vec![]);
-                            if line_changes.is_empty() {
-                                line_changes.resize_with(changelist.0.len(), Default::default);
-                            }
-                            for (index, change) in changelist.0.iter().enumerate() {
-                                change.as_ref().map(|change| {
-                                    debug!(
-                                        "[merge] {:?}: Mod {} changes line {}",
-                                        path, name, index
-                                    );
-                                    line_changes[index].insert(name.into(), change.clone())
-                                });
-                            }
-                        } else {
-                            unreachable!();
-                        }
+            }
+            if !merged_changes.is_empty() {
+                info!("[merge] {:?}: outputting merged changes", path);
+                merged.insert(
+                    path.clone(),
+                    DiffNode::ModifiedStructured(StructuredChangeset(merged_changes)),
+                );
+            }
+            if !conflict_changes.is_empty() {
+                info!("[merge] {:?}: outputting conflicts", path);
+                // Mods are kept in the bundling order, so that they are always shown in the same order.
+                let conflict_changes = list
+                    .iter()
+                    .filter_map(|(key, _)| {
+                        let changes = conflict_changes.remove(key)?;
+                        Some((
+                            key.clone(),
+                            DiffNode::ModifiedStructured(StructuredChangeset(changes)),
+                        ))
+                    })
+                    .collect();
+                conflicts.insert(path, conflict_changes);
+            }
+        }
+        // Now that's getting tricky.
+        DiffNodeKind::ModifiedText => {
+            debug!("[merge] {:?}: Diff is modifying existing text - trying to merge line-by-line", path);
+            // We will treat as conflict any case when two mods modify the same line.
+            // And we want to merge all non-conflicting cases.
+            // So, we iterate over every changeset, to check which lines are
+            // changed by it.
+            let mut line_changes: Vec<HashMap<String, LineChange>> = vec![];
+            let mut conflict_changes = HashMap::new();
+            // Every mod changes the same original file.
+            let mut original = String::new();
+            for changes in &list {
+                if let (name, DiffNode::ModifiedText(changelist)) = changes {
+                    original.clone_from(&changelist.1);
+                    conflict_changes.insert(name.to_string(), vec![]);
+                    if line_changes.is_empty() {
+                        line_changes.resize_with(changelist.0.len(), Default::default);
                     }
-                    // OK, now we get the list of every change grouped by source line.
-                    let mut merged_changes = vec![];
-                    for (index, line_change) in line_changes.into_iter().enumerate() {
-                        // Trivial case - no changes
-                        if line_change.is_empty() {
-                            merged_changes.push(None);
-                            for change in conflict_changes.values_mut() {
-                                change.push(None);
-                            }
-                        }
-                        // Good case - change from exactly one mod.
-                        else if line_change.len() == 1 {
-                            let (name, change) = line_change.into_iter().next().unwrap();
-                            debug!(
-                                "[merge] {:?}: Exactly one change for line {}, mod = {}",
-                                path, index, name
-                            );
-                            merged_changes.push(Some(change));
-                            for change in conflict_changes.values_mut() {
-                                change.push(None);
-                            }
-                        }
-                        // Bad case - there's a conflict!
-                        else {
-                            // Don't panic yet! Let's check if all the changes are indeed the same.
-                            let set: HashSet<_> = line_change.values().collect();
-                            if set.len() == 1 {
-                                // All changes are equal - no problem!
-                                let (_, change) = line_change.into_iter().next().unwrap();
-                                debug!(
-                                    "[merge] {:?}: Multiple equal changes for line {}",
-                                    path, index
-                                );
-                                merged_changes.push(Some(change));
-                                for change in conflict_changes.values_mut() {
-                                    change.push(None);
-                                }
-                                continue;
-                            }
-                            // OK, that's really a conflict.
-                            // First of all, push "unchanged" marker to the merges list.
-                            merged_changes.push(None);
-                            // Now, let's operate on "conflicts".
+                    for (index, change) in changelist.0.iter().enumerate() {
+                        change.as_ref().map(|change| {
                             debug!(
-                                "[merge] {:?}: Conflicting changes for line {}, mods: {:?}",
-                                path,
-                                index,
-                                line_change.keys().collect::<Vec<_>>()
+                                "[merge] {:?}: Mod {} changes line {}",
+                                path, name, index
                             );
-                            let mut line_change = line_change;
-                            for (name, conflict) in conflict_changes.iter_mut() {
-                                let change = line_change.remove(name);
-                                conflict.push(change);
-                            }
-                        }
+                            line_changes[index].insert(name.into(), change.clone())
+                        });
+                    }
+                } else {
+                    unreachable!();
+                }
+            }
+            // OK, now we get the list of every change grouped by source line.
+            let mut merged_changes = vec![];
+            for (index, line_change) in line_changes.into_iter().enumerate() {
+                // Trivial case - no changes
+                if line_change.is_empty() {
+                    merged_changes.push(None);
+                    for change in conflict_changes.values_mut() {
+                        change.push(None);
                     }
-                    // Woof! Finally, we can put the results into the output maps.
-                    if !merged_changes.iter().all(Option::is_none) {
-                        info!("[merge] {:?}: outputting merged changes", path);
-                        merged.insert(
-                            path.clone(),
-                            DiffNode::ModifiedText(LinesChangeset(merged_changes)),
+                }
+                // Good case - change from exactly one mod.
+                else if line_change.len() == 1 {
+                    let (name, change) = line_change.into_iter().next().unwrap();
+                    debug!(
+                        "[merge] {:?}: Exactly one change for line {}, mod = {}",
+                        path, index, name
+                    );
+                    merged_changes.push(Some(change));
+                    for change in conflict_changes.values_mut() {
+                        change.push(None);
+                    }
+                }
+                // Bad case - there's a conflict!
+                else {
+                    // Don't panic yet! Let's check if all the changes are indeed the same.
+                    let set: HashSet<_> = line_change.values().collect();
+                    if set.len() == 1 {
+                        // All changes are equal - no problem!
+                        let (_, change) = line_change.into_iter().next().unwrap();
+                        debug!(
+                            "[merge] {:?}: Multiple equal changes for line {}",
+                            path, index
                         );
+                        merged_changes.push(Some(change));
+                        for change in conflict_changes.values_mut() {
+                            change.push(None);
+                        }
+                        continue;
                     }
-                    conflict_changes.retain(|_, list| !list.iter().all(Option::is_none));
-                    if !conflict_changes.is_empty() {
-                        info!("[merge] {:?}: outputting conflicts", path);
-                        let conflict_changes = conflict_changes
-                            .into_iter()
-                            .map(|(key, list)| {
-                                debug!("[merge] {:?}: conflicting changes from mod {}", path, key);
-                                (key, DiffNode::ModifiedText(LinesChangeset(list)))
-                            })
-                            .collect();
-                        conflicts.insert(path, conflict_changes);
+                    // OK, that's really a conflict.
+                    // First of all, push "unchanged" marker to the merges list.
+                    merged_changes.push(None);
+                    // Now, let's operate on "conflicts".
+                    debug!(
+                        "[merge] {:?}: Conflicting changes for line {}, mods: {:?}",
+                        path,
+                        index,
+                        line_change.keys().collect::<Vec<_>>()
+                    );
+                    let mut line_change = line_change;
+                    for (name, conflict) in conflict_changes.iter_mut() {
+                        let change = line_change.remove(name);
+                        conflict.push(change);
                     }
                 }
             }
+            // Woof! Finally, we can put the results into the output maps.
+            if !merged_changes.iter().all(Option::is_none) {
+                info!("[merge] {:?}: outputting merged changes", path);
+                merged.insert(
+                    path.clone(),
+                    DiffNode::ModifiedText(LinesChangeset(merged_changes, original.clone())),
+                );
+            }
+            conflict_changes.retain(|_, list| !list.iter().all(Option::is_none));
+            if !conflict_changes.is_empty() {
+                info!("[merge] {:?}: outputting conflicts", path);
+                let conflict_changes = list
+                    .iter()
+                    .filter_map(|(key, _)| {
+                        let changes = conflict_changes.remove(key)?;
+                        debug!("[merge] {:?}: conflicting changes from mod {}", path, key);
+                        Some((key.clone(), DiffNode::ModifiedText(LinesChangeset(changes, original.clone()))))
+                    })
+                    .collect();
+                conflicts.insert(path, conflict_changes);
+            }
         }
     }
 
     (merged, conflicts)
 }
 
+/// Changes of the structured file added by every mod in the list, made against the empty file,
+/// so that the added files are merged value by value and not chosen as a whole.
+///
+/// Returns `None` if the file is not of the type merged this way or if some mod's version can't be parsed.
+fn added_structured(path: &Path, list: &[(String, DiffNode)]) -> Option<Vec<(String, DiffNode)>> {
+    let data_type = structures::added_data_type(path)?;
+    list.iter()
+        .map(|(name, node)| match node {
+            DiffNode::AddedText(text) => match StructuredChangeset::diff(data_type.format, "", text) {
+                Ok(changeset) => Some((name.clone(), DiffNode::ModifiedStructured(changeset))),
+                Err(err) => {
+                    warn!("[merge] {:?}: {}; choosing one of the added files as a whole", path, err);
+                    None
+                }
+            },
+            _ => None,
+        })
+        .collect()
+}
+
 pub trait DiffTreeExt: Sized {
-    fn apply_to(self, _: DataTree) -> DataTree;
+    fn apply_to(self, original: &DataTree) -> DataTree {
+        self.apply_reporting(original, |_, _| {})
+    }
+    /// Same as `apply_to`, calling `on_parse_error` for the files whose changes couldn't be put together.
+    fn apply_reporting(self, _: &DataTree, on_parse_error: impl FnMut(&Path, &StructureError)) -> DataTree;
 }
 impl DiffTreeExt for DiffTree {
-    fn apply_to(self, original: DataTree) -> DataTree {
+    fn apply_reporting(self, original: &DataTree, mut on_parse_error: impl FnMut(&Path, &StructureError)) -> DataTree {
+        profile_span!("apply");
         info!("Applying calculated diff to the source tree");
         self.into_iter()
             .map(|(path, changes)| match changes {
@@ -559,7 +886,418 @@ impl DiffTreeExt for DiffTree {
                         .join("\n");
                     (path, DataNode::new("", text))
                 }
+                DiffNode::ModifiedStructured(changeset) => {
+                    debug!("[apply] {:?}: modified structured data", path);
+                    // Files added by several mods are merged against the empty one.
+                    let orig = match original.get(&path).map(|node| &node.content) {
+                        Some(DataNodeContent::Binary) => unreachable!(),
+                        Some(DataNodeContent::Text(text)) => text.as_str(),
+                        None => "",
+                    };
+                    let format = structures::data_type(&path)
+                        .expect("Structured changes for the file of unknown type")
+                        .format;
+                    let text = match changeset.clone().apply(format, orig) {
+                        Ok(text) => text,
+                        Err(err) => {
+                            warn!("[apply] {:?}: {}; applying the changes which fit together", path, err);
+                            on_parse_error(&path, &err);
+                            changeset.apply_compatible(format, orig)
+                        }
+                    };
+                    (path, DataNode::new("", text))
+                }
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(pairs: &[(&str, &str)]) -> DataTree {
+        pairs
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), DataNode::new(path, text.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn identical_files_are_skipped() {
+        let original = tree(&[("a.txt", "same"), ("b.txt", "old")]);
+        let modded = tree(&[("a.txt", "same"), ("b.txt", "new"), ("c.txt", "added")]);
+        let diff = original.diff(modded);
+        assert_eq!(
+            diff.keys().collect::<Vec<_>>(),
+            vec![Path::new("b.txt"), Path::new("c.txt")]
+        );
+    }
+
+    #[test]
+    fn ambience_tweaks_merge_per_key() {
+        let path = "campaign/ambience/crypts.json";
+        let original = tree(&[(
+            path,
+            r#"{"ambience": [{"name": "crypts", "volume": 1.0, "reverb": 0.5}]}"#,
+        )]);
+        let overhaul = tree(&[(
+            path,
+            r#"{"ambience": [{"name": "crypts", "volume": 0.5, "reverb": 0.5}]}"#,
+        )]);
+        let dungeon = tree(&[(
+            path,
+            r#"{"ambience": [{"name": "crypts", "volume": 1.0, "reverb": 0.8}]}"#,
+        )]);
+        let (merged, conflicts) = vec![
+            ModContent::new("overhaul", original.diff(overhaul)),
+            ModContent::new("dungeon", original.diff(dungeon)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let map = structures::AMBIENCE
+            .format
+            .flatten(modded[Path::new(path)].text().unwrap())
+            .unwrap();
+        let value = |field: &str| {
+            map[&["ambience", "name=crypts", field][..].into()].clone()
+        };
+        assert_eq!(value("volume"), "0.5");
+        assert_eq!(value("reverb"), "0.8");
+    }
+
+    #[test]
+    fn every_file_is_merged() {
+        let files: Vec<_> = (0..50).map(|index| format!("{}.txt", index)).collect();
+        let texts = |text: fn(usize) -> &'static str| {
+            let pairs: Vec<_> = files
+                .iter()
+                .enumerate()
+                .map(|(index, file)| (file.as_str(), text(index)))
+                .collect();
+            tree(&pairs)
+        };
+        let original = texts(|_| "a\nb");
+        let first = texts(|_| "x\nb");
+        // Every other file conflicts, the rest are merged line by line.
+        let second = texts(|index| if index % 2 == 0 { "y\nb" } else { "a\ny" });
+        let (merged, conflicts) = vec![
+            ModContent::new("first", original.diff(first)),
+            ModContent::new("second", original.diff(second)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert_eq!(conflicts.len(), 25);
+        assert_eq!(merged.len(), 25);
+        assert!(conflicts.keys().all(|path| !merged.contains_key(path)));
+    }
+
+    #[test]
+    fn added_effects_merge_by_name() {
+        let path = "effects/base.effects.darkest";
+        let original = tree(&[(path, "effect: .name \"Bleed 1\" .dotBleed 1\n")]);
+        let hag = tree(&[(
+            path,
+            "effect: .name \"Bleed 1\" .dotBleed 1\neffect: .name \"Hex 1\" .debuff 1\n",
+        )]);
+        let exorcist = tree(&[(
+            path,
+            "effect: .name \"Bleed 1\" .dotBleed 1\neffect: .name \"Banish 1\" .stun 1\n",
+        )]);
+        let (merged, conflicts) = vec![
+            ModContent::new("hag", original.diff(hag)),
+            ModContent::new("exorcist", original.diff(exorcist)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let text = modded[Path::new(path)].text().unwrap();
+        assert!(text.contains("effect: .name \"Hex 1\" .debuff 1\n"));
+        assert!(text.contains("effect: .name \"Banish 1\" .stun 1\n"));
+    }
+
+    #[test]
+    fn incompatible_json_changes_are_dropped() {
+        let path = "campaign/town/activities.json";
+        let original = tree(&[(path, r#"{"a": {"b": 1}, "c": 1}"#)]);
+        // One mod replaces the object with a plain value, another one adds the value inside it.
+        let flatten = tree(&[(path, r#"{"a": 5, "c": 1}"#)]);
+        let extend = tree(&[(path, r#"{"a": {"b": 1, "d": 2}, "c": 2}"#)]);
+        let (merged, conflicts) = vec![
+            ModContent::new("flatten", original.diff(flatten)),
+            ModContent::new("extend", original.diff(extend)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let value: serde_json::Value =
+            serde_json::from_str(modded[Path::new(path)].text().unwrap()).unwrap();
+        assert_eq!(value, serde_json::json!({"a": 5, "c": 2}));
+    }
+
+    #[test]
+    fn hero_class_sets_merge_class_by_class() {
+        let path = "raid/camping/default.camping_skills.json";
+        let original = tree(&[(
+            path,
+            r#"{"skills": [{"id": "encourage", "hero_classes": ["crusader", "vestal"]},
+                           {"id": "pray", "hero_classes": ["vestal"]}]}"#,
+        )]);
+        let add_hag = tree(&[(
+            path,
+            r#"{"skills": [{"id": "encourage", "hero_classes": ["crusader", "vestal", "hag"]},
+                           {"id": "pray", "hero_classes": ["vestal"]}]}"#,
+        )]);
+        // Repeated and removed classes must not switch the set to the other representation.
+        let drop_vestal = tree(&[(
+            path,
+            r#"{"skills": [{"id": "encourage", "hero_classes": ["crusader", "crusader"]},
+                           {"id": "pray", "hero_classes": []}]}"#,
+        )]);
+        let (merged, conflicts) = vec![
+            ModContent::new("add_hag", original.diff(add_hag)),
+            ModContent::new("drop_vestal", original.diff(drop_vestal)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let value: serde_json::Value =
+            serde_json::from_str(modded[Path::new(path)].text().unwrap()).unwrap();
+        assert_eq!(value["skills"][0]["hero_classes"], serde_json::json!(["crusader", "hag"]));
+        assert_eq!(value["skills"][1]["hero_classes"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn campaign_start_and_tutorial_merge_by_name() {
+        let start = "campaign/new_game/new_game.json";
+        let tutorial = "campaign/tutorial/tutorial.json";
+        assert_eq!(structures::data_type(Path::new(start)).unwrap().name, structures::CAMPAIGN_START.name);
+        assert_eq!(structures::data_type(Path::new(tutorial)).unwrap().name, structures::TUTORIAL.name);
+        let original = tree(&[
+            (start, r#"{"heroes": [{"name": "Reynauld", "class": "crusader"}, {"name": "Dismas", "class": "highwayman"}]}"#),
+            (tutorial, r#"{"steps": [{"name": "intro", "enabled": true}, {"name": "torch", "enabled": true}]}"#),
+        ]);
+        // Starting party mod swaps a hero, tutorial skip mod disables the steps, overhaul adds a hero.
+        let party = tree(&[
+            (start, r#"{"heroes": [{"name": "Reynauld", "class": "hag"}, {"name": "Dismas", "class": "highwayman"}]}"#),
+        ]);
+        let skip = tree(&[
+            (tutorial, r#"{"steps": [{"name": "intro", "enabled": false}, {"name": "torch", "enabled": false}]}"#),
+        ]);
+        let overhaul = tree(&[
+            (start, r#"{"heroes": [{"name": "Reynauld", "class": "crusader"}, {"name": "Dismas", "class": "highwayman"},
+                                   {"name": "Junia", "class": "vestal"}]}"#),
+            (tutorial, r#"{"steps": [{"name": "intro", "enabled": true}, {"name": "torch", "enabled": true},
+                                     {"name": "camp", "enabled": true}]}"#),
+        ]);
+        let (merged, conflicts) = vec![
+            ModContent::new("party", original.diff(party.clone())),
+            ModContent::new("skip", original.diff(skip)),
+            ModContent::new("overhaul", original.diff(overhaul)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let value = |path: &str| -> serde_json::Value {
+            serde_json::from_str(modded[Path::new(path)].text().unwrap()).unwrap()
+        };
+        assert_eq!(value(start)["heroes"][0]["class"], "hag");
+        assert_eq!(value(start)["heroes"][2]["name"], "Junia");
+        assert_eq!(value(tutorial)["steps"][1]["enabled"], false);
+        assert_eq!(value(tutorial)["steps"][2]["name"], "camp");
+
+        // Swapping the same hero differently is asked about, as the policy says.
+        assert_eq!(structures::CAMPAIGN_START.default_policy, crate::bundler::MergePolicy::AlwaysAsk);
+        let other_party = tree(&[
+            (start, r#"{"heroes": [{"name": "Reynauld", "class": "leper"}, {"name": "Dismas", "class": "highwayman"}]}"#),
+        ]);
+        let (_, conflicts) = vec![
+            ModContent::new("party", original.diff(party)),
+            ModContent::new("other_party", original.diff(other_party)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert_eq!(conflicts.keys().collect::<Vec<_>>(), [Path::new(start)]);
+    }
+
+    #[test]
+    fn plot_quests_merge_by_quest_id() {
+        let path = "dungeons/plot/darkest.plot_quests.json";
+        assert_eq!(structures::data_type(Path::new(path)).unwrap().name, structures::PLOT_QUESTS.name);
+        let original = tree(&[(
+            path,
+            r#"{"plot_quests": [{"id": "tutorial", "length": 1}, {"id": "darkest_1", "length": 3}]}"#,
+        )]);
+        let tweak = |length: u32| tree(&[(
+            path,
+            &format!(r#"{{"plot_quests": [{{"id": "tutorial", "length": 1}}, {{"id": "darkest_1", "length": {}}}]}}"#, length),
+        )]);
+        let campaign = tree(&[(
+            path,
+            r#"{"plot_quests": [{"id": "tutorial", "length": 1}, {"id": "darkest_1", "length": 3},
+                                {"id": "lair_1", "length": 2}]}"#,
+        )]);
+        let (merged, conflicts) = vec![
+            ModContent::new("tweak", original.diff(tweak(2))),
+            ModContent::new("campaign", original.diff(campaign)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let value: serde_json::Value =
+            serde_json::from_str(modded[Path::new(path)].text().unwrap()).unwrap();
+        assert_eq!(value["plot_quests"][1]["length"], 2);
+        assert_eq!(value["plot_quests"][2]["id"], "lair_1");
+
+        // Only the edits of the same quest are asked about.
+        assert_eq!(structures::PLOT_QUESTS.default_policy, crate::bundler::MergePolicy::AlwaysAsk);
+        let (_, conflicts) = vec![
+            ModContent::new("tweak", original.diff(tweak(2))),
+            ModContent::new("other_tweak", original.diff(tweak(4))),
+        ]
+        .into_iter()
+        .merge(None);
+        assert_eq!(conflicts.keys().collect::<Vec<_>>(), [Path::new(path)]);
+    }
+
+    #[test]
+    fn trinkets_merge_per_trinket() {
+        let entries = "trinkets/base.entries.trinkets.json";
+        let rarities = "trinkets/base.rarities.trinkets.json";
+        for path in [entries, rarities] {
+            assert_eq!(structures::data_type(Path::new(path)).unwrap().name, structures::TRINKETS.name);
+        }
+        let original = tree(&[
+            (entries, r#"{"entries": [{"id": "sun_ring", "rarity": "common", "price": 1000}]}"#),
+            (rarities, r#"{"rarities": [{"id": "common", "insert_chance": 0.5}]}"#),
+        ]);
+        let balance = |price: u32| tree(&[
+            (entries, &format!(r#"{{"entries": [{{"id": "sun_ring", "rarity": "common", "price": {}}}]}}"#, price)),
+            (rarities, r#"{"rarities": [{"id": "common", "insert_chance": 0.4}]}"#),
+        ]);
+        let pack = tree(&[
+            (entries, r#"{"entries": [{"id": "sun_ring", "rarity": "common", "price": 1000},
+                                      {"id": "moon_ring", "rarity": "mythic", "price": 5000}]}"#),
+            (rarities, r#"{"rarities": [{"id": "common", "insert_chance": 0.5}, {"id": "mythic", "insert_chance": 0.1}]}"#),
+        ]);
+        let (merged, conflicts) = vec![
+            ModContent::new("balance", original.diff(balance(1500))),
+            ModContent::new("pack", original.diff(pack)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        let value = |path: &str| -> serde_json::Value {
+            serde_json::from_str(modded[Path::new(path)].text().unwrap()).unwrap()
+        };
+        assert_eq!(value(entries)["entries"][0]["price"], 1500);
+        assert_eq!(value(entries)["entries"][1]["id"], "moon_ring");
+        assert_eq!(value(rarities)["rarities"][0]["insert_chance"], 0.4);
+        assert_eq!(value(rarities)["rarities"][1]["id"], "mythic");
+
+        // Different prices of the same trinket are asked about.
+        assert_eq!(structures::TRINKETS.default_policy, crate::bundler::MergePolicy::AlwaysAsk);
+        let (_, conflicts) = vec![
+            ModContent::new("balance", original.diff(balance(1500))),
+            ModContent::new("other_balance", original.diff(balance(2000))),
+        ]
+        .into_iter()
+        .merge(None);
+        assert_eq!(conflicts.keys().collect::<Vec<_>>(), [Path::new(entries)]);
+    }
+
+    #[test]
+    fn curio_tweaks_merge_with_added_curios() {
+        let path = "curios/curio_type_library.csv";
+        let original = tree(&[(
+            path,
+            "ID,Full ID,Results,\n1,altar,,\n,,loot,0.5\n",
+        )]);
+        let tweak = tree(&[(
+            path,
+            "ID,Full ID,Results,\n1,altar,,\n,,loot,0.8\n",
+        )]);
+        let overhaul = tree(&[(
+            path,
+            "ID,Full ID,Results,\n1,altar,,\n,,loot,0.5\n2,shrine,,\n,,purge,1\n",
+        )]);
+        let (merged, conflicts) = vec![
+            ModContent::new("tweak", original.diff(tweak)),
+            ModContent::new("overhaul", original.diff(overhaul)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        assert_eq!(
+            modded[Path::new(path)].text().unwrap(),
+            "ID,Full ID,Results,\n1,altar,,\n,,loot,0.8\n2,shrine\n,,purge,1\n"
+        );
+    }
+
+    #[test]
+    fn hero_overrides_merge_by_section() {
+        let path = "heroes/hag/hag.override.darkest";
+        let original = tree(&[(path, "skill: .id \"curse\" .level 0 .dmg 1 2\n")]);
+        let rebalance = tree(&[(path, "skill: .id \"curse\" .level 0 .dmg 2 4\n")]);
+        let outsider = tree(&[(
+            path,
+            "skill: .id \"curse\" .level 0 .dmg 1 2\ntag: .id \"outsider\"\n",
+        )]);
+        let (merged, conflicts) = vec![
+            ModContent::new("rebalance", original.diff(rebalance)),
+            ModContent::new("outsider", original.diff(outsider)),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        assert_eq!(
+            modded[Path::new(path)].text().unwrap(),
+            "skill: .id \"curse\" .level 0 .dmg 2 4\ntag: .id \"outsider\"\n"
+        );
+    }
+
+    #[test]
+    fn hero_overrides_added_by_several_mods_merge_by_section() {
+        let path = "heroes/hag/hag.override.darkest";
+        let original = tree(&[]);
+        let rebalance = tree(&[(path, "skill: .id \"curse\" .level 0 .dmg 2 4\n")]);
+        let outsider = tree(&[(path, "tag: .id \"outsider\"\n")]);
+        let rival = tree(&[(path, "skill: .id \"curse\" .level 0 .dmg 3 5\n")]);
+        let (merged, conflicts) = vec![
+            ModContent::new("rebalance", original.diff(rebalance.clone())),
+            ModContent::new("outsider", original.diff(outsider.clone())),
+        ]
+        .into_iter()
+        .merge(None);
+        assert!(conflicts.is_empty());
+        let modded = merged.apply_to(&original);
+        assert_eq!(
+            modded[Path::new(path)].text().unwrap(),
+            "skill: .id \"curse\" .level 0 .dmg 2 4\ntag: .id \"outsider\"\n"
+        );
+
+        // Only the values changed by both mods are left to choose from.
+        let (_, conflicts) = vec![
+            ModContent::new("rebalance", original.diff(rebalance)),
+            ModContent::new("outsider", original.diff(outsider)),
+            ModContent::new("rival", original.diff(rival)),
+        ]
+        .into_iter()
+        .merge(None);
+        let conflict = &conflicts[Path::new(path)];
+        assert_eq!(conflict.len(), 2);
+        assert!(conflict
+            .iter()
+            .all(|(_, node)| node.kind() == DiffNodeKind::ModifiedStructured));
+    }
+}
@@ -0,0 +1,154 @@
+use super::{diff::DiffTree, dry_run::describe, error::ExtractionError};
+use crate::{config::Config, loader::GlobalData};
+use cursive::{
+    traits::Scrollable,
+    views::{Checkbox, Dialog, LinearLayout, TextView},
+    Cursive,
+};
+use log::*;
+use std::{collections::BTreeMap, fs::ReadDir, io::ErrorKind, path::Path};
+
+/// Lists the contents of the game's `dlc` directory, or nothing, if there's no such directory,
+/// as in the GOG builds or the stripped installs.
+pub fn entries(dlc_path: &Path) -> Result<Option<ReadDir>, ExtractionError> {
+    match std::fs::read_dir(dlc_path) {
+        Ok(entries) => Ok(Some(entries)),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            warn!(
+                "[dlc] No DLC directory at {:?}, treating as no DLC",
+                dlc_path
+            );
+            Ok(None)
+        }
+        Err(err) => Err(ExtractionError::from_io(dlc_path)(err)),
+    }
+}
+
+/// Names of the DLC directories in the game's `dlc` directory, sorted.
+pub fn installed(dlc_path: &Path) -> Vec<String> {
+    let mut names: Vec<_> = entries(dlc_path)
+        .ok()
+        .flatten()
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Screen to leave some DLCs out of the baseline the mods are compared against, in the active profile.
+pub fn screen(cursive: &mut Cursive) {
+    let base_path = &cursive
+        .user_data::<GlobalData>()
+        .expect("No data was set")
+        .base_path;
+    let installed = installed(&crate::paths::game(base_path).join("dlc"));
+    let config = Config::load();
+    let mut list = LinearLayout::vertical().child(TextView::new(
+        "Mods are compared against the game with every DLC checked here. Uncheck the DLCs \
+         the mods were made without, so that the files these DLCs change aren't taken for the mods' changes.\n",
+    ));
+    if installed.is_empty() {
+        list.add_child(TextView::new("No DLC is installed."));
+    }
+    for dlc in installed {
+        let included = !config.dlc_excluded(&dlc);
+        let label = format!(" {}", dlc);
+        list.add_child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(included)
+                        .on_change(move |_, checked| {
+                            info!("[dlc] Including {} in the baseline: {}", dlc, checked);
+                            let mut config = Config::load();
+                            let excluded = &mut config.profile_mut().excluded_dlc;
+                            excluded.retain(|excluded| *excluded != dlc);
+                            if !checked {
+                                excluded.push(dlc.clone());
+                            }
+                            config.save();
+                        }),
+                )
+                .child(TextView::new(label)),
+        );
+    }
+    cursive.add_layer(
+        Dialog::around(list.scrollable())
+            .title(format!(
+                "DLC baseline (profile \"{}\")",
+                config
+                    .active_profile
+                    .as_deref()
+                    .unwrap_or(crate::config::DEFAULT_PROFILE)
+            ))
+            .dismiss_button("Close"),
+    );
+}
+
+/// Describes what every DLC changes in the vanilla data, file by file and value by value.
+pub fn report(changes: &[(String, DiffTree)]) -> String {
+    let mut text = String::new();
+    for (dlc, diff) in changes {
+        text.push_str(&format!("DLC {}: {} files\n", dlc, diff.len()));
+        let sorted: BTreeMap<_, _> = diff.iter().collect();
+        for (path, node) in sorted {
+            text.push_str(&describe(path, node));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::{
+        diff::{DiffNode, StructuredChangeset},
+        structures::DataPath,
+    };
+
+    #[test]
+    fn lists_changed_values() {
+        let mut diff = DiffTree::new();
+        diff.insert(
+            "dungeons/crypts/crypts.props.darkest".into(),
+            DiffNode::ModifiedStructured(StructuredChangeset::from(vec![
+                (
+                    DataPath::from(&["props", "#0", "chance"][..]),
+                    Some("0.5".into()),
+                ),
+                (DataPath::from(&["props", "#0", "count"][..]), None),
+            ])),
+        );
+        diff.insert(
+            "heroes/flagellant/flagellant.info.darkest".into(),
+            DiffNode::AddedText(String::new()),
+        );
+        assert_eq!(
+            report(&[("flagellant".into(), diff)]),
+            "DLC flagellant: 2 files\n\
+             - dungeons/crypts/crypts.props.darkest: 2 values changed\n    \
+             props/#0/chance = 0.5\n    \
+             props/#0/count = <removed>\n\
+             - heroes/flagellant/flagellant.info.darkest: added\n\n"
+        );
+    }
+
+    #[test]
+    fn installed_dlc_are_directories() {
+        let dlc = crate::fixtures::temp_dir("dlc");
+        assert!(installed(&dlc).is_empty());
+        std::fs::create_dir_all(dlc.join("735730_color_of_madness")).unwrap();
+        std::fs::create_dir_all(dlc.join("580100_crimson_court")).unwrap();
+        std::fs::write(dlc.join("readme.txt"), "").unwrap();
+        assert_eq!(
+            installed(&dlc),
+            ["580100_crimson_court", "735730_color_of_madness"]
+        );
+        std::fs::remove_dir_all(&dlc).unwrap();
+    }
+}
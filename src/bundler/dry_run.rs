@@ -0,0 +1,125 @@
+//! Report-only bundling: instead of deploying, describes what the selected mods touch and how they would be merged,
+//! so that the compatibility of mods can be checked before making the bundle.
+
+use super::diff::{Conflicts, DiffNode, DiffTree};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Files changed by every mod, in the bundling order.
+pub type Touched = Vec<(String, Vec<PathBuf>)>;
+
+/// Renders the Markdown report of the merge made without asking anything.
+pub fn report(touched: &Touched, merged: &DiffTree, conflicts: &Conflicts) -> String {
+    let mut changed_by = BTreeMap::<&PathBuf, Vec<&str>>::new();
+    for (name, paths) in touched {
+        for path in paths {
+            changed_by.entry(path).or_default().push(name);
+        }
+    }
+    let overlapping: Vec<_> = changed_by
+        .iter()
+        .filter(|(_, mods)| mods.len() > 1)
+        .collect();
+
+    let mut text = String::from("# Conflict report\n\nMods, in the bundling order:\n");
+    for (name, paths) in touched {
+        text.push_str(&format!("- {}: {} files\n", name, paths.len()));
+    }
+    text.push_str(&format!(
+        "\n{} files are changed by several mods: {} of them are merged automatically, {} need a decision.\n",
+        overlapping.len(),
+        overlapping.len() - conflicts.len(),
+        conflicts.len()
+    ));
+
+    text.push_str("\n## Files changed by several mods\n");
+    for (path, mods) in &overlapping {
+        let path: &PathBuf = path;
+        text.push_str(&format!(
+            "\n### {}\n\nChanged by: {}\n\n",
+            path.to_string_lossy(),
+            mods.join(", ")
+        ));
+        match (conflicts.get(path), merged.get(path)) {
+            (Some(conflict), _) => {
+                text.push_str("Conflict, the decision would be asked:\n\n```\n");
+                for (name, node) in conflict {
+                    text.push_str(&format!("{}:\n{}", name, describe(path, node)));
+                }
+                text.push_str("```\n");
+            }
+            (None, Some(node)) => {
+                text.push_str("Merged automatically:\n\n```\n");
+                text.push_str(&describe(path, node));
+                text.push_str("```\n");
+            }
+            (None, None) => text.push_str("Changes cancel each other out.\n"),
+        }
+    }
+
+    text.push_str("\n## Files changed by one mod\n");
+    for (name, paths) in touched {
+        let own: Vec<_> = paths
+            .iter()
+            .filter(|path| changed_by[path].len() == 1)
+            .map(|path| format!("- {}", path.to_string_lossy()))
+            .collect();
+        text.push_str(&format!("\n### {}\n\n{}\n", name, own.join("\n")));
+    }
+    text
+}
+
+/// Describes the change of one file, value by value for the structured ones.
+pub fn describe(path: &Path, node: &DiffNode) -> String {
+    let mut text = String::new();
+    let path = path.to_string_lossy();
+    match node {
+        DiffNode::AddedText(_) => text.push_str(&format!("- {}: added\n", path)),
+        DiffNode::Binary(_) => text.push_str(&format!("- {}: binary file\n", path)),
+        DiffNode::ModifiedText(changeset) => text.push_str(&format!(
+            "- {}: {} lines changed\n",
+            path,
+            changeset.0.iter().filter(|change| change.is_some()).count()
+        )),
+        DiffNode::ModifiedStructured(changeset) => {
+            text.push_str(&format!(
+                "- {}: {} values changed\n",
+                path,
+                changeset.0.len()
+            ));
+            for (data_path, value) in &changeset.0 {
+                text.push_str(&format!(
+                    "    {} = {}\n",
+                    data_path,
+                    value.as_deref().unwrap_or("<removed>")
+                ));
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlaps_are_described() {
+        let touched = vec![
+            (
+                "First".to_string(),
+                vec!["a.txt".into(), "heroes/hag.png".into()],
+            ),
+            ("Second".to_string(), vec!["a.txt".into(), "b.txt".into()]),
+        ];
+        let mut merged = DiffTree::new();
+        merged.insert("a.txt".into(), DiffNode::AddedText("merged".into()));
+        let report = report(&touched, &merged, &Conflicts::new());
+        assert!(report
+            .contains("1 files are changed by several mods: 1 of them are merged automatically"));
+        assert!(report.contains("### a.txt\n\nChanged by: First, Second\n\nMerged automatically:\n\n```\n- a.txt: added\n```"));
+        assert!(report.contains("### Second\n\n- b.txt\n"));
+    }
+}
@@ -0,0 +1,435 @@
+//! Values set by user directly, bundled as one more mod after every selected one.
+
+use super::{
+    diff::{DataNode, DataTree, DiffNode, DiffTree, StructuredChangeset},
+    lock::{hash_bytes, LockedMod},
+    structures::{self, DataMap, DataPath},
+    validate::Issue,
+    BUNDLE_DIR,
+};
+use crate::{config::Config, loader::GlobalData};
+use cursive::{
+    traits::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Title of the virtual mod containing the edits.
+pub const USER_EDITS: &str = "User edits";
+/// Directory of the virtual mod in the lock file; it can't be the name of any real mod directory.
+pub const USER_EDITS_DIRECTORY: &str = "<user edits>";
+
+/// Value in the structured game file, set by user.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValueEdit {
+    /// File path, relative to the game directory.
+    pub path: PathBuf,
+    pub data_path: DataPath,
+    pub value: String,
+}
+
+impl std::fmt::Display for ValueEdit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} = {}",
+            self.path.to_string_lossy(),
+            self.data_path,
+            self.value
+        )
+    }
+}
+
+/// Splits the edits into the patch of the virtual mod, which is merged with the selected mods,
+/// and the ones which can only be applied to the merged data, since the value doesn't exist in the vanilla game.
+pub fn split(original: &DataTree, edits: &[ValueEdit]) -> (DiffTree, Vec<ValueEdit>) {
+    let mut changes: BTreeMap<PathBuf, Vec<(DataPath, Option<String>)>> = BTreeMap::new();
+    let mut late = vec![];
+    for edit in edits {
+        let in_vanilla = structures::data_type(&edit.path)
+            .zip(original.get(&edit.path).and_then(DataNode::text))
+            .and_then(|(data_type, text)| data_type.format.flatten_cached(text).ok())
+            .is_some_and(|map| map.contains_key(&edit.data_path));
+        if in_vanilla {
+            changes
+                .entry(edit.path.clone())
+                .or_default()
+                .push((edit.data_path.clone(), Some(edit.value.clone())));
+        } else {
+            debug!("[edits] {} is applied after merging", edit);
+            late.push(edit.clone());
+        }
+    }
+    let patch = changes
+        .into_iter()
+        .map(|(path, changes)| {
+            (
+                path,
+                DiffNode::ModifiedStructured(StructuredChangeset::from(changes)),
+            )
+        })
+        .collect();
+    (patch, late)
+}
+
+/// Entry for the virtual mod in the lock file, so that the changed edits are reported like the changed mods.
+pub fn locked(edits: &[ValueEdit]) -> LockedMod {
+    let mut files: BTreeMap<PathBuf, String> = BTreeMap::new();
+    for edit in edits {
+        let entry = files.entry(edit.path.clone()).or_default();
+        *entry = hash_bytes(format!("{}{}", entry, edit).as_bytes());
+    }
+    LockedMod {
+        title: USER_EDITS.into(),
+        directory: USER_EDITS_DIRECTORY.into(),
+        version: None,
+        hash: hash_bytes(
+            edits
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .as_bytes(),
+        ),
+        files,
+    }
+}
+
+/// Sets the edited values in the bundled files, taking the vanilla ones into the bundle if necessary.
+///
+/// Edits which can't be applied (e.g. the value is no longer there) are reported as issues.
+pub fn apply(original: &DataTree, modded: &mut DataTree, edits: &[ValueEdit]) -> Vec<Issue> {
+    let mut by_file: BTreeMap<&Path, Vec<&ValueEdit>> = BTreeMap::new();
+    for edit in edits {
+        by_file.entry(&edit.path).or_default().push(edit);
+    }
+    let mut issues = vec![];
+    let mut issue = |path: &Path, message: String| {
+        warn!("[edits] {:?}: {}", path, message);
+        issues.push(Issue {
+            path: path.to_owned(),
+            message: format!("{}: {}", USER_EDITS, message),
+        });
+    };
+    for (path, edits) in by_file {
+        let data_type = match structures::data_type(path) {
+            Some(data_type) => data_type,
+            None => {
+                issue(path, "not a structured file, edits are ignored".into());
+                continue;
+            }
+        };
+        let text = match modded
+            .get(path)
+            .or_else(|| original.get(path))
+            .and_then(DataNode::text)
+        {
+            Some(text) => text.to_owned(),
+            None => {
+                issue(path, "file is not found, edits are ignored".into());
+                continue;
+            }
+        };
+        let mut map = match data_type.format.flatten(&text) {
+            Ok(map) => map,
+            Err(err) => {
+                issue(path, format!("unable to parse, edits are ignored: {}", err));
+                continue;
+            }
+        };
+        let mut changed = false;
+        for edit in edits {
+            match map.get_mut(&edit.data_path) {
+                Some(value) => {
+                    info!("[edits] Setting {}", edit);
+                    *value = edit.value.clone();
+                    changed = true;
+                }
+                None => issue(path, format!("{} is no longer there", edit.data_path)),
+            }
+        }
+        if !changed {
+            continue;
+        }
+        match data_type.format.rebuild(&text, map) {
+            Ok(rebuilt) => {
+                modded.insert(path.to_owned(), DataNode::new("", rebuilt));
+            }
+            Err(err) => issue(path, format!("unable to write the edited file: {}", err)),
+        }
+    }
+    issues
+}
+
+fn edits_list(config: &Config) -> SelectView<usize> {
+    let edits = config
+        .profile()
+        .map_or(&[][..], |profile| profile.value_edits.as_slice());
+    SelectView::new()
+        .with_all(
+            edits
+                .iter()
+                .enumerate()
+                .map(|(index, edit)| (edit.to_string(), index)),
+        )
+        .on_submit(|cursive, index: &usize| {
+            let index = *index;
+            cursive.add_layer(
+                Dialog::text("Remove this edit?")
+                    .button("Remove", move |cursive| {
+                        let mut config = Config::load();
+                        let edits = &mut config.profile_mut().value_edits;
+                        if index < edits.len() {
+                            info!("[edits] Removing {}", edits.remove(index));
+                        }
+                        config.save();
+                        cursive.pop_layer();
+                        cursive.call_on_name("Value edits", |list: &mut SelectView<usize>| {
+                            *list = edits_list(&config);
+                        });
+                    })
+                    .dismiss_button("Cancel"),
+            );
+        })
+}
+
+/// The file as it will be edited: from the deployed bundle, if it is there, or from the game itself.
+fn current_file(base_path: &Path, path: &Path) -> Option<(&'static str, DataMap)> {
+    let data_type = structures::data_type(path)?;
+    let game = crate::paths::game(base_path);
+    [("bundle", game.join(BUNDLE_DIR)), ("game", game)]
+        .iter()
+        .find_map(|(source, dir)| {
+            let text = std::fs::read_to_string(dir.join(path)).ok()?;
+            match data_type.format.flatten(&text) {
+                Ok(map) => Some((*source, map)),
+                Err(err) => {
+                    warn!(
+                        "[edits] Unable to parse {:?} from {}: {}",
+                        path, source, err
+                    );
+                    None
+                }
+            }
+        })
+}
+
+fn find_values(cursive: &mut Cursive) {
+    let content = |cursive: &mut Cursive, name: &str| {
+        cursive
+            .call_on_name(name, |view: &mut EditView| view.get_content())
+            .map(|content| content.trim().to_owned())
+            .unwrap_or_default()
+    };
+    let path = PathBuf::from(content(cursive, "Edited file"));
+    let filter = content(cursive, "Edited value filter");
+    let base_path = &cursive
+        .user_data::<GlobalData>()
+        .expect("No data was set")
+        .base_path;
+    let (source, map) = match current_file(base_path, &path) {
+        Some(found) => found,
+        None => {
+            cursive.add_layer(Dialog::info(format!(
+                "{} is not a known structured file, or it can't be read",
+                path.to_string_lossy()
+            )));
+            return;
+        }
+    };
+    let values: Vec<_> = map
+        .into_iter()
+        .filter(|(data_path, value)| {
+            data_path.to_string().contains(&filter) || value.contains(&filter)
+        })
+        .collect();
+    let list =
+        SelectView::new()
+            .with_all(values.into_iter().map(|(data_path, value)| {
+                (format!("{} = {}", data_path, value), (data_path, value))
+            }))
+            .on_submit(move |cursive, (data_path, value): &(DataPath, String)| {
+                set_value(cursive, path.clone(), data_path.clone(), value)
+            });
+    cursive.add_layer(
+        Dialog::around(list.scrollable())
+            .title(format!("Values (from the {})", source))
+            .dismiss_button("Back")
+            .full_screen(),
+    );
+}
+
+fn set_value(cursive: &mut Cursive, path: PathBuf, data_path: DataPath, current: &str) {
+    cursive.add_layer(
+        Dialog::around(
+            EditView::new()
+                .content(current)
+                .with_name("Edited value")
+                .full_width(),
+        )
+        .title(data_path.to_string())
+        .button("Save", move |cursive| {
+            let value = cursive
+                .call_on_name("Edited value", |view: &mut EditView| view.get_content())
+                .unwrap_or_default()
+                .to_string();
+            let edit = ValueEdit {
+                path: path.clone(),
+                data_path: data_path.clone(),
+                value,
+            };
+            info!("[edits] Adding {}", edit);
+            let mut config = Config::load();
+            let edits = &mut config.profile_mut().value_edits;
+            edits.retain(|old| old.path != edit.path || old.data_path != edit.data_path);
+            edits.push(edit);
+            config.save();
+            // Back to the list of edits.
+            cursive.pop_layer();
+            cursive.pop_layer();
+            cursive.pop_layer();
+            cursive.call_on_name("Value edits", |list: &mut SelectView<usize>| {
+                *list = edits_list(&config);
+            });
+        })
+        .dismiss_button("Cancel"),
+    );
+}
+
+fn add(cursive: &mut Cursive) {
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "Structured file, relative to the game directory (e.g. `campaign/town/town.json`):",
+                ))
+                .child(EditView::new().with_name("Edited file").full_width())
+                .child(TextView::new("Show only values containing:"))
+                .child(
+                    EditView::new()
+                        .on_submit(|cursive, _| find_values(cursive))
+                        .with_name("Edited value filter")
+                        .full_width(),
+                ),
+        )
+        .title("Add value edit")
+        .button("Find", find_values)
+        .dismiss_button("Cancel"),
+    );
+}
+
+/// Screen listing the values set by user in the active profile, which are applied after every mod.
+pub fn screen(cursive: &mut Cursive) {
+    let config = Config::load();
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "These values are set in the bundle after all the mods are merged. Select one to remove it.",
+                ))
+                .child(edits_list(&config).with_name("Value edits").scrollable()),
+        )
+        .title(format!(
+            "Value edits (profile \"{}\")",
+            config
+                .active_profile
+                .as_deref()
+                .unwrap_or(crate::config::DEFAULT_PROFILE)
+        ))
+        .button("Add", add)
+        .dismiss_button("Close"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_vanilla_values_are_patched_by_virtual_mod() {
+        let original: DataTree = vec![(
+            "loot/loot.darkest".into(),
+            DataNode::new("", "table: .id \"A\" .chance 1\n".to_string()),
+        )]
+        .into_iter()
+        .collect();
+        let in_vanilla = ValueEdit {
+            path: "loot/loot.darkest".into(),
+            data_path: DataPath::from(&["table", "A", "chance"][..]),
+            value: "5".into(),
+        };
+        let added_by_mod = ValueEdit {
+            path: "loot/loot.darkest".into(),
+            data_path: DataPath::from(&["table", "B", "chance"][..]),
+            value: "2".into(),
+        };
+        let (patch, late) = split(&original, &[in_vanilla.clone(), added_by_mod.clone()]);
+        assert_eq!(late, [added_by_mod]);
+        assert!(matches!(
+            &patch[Path::new("loot/loot.darkest")],
+            DiffNode::ModifiedStructured(changeset)
+                if changeset.0.len() == 1 && changeset.0[&in_vanilla.data_path].as_deref() == Some("5")
+        ));
+    }
+
+    #[test]
+    fn edits_are_applied_to_bundled_and_vanilla_files() {
+        let original: DataTree = vec![
+            (
+                "loot/loot.darkest".into(),
+                DataNode::new("", "table: .id \"A\" .chance 1\n".to_string()),
+            ),
+            (
+                "monsters/a/a.info.darkest".into(),
+                DataNode::new("", "hp: .id \"base\" .value 10\n".to_string()),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut modded: DataTree = vec![(
+            "monsters/a/a.info.darkest".into(),
+            DataNode::new("", "hp: .id \"base\" .value 20\n".to_string()),
+        )]
+        .into_iter()
+        .collect();
+        let edits = [
+            ValueEdit {
+                path: "loot/loot.darkest".into(),
+                data_path: DataPath::from(&["table", "A", "chance"][..]),
+                value: "5".into(),
+            },
+            ValueEdit {
+                path: "monsters/a/a.info.darkest".into(),
+                data_path: DataPath::from(&["hp", "base", "value"][..]),
+                value: "30".into(),
+            },
+            ValueEdit {
+                path: "monsters/a/a.info.darkest".into(),
+                data_path: DataPath::from(&["hp", "base", "regen"][..]),
+                value: "1".into(),
+            },
+        ];
+        let issues = apply(&original, &mut modded, &edits);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "monsters/a/a.info.darkest: User edits: hp/base/regen is no longer there"
+        );
+        let value = |path: &str, data_path: &[&str]| {
+            let text = modded[Path::new(path)].text().unwrap();
+            let data_path: DataPath = data_path.iter().map(|part| part.to_string()).collect();
+            structures::Format::Darkest.flatten(text).unwrap()[&data_path].clone()
+        };
+        assert_eq!(value("loot/loot.darkest", &["table", "A", "chance"]), "5");
+        assert_eq!(
+            value("monsters/a/a.info.darkest", &["hp", "base", "value"]),
+            "30"
+        );
+    }
+}
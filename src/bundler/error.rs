@@ -7,6 +7,10 @@ pub enum BundlerError {
     Extraction(#[from] ExtractionError),
     #[error("Error while deploying bundle")]
     Deployment(#[from] DeploymentError),
+    #[error("Error while saving resolution session")]
+    Session(#[from] SessionError),
+    #[error("Unable to write the report to {1}")]
+    Report(#[source] std::io::Error, PathBuf),
 }
 
 #[derive(Debug, Error)]
@@ -28,6 +32,12 @@ pub enum DeploymentError {
     Io(#[source] std::io::Error, PathBuf),
     #[error("User chose not to overwrite existing directory")]
     AlreadyExists,
+    #[error("Directory to deploy the bundle to is not set")]
+    NoTargetDirectory,
+    #[error("Bundle can't be copied to {0}, since it is deployed there")]
+    TargetIsBundle(PathBuf),
+    #[error("Unable to write the bundle archive")]
+    Archive(#[from] zip::result::ZipError),
 }
 
 impl DeploymentError {
@@ -36,3 +46,58 @@ impl DeploymentError {
         |err| Self::Io(err, path)
     }
 }
+
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error("IO error encountered on path {1}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("Malformed lock file {1}")]
+    Json(#[source] serde_json::Error, PathBuf),
+    #[error("Bundle data can't be stored in lock file: {0}")]
+    Serialization(#[source] serde_json::Error),
+}
+
+impl LockError {
+    pub fn from_io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        |err| Self::Io(err, path)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("IO error encountered on path {1}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("Malformed session file {1}")]
+    Json(#[source] serde_json::Error, PathBuf),
+    #[error("Resolutions can't be stored in session file: {0}")]
+    Serialization(#[source] serde_json::Error),
+}
+
+impl SessionError {
+    pub fn from_io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        |err| Self::Io(err, path)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PresetError {
+    #[error("IO error encountered on path {1}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("Malformed preset file {1}")]
+    Json(#[source] serde_json::Error, PathBuf),
+    #[error("Preset can't be stored in file: {0}")]
+    Serialization(#[source] serde_json::Error),
+    #[error("Unable to read the lock file of the deployed bundle")]
+    Lock(#[source] LockError),
+    #[error("Malformed conflict spreadsheet {1}: {0}")]
+    Csv(String, PathBuf),
+}
+
+impl PresetError {
+    pub fn from_io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        |err| Self::Io(err, path)
+    }
+}
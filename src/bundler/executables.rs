@@ -0,0 +1,117 @@
+//! Files which are not the game data, but are run by the game or next to it: libraries, programs, scripts
+//! and shaders some mods ship. They are left out of the bundle, unless user has explicitly allowed them.
+
+use super::{diff::DataTree, lock::LockedMod, validate::Issue};
+use log::*;
+use std::path::Path;
+
+/// Globs of the executable files, matched against the paths relative to the mod directory.
+const EXECUTABLE_GLOBS: &[&str] = &[
+    "**/*.dll",
+    "**/*.exe",
+    "**/*.so",
+    "**/*.dylib",
+    "**/*.bat",
+    "**/*.cmd",
+    "**/*.ps1",
+    "**/*.sh",
+    "shaders/**",
+    "**/*.fx",
+    "**/*.hlsl",
+    "**/*.glsl",
+];
+
+pub fn is_executable(path: &Path) -> bool {
+    EXECUTABLE_GLOBS
+        .iter()
+        .any(|glob| crate::glob::matches(glob, path))
+}
+
+/// Removes the executable files from the mod's content, reporting every one of them.
+pub fn drop_from(content: &mut DataTree, mod_name: &str) -> Vec<Issue> {
+    let mut dropped = vec![];
+    content.retain(|path, _| {
+        if !is_executable(path) {
+            return true;
+        }
+        warn!("Mod {}: executable file {:?} is left out", mod_name, path);
+        dropped.push(Issue {
+            path: path.clone(),
+            message: format!(
+                "Executable file from mod \"{}\" is left out; allow executable files in the options to bundle it",
+                mod_name
+            ),
+        });
+        false
+    });
+    dropped
+}
+
+/// Executable files in the bundle, each with the titles of the mods it comes from.
+pub fn listed(bundle: &DataTree, mods: &[LockedMod]) -> Vec<String> {
+    bundle
+        .keys()
+        .filter(|path| is_executable(path))
+        .map(|path| {
+            let titles: Vec<_> = mods
+                .iter()
+                .filter(|the_mod| the_mod.files.contains_key(path))
+                .map(|the_mod| the_mod.title.as_str())
+                .collect();
+            format!("- {} (from {})", path.to_string_lossy(), titles.join(", "))
+        })
+        .collect()
+}
+
+/// Warns that the bundle contains executable files, and waits for user to acknowledge it.
+pub fn confirm(sink: &mut cursive::CbSink, bundle: &DataTree, mods: &[LockedMod]) {
+    let listed = listed(bundle, mods);
+    if listed.is_empty() {
+        return;
+    }
+    warn!("Bundling executable files:\n{}", listed.join("\n"));
+    super::show_and_wait(
+        sink,
+        "Bundle contains executable files",
+        format!(
+            "These files are not game data, but programs, libraries or shaders run together with the game.
+They are copied into the bundle, since executable files are allowed in the options;
+make sure you trust the mods shipping them:
+{}",
+            listed.join("\n")
+        ),
+        "Deploy anyway",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::DataNode;
+    use std::path::PathBuf;
+
+    #[test]
+    fn executable_files_are_left_out() {
+        let mut content: DataTree = [
+            "heroes/hag/hag.info.darkest",
+            "heroes/hag/hag.png",
+            "bin/hook.dll",
+            "Shaders/glow.psh",
+            "scripts/patch.EXE",
+        ]
+        .iter()
+        .map(|path| (PathBuf::from(path), DataNode::new("", path.to_string())))
+        .collect();
+        let dropped = drop_from(&mut content, "Hag");
+        let dropped: Vec<_> = dropped.iter().map(|issue| issue.path.clone()).collect();
+        assert_eq!(
+            dropped,
+            [
+                PathBuf::from("Shaders/glow.psh"),
+                "bin/hook.dll".into(),
+                "scripts/patch.EXE".into()
+            ]
+        );
+        assert_eq!(content.len(), 2);
+    }
+}
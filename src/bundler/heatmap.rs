@@ -0,0 +1,139 @@
+use super::diff::{Conflict, Conflicts, DiffNode};
+use cursive::{
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+};
+use log::*;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Amount of conflicts inside the directory, including its subdirectories.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct DirStats {
+    pub files: usize,
+    /// Conflicting lines or values; whole-file conflicts count as one.
+    pub items: usize,
+}
+
+/// Number of separate decisions the conflict might require.
+fn conflict_size(conflict: &Conflict) -> usize {
+    let mut lines = BTreeSet::new();
+    let mut values = BTreeSet::new();
+    for (_, node) in conflict {
+        match node {
+            DiffNode::ModifiedText(changeset) => lines.extend(
+                changeset
+                    .0
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, change)| change.is_some())
+                    .map(|(index, _)| index),
+            ),
+            DiffNode::ModifiedStructured(changeset) => values.extend(changeset.0.keys()),
+            DiffNode::Binary(_) | DiffNode::AddedText(_) => return 1,
+        }
+    }
+    (lines.len() + values.len()).max(1)
+}
+
+/// Conflict statistics for every directory containing conflicting files.
+pub fn heatmap(conflicts: &Conflicts) -> BTreeMap<PathBuf, DirStats> {
+    let mut map = BTreeMap::<PathBuf, DirStats>::new();
+    for (path, conflict) in conflicts {
+        let size = conflict_size(conflict);
+        for dir in path.ancestors().skip(1) {
+            let stats = map.entry(dir.to_owned()).or_default();
+            stats.files += 1;
+            stats.items += size;
+        }
+    }
+    map
+}
+
+fn severity_color(stats: DirStats) -> Color {
+    match stats.items {
+        0..=9 => Color::Dark(BaseColor::Green),
+        10..=99 => Color::Dark(BaseColor::Yellow),
+        _ => Color::Light(BaseColor::Red),
+    }
+}
+
+fn render(map: &BTreeMap<PathBuf, DirStats>) -> StyledString {
+    let mut text = StyledString::new();
+    for (dir, stats) in map {
+        let depth = dir.components().count();
+        let name = match dir.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None if dir == Path::new("") => "<game data>".into(),
+            None => dir.to_string_lossy(),
+        };
+        text.append_plain(format!("{}{} ", "  ".repeat(depth), name));
+        text.append_styled(
+            format!("{} files, {} conflicts\n", stats.files, stats.items),
+            severity_color(*stats),
+        );
+    }
+    text
+}
+
+/// Shows where the conflicts are concentrated and waits for user to start resolving them.
+pub fn show(sink: &mut cursive::CbSink, conflicts: &Conflicts) {
+    let map = heatmap(conflicts);
+    for (dir, stats) in &map {
+        info!("[heatmap] {:?}: {:?}", dir, stats);
+    }
+    super::show_and_wait(
+        sink,
+        "Conflicts by directory",
+        render(&map),
+        "Resolve conflicts",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::{LineChange, LinesChangeset};
+
+    #[test]
+    fn aggregates_to_parents() {
+        let text = |lines: Vec<Option<LineChange>>| {
+            DiffNode::ModifiedText(LinesChangeset(lines, String::new()))
+        };
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            "heroes/crusader/crusader.info.darkest".into(),
+            vec![
+                (
+                    "a".into(),
+                    text(vec![Some(LineChange::Removed), None, None]),
+                ),
+                (
+                    "b".into(),
+                    text(vec![
+                        Some(LineChange::Removed),
+                        None,
+                        Some(LineChange::Removed),
+                    ]),
+                ),
+            ],
+        );
+        conflicts.insert(
+            "heroes/crusader/crusader.png".into(),
+            vec![
+                ("a".into(), DiffNode::Binary("a.png".into())),
+                ("b".into(), DiffNode::Binary("b.png".into())),
+            ],
+        );
+        let map = heatmap(&conflicts);
+        assert_eq!(
+            map[Path::new("heroes/crusader")],
+            DirStats { files: 2, items: 3 }
+        );
+        assert_eq!(map[Path::new("heroes")], DirStats { files: 2, items: 3 });
+        assert_eq!(map[Path::new("")], DirStats { files: 2, items: 3 });
+        assert_eq!(map.len(), 3);
+    }
+}
@@ -0,0 +1,260 @@
+use super::diff::{Conflict, DiffNode};
+use cursive::{
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+};
+use difference::{Changeset, Difference};
+use std::collections::{BTreeMap, BTreeSet};
+
+const KEY_COLOR: Color = Color::Dark(BaseColor::Cyan);
+const VALUE_COLOR: Color = Color::Dark(BaseColor::Yellow);
+const COMMENT_COLOR: Color = Color::Light(BaseColor::Black);
+const DIFFERING_COLOR: Color = Color::Light(BaseColor::Red);
+
+/// Shown in place of the value the mod removes.
+const REMOVED: &str = "<removed>";
+
+/// Values of one entry as changed by every mod, in the order mods are bundled.
+#[derive(Default)]
+struct EntryChanges<'a> {
+    fields: BTreeSet<&'a str>,
+    mods: Vec<(&'a str, BTreeMap<&'a str, Option<&'a str>>)>,
+}
+
+impl EntryChanges<'_> {
+    /// Whether every mod changing the entry sets it in the same way, e.g. for the skill levels
+    /// the mods don't disagree on.
+    fn is_identical(&self) -> bool {
+        self.mods.len() > 1 && self.mods.windows(2).all(|pair| pair[0].1 == pair[1].1)
+    }
+}
+
+/// Renders the changes every mod makes to the conflicting darkest entries as the raw entry lines,
+/// one line per mod, with the fields aligned in columns so that the differing values are one under another.
+///
+/// Unless `show_identical` is set, the entries every mod agrees on are collapsed into a single comment line.
+pub fn darkest_conflict(conflict: &Conflict, show_identical: bool) -> StyledString {
+    let mut entries = BTreeMap::<(&str, &str), EntryChanges>::new();
+    for (name, node) in conflict {
+        let changeset = match node {
+            DiffNode::ModifiedStructured(changeset) => changeset,
+            _ => continue,
+        };
+        for (path, value) in &changeset.0 {
+            let (key, id, field) = match &path[..] {
+                [key, id, field] => (key.as_str(), id.as_str(), field.as_str()),
+                _ => continue,
+            };
+            let entry = entries.entry((key, id)).or_default();
+            entry.fields.insert(field);
+            if entry.mods.last().map(|(last, _)| *last) != Some(name.as_str()) {
+                entry.mods.push((name, BTreeMap::new()));
+            }
+            if let Some((_, values)) = entry.mods.last_mut() {
+                values.insert(field, value.as_deref());
+            }
+        }
+    }
+
+    let mut text = StyledString::new();
+    for ((key, id), entry) in entries {
+        if !show_identical && entry.is_identical() {
+            text.append_styled(
+                format!("// {} {}: same in every mod\n", key, id),
+                COMMENT_COLOR,
+            );
+            continue;
+        }
+        text.append_styled(format!("// {} {}\n", key, id), COMMENT_COLOR);
+        let widths: BTreeMap<_, _> = entry
+            .fields
+            .iter()
+            .map(|field| {
+                let width = entry
+                    .mods
+                    .iter()
+                    .filter_map(|(_, values)| values.get(field))
+                    .map(|value| value.unwrap_or(REMOVED).len())
+                    .max()
+                    .unwrap_or_default();
+                (*field, width)
+            })
+            .collect();
+        for (name, values) in &entry.mods {
+            text.append_styled(format!("{}:", key), KEY_COLOR);
+            for (field, width) in &widths {
+                match values.get(field) {
+                    Some(value) => {
+                        text.append_styled(format!(" .{} ", field), KEY_COLOR);
+                        match value {
+                            Some(value) => {
+                                text.append_styled(format!("{:<1$}", value, width), VALUE_COLOR)
+                            }
+                            None => {
+                                text.append_styled(format!("{:<1$}", REMOVED, width), COMMENT_COLOR)
+                            }
+                        }
+                    }
+                    None => text.append_plain(" ".repeat(field.len() + width + 3)),
+                }
+            }
+            text.append_styled(format!("  // {}\n", name), COMMENT_COLOR);
+        }
+    }
+    text
+}
+
+/// Indices of the space-separated tokens of `value` which have no counterpart in `other`.
+fn added_tokens(other: &str, value: &str) -> BTreeSet<usize> {
+    let mut added = BTreeSet::new();
+    let mut index = 0;
+    for diff in Changeset::new(other, value, " ").diffs {
+        match diff {
+            Difference::Same(tokens) => index += tokens.split(' ').count(),
+            Difference::Add(tokens) => {
+                let count = tokens.split(' ').count();
+                added.extend(index..index + count);
+                index += count;
+            }
+            Difference::Rem(_) => {}
+        }
+    }
+    added
+}
+
+/// Renders the conflicting variants of the value, e.g. the long effect lists or the localized strings,
+/// so that they can be compared token by token: the tokens which differ from the other variants are red,
+/// and the ones which only differ from the `vanilla` value are yellow.
+pub fn token_diff(variants: &[&str], vanilla: Option<&str>) -> Vec<StyledString> {
+    variants
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let differing: BTreeSet<_> = variants
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .flat_map(|(_, other)| added_tokens(other, value))
+                .collect();
+            let changed = vanilla
+                .map(|vanilla| added_tokens(vanilla, value))
+                .unwrap_or_default();
+            let mut text = StyledString::new();
+            for (index, token) in value.split(' ').enumerate() {
+                if index > 0 {
+                    text.append_plain(" ");
+                }
+                if differing.contains(&index) {
+                    text.append_styled(token, DIFFERING_COLOR);
+                } else if changed.contains(&index) {
+                    text.append_styled(token, VALUE_COLOR);
+                } else {
+                    text.append_plain(token);
+                }
+            }
+            text
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::{diff::StructuredChangeset, structures::DataPath};
+    use cursive::theme::Style;
+
+    #[test]
+    fn fields_are_aligned() {
+        let changes = |values: Vec<(&str, Option<&str>)>| {
+            DiffNode::ModifiedStructured(StructuredChangeset(
+                values
+                    .into_iter()
+                    .map(|(field, value)| {
+                        (
+                            DataPath::from(&["hp", "base", field][..]),
+                            value.map(String::from),
+                        )
+                    })
+                    .collect(),
+            ))
+        };
+        let conflict = vec![
+            (
+                "First".to_string(),
+                changes(vec![("value", Some("20")), ("regen", Some("1"))]),
+            ),
+            ("Second".to_string(), changes(vec![("value", Some("125"))])),
+            ("Third".to_string(), changes(vec![("regen", None)])),
+        ];
+        assert_eq!(
+            darkest_conflict(&conflict, false).source(),
+            "// hp base\n\
+             hp: .regen 1         .value 20   // First\n\
+             hp:                  .value 125  // Second\n\
+             hp: .regen <removed>             // Third\n"
+        );
+    }
+
+    #[test]
+    fn identical_levels_are_collapsed() {
+        let level = |level: &str, power: &str| {
+            (
+                DataPath::from(&["combat_skill", level, "dmg"][..]),
+                Some(power.to_string()),
+            )
+        };
+        let conflict = vec![
+            (
+                "First".to_string(),
+                DiffNode::ModifiedStructured(StructuredChangeset(
+                    vec![level("0", "0%"), level("1", "5%")]
+                        .into_iter()
+                        .collect(),
+                )),
+            ),
+            (
+                "Second".to_string(),
+                DiffNode::ModifiedStructured(StructuredChangeset(
+                    vec![level("0", "0%"), level("1", "10%")]
+                        .into_iter()
+                        .collect(),
+                )),
+            ),
+        ];
+        assert_eq!(
+            darkest_conflict(&conflict, false).source(),
+            "// combat_skill 0: same in every mod\n\
+             // combat_skill 1\n\
+             combat_skill: .dmg 5%   // First\n\
+             combat_skill: .dmg 10%  // Second\n"
+        );
+        assert!(darkest_conflict(&conflict, true)
+            .source()
+            .contains("combat_skill: .dmg 0%  // Second"));
+    }
+
+    #[test]
+    fn differing_tokens_are_highlighted() {
+        let vanilla = ".effect \"Stun 1\" \"Bleed 1\" .chance 100%";
+        let variants = [
+            ".effect \"Stun 1\" \"Bleed 2\" .chance 100%",
+            ".effect \"Stun 1\" \"Bleed 1\" .chance 90%",
+        ];
+        let styled = |text: &StyledString, color: Color| {
+            text.spans()
+                .filter(|span| *span.attr == Style::from(color))
+                .map(|span| span.content.to_owned())
+                .collect::<Vec<_>>()
+        };
+        let rendered = token_diff(&variants, Some(vanilla));
+        assert_eq!(rendered[0].source(), variants[0]);
+        assert_eq!(styled(&rendered[0], DIFFERING_COLOR), ["2\"", "100%"]);
+        assert_eq!(styled(&rendered[1], DIFFERING_COLOR), ["1\"", "90%"]);
+        assert!(styled(&rendered[1], VALUE_COLOR).is_empty());
+
+        // Tokens the variants agree on are still marked as changed from the vanilla value.
+        let rendered = token_diff(&[variants[0], variants[0]], Some(vanilla));
+        assert_eq!(styled(&rendered[1], VALUE_COLOR), ["2\""]);
+        assert!(styled(&rendered[1], DIFFERING_COLOR).is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+use super::{
+    diff::{DataNode, DataTree},
+    structures::localization::StringsTable,
+    validate::{is_string_table, Issue},
+};
+use log::*;
+
+/// Leaves only the target language in the bundled string tables,
+/// filling the strings missing in it from the primary language, so that they are not blank in game.
+pub fn restrict(tree: &mut DataTree, language: &str) -> Vec<Issue> {
+    let mut issues = vec![];
+    for (path, node) in tree.iter_mut().filter(|(path, _)| is_string_table(path)) {
+        let table = match node.text().map(StringsTable::parse) {
+            Some(Ok(table)) => table,
+            Some(Err(err)) => {
+                issues.push(Issue {
+                    path: path.clone(),
+                    message: format!(
+                        "Unable to parse string table, keeping every language: {}",
+                        err
+                    ),
+                });
+                continue;
+            }
+            None => {
+                warn!("[language] String table {:?} is not a text file", path);
+                continue;
+            }
+        };
+        let (table, fallbacks) = table.only_language(language);
+        info!(
+            "[language] {:?}: leaving only {}, {} strings taken from the primary language",
+            path, language, fallbacks
+        );
+        *node = DataNode::new(node.source(), table.render());
+    }
+    issues
+}
@@ -0,0 +1,288 @@
+use super::{
+    diff::{DataNodeContent, DataTree, DiffTree},
+    error::{DeploymentError, LockError},
+    validate::Issue,
+};
+use crate::loader::Mod;
+use log::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+pub const LOCK_FILE: &str = "bundle.lock.json";
+/// File in the game directory with the revision the game was built from, changed by every game update.
+const GAME_BUILD_FILE: &str = "svn_revision.txt";
+
+/// Selected mod, as it was at the moment of bundling.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockedMod {
+    pub title: String,
+    /// Name of the mod directory, i.e. Workshop ID for Workshop mods.
+    pub directory: String,
+    pub version: Option<String>,
+    pub hash: String,
+    /// Hash of every file in the mod, by its path relative to the mod directory.
+    #[serde(default)]
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl LockedMod {
+    pub fn new(the_mod: &Mod) -> std::io::Result<Self> {
+        let (hash, files) = hash_dir(&the_mod.path)?;
+        Ok(Self {
+            title: the_mod.name().to_owned(),
+            directory: the_mod
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            version: the_mod.version(),
+            hash,
+            files,
+        })
+    }
+}
+
+/// Everything necessary to rebuild the bundle and to check that it was rebuilt exactly.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LockFile {
+    pub tool_version: String,
+    pub mods: Vec<LockedMod>,
+    /// Decisions made for every conflicting file.
+    pub resolutions: DiffTree,
+    /// Hashes of the conflicting changes the decisions were made for.
+    #[serde(default)]
+    pub inputs: BTreeMap<PathBuf, String>,
+    /// Hashes of every bundled file.
+    pub output: BTreeMap<PathBuf, String>,
+    /// Build of the game the bundle was made against, if the game has the build metadata.
+    #[serde(default)]
+    pub game_build: Option<String>,
+    /// DLC directories which were left out of the baseline.
+    #[serde(default)]
+    pub excluded_dlc: Vec<String>,
+}
+
+/// Build of the game in the given directory, i.e. the first line of its `svn_revision.txt`, if there is one.
+pub fn game_build(game_path: &Path) -> Option<String> {
+    let path = game_path.join(GAME_BUILD_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(String::from),
+        Err(err) => {
+            info!(
+                "Game build metadata is not available in {:?}: {}",
+                path, err
+            );
+            None
+        }
+    }
+}
+
+impl LockFile {
+    pub fn new(
+        mods: Vec<LockedMod>,
+        resolutions: DiffTree,
+        output: BTreeMap<PathBuf, String>,
+    ) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").into(),
+            mods,
+            resolutions,
+            inputs: BTreeMap::new(),
+            output,
+            game_build: None,
+            excluded_dlc: vec![],
+        }
+    }
+
+    pub fn with_inputs(self, inputs: BTreeMap<PathBuf, String>) -> Self {
+        Self { inputs, ..self }
+    }
+
+    pub fn with_game_build(self, game_build: Option<String>) -> Self {
+        Self { game_build, ..self }
+    }
+
+    pub fn with_excluded_dlc(self, excluded_dlc: Vec<String>) -> Self {
+        Self {
+            excluded_dlc,
+            ..self
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, LockError> {
+        let text = std::fs::read_to_string(path).map_err(LockError::from_io(path))?;
+        serde_json::from_str(&text).map_err(|err| LockError::Json(err, path.into()))
+    }
+
+    /// Fails if some paths can't be represented in JSON, i.e. are not valid Unicode.
+    pub fn write(&self, bundle_path: &Path) -> Result<(), LockError> {
+        let path = bundle_path.join(LOCK_FILE);
+        info!("Writing lock file to {:?}", path);
+        let text = serde_json::to_string_pretty(self).map_err(LockError::Serialization)?;
+        std::fs::write(&path, text).map_err(LockError::from_io(&path))
+    }
+
+    /// Lists everything which differs between the locked bundle and the rebuilt one.
+    pub fn differences(&self, rebuilt: &LockFile) -> Vec<Issue> {
+        let mut issues = vec![];
+        if self.tool_version != rebuilt.tool_version {
+            issues.push(Issue {
+                path: LOCK_FILE.into(),
+                message: format!(
+                    "Bundle was locked by bundler version {}, current version is {}",
+                    self.tool_version, rebuilt.tool_version
+                ),
+            });
+        }
+        if let (Some(locked), Some(current)) = (&self.game_build, &rebuilt.game_build) {
+            if locked != current {
+                issues.push(Issue {
+                    path: LOCK_FILE.into(),
+                    message: format!(
+                        "Bundle was made against game build {}, current build is {}",
+                        locked, current
+                    ),
+                });
+            }
+        }
+        if self.excluded_dlc != rebuilt.excluded_dlc {
+            issues.push(Issue {
+                path: LOCK_FILE.into(),
+                message: format!(
+                    "Bundle was made without DLC {:?}, now without {:?}",
+                    self.excluded_dlc, rebuilt.excluded_dlc
+                ),
+            });
+        }
+        for locked in &self.mods {
+            let message = match rebuilt
+                .mods
+                .iter()
+                .find(|m| m.directory == locked.directory)
+            {
+                None => format!("Mod \"{}\" is no longer available", locked.title),
+                Some(current) if current.hash != locked.hash => format!(
+                    "Mod \"{}\" was changed (version {} -> {})",
+                    locked.title,
+                    locked.version.as_deref().unwrap_or("unknown"),
+                    current.version.as_deref().unwrap_or("unknown"),
+                ),
+                Some(_) => continue,
+            };
+            issues.push(Issue {
+                path: locked.directory.clone().into(),
+                message,
+            });
+        }
+        for (path, hash) in &self.output {
+            let message = match rebuilt.output.get(path) {
+                None => "File is no longer in the bundle",
+                Some(current) if current != hash => "File content differs from the locked one",
+                Some(_) => continue,
+            };
+            issues.push(Issue {
+                path: path.clone(),
+                message: message.into(),
+            });
+        }
+        for path in rebuilt.output.keys() {
+            if !self.output.contains_key(path) {
+                issues.push(Issue {
+                    path: path.clone(),
+                    message: "File is new in the bundle".into(),
+                });
+            }
+        }
+        issues
+    }
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Hash of every file in the directory, together with their relative paths,
+/// and the separate hashes of each file.
+pub fn hash_dir(path: &Path) -> std::io::Result<(String, BTreeMap<PathBuf, String>)> {
+    fn walk(
+        base: &Path,
+        dir: &Path,
+        hasher: &mut Sha256,
+        files: &mut BTreeMap<PathBuf, String>,
+    ) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                walk(base, &entry, hasher, files)?;
+            } else {
+                let rel = entry.strip_prefix(base).unwrap_or(&entry);
+                let content = std::fs::read(&entry)?;
+                hasher.update(rel.to_string_lossy().as_bytes());
+                hasher.update(&content);
+                files.insert(rel.to_owned(), hash_bytes(&content));
+            }
+        }
+        Ok(())
+    }
+    let mut hasher = Sha256::new();
+    let mut files = BTreeMap::new();
+    walk(path, path, &mut hasher, &mut files)?;
+    Ok((to_hex(&hasher.finalize()), files))
+}
+
+/// Hashes of every file as it will be written to disk.
+pub fn hash_tree(tree: &DataTree) -> Result<BTreeMap<PathBuf, String>, DeploymentError> {
+    tree.iter()
+        .map(|(path, node)| {
+            let hash = match node.content() {
+                DataNodeContent::Text(text) => hash_bytes(text.as_bytes()),
+                DataNodeContent::Binary => {
+                    let source = node.source();
+                    hash_bytes(&std::fs::read(source).map_err(DeploymentError::from_io(source))?)
+                }
+            };
+            Ok((path.clone(), hash))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_paths_round_trip() {
+        let output = vec![
+            (
+                PathBuf::from("localization/русский.string_table.xml"),
+                hash_bytes(b"a"),
+            ),
+            (
+                PathBuf::from("heroes/英雄/英雄.info.darkest"),
+                hash_bytes(b"b"),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let lock = LockFile::new(vec![], DiffTree::new(), output);
+        let text = serde_json::to_string(&lock).unwrap();
+        let read: LockFile = serde_json::from_str(&text).unwrap();
+        assert_eq!(read.output, lock.output);
+        assert!(lock.differences(&read).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use super::{
+    diff::{DataTree, DiffNode, DiffTree},
+    structures::localization::{StringsTable, PRIMARY_LANGUAGE},
+    validate::{is_string_table, segments},
+};
+use log::*;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Localized names of the game entities, shown next to their internal ids,
+/// since users recognize "Runaway" much faster than `runaway`.
+#[derive(Debug, Default)]
+pub struct DisplayNames {
+    table: StringsTable,
+    language: String,
+    /// DLC which provides the baseline version of the file, for the files not coming from the vanilla game.
+    origins: BTreeMap<PathBuf, String>,
+}
+
+impl DisplayNames {
+    /// Collects the vanilla string tables and the ones added by mods without conflicts.
+    pub fn new(original: &DataTree, merged: &DiffTree, language: Option<&str>) -> Self {
+        let mut table = StringsTable::default();
+        let texts = original
+            .iter()
+            .filter_map(|(path, node)| Some((path, node.text()?)))
+            .chain(merged.iter().filter_map(|(path, node)| match node {
+                DiffNode::AddedText(text) => Some((path, text.as_str())),
+                _ => None,
+            }));
+        for (path, text) in texts.filter(|(path, _)| is_string_table(path)) {
+            match StringsTable::parse(text) {
+                Ok(parsed) => table.extend(parsed),
+                Err(err) => debug!("[names] Skipping string table {:?}: {}", path, err),
+            }
+        }
+        Self {
+            table,
+            language: language.unwrap_or(PRIMARY_LANGUAGE).to_owned(),
+            origins: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_origins(self, origins: BTreeMap<PathBuf, String>) -> Self {
+        Self { origins, ..self }
+    }
+
+    /// Display name of the hero class, in the bundle language if it has one.
+    pub fn hero(&self, class: &str) -> Option<&str> {
+        let id = format!("hero_class_name_{}", class);
+        self.table
+            .get(&self.language, &id)
+            .or_else(|| self.table.get(PRIMARY_LANGUAGE, &id))
+    }
+
+    /// File path as it is shown to the user, with the name of the hero it belongs to, if any,
+    /// and the DLC its baseline comes from.
+    pub fn describe(&self, path: &Path) -> String {
+        let shown = path.to_string_lossy().into_owned();
+        let shown = match segments(path).as_slice() {
+            [heroes, class, _, ..] if heroes == "heroes" => match self.hero(class) {
+                Some(name) => format!("{} (hero: {})", shown, name),
+                None => shown,
+            },
+            _ => shown,
+        };
+        match self.origins.get(path) {
+            Some(dlc) => format!("{} (baseline from DLC {})", shown, dlc),
+            None => shown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::DataNode;
+
+    #[test]
+    fn hero_names() {
+        let original: DataTree = vec![(
+            "localization/heroes.string_table.xml".into(),
+            DataNode::new(
+                "",
+                r#"<root><language id="english">
+                    <entry id="hero_class_name_crusader">Crusader</entry>
+                </language></root>"#
+                    .to_string(),
+            ),
+        )]
+        .into_iter()
+        .collect();
+        let merged: DiffTree = vec![(
+            "localization/runaway.string_table.xml".into(),
+            DiffNode::AddedText(
+                r#"<root><language id="english">
+                    <entry id="hero_class_name_runaway">Runaway</entry>
+                </language></root>"#
+                    .into(),
+            ),
+        )]
+        .into_iter()
+        .collect();
+        let names = DisplayNames::new(&original, &merged, Some("russian"));
+        assert_eq!(
+            names.describe(Path::new("heroes/runaway/runaway.info.darkest")),
+            "heroes/runaway/runaway.info.darkest (hero: Runaway)"
+        );
+        assert_eq!(names.hero("crusader"), Some("Crusader"));
+        assert_eq!(
+            names.describe(Path::new("heroes/unknown/unknown.info.darkest")),
+            "heroes/unknown/unknown.info.darkest"
+        );
+        assert_eq!(
+            names.describe(Path::new("heroes/crusader.png")),
+            "heroes/crusader.png"
+        );
+        let names = names.with_origins(
+            vec![(
+                "heroes/flagellant/flagellant.info.darkest".into(),
+                "flagellant".into(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            names.describe(Path::new("heroes/flagellant/flagellant.info.darkest")),
+            "heroes/flagellant/flagellant.info.darkest (baseline from DLC flagellant)"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use super::{
+    diff::{DataNode, DataTree},
+    lock::LockedMod,
+    structures::{DataMap, Format, StructureError},
+    validate::{segments, Issue},
+};
+use log::*;
+use std::path::{Path, PathBuf};
+
+const INFO_SUFFIX: &str = ".info.darkest";
+const OVERRIDE_SUFFIX: &str = ".override.darkest";
+
+/// Hero defined both by the info file and by the override file, coming from different mods.
+#[derive(Debug, PartialEq, Eq)]
+struct Overlap {
+    class: String,
+    info: PathBuf,
+    override_path: PathBuf,
+}
+
+fn hero_files(class: &str) -> (PathBuf, PathBuf) {
+    let dir = Path::new("heroes").join(class);
+    (
+        dir.join(format!("{}{}", class, INFO_SUFFIX)),
+        dir.join(format!("{}{}", class, OVERRIDE_SUFFIX)),
+    )
+}
+
+/// Hero class, if the file is its info or override.
+fn hero_class(path: &Path) -> Option<String> {
+    match segments(path).as_slice() {
+        [heroes, class, file] if heroes == "heroes" => {
+            let stem = file
+                .strip_suffix(INFO_SUFFIX)
+                .or_else(|| file.strip_suffix(OVERRIDE_SUFFIX))?;
+            Some(class.clone()).filter(|class| class == stem)
+        }
+        _ => None,
+    }
+}
+
+fn sources<'a>(path: &Path, mods: &'a [LockedMod]) -> Vec<&'a str> {
+    mods.iter()
+        .filter(|the_mod| the_mod.files.contains_key(path))
+        .map(|the_mod| the_mod.title.as_str())
+        .collect()
+}
+
+/// Finds the heroes which bundle defines by both files shipped by different mods.
+///
+/// The override coming on top of the vanilla info file is how the override mods work, and the files
+/// coming from the same mods are combined on purpose, so neither is an overlap.
+fn overlaps(original: &DataTree, modded: &DataTree, mods: &[LockedMod]) -> Vec<Overlap> {
+    let mut classes: Vec<_> = modded.keys().filter_map(|path| hero_class(path)).collect();
+    classes.dedup();
+    classes
+        .into_iter()
+        .filter_map(|class| {
+            let (info, override_path) = hero_files(&class);
+            let exists = |path: &PathBuf| modded.contains_key(path) || original.contains_key(path);
+            if !exists(&info) || !exists(&override_path) {
+                return None;
+            }
+            let (info_from, override_from) = (sources(&info, mods), sources(&override_path, mods));
+            if info_from.is_empty() || override_from.is_empty() {
+                debug!(
+                    "[overrides] {}: one of the files comes from the game itself",
+                    class
+                );
+                return None;
+            }
+            if info_from == override_from {
+                debug!(
+                    "[overrides] {}: info and override come from the same mods",
+                    class
+                );
+                return None;
+            }
+            Some(Overlap {
+                class,
+                info,
+                override_path,
+            })
+        })
+        .collect()
+}
+
+/// Applies the override to the info file, the same way the game does:
+/// every entry defined by the override replaces the entry with the same identity, others are added.
+fn fold(info: &str, override_text: &str) -> Result<String, StructureError> {
+    let mut map = Format::Darkest.flatten(info)?;
+    let overriding: DataMap = Format::Darkest.flatten(override_text)?;
+    map.retain(|path, _| {
+        !overriding
+            .keys()
+            .any(|overriding| overriding.get(..2) == path.get(..2))
+    });
+    map.extend(overriding);
+    Format::Darkest.rebuild(info, map)
+}
+
+/// Detects heroes whose info and override files come from different mods and lets user fold the override into the info.
+///
+/// Overlaps which are kept are reported as issues, since the result in game might be not what either mod intended.
+pub fn resolve(
+    sink: &mut cursive::CbSink,
+    original: &DataTree,
+    modded: &mut DataTree,
+    mods: &[LockedMod],
+) -> Vec<Issue> {
+    let mut issues = vec![];
+    for overlap in overlaps(original, modded, mods) {
+        let info_from = sources(&overlap.info, mods).join(", ");
+        let override_from = sources(&overlap.override_path, mods).join(", ");
+        info!(
+            "[overrides] {}: info from {}, override from {}",
+            overlap.class, info_from, override_from
+        );
+        let text = |path: &PathBuf| {
+            modded
+                .get(path)
+                .or_else(|| original.get(path))
+                .and_then(DataNode::text)
+                .map(String::from)
+        };
+        let (info, override_text) = match (text(&overlap.info), text(&overlap.override_path)) {
+            (Some(info), Some(override_text)) => (info, override_text),
+            _ => {
+                warn!("[overrides] {}: hero files are not text", overlap.class);
+                continue;
+            }
+        };
+        // The override can be removed only if the bundle is the one providing it.
+        let options = if modded.contains_key(&overlap.override_path) {
+            vec![
+                ("Fold the override into the info file".to_string(), true),
+                ("Keep both files".to_string(), false),
+            ]
+        } else {
+            vec![("Keep both files".to_string(), false)]
+        };
+        // Both files are kept as they are in the headless run, and the overlap is reported.
+        let fold_chosen = !crate::prompt::is_headless()
+            && super::resolve::ask_for_resolve(
+                sink,
+                format!(
+                    "Hero \"{}\" is defined by {} (from {}) and by {} (from {}).
+The game reads the info file first and then applies the override on top of it,
+so every entry redefined by the override wins, whatever the info file says.",
+                    overlap.class,
+                    overlap.info.to_string_lossy(),
+                    info_from,
+                    overlap.override_path.to_string_lossy(),
+                    override_from
+                ),
+                options,
+            );
+        if !fold_chosen {
+            issues.push(Issue {
+                path: overlap.override_path.clone(),
+                message: format!(
+                    "Overrides the entries of {} from {}",
+                    overlap.info.to_string_lossy(),
+                    info_from
+                ),
+            });
+            continue;
+        }
+        match fold(&info, &override_text) {
+            Ok(folded) => {
+                info!("[overrides] {}: override folded into info", overlap.class);
+                modded.insert(overlap.info, DataNode::new("", folded));
+                modded.remove(&overlap.override_path);
+            }
+            Err(err) => issues.push(Issue {
+                path: overlap.override_path,
+                message: format!("Unable to fold override into the info file: {}", err),
+            }),
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_replaces_whole_entries() {
+        let info = "hp: .id \"base\" .value 10 .regen 1\nresistances: .stun 40% .poison 20%\n";
+        let override_text = "hp: .id \"base\" .value 20\ncombat_skill: .id \"new\" .level 0\n";
+        let folded = Format::Darkest
+            .flatten(&fold(info, override_text).unwrap())
+            .unwrap();
+        let expected = Format::Darkest
+            .flatten(
+                "hp: .id \"base\" .value 20\nresistances: .stun 40% .poison 20%\n\
+                 combat_skill: .id \"new\" .level 0\n",
+            )
+            .unwrap();
+        assert_eq!(folded, expected);
+    }
+
+    #[test]
+    fn detects_overlap_from_different_mods() {
+        let node = || DataNode::new("", String::new());
+        let info = || PathBuf::from("heroes/hag/hag.info.darkest");
+        let override_path = || PathBuf::from("heroes/hag/hag.override.darkest");
+        let the_mod = |title: &str, files: &[PathBuf]| LockedMod {
+            title: title.into(),
+            directory: title.into(),
+            version: None,
+            hash: String::new(),
+            files: files
+                .iter()
+                .map(|path| (path.clone(), String::new()))
+                .collect(),
+        };
+
+        // Vanilla info with the override from a mod is the usual override mod.
+        let original: DataTree = vec![(info(), node())].into_iter().collect();
+        let modded: DataTree = vec![(override_path(), node())].into_iter().collect();
+        assert_eq!(overlaps(&original, &modded, &[]), vec![]);
+        let override_mod = the_mod("Override", &[override_path()]);
+        assert_eq!(
+            overlaps(&original, &modded, std::slice::from_ref(&override_mod)),
+            vec![]
+        );
+
+        // Both files from the same mod are combined on purpose.
+        let modded: DataTree = vec![(info(), node()), (override_path(), node())]
+            .into_iter()
+            .collect();
+        let full_mod = the_mod("Full", &[info(), override_path()]);
+        assert_eq!(overlaps(&original, &modded, &[full_mod]), vec![]);
+
+        let info_mod = the_mod("Info", &[info()]);
+        assert_eq!(
+            overlaps(&original, &modded, &[info_mod, override_mod]),
+            vec![Overlap {
+                class: "hag".into(),
+                info: info(),
+                override_path: override_path(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use super::{
+    lock::LockedMod,
+    structures::{self, DATA_TYPES},
+};
+use crate::config::Config;
+use cursive::{
+    traits::{Nameable, Scrollable, With},
+    views::{Checkbox, Dialog, LinearLayout, ListView, SelectView, TextView},
+    Cursive,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// What to do when several mods change the same value of the structured file differently.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    #[default]
+    AlwaysAsk,
+    /// Lists of values are joined, keeping every item added by any mod.
+    UnionLists,
+    /// Numbers are resolved to the largest one.
+    HighestNumber,
+    /// The mod selected last wins.
+    PreferLastMod,
+    /// The mod selected first wins.
+    PreferFirstMod,
+    /// The mod chosen by user wins, if it is involved; otherwise, user is asked.
+    PreferChosenMod,
+}
+
+impl MergePolicy {
+    pub const ALL: &'static [MergePolicy] = &[
+        MergePolicy::AlwaysAsk,
+        MergePolicy::UnionLists,
+        MergePolicy::HighestNumber,
+        MergePolicy::PreferLastMod,
+        MergePolicy::PreferFirstMod,
+        MergePolicy::PreferChosenMod,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            MergePolicy::AlwaysAsk => "Always ask",
+            MergePolicy::UnionLists => "Join lists, ask for others",
+            MergePolicy::HighestNumber => "Take highest number, ask for others",
+            MergePolicy::PreferLastMod => "Prefer the mod selected last",
+            MergePolicy::PreferFirstMod => "Prefer the mod selected first",
+            MergePolicy::PreferChosenMod => "Prefer the chosen mod, ask for others",
+        }
+    }
+
+    /// Chooses the change from the conflicting ones, listed in the order of mods selection.
+    ///
+    /// Returns `None` if the policy doesn't apply, and the user must be asked.
+    /// The mod preferred by user is only known to [`Policies`], so `PreferChosenMod` never applies here.
+    pub fn resolve(self, changes: &[(String, Option<String>)]) -> Option<Option<String>> {
+        let values = || {
+            changes
+                .iter()
+                .map(|(_, change)| change.as_deref())
+                .collect::<Option<Vec<_>>>()
+        };
+        match self {
+            MergePolicy::AlwaysAsk => None,
+            MergePolicy::UnionLists => {
+                let lists = values()?
+                    .into_iter()
+                    .map(structures::split_values)
+                    .collect::<Option<Vec<_>>>()?;
+                if !lists.iter().any(|list| list.len() > 1) {
+                    return None;
+                }
+                let mut items: Vec<String> = vec![];
+                for item in lists.into_iter().flatten() {
+                    if !items.contains(&item) {
+                        items.push(item);
+                    }
+                }
+                Some(Some(items.join(" ")))
+            }
+            MergePolicy::HighestNumber => {
+                let values = values()?;
+                let numbers = values
+                    .iter()
+                    .map(|value| value.trim_end_matches('%').parse::<f64>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                let (index, _) = numbers
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+                Some(Some(values[index].to_owned()))
+            }
+            MergePolicy::PreferLastMod => changes.last().map(|(_, change)| change.clone()),
+            MergePolicy::PreferFirstMod => changes.first().map(|(_, change)| change.clone()),
+            MergePolicy::PreferChosenMod => None,
+        }
+    }
+}
+
+/// Merge policies for every data type, as configured by user.
+#[derive(Debug, Default)]
+pub struct Policies {
+    by_type: BTreeMap<String, MergePolicy>,
+    /// Title of the mod which wins the conflicts in the data types with `PreferChosenMod` policy.
+    preferred_mod: Option<String>,
+}
+
+impl Policies {
+    /// Policies for bundling the given mods; the preferred mod is chosen by its directory, since the titles
+    /// of different mods can be the same.
+    pub fn from_config(config: &Config, mods: &[LockedMod]) -> Self {
+        let preferred_mod = config.preferred_mod.as_deref().and_then(|preferred| {
+            mods.iter()
+                .find(|the_mod| the_mod.directory == preferred)
+                // Earlier versions stored the title instead.
+                .or_else(|| mods.iter().find(|the_mod| the_mod.title == preferred))
+                .map(|the_mod| the_mod.title.clone())
+        });
+        Self {
+            by_type: config.merge_policies.clone(),
+            preferred_mod,
+        }
+    }
+
+    /// Chooses the change from the conflicting ones in the file on the given path, according to its policy.
+    ///
+    /// Returns `None` if the policy doesn't apply, and the user must be asked.
+    pub fn resolve(
+        &self,
+        path: &Path,
+        changes: &[(String, Option<String>)],
+    ) -> Option<Option<String>> {
+        match self.for_path(path) {
+            MergePolicy::PreferChosenMod => {
+                let preferred = self.preferred_mod.as_deref()?;
+                changes
+                    .iter()
+                    .find(|(name, _)| name == preferred)
+                    .map(|(_, change)| change.clone())
+            }
+            policy => policy.resolve(changes),
+        }
+    }
+
+    pub fn for_path(&self, path: &Path) -> MergePolicy {
+        structures::data_type(path)
+            .map(|data_type| {
+                self.by_type
+                    .get(data_type.name)
+                    .copied()
+                    .unwrap_or(data_type.default_policy)
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Sets the same merge policy for every structured data type.
+pub fn set_for_all(config: &mut Config, policy: MergePolicy) {
+    for data_type in DATA_TYPES {
+        config.merge_policies.insert(data_type.name.into(), policy);
+    }
+}
+
+/// Screen to choose merge policy for every structured data type.
+pub fn settings(cursive: &mut Cursive) {
+    let config = Config::load();
+    let mut list = ListView::new();
+    for data_type in DATA_TYPES {
+        let current = config
+            .merge_policies
+            .get(data_type.name)
+            .copied()
+            .unwrap_or(data_type.default_policy);
+        let name = data_type.name;
+        let select = SelectView::new()
+            .popup()
+            .with_all(
+                MergePolicy::ALL
+                    .iter()
+                    .map(|policy| (policy.description(), *policy)),
+            )
+            .selected(
+                MergePolicy::ALL
+                    .iter()
+                    .position(|policy| *policy == current)
+                    .unwrap_or_default(),
+            )
+            .on_submit(move |_, policy: &MergePolicy| {
+                info!("Setting merge policy for {} to {:?}", name, policy);
+                let mut config = Config::load();
+                config.merge_policies.insert(name.into(), *policy);
+                config.save();
+            })
+            .with_name(format!("Policy {}", name));
+        list.add_child(name, select);
+    }
+    let for_all = SelectView::new()
+        .popup()
+        .item("Choose to set for every type", None)
+        .with_all(
+            MergePolicy::ALL
+                .iter()
+                .map(|policy| (policy.description(), Some(*policy))),
+        )
+        .on_submit(|cursive, policy: &Option<MergePolicy>| {
+            let policy = match policy {
+                Some(policy) => *policy,
+                None => return,
+            };
+            info!("Setting merge policy for every data type to {:?}", policy);
+            let mut config = Config::load();
+            set_for_all(&mut config, policy);
+            config.save();
+            let index = MergePolicy::ALL
+                .iter()
+                .position(|known| *known == policy)
+                .unwrap_or_default();
+            for data_type in DATA_TYPES {
+                cursive.call_on_name(
+                    &format!("Policy {}", data_type.name),
+                    |select: &mut SelectView<MergePolicy>| select.set_selection(index),
+                );
+            }
+        });
+    let mut mods: Vec<(String, String)> = cursive
+        .user_data::<crate::loader::GlobalData>()
+        .map(|data| {
+            data.mods
+                .iter()
+                .filter(|the_mod| the_mod.recognized)
+                .map(|the_mod| (the_mod.name().to_owned(), the_mod.directory()))
+                .collect()
+        })
+        .unwrap_or_default();
+    mods.sort();
+    let preferred = SelectView::new()
+        .popup()
+        .item("None", None)
+        .with_all(
+            mods.into_iter()
+                .map(|(name, directory)| (name, Some(directory))),
+        )
+        .with(|select| {
+            let index = select
+                .iter()
+                .position(|(_, name)| *name == config.preferred_mod)
+                .unwrap_or_default();
+            select.set_selection(index);
+        })
+        .on_submit(|_, directory: &Option<String>| {
+            info!("Setting preferred mod to {:?}", directory);
+            let mut config = Config::load();
+            config.preferred_mod = directory.clone();
+            config.save();
+        });
+    list.add_delimiter();
+    list.add_child("Every data type", for_all);
+    list.add_child("Preferred mod", preferred);
+    let cosmetic = LinearLayout::horizontal()
+        .child(
+            Checkbox::new()
+                .with_checked(config.auto_resolve_cosmetic)
+                .on_change(|_, checked| {
+                    info!(
+                        "Setting auto-resolving of cosmetic conflicts to {}",
+                        checked
+                    );
+                    let mut config = Config::load();
+                    config.auto_resolve_cosmetic = checked;
+                    config.save();
+                }),
+        )
+        .child(TextView::new(
+            " Resolve conflicts in art, colours, fonts and strings by mods order, without asking",
+        ));
+    let sprites = LinearLayout::horizontal()
+        .child(
+            Checkbox::new()
+                .with_checked(config.validate_sprites)
+                .on_change(|_, checked| {
+                    info!("Setting validation of chosen sprites to {}", checked);
+                    let mut config = Config::load();
+                    config.validate_sprites = checked;
+                    config.save();
+                }),
+        )
+        .child(TextView::new(
+            " Warn if the hero or monster sprite chosen in a conflict has another size than the game expects",
+        ));
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(cosmetic)
+                .child(sprites)
+                .child(list.scrollable()),
+        )
+        .title("Merge policies for conflicting values")
+        .dismiss_button("Close"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(values: &[Option<&str>]) -> Vec<(String, Option<String>)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (format!("mod{}", index), value.map(String::from)))
+            .collect()
+    }
+
+    #[test]
+    fn union_lists() {
+        let policy = MergePolicy::UnionLists;
+        assert_eq!(
+            policy.resolve(&changes(&[Some("a b c"), Some("a b d")])),
+            Some(Some("a b c d".into()))
+        );
+        assert_eq!(policy.resolve(&changes(&[Some("1"), Some("2")])), None);
+        assert_eq!(policy.resolve(&changes(&[Some("a b"), None])), None);
+        // Quoted strings are single values, even with the spaces inside.
+        assert_eq!(
+            policy.resolve(&changes(&[Some("\"a b\" c"), Some("\"a b\" d")])),
+            Some(Some("\"a b\" c d".into()))
+        );
+        assert_eq!(
+            policy.resolve(&changes(&[Some("\"a b\""), Some("\"a c\"")])),
+            None
+        );
+    }
+
+    #[test]
+    fn highest_number() {
+        let policy = MergePolicy::HighestNumber;
+        assert_eq!(
+            policy.resolve(&changes(&[Some("15%"), Some("20%"), Some("5%")])),
+            Some(Some("20%".into()))
+        );
+        assert_eq!(policy.resolve(&changes(&[Some("1"), Some("x")])), None);
+    }
+
+    #[test]
+    fn progression_thresholds_take_highest_by_default() {
+        let policies = Policies::default();
+        let path = Path::new("shared/progression/resolve_levels.darkest");
+        assert_eq!(policies.for_path(path), MergePolicy::HighestNumber);
+        assert_eq!(
+            policies
+                .for_path(path)
+                .resolve(&changes(&[Some("12"), Some("16")])),
+            Some(Some("16".into()))
+        );
+        assert_eq!(
+            policies.for_path(Path::new("loot/loot.darkest")),
+            MergePolicy::AlwaysAsk
+        );
+        // Upgrade costs are numbers too, but a higher one is not what the mods agree on.
+        assert_eq!(
+            policies.for_path(Path::new("upgrades/buildings/abbey.upgrades.json")),
+            MergePolicy::AlwaysAsk
+        );
+    }
+
+    #[test]
+    fn prefer_last_mod() {
+        let policy = MergePolicy::PreferLastMod;
+        assert_eq!(policy.resolve(&changes(&[Some("a"), None])), Some(None));
+        let policy = MergePolicy::PreferFirstMod;
+        assert_eq!(
+            policy.resolve(&changes(&[Some("a"), None])),
+            Some(Some("a".into()))
+        );
+    }
+
+    #[test]
+    fn prefer_chosen_mod() {
+        let path = Path::new("loot/loot.darkest");
+        let mut config = Config::default();
+        config.merge_policies.insert(
+            structures::LOOT_TABLES.name.into(),
+            MergePolicy::PreferChosenMod,
+        );
+        // Both mods have the same title, but the chosen one is told apart by its directory.
+        let locked = |title: &str, directory: &str| LockedMod {
+            title: title.into(),
+            directory: directory.into(),
+            version: None,
+            hash: String::new(),
+            files: Default::default(),
+        };
+        let mods = [locked("mod0", "123"), locked("mod1", "456")];
+        assert_eq!(
+            Policies::from_config(&config, &mods).resolve(path, &changes(&[Some("a"), Some("b")])),
+            None
+        );
+        config.preferred_mod = Some("456".into());
+        let policies = Policies::from_config(&config, &mods);
+        assert_eq!(
+            policies.resolve(path, &changes(&[Some("a"), Some("b")])),
+            Some(Some("b".into()))
+        );
+        let others = vec![("other".into(), Some("c".into()))];
+        assert_eq!(policies.resolve(path, &others), None);
+        config.preferred_mod = Some("mod0".into());
+        assert_eq!(
+            Policies::from_config(&config, &mods).resolve(path, &changes(&[Some("a"), Some("b")])),
+            Some(Some("a".into()))
+        );
+    }
+}
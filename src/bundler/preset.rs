@@ -0,0 +1,347 @@
+use super::{
+    decisions::Decisions,
+    diff::{Conflicts, DiffNode, DiffTree},
+    error::PresetError,
+    lock::{LockFile, LOCK_FILE},
+    MergePolicy, BUNDLE_DIR,
+};
+use crate::{config::Config, loader::GlobalData};
+use cursive::{
+    traits::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, TextView},
+    Cursive,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// File name offered for export, relative to the working directory.
+const DEFAULT_EXPORT: &str = "resolution_preset.json";
+
+/// Mod the preset was made for, so that users know which mod list it is meant for.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PresetMod {
+    pub title: String,
+    /// Workshop ID.
+    pub directory: String,
+}
+
+/// Conflict decisions and merge policies, shared between users bundling the same mods.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Preset {
+    pub tool_version: String,
+    pub mods: Vec<PresetMod>,
+    pub merge_policies: BTreeMap<String, MergePolicy>,
+    /// Decisions made for the conflicting files; binary files are referred relative to the Workshop directory.
+    pub resolutions: DiffTree,
+    /// Hashes of the conflicting changes the decisions were made for.
+    #[serde(default)]
+    pub inputs: BTreeMap<PathBuf, String>,
+    /// Mod to take the whole file from, by the file, as decided in the conflict spreadsheet.
+    #[serde(default)]
+    pub chosen_mods: BTreeMap<PathBuf, String>,
+}
+
+/// Replaces the `from` prefix of the binary file sources with `to`.
+fn relocate(resolutions: DiffTree, from: &Path, to: &Path) -> DiffTree {
+    resolutions
+        .into_iter()
+        .map(|(path, node)| match node {
+            DiffNode::Binary(source) => {
+                let source = match source.strip_prefix(from) {
+                    Ok(relative) => to.join(relative),
+                    Err(_) => source,
+                };
+                (path, DiffNode::Binary(source))
+            }
+            node => (path, node),
+        })
+        .collect()
+}
+
+impl Preset {
+    fn read(path: &Path) -> Result<Self, PresetError> {
+        let text = std::fs::read_to_string(path).map_err(PresetError::from_io(path))?;
+        serde_json::from_str(&text).map_err(|err| PresetError::Json(err, path.into()))
+    }
+
+    fn write(&self, path: &Path) -> Result<(), PresetError> {
+        let text = serde_json::to_string_pretty(self).map_err(PresetError::Serialization)?;
+        std::fs::write(path, text).map_err(PresetError::from_io(path))
+    }
+
+    /// Makes the preset from the decisions recorded in the lock file and from the current merge policies.
+    fn from_lock(lock: LockFile, config: &Config, workshop: &Path) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").into(),
+            mods: lock
+                .mods
+                .into_iter()
+                .map(|the_mod| PresetMod {
+                    title: the_mod.title,
+                    directory: the_mod.directory,
+                })
+                .collect(),
+            merge_policies: config.merge_policies.clone(),
+            resolutions: relocate(lock.resolutions, workshop, Path::new("")),
+            inputs: lock.inputs,
+            chosen_mods: BTreeMap::new(),
+        }
+    }
+
+    /// Imported preset, with the binary files pointing into the given Workshop directory, if there's one.
+    pub fn load_active(workshop: &Path) -> Option<Self> {
+        let path = crate::paths::preset();
+        if !path.exists() {
+            return None;
+        }
+        match Self::read(&path) {
+            Ok(mut preset) => {
+                info!(
+                    "[preset] Using preset with {} decisions for {} mods",
+                    preset.resolutions.len(),
+                    preset.mods.len()
+                );
+                preset.resolutions = relocate(
+                    std::mem::take(&mut preset.resolutions),
+                    Path::new(""),
+                    workshop,
+                );
+                Some(preset)
+            }
+            Err(err) => {
+                warn!("[preset] Unable to read the active preset: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Takes the changes of the chosen mods for the conflicting files they were chosen for.
+    ///
+    /// Returns the taken resolutions and the conflicts which must be resolved otherwise.
+    pub fn choose(&self, conflicts: Conflicts) -> (DiffTree, Conflicts) {
+        let mut chosen = DiffTree::new();
+        let mut remaining = Conflicts::new();
+        for (path, conflict) in conflicts {
+            let name = self.chosen_mods.get(&path);
+            match conflict.iter().find(|(mod_name, _)| Some(mod_name) == name) {
+                Some((mod_name, node)) => {
+                    info!("[preset] {:?}: Taking the changes of {}", path, mod_name);
+                    chosen.insert(path, node.clone());
+                }
+                None => {
+                    if let Some(name) = name {
+                        warn!(
+                            "[preset] {:?}: Chosen mod {} doesn't change this file",
+                            path, name
+                        );
+                    }
+                    remaining.insert(path, conflict);
+                }
+            }
+        }
+        (chosen, remaining)
+    }
+}
+
+fn path_input(cursive: &mut Cursive) -> PathBuf {
+    cursive
+        .call_on_name("Preset path", |view: &mut EditView| view.get_content())
+        .map(|content| PathBuf::from(content.trim()))
+        .unwrap_or_default()
+}
+
+/// Puts the default file name into the chosen directory, so that a new export file can be placed there.
+fn name_export(cursive: &mut Cursive, dir: &str) {
+    let target = Path::new(dir).join(DEFAULT_EXPORT);
+    cursive.call_on_name("Preset path", |view: &mut EditView| {
+        view.set_content(target.to_string_lossy())
+    });
+}
+
+fn export(cursive: &mut Cursive) {
+    let mut target = path_input(cursive);
+    if target.is_dir() {
+        target.push(DEFAULT_EXPORT);
+    }
+    let base_path = &cursive
+        .user_data::<GlobalData>()
+        .expect("No data was set")
+        .base_path;
+    let lock_path = crate::paths::game(base_path)
+        .join(BUNDLE_DIR)
+        .join(LOCK_FILE);
+    let workshop = crate::paths::workshop(base_path);
+    let result = LockFile::read(&lock_path)
+        .map_err(PresetError::Lock)
+        .and_then(|lock| Preset::from_lock(lock, &Config::load(), &workshop).write(&target));
+    match result {
+        Ok(()) => {
+            info!("[preset] Exported preset to {:?}", target);
+            cursive.add_layer(Dialog::info(format!(
+                "Decisions of the deployed bundle and the merge policies are exported to {}",
+                target.to_string_lossy()
+            )));
+        }
+        Err(err) => crate::error(cursive, &err),
+    }
+}
+
+fn import(cursive: &mut Cursive) {
+    let source = path_input(cursive);
+    let preset = match Preset::read(&source) {
+        Ok(preset) => preset,
+        Err(err) => return crate::error(cursive, &err),
+    };
+    if let Err(err) = preset.write(&crate::paths::preset()) {
+        return crate::error(cursive, &err);
+    }
+    let mut config = Config::load();
+    config.merge_policies.extend(preset.merge_policies.clone());
+    config.save();
+    info!("[preset] Imported preset from {:?}", source);
+    cursive.pop_layer();
+    cursive.add_layer(Dialog::info(format!(
+        "Preset imported: its merge policies are set, and its {} decisions will be used when bundling.\n\
+         It was made for these mods:\n{}",
+        preset.resolutions.len(),
+        preset
+            .mods
+            .iter()
+            .map(|the_mod| format!("- {} ({})", the_mod.title, the_mod.directory))
+            .collect::<Vec<_>>()
+            .join("\n")
+    )));
+}
+
+/// Adds the decisions from the filled conflict spreadsheet to the active preset, making one if there's none.
+fn import_csv(cursive: &mut Cursive) {
+    let source = path_input(cursive);
+    let decisions = std::fs::read_to_string(&source)
+        .map_err(PresetError::from_io(&source))
+        .and_then(|text| {
+            super::csv::decisions(&text).map_err(|reason| PresetError::Csv(reason, source.clone()))
+        });
+    let decisions = match decisions {
+        Ok(decisions) => decisions,
+        Err(err) => return crate::error(cursive, &err),
+    };
+    let active = crate::paths::preset();
+    let mut preset = if active.exists() {
+        match Preset::read(&active) {
+            Ok(preset) => preset,
+            Err(err) => return crate::error(cursive, &err),
+        }
+    } else {
+        Preset {
+            tool_version: env!("CARGO_PKG_VERSION").into(),
+            ..Preset::default()
+        }
+    };
+    let count = decisions.len();
+    preset.chosen_mods.extend(decisions);
+    if let Err(err) = preset.write(&active) {
+        return crate::error(cursive, &err);
+    }
+    info!("[preset] Imported {} decisions from {:?}", count, source);
+    cursive.pop_layer();
+    cursive.add_layer(Dialog::info(format!(
+        "{} decisions from the spreadsheet will be used when bundling.",
+        count
+    )));
+}
+
+fn remove(cursive: &mut Cursive) {
+    let path = crate::paths::preset();
+    if let Err(err) = std::fs::remove_file(&path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            return crate::error(cursive, &PresetError::from_io(&path)(err));
+        }
+    }
+    info!("[preset] Active preset removed");
+    cursive.pop_layer();
+}
+
+fn forget_decisions(cursive: &mut Cursive) {
+    Decisions::forget();
+    cursive.pop_layer();
+}
+
+/// Screen to export the decisions of the deployed bundle as a preset, or to import the preset made by others.
+pub fn screen(cursive: &mut Cursive) {
+    let mut status = match Preset::read(&crate::paths::preset()) {
+        Ok(preset) => format!(
+            "Active preset: {} decisions for {} mods, {} from the spreadsheet.",
+            preset.resolutions.len(),
+            preset.mods.len(),
+            preset.chosen_mods.len()
+        ),
+        Err(_) => "No preset is active.".into(),
+    };
+    status.push_str(&format!(
+        "\nDecisions remembered from the earlier bundlings: {}. They are used before the preset, \
+         but the lock file of the rebuilt bundle and the resumed session come first.",
+        Decisions::load().count()
+    ));
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(status))
+                .child(TextView::new("Preset file:"))
+                .child(
+                    EditView::new()
+                        .content(DEFAULT_EXPORT)
+                        .with_name("Preset path")
+                        .full_width(),
+                ),
+        )
+        .title("Resolution presets")
+        .button("Browse...", |cursive| {
+            crate::browse::browse(cursive, "Preset path", true, |_, _| {})
+        })
+        .button("Browse folder...", |cursive| {
+            crate::browse::browse(cursive, "Preset path", false, name_export)
+        })
+        .button("Import", import)
+        .button("Import CSV", import_csv)
+        .button("Export", export)
+        .button("Remove active", remove)
+        .button("Forget decisions", forget_decisions)
+        .dismiss_button("Close"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_sources_are_portable() {
+        let resolutions: DiffTree = vec![
+            (
+                "heroes/hag/hag.png".into(),
+                DiffNode::Binary("/steam/workshop/123/heroes/hag/hag.png".into()),
+            ),
+            ("raid/raid.json".into(), DiffNode::AddedText("{}".into())),
+        ]
+        .into_iter()
+        .collect();
+        let portable = relocate(resolutions, Path::new("/steam/workshop"), Path::new(""));
+        assert!(matches!(
+            &portable[Path::new("heroes/hag/hag.png")],
+            DiffNode::Binary(source) if source == Path::new("123/heroes/hag/hag.png")
+        ));
+        let local = relocate(portable, Path::new(""), Path::new("/home/workshop"));
+        assert!(matches!(
+            &local[Path::new("heroes/hag/hag.png")],
+            DiffNode::Binary(source) if source == Path::new("/home/workshop/123/heroes/hag/hag.png")
+        ));
+        assert!(matches!(
+            &local[Path::new("raid/raid.json")],
+            DiffNode::AddedText(text) if text == "{}"
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+//! Progress of the current bundling phase (loading the game, every DLC and every mod, merging and deploying),
+//! shown as the percentage of the files processed and the estimated time left.
+//!
+//! Every phase is also listed in the bundling dialog with its own percentage, so that it's seen
+//! how far the loading is with many mods, some of them being loaded at once.
+
+use cursive::{
+    utils::Counter,
+    views::{ProgressBar, TextView},
+};
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Name of the progress bar in the bundling dialog.
+pub const PROGRESS_BAR: &str = "Loading progress";
+/// Name of the list of the phases in the bundling dialog.
+pub const PHASE_LIST: &str = "Loading phases";
+
+/// Phases of the current bundling, in order.
+static PHASES: Mutex<Vec<(Phase, PhaseState)>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Vanilla,
+    /// DLC by the name of its directory.
+    Dlc(String),
+    /// Loading of the mod by its name, including the calculation of its changes.
+    Mod(String),
+    Merge,
+    Deploy,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Vanilla => f.write_str("Vanilla game"),
+            Phase::Dlc(name) => write!(f, "DLC {}", name),
+            Phase::Mod(name) => f.write_str(name),
+            Phase::Merge => f.write_str("Merging"),
+            Phase::Deploy => f.write_str("Deploying"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PhaseState {
+    Pending,
+    Running { done: usize, total: usize },
+    Done,
+}
+
+/// Lists the phases of the bundling in the dialog, none of them started yet.
+pub fn plan(sink: &mut cursive::CbSink, phases: Vec<Phase>) {
+    *PHASES.lock().expect("Phase list is poisoned") = phases
+        .into_iter()
+        .map(|phase| (phase, PhaseState::Pending))
+        .collect();
+    show_phases(sink);
+}
+
+/// Marks the phase as done, even if not all of its files were processed (e.g. the mod was skipped).
+pub fn finish(sink: &mut cursive::CbSink, phase: &Phase) {
+    if update_phase(phase, |_| PhaseState::Done) {
+        show_phases(sink);
+    }
+}
+
+/// Changes the state of the phase, if it is listed; returns whether the list should be redrawn.
+fn update_phase(phase: &Phase, update: impl FnOnce(PhaseState) -> PhaseState) -> bool {
+    let mut phases = PHASES.lock().expect("Phase list is poisoned");
+    let (_, state) = match phases.iter_mut().find(|(listed, _)| listed == phase) {
+        Some(listed) => listed,
+        None => return false,
+    };
+    let old = *state;
+    *state = match update(old) {
+        PhaseState::Running { done, total } if done >= total => PhaseState::Done,
+        new => new,
+    };
+    render_state(old) != render_state(*state)
+}
+
+fn show_phases(sink: &mut cursive::CbSink) {
+    crate::run_update(sink, |cursive| {
+        cursive.call_on_name(PHASE_LIST, |text: &mut TextView| {
+            text.set_content(render(&PHASES.lock().expect("Phase list is poisoned")));
+        });
+    });
+}
+
+fn render_state(state: PhaseState) -> String {
+    match state {
+        PhaseState::Pending => String::new(),
+        PhaseState::Running { done, total } => format!("{}%", done * 100 / total.max(1)),
+        PhaseState::Done => "done".into(),
+    }
+}
+
+/// Phase list as shown in the dialog: finished phases are checked, and the running ones have their percentage.
+fn render(phases: &[(Phase, PhaseState)]) -> String {
+    phases
+        .iter()
+        .map(|(phase, state)| match state {
+            PhaseState::Pending => format!("  {}", phase),
+            PhaseState::Running { .. } => format!("> {} {}", phase, render_state(*state)),
+            PhaseState::Done => format!("\u{2713} {}", phase),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+/// Estimation is too unstable before this part of the files is processed.
+const ESTIMATE_AFTER: Duration = Duration::from_millis(500);
+
+/// Counter of the files processed in the current phase.
+pub struct Progress {
+    counter: Counter,
+    /// Listed phase which this progress is reported to as well.
+    phase: Option<(Phase, cursive::CbSink)>,
+}
+
+impl Progress {
+    /// Starts the phase with `total` files, resetting the progress bar if it is shown.
+    pub fn start(sink: &mut cursive::CbSink, total: usize) -> Self {
+        let counter = Counter::new(0);
+        let shown = counter.clone();
+        let started = Instant::now();
+        crate::run_update(sink, move |cursive| {
+            cursive.call_on_name(PROGRESS_BAR, |bar: &mut ProgressBar| {
+                *bar = ProgressBar::new()
+                    .range(0, total.max(1))
+                    .with_value(shown)
+                    .with_label(move |value, (_, max)| label(value, max, started.elapsed()));
+            });
+        });
+        Self {
+            counter,
+            phase: None,
+        }
+    }
+
+    /// Starts the listed phase with `total` files, resetting the progress bar as [`Progress::start`] does.
+    pub fn phase(sink: &mut cursive::CbSink, phase: Phase, total: usize) -> Self {
+        let counter = Self::start(sink, total).counter;
+        Self::part_of(counter, sink, phase, total)
+    }
+
+    /// Starts the listed phase which is a part of the current one, e.g. loading of one of the mods loaded at once;
+    /// its files are counted both on the shared bar and in the phase list.
+    pub fn part(&self, sink: &mut cursive::CbSink, phase: Phase, total: usize) -> Self {
+        Self::part_of(self.counter.clone(), sink, phase, total)
+    }
+
+    fn part_of(counter: Counter, sink: &mut cursive::CbSink, phase: Phase, total: usize) -> Self {
+        if update_phase(&phase, |_| PhaseState::Running { done: 0, total }) {
+            show_phases(sink);
+        }
+        Self {
+            counter,
+            phase: Some((phase, sink.clone())),
+        }
+    }
+
+    /// Marks one more file as processed; the bar is redrawn with the next update of the dialog.
+    pub fn advance(&self) {
+        self.counter.tick(1);
+        if let Some((phase, sink)) = &self.phase {
+            let advanced = update_phase(phase, |state| match state {
+                PhaseState::Running { done, total } => PhaseState::Running {
+                    done: done + 1,
+                    total,
+                },
+                state => state,
+            });
+            if advanced {
+                show_phases(&mut sink.clone());
+            }
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{} s", seconds)
+    } else {
+        format!("{} min {} s", seconds / 60, seconds % 60)
+    }
+}
+
+/// Percentage of the processed files, with the time left if it can already be estimated.
+fn label(done: usize, total: usize, elapsed: Duration) -> String {
+    let done = done.min(total);
+    let percent = done * 100 / total.max(1);
+    if done == 0 || done == total || elapsed < ESTIMATE_AFTER {
+        return format!("{}%", percent);
+    }
+    let left = elapsed.mul_f64((total - done) as f64 / done as f64);
+    format!("{}% (about {} left)", percent, format_duration(left))
+}
+
+/// Number of files the extraction will read from the directory, following the same rules:
+/// the DLC directories are skipped, as are the files directly in the root.
+///
+/// Unreadable directories are not counted; the extraction itself reports them.
+pub fn count_files(path: &Path, root: bool) -> usize {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                if path.file_name().and_then(std::ffi::OsStr::to_str) == Some("dlc") {
+                    0
+                } else {
+                    count_files(&path, false)
+                }
+            } else if root {
+                0
+            } else {
+                1
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_left_is_estimated_from_elapsed() {
+        assert_eq!(label(0, 200, Duration::from_secs(10)), "0%");
+        assert_eq!(label(50, 200, Duration::from_millis(100)), "25%");
+        assert_eq!(
+            label(50, 200, Duration::from_secs(10)),
+            "25% (about 30 s left)"
+        );
+        assert_eq!(
+            label(20, 200, Duration::from_secs(10)),
+            "10% (about 1 min 30 s left)"
+        );
+        assert_eq!(label(200, 200, Duration::from_secs(10)), "100%");
+    }
+
+    #[test]
+    fn phases_are_listed_with_progress() {
+        let phases = [
+            (Phase::Vanilla, PhaseState::Done),
+            (Phase::Dlc("arena_mp".into()), PhaseState::Done),
+            (
+                Phase::Mod("Hag".into()),
+                PhaseState::Running {
+                    done: 45,
+                    total: 100,
+                },
+            ),
+            (Phase::Mod("Marvin".into()), PhaseState::Pending),
+            (Phase::Merge, PhaseState::Pending),
+        ];
+        assert_eq!(
+            render(&phases),
+            "\u{2713} Vanilla game\n\u{2713} DLC arena_mp\n> Hag 45%\n  Marvin\n  Merging"
+        );
+        // The list is redrawn only when the percentage changes.
+        assert_eq!(
+            render_state(PhaseState::Running {
+                done: 450,
+                total: 1000
+            }),
+            render_state(PhaseState::Running {
+                done: 451,
+                total: 1000
+            })
+        );
+    }
+}
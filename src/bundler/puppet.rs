@@ -0,0 +1,118 @@
+//! Headless driving of the prompts on the puppet backend, for the tests of the dialogs asked from the bundling thread.
+//!
+//! Every dialog is captured as text when it is first shown and compared to its snapshot in `src/bundler/snapshots`.
+//! Run the tests with `UPDATE_SNAPSHOTS` set to write the snapshots anew, and review the changes before committing.
+
+use cursive::{
+    backends::puppet::{observed::ObservedScreen, Backend},
+    event::Event,
+    Cursive, Vec2,
+};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// Long enough for the slowest CI machine, short enough not to hang the tests on the unexpected prompt.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn screen_size() -> Vec2 {
+    Vec2::new(100, 40)
+}
+
+/// Text of the frame, with the trailing spaces and the empty lines around the dialog trimmed.
+fn frame_text(frame: &ObservedScreen) -> String {
+    let size = frame.size();
+    let lines: Vec<_> = (0..size.y)
+        .map(|y| {
+            let line: String = (0..size.x)
+                .filter_map(|x| match &frame[Vec2::new(x, y)] {
+                    Some(cell) => cell.letter.as_option().cloned(),
+                    None => Some(" ".into()),
+                })
+                .collect();
+            line.trim_end().to_owned()
+        })
+        .collect();
+    let first = lines.iter().position(|line| !line.is_empty()).unwrap_or(0);
+    let mut text = lines[first..].join("\n").trim_end().to_owned();
+    text.push('\n');
+    text
+}
+
+/// Steps the event loop until `done` holds, failing the test if it doesn't in time.
+fn step_until(cursive: &mut Cursive, what: &str, mut done: impl FnMut(&mut Cursive) -> bool) {
+    let started = Instant::now();
+    while !done(cursive) {
+        assert!(
+            started.elapsed() < TIMEOUT,
+            "Timed out waiting for {}",
+            what
+        );
+        cursive.step();
+    }
+}
+
+/// Runs `ask` on its own thread, as the bundling does, answering every dialog it shows with the next of `inputs`.
+///
+/// Every dialog must be closed by its inputs. Returns the answer and the text of every dialog as it was first shown.
+pub fn drive<T: Send + 'static>(
+    ask: impl FnOnce(&mut cursive::CbSink) -> T + Send + 'static,
+    inputs: Vec<Vec<Event>>,
+) -> (T, Vec<String>) {
+    let backend = Backend::init(Some(screen_size()));
+    let input = backend.input();
+    let stream = backend.stream();
+    let mut cursive = Cursive::new(move || backend);
+    let mut sink = cursive.cb_sink().clone();
+    let asking = std::thread::spawn(move || ask(&mut sink));
+
+    let mut frames = vec![];
+    for (index, events) in inputs.into_iter().enumerate() {
+        step_until(&mut cursive, &format!("dialog #{}", index), |cursive| {
+            !cursive.screen().is_empty()
+        });
+        cursive.refresh();
+        let frame = stream.try_iter().last().expect("Frame is rendered");
+        frames.push(frame_text(&frame));
+        for event in events {
+            input.send(Some(event)).expect("Backend is alive");
+        }
+        step_until(
+            &mut cursive,
+            &format!("dialog #{} to close", index),
+            |cursive| cursive.screen().is_empty(),
+        );
+    }
+    step_until(&mut cursive, "the answer", |cursive| {
+        assert_eq!(cursive.screen().len(), 0, "Unexpected dialog is shown");
+        asking.is_finished()
+    });
+    (asking.join().expect("Asking thread panicked"), frames)
+}
+
+/// Compares the frame with the stored snapshot, or stores it, if `UPDATE_SNAPSHOTS` is set.
+pub fn assert_snapshot(name: &str, frame: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/bundler/snapshots")
+        .join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, frame).unwrap();
+        return;
+    }
+    let stored = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "No snapshot {:?} ({}); run with UPDATE_SNAPSHOTS set to create it",
+            path, err
+        )
+    });
+    assert!(
+        stored == frame,
+        "Dialog differs from the snapshot {:?}; run with UPDATE_SNAPSHOTS set if it's intended.\n\
+         Stored:\n{}\nRendered:\n{}",
+        path,
+        stored,
+        frame
+    );
+}
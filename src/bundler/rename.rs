@@ -0,0 +1,321 @@
+//! Renaming the ids defined by some mod, so that they don't collide with the same ids defined by other mods.
+
+use super::{
+    diff::{DataNode, DataNodeContent, DataTree},
+    search,
+    validate::is_string_table,
+};
+use crate::{config::Config, loader::GlobalData};
+use cursive::{
+    traits::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use log::*;
+use std::collections::BTreeMap;
+
+/// Ids are delimited by anything which can't be a part of them, e.g. quotes, spaces, dots or `+`.
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Beginnings of the string table keys which are followed by the id right away, without any delimiter.
+const KEY_PREFIXES: &[&str] = &[
+    "str_inventory_title_trinket",
+    "str_inventory_description_trinket",
+    "str_quirk_name_",
+    "str_quirk_description_",
+    "hero_class_name_",
+];
+
+/// Replaces every standalone occurrence of the id, leaving the longer ids containing it intact.
+///
+/// If `prefixed` is set, the id is also replaced when it directly follows one of the string table key prefixes
+/// at the start of the identifier, e.g. `str_inventory_title_trinket<id>`, but not in the other ids ending with it.
+pub fn rename_id(text: &str, from: &str, to: &str, prefixed: bool) -> String {
+    let mut renamed = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find(from) {
+        let (before, after) = (&rest[..index], &rest[index + from.len()..]);
+        renamed.push_str(before);
+        // Part of the identifier preceding the occurrence, which might have started in the previous chunks.
+        let start = renamed
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_id_char(*c))
+            .map_or(0, |(index, c)| index + c.len_utf8());
+        let head = &renamed[start..];
+        let standalone = (head.is_empty() || prefixed && KEY_PREFIXES.contains(&head))
+            && !after.chars().next().is_some_and(is_id_char);
+        renamed.push_str(if standalone { to } else { from });
+        rest = after;
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+fn rename_all(text: &str, renames: &BTreeMap<String, String>, prefixed: bool) -> String {
+    renames.iter().fold(text.to_owned(), |text, (from, to)| {
+        rename_id(&text, from, to, prefixed)
+    })
+}
+
+/// Renames the ids in the file contents and in the file names (e.g. the trinket icons) of the mod;
+/// the directories are left as they are, since the game looks for them by their own names.
+pub fn apply(content: DataTree, renames: &BTreeMap<String, String>) -> DataTree {
+    if renames.is_empty() {
+        return content;
+    }
+    content
+        .into_iter()
+        .map(|(path, node)| {
+            let renamed_path = match path.file_name() {
+                Some(name) => {
+                    path.with_file_name(rename_all(&name.to_string_lossy(), renames, false))
+                }
+                None => path.clone(),
+            };
+            let prefixed = is_string_table(&path);
+            if renamed_path != path {
+                debug!("[rename] {:?} is renamed to {:?}", path, renamed_path);
+            }
+            let node = match node.into_parts() {
+                (source, DataNodeContent::Text(text)) => {
+                    DataNode::new(source, rename_all(&text, renames, prefixed))
+                }
+                (source, DataNodeContent::Binary) => DataNode::new(source, DataNodeContent::Binary),
+            };
+            (renamed_path, node)
+        })
+        .collect()
+}
+
+fn save(cursive: &mut Cursive, directory: String, id: String) {
+    let new_id = cursive
+        .call_on_name("New id", |view: &mut EditView| view.get_content())
+        .map(|content| content.trim().to_owned())
+        .unwrap_or_default();
+    if new_id.is_empty() || new_id == id {
+        return;
+    }
+    info!(
+        "[rename] Renaming {} to {} in mod {}",
+        id, new_id, directory
+    );
+    let mut config = Config::load();
+    config
+        .profile_mut()
+        .id_renames
+        .entry(directory)
+        .or_default()
+        .insert(id, new_id);
+    config.save();
+    cursive.pop_layer();
+    cursive.pop_layer();
+}
+
+/// Asks for the new id for the chosen mod, showing where it is used there.
+fn choose_new_id(
+    cursive: &mut Cursive,
+    id: &str,
+    (source, directory, hits): &(String, String, String),
+) {
+    let (id, directory) = (id.to_owned(), directory.clone());
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(format!(
+                    "Every occurrence of \"{}\" in this mod (definitions, references, string table keys \
+                     and file names) will be renamed before merging:",
+                    id
+                )))
+                .child(TextView::new(hits.clone()).scrollable().max_height(15))
+                .child(TextView::new("New id:"))
+                .child(
+                    EditView::new()
+                        .content(format!("{}_{}", id, directory))
+                        .with_name("New id")
+                        .full_width(),
+                ),
+        )
+        .title(format!("Rename in {}", source))
+        .button("Rename", move |cursive| {
+            save(cursive, directory.clone(), id.clone())
+        })
+        .dismiss_button("Cancel"),
+    );
+}
+
+fn find(cursive: &mut Cursive, id: &str) {
+    let id = id.trim().to_owned();
+    if id.is_empty() {
+        return;
+    }
+    let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+    let base_path = global_data.base_path.clone();
+    let mods = global_data.mods.clone();
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(" ").with_name("Loading part"))
+                .child(TextView::new(" ").with_name("Loading filename")),
+        )
+        .title(format!("Searching for \"{}\"...", id)),
+    );
+    let mut sink = cursive.cb_sink().clone();
+    std::thread::spawn(move || {
+        let result = search::search_all(&mut sink, &base_path, &mods, &id);
+        crate::run_update(&mut sink, move |cursive| {
+            cursive.pop_layer();
+            let results = match result {
+                Ok(results) => results,
+                Err(err) => return crate::error(cursive, &err),
+            };
+            let in_game = results.iter().any(|found| found.mod_directory.is_none());
+            let in_mods: Vec<_> = results
+                .into_iter()
+                .filter_map(|found| {
+                    let directory = found.mod_directory?;
+                    let hits = search::render_hits(&found.hits);
+                    Some((found.source.clone(), (found.source, directory, hits)))
+                })
+                .collect();
+            let mut text = format!("\"{}\" is used by {} mods.", id, in_mods.len());
+            if in_game {
+                text.push_str(
+                    "\nIt is also used by the game itself, so renaming it would only detach \
+                     the mod from the game's entry.",
+                );
+            }
+            let list = SelectView::new()
+                .with_all(in_mods)
+                .on_submit(move |cursive, chosen| choose_new_id(cursive, &id, chosen));
+            cursive.add_layer(
+                Dialog::around(
+                    LinearLayout::vertical()
+                        .child(TextView::new(text))
+                        .child(TextView::new("Choose the mod to rename the id in:"))
+                        .child(list.scrollable()),
+                )
+                .title("Colliding id")
+                .dismiss_button("Close"),
+            );
+        });
+    });
+}
+
+fn renames_list(config: &Config) -> String {
+    let lines: Vec<_> = config
+        .profile()
+        .into_iter()
+        .flat_map(|profile| &profile.id_renames)
+        .flat_map(|(directory, renames)| {
+            renames
+                .iter()
+                .map(move |(from, to)| format!("- {}: {} -> {}", directory, from, to))
+        })
+        .collect();
+    if lines.is_empty() {
+        "No ids are renamed.".into()
+    } else {
+        format!("Renamed ids:\n{}", lines.join("\n"))
+    }
+}
+
+fn clear(cursive: &mut Cursive) {
+    info!("[rename] Removing every id rename");
+    let mut config = Config::load();
+    config.profile_mut().id_renames.clear();
+    config.save();
+    cursive.pop_layer();
+}
+
+/// Screen to rename the id defined by several mods in one of them, so that both definitions survive the merge.
+pub fn screen(cursive: &mut Cursive) {
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(renames_list(&Config::load())))
+                .child(TextView::new(
+                    "\nId defined by several mods (e.g. a trinket, buff or skill id):",
+                ))
+                .child(
+                    EditView::new()
+                        .on_submit(find)
+                        .with_name("Colliding id")
+                        .full_width(),
+                ),
+        )
+        .title("Rename colliding ids")
+        .button("Find", |cursive| {
+            let id = cursive
+                .call_on_name("Colliding id", |view: &mut EditView| view.get_content())
+                .unwrap_or_default();
+            find(cursive, &id);
+        })
+        .button("Remove all renames", clear)
+        .dismiss_button("Close"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn only_standalone_ids_are_renamed() {
+        assert_eq!(
+            rename_id(
+                "trinket: .id \"sun_ring\" .buffs sun_ring_1 sun_ring",
+                "sun_ring",
+                "sun_ring_123",
+                false
+            ),
+            "trinket: .id \"sun_ring_123\" .buffs sun_ring_1 sun_ring_123"
+        );
+        assert_eq!(
+            rename_id(
+                "panels/icons_equip/trinket/inv_trinket+sun_ring.png",
+                "sun_ring",
+                "x",
+                false
+            ),
+            "panels/icons_equip/trinket/inv_trinket+x.png"
+        );
+        let key = "<entry id=\"str_inventory_title_trinketsun_ring\">";
+        assert_eq!(rename_id(key, "sun_ring", "x", false), key);
+        assert_eq!(
+            rename_id(key, "sun_ring", "x", true),
+            "<entry id=\"str_inventory_title_trinketx\">"
+        );
+    }
+
+    #[test]
+    fn ids_ending_with_the_renamed_one_are_left_intact() {
+        let table = "<entry id=\"str_quirk_name_hp\">HP</entry><entry id=\"max_hp\">Max HP</entry>";
+        assert_eq!(
+            rename_id(table, "hp", "hp_123", true),
+            "<entry id=\"str_quirk_name_hp_123\">HP</entry><entry id=\"max_hp\">Max HP</entry>"
+        );
+        assert_eq!(
+            rename_id("buff: .stat_type \"max_hp\" .id \"hp\"", "hp", "x", true),
+            "buff: .stat_type \"max_hp\" .id \"x\""
+        );
+    }
+
+    #[test]
+    fn directories_are_not_renamed() {
+        let path = PathBuf::from("heroes/sun_ring/sun_ring.info.darkest");
+        let content: DataTree = vec![(path, DataNode::new("", "hero: .id sun_ring".to_string()))]
+            .into_iter()
+            .collect();
+        let renames = vec![("sun_ring".to_string(), "x".to_string())]
+            .into_iter()
+            .collect();
+        let renamed = apply(content, &renames);
+        let (path, node) = renamed.iter().next().unwrap();
+        assert_eq!(path, &PathBuf::from("heroes/sun_ring/x.info.darkest"));
+        assert_eq!(node.text(), Some("hero: .id x"));
+    }
+}
@@ -1,56 +1,214 @@
-use super::diff::{
-    Conflict, Conflicts, DataNode, DataNodeContent, DataTree, DataTreeExt, DiffNode, DiffNodeKind,
-    DiffTree, DiffTreeExt, DiffTreesExt, LineChange, LineModification, LinesChangeset, ModContent,
+use super::{
+    diff::{
+        Conflict, Conflicts, DataNode, DataNodeContent, DataTree, DataTreeExt, DiffNode,
+        DiffNodeKind, DiffTree, DiffTreeExt, DiffTreesExt, LineChange, LineModification,
+        LinesChangeset, ModContent, StructuredChangeset,
+    },
+    lock::hash_bytes,
+    names::DisplayNames,
+    structures::{DataPath, Format},
 };
-use crossbeam_channel::bounded;
 use cursive::{
     align::HAlign,
-    traits::{Nameable, Resizable},
-    views::{Button, Dialog, LinearLayout, Panel, SelectView, TextArea, TextView},
+    traits::{Nameable, Resizable, Scrollable},
+    utils::markup::StyledString,
+    views::{Button, Checkbox, Dialog, LinearLayout, Panel, SelectView, TextArea, TextView},
+    Cursive,
 };
 use log::*;
 use std::fmt::Debug;
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Directories of the bundled mods, to show the files the conflicting changes come from.
+#[derive(Debug, Default)]
+pub struct Sources(BTreeMap<String, PathBuf>);
 
-pub fn resolve(sink: &mut cursive::CbSink, conflicts: Conflicts) -> DiffTree {
+/// Files of the conflicting mods, by the mod name.
+type SourceFiles = BTreeMap<String, PathBuf>;
+
+impl Sources {
+    pub fn new(mods: impl IntoIterator<Item = (String, PathBuf)>) -> Self {
+        Self(mods.into_iter().collect())
+    }
+
+    /// Files changed by every mod in the conflict; mods without their own files (e.g. user edits) are skipped.
+    fn files(&self, path: &Path, conflict: &Conflict) -> SourceFiles {
+        conflict
+            .iter()
+            .filter_map(|(name, _)| {
+                let file = self.0.get(name)?.join(path);
+                file.exists().then(|| (name.clone(), file))
+            })
+            .collect()
+    }
+}
+
+/// Copies the file into the temporary space, so that editing it in the opened program doesn't change the mod.
+fn temp_copy(file: &Path) -> Result<PathBuf, crate::temp::TempError> {
+    let dir = crate::temp::create("open")?;
+    let copy = dir.path().join(file.file_name().unwrap_or_default());
+    std::fs::copy(file, &copy).map_err(crate::temp::TempError::from_io(&copy))?;
+    // The program opening the file runs on its own, so the copy stays until the exit.
+    dir.keep();
+    Ok(copy)
+}
+
+/// Opens the copy of the file in the program the system associates it with.
+fn open_source(cursive: &mut Cursive, file: &Path) {
+    info!("[resolve] Opening source file {:?}", file);
+    let file = match temp_copy(file) {
+        Ok(copy) => copy,
+        Err(err) => return crate::error(cursive, &err),
+    };
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    let spawned = command
+        .arg(&file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(err) = spawned {
+        warn!("[resolve] Unable to open {:?}: {}", file, err);
+        crate::error(cursive, &err);
+    }
+}
+
+/// Asks user to resolve every conflict, passing every file to `on_resolved` as soon as it is resolved.
+pub fn resolve(
+    sink: &mut cursive::CbSink,
+    conflicts: Conflicts,
+    original: &DataTree,
+    names: &DisplayNames,
+    sources: &Sources,
+    mut on_resolved: impl FnMut(&Path, &DiffNode),
+) -> DiffTree {
     conflicts
         .into_iter()
         .map(|(path, conflict)| {
             info!("[resolve] {:?}: Resolving conflict", path);
+            let shown = names.describe(&path);
+            let files = sources.files(&path, &conflict);
+            let vanilla = original.get(&path).and_then(DataNode::text);
             let kind = conflict[0].1.kind();
+            if conflict.iter().any(|(_, node)| node.kind() != kind) {
+                info!("[resolve] {:?}: Incompatible changes", path);
+                let resolved = resolve_whole_file(sink, &shown, conflict, files);
+                return (path, resolved);
+            }
             match kind {
                 DiffNodeKind::AddedText => {
                     info!("[resolve] {:?}: Multiple added texts", path);
-                    let (base, changes) = resolve_added_text(sink, path.clone(), conflict);
-                    // Here, we have to do a little differently, since we're essentially resolving conflict
-                    // by applying two actions, but have to make them as one.
-                    let base: DataTree = vec![(path.clone(), DataNode::new(path.clone(), base))]
-                        .into_iter()
-                        .collect();
-                    let changes: DiffTree = vec![(path.clone(), DiffNode::ModifiedText(changes))]
-                        .into_iter()
-                        .collect();
-                    match changes.apply_to(base).remove(&path).unwrap().into_content() {
-                        DataNodeContent::Text(text) => (path, DiffNode::AddedText(text)),
-                        _ => unreachable!(),
-                    }
+                    let text =
+                        resolve_added_text(sink, path.clone(), &shown, conflict, names, sources);
+                    (path, DiffNode::AddedText(text))
                 }
                 DiffNodeKind::Binary => {
                     info!("[resolve] {:?}: Multiple binaries", path);
-                    let resolved = resolve_binary(sink, path.clone(), conflict);
+                    let resolved = resolve_binary(sink, &shown, conflict, files);
                     debug!("[resolve] {:?}: Using {:?}", path, resolved);
                     (path, DiffNode::Binary(resolved))
                 }
                 DiffNodeKind::ModifiedText => {
                     info!("[resolve] {:?}: Multiple text modifications", path);
-                    let resolved = resolve_modified_text(sink, path.clone(), conflict);
+                    let resolved = resolve_modified_text(sink, &shown, conflict, files, vanilla);
                     (path, DiffNode::ModifiedText(resolved))
                 }
+                DiffNodeKind::ModifiedStructured => {
+                    info!("[resolve] {:?}: Multiple structured modifications", path);
+                    let resolved =
+                        resolve_modified_structured(sink, &path, &shown, conflict, files, vanilla);
+                    (path, DiffNode::ModifiedStructured(resolved))
+                }
             }
         })
+        .inspect(|(path, resolved)| on_resolved(path, resolved))
         .collect()
 }
 
+/// Hashes of the conflicting changes, by file, stored along with the resolutions made for them.
+pub type Inputs = BTreeMap<PathBuf, String>;
+
+/// Hash of the conflicting changes, telling whether the stored resolution was made for exactly the same ones.
+pub fn inputs(conflict: &Conflict) -> String {
+    let serialized = serde_json::to_vec(conflict).expect("Conflict can always be serialized");
+    hash_bytes(&serialized)
+}
+
+pub fn all_inputs(conflicts: &Conflicts) -> Inputs {
+    conflicts
+        .iter()
+        .map(|(path, conflict)| (path.clone(), inputs(conflict)))
+        .collect()
+}
+
+/// Reuses the decisions made earlier, if they still fit the current conflicts.
+///
+/// Returns the replayed resolutions and the conflicts which must be resolved anew.
+pub fn replay(stored: &DiffTree, inputs: &Inputs, conflicts: Conflicts) -> (DiffTree, Conflicts) {
+    let mut replayed = DiffTree::new();
+    let mut remaining = Conflicts::new();
+    for (path, conflict) in conflicts {
+        match stored.get(&path) {
+            Some(resolution) if resolution_fits(resolution, inputs.get(&path), &conflict) => {
+                info!("[resolve] {:?}: Replaying stored resolution", path);
+                replayed.insert(path, resolution.clone());
+            }
+            Some(_) => {
+                warn!(
+                    "[resolve] {:?}: Stored resolution doesn't fit the current conflict",
+                    path
+                );
+                remaining.insert(path, conflict);
+            }
+            None => {
+                remaining.insert(path, conflict);
+            }
+        }
+    }
+    (replayed, remaining)
+}
+
+/// Whether the stored resolution can be used for the current conflict, given the hash of the conflicting changes
+/// it was made for, if it was stored.
+pub fn resolution_fits(
+    resolution: &DiffNode,
+    stored: Option<&String>,
+    conflict: &Conflict,
+) -> bool {
+    match (resolution, &conflict[0].1) {
+        // Same number of lines is not enough, the lines themselves might have changed in the game update.
+        (DiffNode::ModifiedText(stored), DiffNode::ModifiedText(current)) => {
+            stored.0.len() == current.0.len() && stored.1 == current.1
+        }
+        (DiffNode::ModifiedStructured(stored), DiffNode::ModifiedStructured(_)) => {
+            let current: BTreeSet<_> = conflict
+                .iter()
+                .flat_map(|(_, node)| match node {
+                    DiffNode::ModifiedStructured(changeset) => changeset.0.keys().collect(),
+                    _ => vec![],
+                })
+                .collect();
+            stored.0.keys().collect::<BTreeSet<_>>() == current
+        }
+        (DiffNode::Binary(source), DiffNode::Binary(_)) => source.exists(),
+        // Merged text is made of the added files themselves, so it's stale as soon as any of them changes.
+        (DiffNode::AddedText(_), DiffNode::AddedText(_)) => stored == Some(&inputs(conflict)),
+        (resolution, current) => resolution.kind() == current.kind(),
+    }
+}
+
 pub fn merge_resolved(merged: DiffTree, resolved: DiffTree) -> DiffTree {
     let (merged, conflicts) = vec![
         ModContent::new("merged", merged),
@@ -62,93 +220,224 @@ pub fn merge_resolved(merged: DiffTree, resolved: DiffTree) -> DiffTree {
     merged
 }
 
-fn ask_for_resolve<T: Debug + Send + Clone + 'static>(
+pub fn ask_for_resolve<T: Debug + Send + Clone + 'static>(
+    sink: &mut cursive::CbSink,
+    text: impl Into<StyledString>,
+    options: impl IntoIterator<Item = (String, T)>,
+) -> T {
+    ask_with_sources(sink, text, options, SourceFiles::new())
+}
+
+/// Same as `ask_for_resolve`, listing the files of the conflicting mods, which can be opened from there.
+fn ask_with_sources<T: Debug + Send + Clone + 'static>(
     sink: &mut cursive::CbSink,
-    text: impl Into<String>,
+    text: impl Into<StyledString>,
     options: impl IntoIterator<Item = (String, T)>,
+    files: SourceFiles,
+) -> T {
+    ask_with_details(sink, text, None, options, files)
+}
+
+/// Conflicting entries shown below the question, with and without the ones every mod agrees on.
+struct Details {
+    collapsed: StyledString,
+    full: StyledString,
+}
+
+fn ask_with_details<T: Debug + Send + Clone + 'static>(
+    sink: &mut cursive::CbSink,
+    text: impl Into<StyledString>,
+    details: Option<Details>,
+    options: impl IntoIterator<Item = (String, T)>,
+    files: SourceFiles,
 ) -> T {
-    let (sender, receiver) = bounded(0);
     let text = text.into();
     let options: Vec<_> = options.into_iter().collect();
     debug!(
         "[resolve]: Asking for source to be used, variants: {:?}",
         options.iter().map(|(name, _)| name).collect::<Vec<_>>()
     );
-    crate::run_update(sink, move |cursive| {
-        crate::push_screen(
-            cursive,
-            Dialog::around(
-                LinearLayout::vertical()
-                    .child(TextView::new(text))
-                    .child(Panel::new(SelectView::new().with_all(options).on_submit(
-                        move |cursive, value| {
-                            cursive.pop_layer();
-                            let _ = sender.send(value.clone());
-                        },
-                    ))),
-            ),
-        );
-    });
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
+    crate::prompt::ask(sink, move |cursive, reply| {
+        let mut layout = LinearLayout::vertical().child(TextView::new(text).scrollable());
+        if let Some(Details { collapsed, full }) = details {
+            let entries = TextView::new(collapsed.clone())
+                .with_name("Conflict details")
+                .scrollable();
+            layout.add_child(
+                LinearLayout::horizontal()
+                    .child(Checkbox::new().on_change(move |cursive, checked| {
+                        let shown = if checked { &full } else { &collapsed };
+                        cursive.call_on_name("Conflict details", |view: &mut TextView| {
+                            view.set_content(shown.clone())
+                        });
+                    }))
+                    .child(TextView::new(
+                        " Conflicting entries (show the ones which are the same in every mod)",
+                    )),
+            );
+            layout.add_child(entries);
+        }
+        layout.add_child(Panel::new(SelectView::new().with_all(options).on_submit(
+            move |cursive, value| {
+                cursive.pop_layer();
+                reply.send(value.clone());
+            },
+        )));
+        if !files.is_empty() {
+            let sources =
+                SelectView::new()
+                    .with_all(files.into_iter().map(|(name, file)| {
+                        (format!("{}: {}", name, file.to_string_lossy()), file)
+                    }))
+                    .on_submit(|cursive, file: &PathBuf| open_source(cursive, file));
+            layout.add_child(Panel::new(sources).title("Changed files (select to open)"));
+        }
+        crate::push_screen(cursive, Dialog::around(layout));
+    })
 }
 
-fn resolve_binary(sink: &mut cursive::CbSink, target: PathBuf, conflict: Conflict) -> PathBuf {
+fn resolve_binary(
+    sink: &mut cursive::CbSink,
+    shown: &str,
+    conflict: Conflict,
+    files: SourceFiles,
+) -> PathBuf {
     let variants = conflict.into_iter().map(|(name, node)| match node {
         DiffNode::Binary(path) => (name, path),
         _ => unreachable!(),
     });
-    ask_for_resolve(
+    ask_with_sources(
         sink,
         format!(
             "Multiple mods are using the binary file {}. Please choose one you wish to use the file from",
-            target.to_string_lossy()
+            shown
         ),
         variants,
+        files,
+    )
+}
+
+fn resolve_whole_file(
+    sink: &mut cursive::CbSink,
+    shown: &str,
+    conflict: Conflict,
+    files: SourceFiles,
+) -> DiffNode {
+    ask_with_sources(
+        sink,
+        format!(
+            "Mods are changing the file {} in ways which can't be merged.
+Please choose one you wish to use the file from",
+            shown
+        ),
+        conflict,
+        files,
     )
 }
 
-fn render_line_choice(line: String, mod_name: String) -> impl cursive::View {
-    Panel::new(
-        LinearLayout::horizontal()
-            .child(TextView::new(line.clone()).full_width())
-            .child(Button::new("Use this", move |cursive| {
-                let line = line.clone();
-                cursive.call_on_name("Line resolve edit", move |edit: &mut TextArea| {
-                    edit.set_content(line)
-                });
-            })),
+/// Outcome of the single manual choice.
+enum Choice {
+    Resolved(Option<String>),
+    /// User wants to resolve the remaining choices in the file in favor of this mod.
+    TakeRest(String),
+}
+
+fn render_line_choice(
+    line: String,
+    mod_name: String,
+    source: Option<PathBuf>,
+    reply: crate::prompt::Reply<Choice>,
+) -> impl cursive::View {
+    let rest_from = mod_name.clone();
+    let mut buttons = LinearLayout::horizontal()
+        .child(TextView::new(line.clone()).full_width())
+        .child(Button::new("Use this", move |cursive| {
+            let line = line.clone();
+            cursive.call_on_name("Line resolve edit", move |edit: &mut TextArea| {
+                edit.set_content(line)
+            });
+        }))
+        .child(Button::new("Take rest from this mod", move |cursive| {
+            cursive.pop_layer();
+            reply.send(Choice::TakeRest(rest_from.clone()));
+        }));
+    let title = match source {
+        Some(source) => {
+            let title = format!("{} ({})", mod_name, source.to_string_lossy());
+            buttons.add_child(Button::new("Open file", move |cursive| {
+                open_source(cursive, &source)
+            }));
+            title
+        }
+        None => mod_name,
+    };
+    Panel::new(buttons)
+        .title(title)
+        .title_position(HAlign::Left)
+}
+
+/// Conflicting values side by side with the differing tokens highlighted, if they are long enough
+/// to be hard to compare by eye, i.e. have several tokens.
+fn diff_view(
+    lines: &[(String, String, Option<PathBuf>)],
+    vanilla: Option<&str>,
+) -> Option<impl cursive::View> {
+    if !lines.iter().any(|(_, line, _)| line.contains(' ')) {
+        return None;
+    }
+    let values: Vec<_> = lines.iter().map(|(_, line, _)| line.as_str()).collect();
+    let mut columns = LinearLayout::horizontal();
+    if let Some(vanilla) = vanilla {
+        columns.add_child(
+            Panel::new(TextView::new(vanilla))
+                .title("Vanilla")
+                .full_width(),
+        );
+    }
+    for ((name, _, _), text) in lines
+        .iter()
+        .zip(super::highlight::token_diff(&values, vanilla))
+    {
+        columns.add_child(Panel::new(TextView::new(text)).title(name).full_width());
+    }
+    Some(
+        LinearLayout::vertical()
+            .child(TextView::new(
+                "Tokens differing between the mods are red, the ones only changed from the vanilla game are yellow.",
+            ))
+            .child(columns),
     )
-    .title(mod_name)
-    .title_position(HAlign::Left)
 }
 
 fn choose_line(
     sink: &mut cursive::CbSink,
-    index: usize,
-    file: impl Into<PathBuf>,
+    title: String,
+    vanilla: Option<String>,
     lines: impl IntoIterator<Item = (String, String)>,
-) -> Option<String> {
-    let lines: Vec<_> = lines.into_iter().collect();
-    let file = file.into();
-    let (sender, receiver) = bounded(0);
+    files: &SourceFiles,
+) -> Choice {
+    let lines: Vec<_> = lines
+        .into_iter()
+        .map(|(name, line)| {
+            let source = files.get(&name).cloned();
+            (name, line, source)
+        })
+        .collect();
 
-    crate::run_update(sink, move |cursive| {
+    crate::prompt::ask(sink, move |cursive, reply| {
         let mut layout = LinearLayout::vertical();
-        lines
-            .into_iter()
-            .for_each(|(name, line)| layout.add_child(render_line_choice(line, name)));
+        if let Some(view) = diff_view(&lines, vanilla.as_deref()) {
+            layout.add_child(view);
+        }
+        lines.into_iter().for_each(|(name, line, source)| {
+            layout.add_child(render_line_choice(line, name, source, reply.clone()))
+        });
         crate::push_screen(
             cursive,
             Dialog::around(
                 layout.child(TextArea::new().with_name("Line resolve edit").full_width()),
             )
-            .title(format!(
-                "Resolving line {} in file {}",
-                index,
-                file.to_string_lossy()
-            ))
+            .title(title)
             .button("Resolve", move |cursive| {
                 let value = cursive
                     .call_on_name("Line resolve edit", |edit: &mut TextArea| {
@@ -160,25 +449,79 @@ fn choose_line(
                     "" => None,
                     val => Some(val.to_string()),
                 };
-                sender.send(value).unwrap();
+                reply.send(Choice::Resolved(value));
             })
             .h_align(cursive::align::HAlign::Center),
         );
-    });
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
+    })
+}
+
+/// One conflicting part of the file: the dialog title, the vanilla value, if any, and the changes made by each mod.
+struct ResolveItem<T> {
+    title: String,
+    vanilla: Option<String>,
+    options: Vec<(String, T)>,
+}
+
+/// Asks user for every conflicting part of the file in turn.
+///
+/// Returns the chosen change for each item, or `None` if it should be left as is - either because
+/// there were no changes at all, or because the rest of the file was taken from the mod which didn't touch this part.
+fn resolve_each<T: Clone>(
+    sink: &mut cursive::CbSink,
+    items: Vec<ResolveItem<T>>,
+    files: &SourceFiles,
+    to_text: impl Fn(&T) -> String,
+    from_text: impl Fn(Option<String>) -> T,
+) -> Vec<Option<T>> {
+    let mut rest_from: Option<String> = None;
+    items
+        .into_iter()
+        .map(|item| {
+            let ResolveItem {
+                title,
+                vanilla,
+                options,
+            } = item;
+            if options.is_empty() {
+                return None;
+            }
+            if rest_from.is_none() {
+                let texts = options
+                    .iter()
+                    .map(|(name, change)| (name.clone(), to_text(change)));
+                match choose_line(sink, title, vanilla, texts, files) {
+                    Choice::Resolved(value) => return Some(from_text(value)),
+                    Choice::TakeRest(name) => {
+                        info!("[resolve] Taking the rest of file from {}", name);
+                        rest_from = Some(name);
+                    }
+                }
+            }
+            let rest_from = rest_from.as_ref().unwrap();
+            options
+                .into_iter()
+                .find(|(name, _)| name == rest_from)
+                .map(|(_, change)| change)
+        })
+        .collect()
 }
 
 fn resolve_changes_manually(
     sink: &mut cursive::CbSink,
-    target: PathBuf,
+    shown: &str,
     conflict: Conflict,
+    files: &SourceFiles,
+    vanilla: Option<&str>,
 ) -> LinesChangeset {
+    let mut original = String::new();
     let changes: Vec<_> = conflict
         .into_iter()
         .map(|(name, node)| match node {
-            DiffNode::ModifiedText(changeset) => (name, changeset.0),
+            DiffNode::ModifiedText(changeset) => {
+                original = changeset.1;
+                (name, changeset.0)
+            }
             _ => unreachable!(),
         })
         .collect();
@@ -198,51 +541,64 @@ fn resolve_changes_manually(
             .zip(change)
             .for_each(|(v, change)| v.push((name.clone(), change)));
     }
-    let line_changes: Vec<Vec<_>> = line_changes
-        .into_iter()
-        .map(|v| {
-            v.into_iter()
-                .filter_map(|(name, change)| change.map(|change| (name, change)))
-                .collect()
-        })
-        .collect();
-
-    let changes = line_changes
+    // Changesets have an item for every line of the original file.
+    let vanilla_lines: Vec<_> = vanilla
+        .map(|text| text.split('\n').collect())
+        .unwrap_or_default();
+    let line_changes: Vec<_> = line_changes
         .into_iter()
         .enumerate()
-        .map(|(index, change)| {
-            if change.is_empty() {
-                None
-            } else {
-                let options = change.into_iter().map(|(name, change)| {
-                    (
-                        name,
-                        match change {
-                            LineChange::Removed => "".into(),
-                            LineChange::Modified(modification) => {
-                                match modification {
-                                    LineModification::Replaced(repl) => repl,
-                                    // FIXME - how this should be handled more gracefully?
-                                    LineModification::Added(_) => unimplemented!(),
-                                }
-                            }
-                        },
-                    )
-                });
-                Some(match choose_line(sink, index, &target, options) {
-                    Some(line) => LineChange::Modified(LineModification::Replaced(line)),
-                    None => LineChange::Removed,
+        .map(|(index, v)| {
+            let vanilla = vanilla_lines.get(index).map(|line| line.to_string());
+            // Lines added after this one are shown together with it, as if it was replaced with all of them,
+            // so that they can be compared with the changes of other mods to the same line.
+            let options = v
+                .into_iter()
+                .filter_map(|(name, change)| {
+                    let change = match change? {
+                        LineChange::Modified(LineModification::Added(added)) => {
+                            let kept = vanilla.as_deref().unwrap_or_default();
+                            LineChange::Modified(LineModification::Replaced(format!(
+                                "{}\n{}",
+                                kept, added
+                            )))
+                        }
+                        change => change,
+                    };
+                    Some((name, change))
                 })
+                .collect();
+            ResolveItem {
+                title: format!("Resolving line {} in file {}", index, shown),
+                vanilla,
+                options,
             }
         })
         .collect();
-    LinesChangeset(changes)
+
+    let changes = resolve_each(
+        sink,
+        line_changes,
+        files,
+        |change| match change {
+            LineChange::Removed => "".into(),
+            LineChange::Modified(LineModification::Replaced(repl))
+            | LineChange::Modified(LineModification::Added(repl)) => repl.clone(),
+        },
+        |line| match line {
+            Some(line) => LineChange::Modified(LineModification::Replaced(line)),
+            None => LineChange::Removed,
+        },
+    );
+    LinesChangeset(changes, original)
 }
 
 fn resolve_modified_text(
     sink: &mut cursive::CbSink,
-    target: PathBuf,
+    shown: &str,
     conflict: Conflict,
+    files: SourceFiles,
+    vanilla: Option<&str>,
 ) -> LinesChangeset {
     // Clone conflict, to use it later in manual resolution if necessary
     let variants = conflict
@@ -253,30 +609,128 @@ fn resolve_modified_text(
             _ => unreachable!(),
         })
         .chain(std::iter::once(("Resolve manually".into(), None)));
-    let changeset = ask_for_resolve(
+    let changeset = ask_with_sources(
         sink,
         format!(
             "Multiple mods are changing the text file {}.
 Non-conflicting changes were already merged.
 Please choose the file you wish to use for conflicting cases, or resolve changes to each line manually
 ",
-            target.to_string_lossy()
+            shown
         ),
         variants,
+        files.clone(),
     );
     match changeset {
         Some(changeset) => changeset,
-        None => resolve_changes_manually(sink, target, conflict),
+        None => resolve_changes_manually(sink, shown, conflict, &files, vanilla),
+    }
+}
+
+fn resolve_structured_manually(
+    sink: &mut cursive::CbSink,
+    shown: &str,
+    conflict: Conflict,
+    files: &SourceFiles,
+    vanilla: &BTreeMap<DataPath, String>,
+) -> StructuredChangeset {
+    // Same "transpose" as for the text, but values are identified by their paths.
+    let mut value_changes: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for (name, node) in conflict {
+        match node {
+            DiffNode::ModifiedStructured(changeset) => {
+                for (data_path, change) in changeset.0 {
+                    value_changes
+                        .entry(data_path)
+                        .or_default()
+                        .push((name.clone(), change));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    let (paths, items): (Vec<_>, Vec<_>) = value_changes
+        .into_iter()
+        .map(|(data_path, options)| {
+            let item = ResolveItem {
+                title: format!("Resolving value {} in file {}", data_path, shown),
+                vanilla: vanilla.get(&data_path).cloned(),
+                options,
+            };
+            (data_path, item)
+        })
+        .unzip();
+    let changes = resolve_each(
+        sink,
+        items,
+        files,
+        |value: &Option<String>| value.clone().unwrap_or_default(),
+        |value| value,
+    );
+    let changes = paths
+        .into_iter()
+        .zip(changes)
+        .filter_map(|(data_path, change)| change.map(|change| (data_path, change)))
+        .collect();
+    StructuredChangeset(changes)
+}
+
+fn resolve_modified_structured(
+    sink: &mut cursive::CbSink,
+    path: &Path,
+    shown: &str,
+    conflict: Conflict,
+    files: SourceFiles,
+    vanilla: Option<&str>,
+) -> StructuredChangeset {
+    let variants = conflict
+        .clone()
+        .into_iter()
+        .map(|(name, node)| match node {
+            DiffNode::ModifiedStructured(changeset) => (name, Some(changeset)),
+            _ => unreachable!(),
+        })
+        .chain(std::iter::once(("Resolve manually".into(), None)));
+    let text = format!(
+        "Multiple mods are changing the same values in the file {}.
+Non-conflicting changes were already merged.
+Please choose the mod you wish to use for conflicting values, or resolve each value manually
+",
+        shown
+    );
+    let data_type = super::structures::data_type(path);
+    let details = matches!(
+        data_type.map(|data_type| data_type.format),
+        Some(Format::Darkest)
+    )
+    .then(|| Details {
+        collapsed: super::highlight::darkest_conflict(&conflict, false),
+        full: super::highlight::darkest_conflict(&conflict, true),
+    });
+    let changeset = ask_with_details(sink, text, details, variants, files.clone());
+    match changeset {
+        Some(changeset) => changeset,
+        None => {
+            let vanilla = vanilla
+                .zip(data_type)
+                .and_then(|(text, data_type)| data_type.format.flatten(text).ok())
+                .unwrap_or_default();
+            resolve_structured_manually(sink, shown, conflict, &files, &vanilla)
+        }
     }
 }
 
 fn resolve_added_text(
     sink: &mut cursive::CbSink,
     target: PathBuf,
+    shown: &str,
     conflict: Conflict,
-) -> (String, LinesChangeset) {
-    // First, store the data a little more appropriately.
-    let mut data: std::collections::HashMap<_, _> = conflict
+    names: &DisplayNames,
+    sources: &Sources,
+) -> String {
+    let files = sources.files(&target, &conflict);
+    // First, store the data a little more appropriately, keeping the mods in the bundling order.
+    let mut data: Vec<_> = conflict
         .into_iter()
         .map(|(name, node)| match node {
             DiffNode::AddedText(text) => (name, text),
@@ -284,19 +738,21 @@ fn resolve_added_text(
         })
         .collect();
 
-    let variants = data.keys().cloned().map(|name| (name.clone(), name));
-    let choice = ask_for_resolve(
+    let variants = data.iter().map(|(name, _)| (name.clone(), name.clone()));
+    let choice = ask_with_sources(
         sink,
         format!(
             "Multiple mods are adding the text file {}.
 In this case, we treat one of them as if it is a part of vanilla game, and merge others based on it.
 Please choose one you wish to use as basic one.
 ",
-            target.to_string_lossy()
+            shown
         ),
         variants,
+        files,
     );
-    let chosen = data.remove(&choice).unwrap();
+    let chosen = data.iter().position(|(name, _)| *name == choice).unwrap();
+    let (_, chosen) = data.remove(chosen);
     let base: DataTree = vec![(target.clone(), DataNode::new("", chosen.clone()))]
         .into_iter()
         .collect();
@@ -314,16 +770,322 @@ Please choose one you wish to use as basic one.
             )
         })
         .merge(None);
-    let resolved = resolve(sink, conflicts);
-    let mut merged = merge_resolved(merged, resolved);
+    let resolved = resolve(sink, conflicts, &base, names, sources, |_, _| {});
+    // Here, we have to do a little differently, since we're essentially resolving conflict
+    // by applying two actions, but have to make them as one.
+    match merge_resolved(merged, resolved)
+        .apply_to(&base)
+        .remove(&target)
+        .map(DataNode::into_content)
+    {
+        Some(DataNodeContent::Text(text)) => text,
+        Some(DataNodeContent::Binary) => unreachable!(),
+        // Other mods didn't change anything compared to the chosen one.
+        None => chosen,
+    }
+}
 
-    let changeset = match merged.remove(&target) {
-        Some(changes) => match changes {
-            DiffNode::ModifiedText(changeset) => changeset,
-            _ => unreachable!(),
-        },
-        None => unreachable!(),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::puppet::{assert_snapshot, drive};
+    use cursive::event::{Event, Key};
 
-    (chosen, changeset)
+    /// Conflicts between the mods changing the same file of the game.
+    fn conflicts(original: &DataTree, mods: &[(&str, &str, &str)]) -> Conflicts {
+        let (_, conflicts) = mods
+            .iter()
+            .map(|(name, path, text)| {
+                let modded = vec![(PathBuf::from(path), DataNode::new(*name, text.to_string()))];
+                ModContent::new(*name, original.diff(modded.into_iter().collect()))
+            })
+            .merge(None);
+        conflicts
+    }
+
+    /// Resolves the conflicts with the scripted inputs, checking every shown dialog against its snapshot.
+    fn resolve_scripted(
+        name: &str,
+        original: DataTree,
+        conflicts: Conflicts,
+        inputs: Vec<Vec<Event>>,
+    ) -> DiffTree {
+        let ((resolved, reported), frames) = drive(
+            move |sink| {
+                let names = DisplayNames::new(&original, &DiffTree::new(), None);
+                let mut reported = vec![];
+                let resolved = resolve(
+                    sink,
+                    conflicts,
+                    &original,
+                    &names,
+                    &Sources::default(),
+                    |path, _| reported.push(path.to_owned()),
+                );
+                (resolved, reported)
+            },
+            inputs,
+        );
+        for (index, frame) in frames.iter().enumerate() {
+            assert_snapshot(&format!("{}_{}", name, index), frame);
+        }
+        // Every file is reported as resolved, so that it is saved in the session.
+        let mut reported = reported;
+        reported.sort();
+        assert_eq!(reported, resolved.keys().cloned().collect::<Vec<_>>());
+        resolved
+    }
+
+    fn tree(files: &[(&str, &str)]) -> DataTree {
+        files
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), DataNode::new("", text.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn binary_file_is_chosen() {
+        let path = PathBuf::from("heroes/hag/hag.png");
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            path.clone(),
+            vec![
+                (
+                    "Hag".into(),
+                    DiffNode::Binary("hag/heroes/hag/hag.png".into()),
+                ),
+                (
+                    "Marvin".into(),
+                    DiffNode::Binary("marvin/heroes/hag/hag.png".into()),
+                ),
+            ],
+        );
+        let resolved = resolve_scripted(
+            "binary",
+            DataTree::new(),
+            conflicts,
+            vec![vec![Event::Key(Key::Down), Event::Key(Key::Enter)]],
+        );
+        assert!(
+            matches!(&resolved[&path], DiffNode::Binary(chosen) if chosen == Path::new("marvin/heroes/hag/hag.png"))
+        );
+    }
+
+    #[test]
+    fn text_resolution_fits_only_the_same_original() {
+        let path = "scripts/notes.txt";
+        let mods = [
+            ("Hag", path, "first\nchanged\nthird"),
+            ("Marvin", path, "first\nother\nthird"),
+        ];
+        let original = tree(&[(path, "first\nsecond\nthird")]);
+        let conflict = conflicts(&original, &mods).remove(Path::new(path)).unwrap();
+        let resolution = conflict[0].1.clone();
+        assert!(resolution_fits(&resolution, None, &conflict));
+
+        // Game update has changed the line, keeping the number of them.
+        let updated = tree(&[(path, "first\nsecond, updated\nthird")]);
+        let stale = conflicts(&updated, &mods).remove(Path::new(path)).unwrap();
+        assert!(!resolution_fits(&resolution, None, &stale));
+
+        // Resolutions stored without the hash of the original are asked for again.
+        let unhashed: DiffNode = serde_json::from_str(
+            r#"{"ModifiedText": [null, {"Modified": {"Replaced": "x"}}, null]}"#,
+        )
+        .unwrap();
+        assert!(!resolution_fits(&unhashed, None, &conflict));
+    }
+
+    #[test]
+    fn added_text_resolution_fits_only_the_same_files() {
+        let path = "raid/raid.json";
+        let mods = [("Hag", path, "{\"a\": 1}"), ("Marvin", path, "{\"b\": 2}")];
+        let conflict = conflicts(&DataTree::new(), &mods)
+            .remove(Path::new(path))
+            .unwrap();
+        let resolution = DiffNode::AddedText("{\"a\": 1, \"b\": 2}".into());
+        let stored = inputs(&conflict);
+        assert!(resolution_fits(&resolution, Some(&stored), &conflict));
+
+        // One of the mods has changed the added file since.
+        let updated = [("Hag", path, "{\"a\": 3}"), mods[1]];
+        let changed = conflicts(&DataTree::new(), &updated)
+            .remove(Path::new(path))
+            .unwrap();
+        assert!(!resolution_fits(&resolution, Some(&stored), &changed));
+
+        // Resolutions stored without the hash of the added files are asked for again.
+        assert!(!resolution_fits(&resolution, None, &conflict));
+    }
+
+    #[test]
+    fn lines_are_resolved_manually() {
+        let path = "scripts/notes.txt";
+        let original = tree(&[(path, "first\nsecond line\nthird")]);
+        let conflicts = conflicts(
+            &original,
+            &[
+                ("Hag", path, "first\nsecond changed line\nthird"),
+                ("Marvin", path, "first\nsecond line, changed\nthird"),
+            ],
+        );
+        // "Resolve manually", then "Take rest from this mod" for Marvin.
+        let resolved = resolve_scripted(
+            "lines",
+            original.clone(),
+            conflicts,
+            vec![
+                vec![
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Enter),
+                ],
+                vec![
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Right),
+                    Event::Key(Key::Enter),
+                ],
+            ],
+        );
+        let text = resolved
+            .apply_to(&original)
+            .remove(Path::new(path))
+            .unwrap();
+        assert_eq!(text.text(), Some("first\nsecond line, changed\nthird"));
+    }
+
+    #[test]
+    fn added_lines_are_resolved_manually() {
+        let path = "scripts/notes.txt";
+        let original = tree(&[(path, "first\nsecond\nthird")]);
+        let conflicts = conflicts(
+            &original,
+            &[
+                ("Hag", path, "first\nsecond\nadded by Hag\nthird"),
+                ("Marvin", path, "first\nsecond, changed\nthird"),
+            ],
+        );
+        // "Resolve manually", then "Use this" for Hag and "Resolve".
+        let resolved = resolve_scripted(
+            "added_lines",
+            original.clone(),
+            conflicts,
+            vec![
+                vec![
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Enter),
+                ],
+                vec![
+                    Event::Key(Key::Enter),
+                    Event::Key(Key::Tab),
+                    Event::Key(Key::Enter),
+                ],
+            ],
+        );
+        let text = resolved
+            .apply_to(&original)
+            .remove(Path::new(path))
+            .unwrap();
+        assert_eq!(text.text(), Some("first\nsecond\nadded by Hag\nthird"));
+    }
+
+    #[test]
+    fn values_are_resolved_manually() {
+        let path = "heroes/hag/hag.info.darkest";
+        let original = tree(&[(
+            path,
+            "combat_skill: .id \"curse\" .effect \"Stun 1\" \"Bleed 1\" .atk 90%\n",
+        )]);
+        let conflicts = conflicts(
+            &original,
+            &[
+                (
+                    "Hag",
+                    path,
+                    "combat_skill: .id \"curse\" .effect \"Stun 1\" \"Bleed 2\" .atk 95%\n",
+                ),
+                (
+                    "Marvin",
+                    path,
+                    "combat_skill: .id \"curse\" .effect \"Stun 2\" \"Bleed 1\" .atk 85%\n",
+                ),
+            ],
+        );
+        // "Resolve manually", then the value typed by hand for every conflicting value.
+        let typed = |value: &str| {
+            let mut events = vec![Event::Key(Key::Down), Event::Key(Key::Down)];
+            events.extend(value.chars().map(Event::Char));
+            events.push(Event::Key(Key::Tab));
+            events.push(Event::Key(Key::Enter));
+            events
+        };
+        let resolved = resolve_scripted(
+            "values",
+            original.clone(),
+            conflicts,
+            vec![
+                // Focus starts on the details checkbox, above the list of mods.
+                vec![
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Down),
+                    Event::Key(Key::Enter),
+                ],
+                typed("90%"),
+                typed("\"Stun 2\" \"Bleed 2\""),
+            ],
+        );
+        let text = resolved
+            .apply_to(&original)
+            .remove(Path::new(path))
+            .unwrap();
+        assert_eq!(
+            text.text(),
+            Some("combat_skill: .id \"curse\" .effect \"Stun 2\" \"Bleed 2\" .atk 90%\n")
+        );
+    }
+
+    #[test]
+    fn added_file_is_based_on_one_mod() {
+        let path = "scripts/new.txt";
+        let original = DataTree::new();
+        let conflicts = conflicts(
+            &original,
+            &[("Hag", path, "one\ntwo"), ("Marvin", path, "one\nthree")],
+        );
+        let resolved = resolve_scripted(
+            "added",
+            original,
+            conflicts,
+            vec![vec![Event::Key(Key::Enter)]],
+        );
+        // The text of the other mod is merged into the chosen one, which is the first in order.
+        assert!(
+            matches!(&resolved[Path::new(path)], DiffNode::AddedText(text) if text == "one\nthree")
+        );
+    }
+
+    #[test]
+    fn incompatible_changes_take_whole_file() {
+        let path = PathBuf::from("scripts/notes.txt");
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            path.clone(),
+            vec![
+                (
+                    "Hag".into(),
+                    DiffNode::Binary("hag/scripts/notes.txt".into()),
+                ),
+                ("Marvin".into(), DiffNode::AddedText("notes".into())),
+            ],
+        );
+        let resolved = resolve_scripted(
+            "whole_file",
+            DataTree::new(),
+            conflicts,
+            vec![vec![Event::Key(Key::Down), Event::Key(Key::Enter)]],
+        );
+        assert!(matches!(&resolved[&path], DiffNode::AddedText(text) if text == "notes"));
+    }
 }
@@ -0,0 +1,168 @@
+//! Opt-in test harness running the whole pipeline on real mods, to catch parser regressions before releases.
+//!
+//! Enabled with the `sample-mods` feature; the mods are taken from the directory in `DD_SAMPLE_MODS`
+//! (one subdirectory per mod, e.g. the Workshop directory), and the game from `DD_GAME_DIR`, if it is set.
+//! Without the game, every mod file is treated as an added one, so only parsing and merging of mods is checked.
+//!
+//! ```text
+//! DD_SAMPLE_MODS=/path/to/workshop/content/262060 cargo test --features sample-mods samples
+//! ```
+//!
+//! The report of the files which couldn't be read or parsed is logged and written to `sample_mods_report.txt`.
+
+use super::{
+    diff::{DataTree, DataTreeExt, DiffTreeExt, DiffTreesExt, ModContent},
+    extract_data, structures,
+};
+use log::*;
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+const REPORT_FILE: &str = "sample_mods_report.txt";
+
+#[derive(Default)]
+struct Report {
+    /// Files with the text extensions which were read as binary, since they aren't valid UTF-8.
+    binary: Vec<String>,
+    /// Structured files which couldn't be parsed, and would be merged line by line.
+    unparsed: Vec<String>,
+    /// Structured files which changed after being flattened and rebuilt without any changes.
+    round_trip: Vec<String>,
+    panics: Vec<String>,
+}
+
+impl Report {
+    fn render(&self) -> String {
+        let section = |title: &str, lines: &[String]| {
+            format!("{} ({}):\n{}\n", title, lines.len(), lines.join("\n"))
+        };
+        [
+            section("Text files read as binary", &self.binary),
+            section("Structured files which couldn't be parsed", &self.unparsed),
+            section("Structured files broken by round trip", &self.round_trip),
+            section("Panics", &self.panics),
+        ]
+        .join("\n")
+    }
+}
+
+fn load(path: &Path) -> DataTree {
+    let (mut sink, _receiver) = crossbeam_channel::unbounded();
+    extract_data(&mut sink, path, path, true, None)
+        .unwrap_or_else(|err| panic!("Unable to read {:?}: {}", path, err))
+}
+
+/// Checks that every structured file is parsed, and that it is flattened in the same way after rebuilding.
+fn check_files(name: &str, tree: &DataTree, report: &mut Report) {
+    for (path, node) in tree {
+        let shown = format!("{}: {}", name, path.to_string_lossy());
+        let text = match node.text() {
+            Some(text) => text,
+            None => {
+                let textual = matches!(
+                    path.extension().and_then(std::ffi::OsStr::to_str),
+                    Some("js") | Some("darkest") | Some("xml") | Some("json") | Some("txt")
+                );
+                if textual {
+                    report.binary.push(shown);
+                }
+                continue;
+            }
+        };
+        let format = match structures::data_type(path) {
+            Some(data_type) => data_type.format,
+            None => continue,
+        };
+        let map = match format.flatten(text) {
+            Ok(map) => map,
+            Err(err) => {
+                report.unparsed.push(format!("{} ({})", shown, err));
+                continue;
+            }
+        };
+        let intact = format
+            .rebuild(text, map.clone())
+            .and_then(|rebuilt| format.flatten(&rebuilt))
+            .is_ok_and(|reparsed| reparsed == map);
+        if !intact {
+            report.round_trip.push(shown);
+        }
+    }
+}
+
+fn sample_mods(dir: &Path) -> Vec<PathBuf> {
+    let mut mods: Vec<_> = std::fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("Unable to read sample mods in {:?}: {}", dir, err))
+        .map(|entry| entry.expect("Unable to read sample mods entry").path())
+        .filter(|path| path.is_dir())
+        .collect();
+    mods.sort();
+    mods
+}
+
+#[test]
+fn samples_survive_the_pipeline() {
+    let dir = match std::env::var_os("DD_SAMPLE_MODS") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            warn!("DD_SAMPLE_MODS is not set, skipping the sample mods");
+            return;
+        }
+    };
+    let original = std::env::var_os("DD_GAME_DIR")
+        .map(|game| load(Path::new(&game)))
+        .unwrap_or_default();
+    let mut report = Report::default();
+    check_files("Game", &original, &mut report);
+
+    let mut contents = vec![];
+    for path in sample_mods(&dir) {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        info!("[samples] Checking mod {}", name);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let tree = load(&path);
+            check_files(&name, &tree, &mut report);
+            original.diff(tree)
+        }));
+        match result {
+            Ok(diff) => contents.push(ModContent::new(name, diff)),
+            Err(_) => report.panics.push(format!("{}: loading or diffing", name)),
+        }
+    }
+    let mods = contents.len();
+    let merged = catch_unwind(AssertUnwindSafe(|| {
+        let (merged, conflicts) = contents.into_iter().merge(None);
+        info!(
+            "[samples] Merged {} mods, {} files with conflicts",
+            mods,
+            conflicts.len()
+        );
+        merged.apply_to(&original).len()
+    }));
+    match merged {
+        Ok(files) => info!("[samples] Bundle would have {} files", files),
+        Err(_) => report
+            .panics
+            .push("Merging or applying the merged changes".into()),
+    }
+
+    let rendered = report.render();
+    info!("[samples] Report:\n{}", rendered);
+    std::fs::write(REPORT_FILE, &rendered).expect("Unable to write the sample mods report");
+    assert!(
+        report.panics.is_empty(),
+        "Pipeline panicked: {:?}",
+        report.panics
+    );
+    assert!(
+        report.round_trip.is_empty(),
+        "Round trip changed the files: {:?}",
+        report.round_trip
+    );
+}
@@ -0,0 +1,251 @@
+use super::{diff::DataTree, error::ExtractionError, structures};
+use crate::loader::GlobalData;
+use cursive::{
+    traits::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, TextView},
+    Cursive,
+};
+use log::*;
+use std::path::Path;
+
+/// One place where the query was found.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hit {
+    path: String,
+    /// Data path of the value, or line number for the unstructured files.
+    location: String,
+    text: String,
+}
+
+/// Finds the query in the file paths, in the values of structured files (and their data paths),
+/// and in the lines of other text files.
+fn search_tree(tree: &DataTree, query: &str) -> Vec<Hit> {
+    let mut hits = vec![];
+    for (path, node) in tree {
+        let shown = path.to_string_lossy().into_owned();
+        if shown.contains(query) {
+            hits.push(Hit {
+                path: shown.clone(),
+                location: "file name".into(),
+                text: String::new(),
+            });
+        }
+        let text = match node.text() {
+            Some(text) => text,
+            None => continue,
+        };
+        let flattened = structures::data_type(path).and_then(|data_type| {
+            data_type
+                .format
+                .flatten(text)
+                .map_err(|err| debug!("[search] {:?} is searched as plain text: {}", path, err))
+                .ok()
+        });
+        match flattened {
+            Some(map) => hits.extend(
+                map.into_iter()
+                    .map(|(data_path, value)| (data_path.to_string(), value))
+                    .filter(|(data_path, value)| data_path.contains(query) || value.contains(query))
+                    .map(|(location, value)| Hit {
+                        path: shown.clone(),
+                        location,
+                        text: value,
+                    }),
+            ),
+            None => hits.extend(
+                text.lines()
+                    .enumerate()
+                    .filter(|(_, line)| line.contains(query))
+                    .map(|(index, line)| Hit {
+                        path: shown.clone(),
+                        location: format!("line {}", index + 1),
+                        text: line.trim().to_owned(),
+                    }),
+            ),
+        }
+    }
+    hits
+}
+
+/// Matches found in the game, in some DLC or in some mod.
+pub struct Found {
+    pub source: String,
+    /// Directory of the mod, if the matches are found in a mod.
+    pub mod_directory: Option<String>,
+    pub hits: Vec<Hit>,
+}
+
+/// Loads vanilla game, every DLC and every mod in turn, searching each of them as soon as it is loaded.
+pub fn search_all(
+    sink: &mut cursive::CbSink,
+    base_path: &Path,
+    mods: &[crate::loader::Mod],
+    query: &str,
+) -> Result<Vec<Found>, ExtractionError> {
+    let game = crate::paths::game(base_path);
+    let mut sources = vec![("Vanilla game".to_string(), game.clone(), None)];
+    let dlc_path = game.join("dlc");
+    for entry in super::dlc::entries(&dlc_path)?.into_iter().flatten() {
+        let path = entry.map_err(ExtractionError::from_io(&dlc_path))?.path();
+        if path.is_dir() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            sources.push((format!("DLC {}", name), path.clone(), None));
+        }
+    }
+    sources.extend(mods.iter().map(|the_mod| {
+        (
+            format!("Mod {}", the_mod.name()),
+            the_mod.path.clone(),
+            Some(the_mod.directory()),
+        )
+    }));
+
+    let mut results = vec![];
+    for (source, path, mod_directory) in sources {
+        info!("[search] Searching {} for \"{}\"", source, query);
+        let shown = source.clone();
+        crate::run_update(sink, move |cursive| {
+            cursive.call_on_name("Loading part", |text: &mut TextView| {
+                text.set_content(shown);
+            });
+        });
+        let tree = super::extract_data(sink, &path, &path, true, None)?;
+        let hits = search_tree(&tree, query);
+        if !hits.is_empty() {
+            results.push(Found {
+                source,
+                mod_directory,
+                hits,
+            });
+        }
+    }
+    Ok(results)
+}
+
+pub fn render_hits(hits: &[Hit]) -> String {
+    let mut text = String::new();
+    for hit in hits {
+        text.push_str(&format!("  {}: {}", hit.path, hit.location));
+        if !hit.text.is_empty() {
+            text.push_str(&format!(" = {}", hit.text));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn render(query: &str, results: &[Found]) -> String {
+    if results.is_empty() {
+        return format!("\"{}\" was not found anywhere.", query);
+    }
+    let mut text = String::new();
+    for found in results {
+        text.push_str(&format!(
+            "{} ({} matches):\n",
+            found.source,
+            found.hits.len()
+        ));
+        text.push_str(&render_hits(&found.hits));
+        text.push('\n');
+    }
+    text
+}
+
+fn run(cursive: &mut Cursive, query: &str) {
+    let query = query.trim().to_owned();
+    if query.is_empty() {
+        return;
+    }
+    let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+    let base_path = global_data.base_path.clone();
+    let mods = global_data.mods.clone();
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(" ").with_name("Loading part"))
+                .child(TextView::new(" ").with_name("Loading filename")),
+        )
+        .title(format!("Searching for \"{}\"...", query)),
+    );
+    let mut sink = cursive.cb_sink().clone();
+    std::thread::spawn(move || {
+        let result = search_all(&mut sink, &base_path, &mods, &query);
+        crate::run_update(&mut sink, move |cursive| {
+            cursive.pop_layer();
+            match result {
+                Ok(results) => cursive.add_layer(
+                    Dialog::around(TextView::new(render(&query, &results)).scrollable())
+                        .title(format!("Search results for \"{}\"", query))
+                        .dismiss_button("Close")
+                        .full_screen(),
+                ),
+                Err(err) => crate::error(cursive, &err),
+            }
+        });
+    });
+}
+
+/// Screen to find every file and value in the game, DLCs and loaded mods containing the given text,
+/// e.g. the buff or skill id.
+pub fn screen(cursive: &mut Cursive) {
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "Text to search for in the game, DLC and mods data (e.g. a buff or skill id):",
+                ))
+                .child(
+                    EditView::new()
+                        .on_submit(run)
+                        .with_name("Search query")
+                        .full_width(),
+                ),
+        )
+        .title("Search loaded data")
+        .button("Search", |cursive| {
+            let query = cursive
+                .call_on_name("Search query", |view: &mut EditView| view.get_content())
+                .unwrap_or_default();
+            run(cursive, &query);
+        })
+        .dismiss_button("Close"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::DataNode;
+
+    #[test]
+    fn finds_values_and_lines() {
+        let tree: DataTree = vec![
+            (
+                "monsters/brigand_cutthroat/brigand_cutthroat.info.darkest".into(),
+                DataNode::new("", "skill: .id \"slice\" .effect \"bleed_1\"\n".to_string()),
+            ),
+            (
+                "shared/buffs/base.buffs.json".into(),
+                DataNode::new("", "{\n  \"id\": \"bleed_1\"\n}\n".to_string()),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let hits = search_tree(&tree, "bleed_1");
+        assert_eq!(
+            hits,
+            vec![
+                Hit {
+                    path: "monsters/brigand_cutthroat/brigand_cutthroat.info.darkest".into(),
+                    location: "skill/slice/effect".into(),
+                    text: "\"bleed_1\"".into(),
+                },
+                Hit {
+                    path: "shared/buffs/base.buffs.json".into(),
+                    location: "line 2".into(),
+                    text: "\"id\": \"bleed_1\"".into(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use super::{
+    category::{self, Category},
+    diff::{Conflicts, DiffNode, DiffTree},
+    error::SessionError,
+    lock::LockedMod,
+    names::DisplayNames,
+};
+use cursive::{
+    traits::{Nameable, Scrollable},
+    views::{Checkbox, Dialog, ListView},
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Resolutions made for the part of the conflicts, so that the rest can be resolved later.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    /// Mods the resolutions were made for; the session is only resumed for exactly the same ones.
+    pub mods: Vec<LockedMod>,
+    pub resolutions: DiffTree,
+    /// Hashes of the conflicting changes the resolutions were made for.
+    #[serde(default)]
+    pub inputs: BTreeMap<PathBuf, String>,
+    /// Files resolved by the user, as opposed to the ones resolved automatically or replayed from the earlier
+    /// decisions, the preset or the translations.
+    #[serde(default)]
+    pub chosen: BTreeSet<PathBuf>,
+}
+
+/// Whether both lists contain the same mods in the same state and order.
+fn same_mods(stored: &[LockedMod], current: &[LockedMod]) -> bool {
+    stored.len() == current.len()
+        && stored.iter().zip(current).all(|(stored, current)| {
+            stored.directory == current.directory && stored.hash == current.hash
+        })
+}
+
+impl Session {
+    fn read() -> Result<Self, SessionError> {
+        let path = crate::paths::session();
+        let text = std::fs::read_to_string(&path).map_err(SessionError::from_io(&path))?;
+        serde_json::from_str(&text).map_err(|err| SessionError::Json(err, path))
+    }
+
+    /// Saved session, if there is one and it can be read.
+    pub fn stored() -> Option<Self> {
+        if !crate::paths::session().exists() {
+            return None;
+        }
+        Self::read()
+            .map_err(|err| warn!("[session] Unable to read saved session: {}", err))
+            .ok()
+    }
+
+    /// Loads the saved session, if there is one and it was made for the given mods.
+    pub fn load(mods: &[LockedMod]) -> Option<Self> {
+        let session = Self::stored()?;
+        if !same_mods(&session.mods, mods) {
+            warn!("[session] Saved session was made for other mods, ignoring it");
+            return None;
+        }
+        info!(
+            "[session] Resuming session with {} resolved files",
+            session.resolutions.len()
+        );
+        Some(session)
+    }
+
+    pub fn save(&self) -> Result<(), SessionError> {
+        let path = crate::paths::session();
+        info!("[session] Saving session to {:?}", path);
+        let text = serde_json::to_string_pretty(self).map_err(SessionError::Serialization)?;
+        std::fs::write(&path, text).map_err(SessionError::from_io(&path))
+    }
+
+    /// Adds the file resolved just now and saves the session at once, so that it survives a crash or quitting.
+    ///
+    /// Failure is only logged: the resolution goes on, and the session is saved again with the next file.
+    pub fn record(&mut self, path: &Path, resolved: &DiffNode) {
+        self.resolutions.insert(path.to_owned(), resolved.clone());
+        self.chosen.insert(path.to_owned());
+        if let Err(err) = self.save() {
+            warn!("[session] Unable to save resolution of {:?}: {}", path, err);
+        }
+    }
+
+    /// Removes the saved session, since all of its conflicts are resolved.
+    pub fn remove() {
+        if crate::paths::session().exists() {
+            info!("[session] All conflicts resolved, removing saved session");
+            Self::delete();
+        }
+    }
+
+    /// Removes the saved session, which the user doesn't want to resume.
+    pub fn discard() {
+        info!("[session] Discarding saved session");
+        Self::delete();
+    }
+
+    fn delete() {
+        let path = crate::paths::session();
+        if let Err(err) = std::fs::remove_file(&path) {
+            warn!("[session] Unable to remove {:?}: {}", path, err);
+        }
+    }
+}
+
+/// Asks user which of the conflicting files to resolve now; the rest is left for the later session.
+pub fn choose_files(
+    sink: &mut cursive::CbSink,
+    conflicts: &Conflicts,
+    names: &DisplayNames,
+) -> BTreeSet<PathBuf> {
+    let paths: Vec<_> = conflicts
+        .keys()
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if paths.len() < 2 {
+        return paths.into_iter().collect();
+    }
+    let all_now = super::resolve::ask_for_resolve(
+        sink,
+        format!(
+            "There are {} files with conflicts.
+You can resolve them all now, or only some of them and resume later: the bundle is not deployed
+until every conflict is resolved, but the decisions made so far are saved.",
+            paths.len()
+        ),
+        vec![
+            ("Resolve all files now".to_string(), true),
+            ("Choose files to resolve now".to_string(), false),
+        ],
+    );
+    if all_now {
+        return paths.into_iter().collect();
+    }
+
+    let shown: Vec<_> = paths
+        .iter()
+        .map(|path| match category::of_conflict(path, &conflicts[path]) {
+            Category::Cosmetic => format!("{} (cosmetic)", names.describe(path)),
+            Category::Gameplay => names.describe(path),
+        })
+        .collect();
+    let chosen: BTreeSet<_> = crate::prompt::ask(sink, move |cursive, reply| {
+        let mut list = ListView::new();
+        for (index, shown) in shown.into_iter().enumerate() {
+            list.add_child(
+                &shown,
+                Checkbox::new().with_name(format!("Resolve now {}", index)),
+            );
+        }
+        crate::push_screen(
+            cursive,
+            Dialog::around(list.scrollable())
+                .title("Files to resolve now")
+                .button("Resolve selected", move |cursive| {
+                    let chosen: Vec<_> = (0..paths.len())
+                        .filter(|index| {
+                            cursive
+                                .call_on_name(
+                                    &format!("Resolve now {}", index),
+                                    |checkbox: &mut Checkbox| checkbox.is_checked(),
+                                )
+                                .unwrap_or_default()
+                        })
+                        .map(|index| paths[index].clone())
+                        .collect();
+                    cursive.pop_layer();
+                    reply.send(chosen);
+                }),
+        );
+    })
+    .into_iter()
+    .collect();
+    info!("[session] Resolving {} files now", chosen.len());
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(directory: &str, hash: &str) -> LockedMod {
+        LockedMod {
+            title: directory.into(),
+            directory: directory.into(),
+            version: None,
+            hash: hash.into(),
+            files: Default::default(),
+        }
+    }
+
+    #[test]
+    fn conflicts_are_resolved_over_several_sessions() {
+        let conflict = |path: &str| {
+            let added = |text: &str| DiffNode::AddedText(text.into());
+            (
+                PathBuf::from(path),
+                vec![("A".to_owned(), added("a")), ("B".to_owned(), added("b"))],
+            )
+        };
+        let conflicts: Conflicts = vec![conflict("raid/a.json"), conflict("raid/b.json")]
+            .into_iter()
+            .collect();
+        let mods = vec![locked("123", "1"), locked("456", "2")];
+
+        // The first session resolves one file and is saved as it would be on disk.
+        let mut first = Session {
+            mods: mods.clone(),
+            resolutions: DiffTree::new(),
+            inputs: crate::bundler::resolve::all_inputs(&conflicts),
+            chosen: BTreeSet::new(),
+        };
+        let resolved = conflicts[Path::new("raid/a.json")][1].1.clone();
+        first
+            .resolutions
+            .insert("raid/a.json".into(), resolved.clone());
+        first.chosen.insert("raid/a.json".into());
+        let stored: Session =
+            serde_json::from_str(&serde_json::to_string(&first).unwrap()).unwrap();
+
+        // The next one replays it and asks only about the rest.
+        assert!(same_mods(&stored.mods, &mods));
+        let (replayed, remaining) =
+            crate::bundler::resolve::replay(&stored.resolutions, &stored.inputs, conflicts);
+        assert_eq!(replayed.len(), 1);
+        assert!(
+            matches!(&replayed[Path::new("raid/a.json")], DiffNode::AddedText(text) if text == "b")
+        );
+        assert_eq!(
+            remaining.keys().collect::<Vec<_>>(),
+            [Path::new("raid/b.json")]
+        );
+        assert_eq!(stored.chosen.len(), 1);
+
+        // Updated mods make the saved resolutions stale.
+        assert!(!same_mods(
+            &stored.mods,
+            &[locked("123", "1"), locked("456", "3")]
+        ));
+        assert!(!same_mods(
+            &stored.mods,
+            &[locked("456", "2"), locked("123", "1")]
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+use super::{
+    coverage::{format_size, node_size},
+    diff::DataTree,
+    lock::LockedMod,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const MEBIBYTE: u64 = 1024 * 1024;
+
+/// Sizes above which the bundle is considered suspiciously large.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SizeLimits {
+    /// Size of the whole bundle, in MiB.
+    pub bundle_mib: u64,
+    /// Size of any single file, in MiB; larger ones are often sources included by accident, like `.psd` or `.wav`.
+    pub file_mib: u64,
+    /// Number of the largest files listed when any limit is exceeded.
+    pub top_files: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            bundle_mib: 1024,
+            file_mib: 64,
+            top_files: 10,
+        }
+    }
+}
+
+/// Size of the bundle which is about to be deployed.
+#[derive(Debug)]
+pub struct BundleSize {
+    pub total: u64,
+    /// Every file with its size, largest first.
+    pub files: Vec<(PathBuf, u64)>,
+}
+
+impl BundleSize {
+    pub fn of(tree: &DataTree) -> Self {
+        let mut files: Vec<_> = tree
+            .iter()
+            .map(|(path, node)| (path.clone(), node_size(node)))
+            .collect();
+        files.sort_by(|(path_a, size_a), (path_b, size_b)| {
+            size_b.cmp(size_a).then_with(|| path_a.cmp(path_b))
+        });
+        Self {
+            total: files.iter().map(|(_, size)| size).sum(),
+            files,
+        }
+    }
+
+    /// Lines describing the exceeded limits, followed by the largest files; empty if the size is fine.
+    pub fn warnings(&self, limits: &SizeLimits, mods: &[LockedMod]) -> Vec<String> {
+        let sources = |path: &PathBuf| {
+            mods.iter()
+                .filter(|the_mod| the_mod.files.contains_key(path))
+                .map(|the_mod| the_mod.title.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut lines = vec![];
+        if self.total > limits.bundle_mib * MEBIBYTE {
+            lines.push(format!(
+                "Bundle takes {}, more than {} MiB",
+                format_size(self.total),
+                limits.bundle_mib
+            ));
+        }
+        for (path, size) in &self.files {
+            if *size <= limits.file_mib * MEBIBYTE {
+                break;
+            }
+            lines.push(format!(
+                "{} takes {}, more than {} MiB (from {})",
+                path.to_string_lossy(),
+                format_size(*size),
+                limits.file_mib,
+                sources(path)
+            ));
+        }
+        if !lines.is_empty() {
+            lines.push("Largest files:".into());
+            lines.extend(
+                self.files
+                    .iter()
+                    .take(limits.top_files)
+                    .map(|(path, size)| {
+                        format!(
+                            "  {}: {} (from {})",
+                            path.to_string_lossy(),
+                            format_size(*size),
+                            sources(path)
+                        )
+                    }),
+            );
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_files_with_sources() {
+        let the_mod = LockedMod {
+            title: "Sounds".into(),
+            directory: "1".into(),
+            version: None,
+            hash: String::new(),
+            files: vec![(PathBuf::from("audio/source.wav"), String::new())]
+                .into_iter()
+                .collect(),
+        };
+        let size = BundleSize {
+            total: 3 * MEBIBYTE,
+            files: vec![
+                ("audio/source.wav".into(), 2 * MEBIBYTE),
+                ("heroes/a.png".into(), MEBIBYTE),
+            ],
+        };
+        let limits = SizeLimits {
+            bundle_mib: 10,
+            file_mib: 1,
+            top_files: 1,
+        };
+        assert_eq!(
+            size.warnings(&limits, &[the_mod]),
+            vec![
+                "audio/source.wav takes 2.0 MiB, more than 1 MiB (from Sounds)",
+                "Largest files:",
+                "  audio/source.wav: 2.0 MiB (from Sounds)",
+            ]
+        );
+        assert!(size.warnings(&SizeLimits::default(), &[]).is_empty());
+    }
+}
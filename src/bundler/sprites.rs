@@ -0,0 +1,136 @@
+//! Checks of the hero and monster sprites chosen when resolving art conflicts: a replacement of another size
+//! than the game expects is drawn stretched or cut, so the mismatches are reported before deploying.
+
+use super::{
+    diff::DataTree,
+    validate::{segments, Issue, MergedView},
+};
+use log::*;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Signature, then the length and type of the `IHDR` chunk, which starts with the width and height.
+const PNG_HEADER_LENGTH: usize = 24;
+/// Only the sprites of these directories are checked, since their sizes are fixed by the art files.
+const SPRITE_ROOTS: &[&str] = &["heroes", "monsters"];
+
+/// Width and height of the PNG image, read from its header.
+fn png_size(header: &[u8]) -> Option<(u32, u32)> {
+    if header.len() < PNG_HEADER_LENGTH
+        || !header.starts_with(PNG_SIGNATURE)
+        || &header[12..16] != b"IHDR"
+    {
+        return None;
+    }
+    let number = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Some((number(&header[16..20]), number(&header[20..24])))
+}
+
+fn read_png_size(path: &Path) -> Option<(u32, u32)> {
+    let mut header = [0; PNG_HEADER_LENGTH];
+    let mut file = std::fs::File::open(path).ok()?;
+    file.read_exact(&mut header).ok()?;
+    png_size(&header)
+}
+
+/// Page sizes declared by the Spine atlas, by the page image name, e.g. `hag.sprite.idle.png`.
+fn atlas_pages(text: &str) -> BTreeMap<String, (u32, u32)> {
+    let mut pages = BTreeMap::new();
+    let mut page = None;
+    for line in text.lines().map(str::trim) {
+        if line.to_lowercase().ends_with(".png") && !line.contains(':') {
+            page = Some(line.to_owned());
+        } else if let (Some(size), Some(name)) = (line.strip_prefix("size:"), &page) {
+            let mut numbers = size.split(',').map(|number| number.trim().parse());
+            if let (Some(Ok(width)), Some(Ok(height))) = (numbers.next(), numbers.next()) {
+                pages.insert(name.clone(), (width, height));
+            }
+            page = None;
+        }
+    }
+    pages
+}
+
+/// Size of the sprite as expected by the atlas next to it, or by the vanilla sprite it replaces.
+fn expected_size(view: &MergedView, original: &DataTree, path: &Path) -> Option<(u32, u32)> {
+    let name = path.file_name()?.to_string_lossy();
+    let from_atlas = view
+        .iter()
+        .filter(|(atlas, _)| {
+            atlas.parent() == path.parent()
+                && atlas
+                    .extension()
+                    .is_some_and(|extension| extension == "atlas")
+        })
+        .find_map(|(_, node)| {
+            let text = match node.text() {
+                Some(text) => Cow::Borrowed(text),
+                None => Cow::Owned(std::fs::read_to_string(node.source()).ok()?),
+            };
+            atlas_pages(&text).remove(name.as_ref())
+        });
+    from_atlas.or_else(|| read_png_size(original.get(path)?.source()))
+}
+
+/// Compares every chosen hero and monster sprite with the size the game expects for it.
+pub fn check<'a>(
+    chosen: impl IntoIterator<Item = &'a PathBuf>,
+    original: &DataTree,
+    modded: &DataTree,
+) -> Vec<Issue> {
+    let view = MergedView::new(original, modded, &[]);
+    chosen
+        .into_iter()
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == "png")
+                && segments(path)
+                    .first()
+                    .is_some_and(|root| SPRITE_ROOTS.contains(&root.as_str()))
+        })
+        .filter_map(|path| {
+            let actual = read_png_size(modded.get(path)?.source())?;
+            let expected = expected_size(&view, original, path)?;
+            if actual == expected {
+                return None;
+            }
+            warn!(
+                "[sprites] {:?} is {}x{}, expected {}x{}",
+                path, actual.0, actual.1, expected.0, expected.1
+            );
+            Some(Issue {
+                path: path.clone(),
+                message: format!(
+                    "Chosen sprite is {}x{}, but the game expects {}x{}, so it may be drawn stretched or cut",
+                    actual.0, actual.1, expected.0, expected.1
+                ),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_read() {
+        let mut header = PNG_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0, 0, 0, 13]);
+        header.extend_from_slice(b"IHDR");
+        header.extend_from_slice(&512u32.to_be_bytes());
+        header.extend_from_slice(&256u32.to_be_bytes());
+        assert_eq!(png_size(&header), Some((512, 256)));
+        assert_eq!(png_size(&header[1..]), None);
+
+        let atlas = "\nhag.sprite.idle.png\nsize: 1024,512\nformat: RGBA8888\n\
+                     filter: Linear,Linear\nbody\n  rotate: false\n  size: 10, 20\n";
+        let pages = atlas_pages(atlas);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages["hag.sprite.idle.png"], (1024, 512));
+    }
+}
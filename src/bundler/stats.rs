@@ -0,0 +1,179 @@
+//! Usage statistics, collected only if user has enabled them and never sent anywhere.
+//!
+//! They show which data types conflict most often, so that user can share them when asked,
+//! to help choosing the file formats worth merging value by value.
+
+use super::{diff::Conflicts, structures::data_type};
+use crate::config::Config;
+use cursive::{
+    views::{Checkbox, Dialog, LinearLayout, TextView},
+    Cursive,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Name under which the conflicts in files of unknown data types are counted.
+const OTHER_FILES: &str = "Other files";
+/// Number of data types shown on the statistics screen.
+const TOP_TYPES: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Stats {
+    /// Bundles deployed since the statistics were enabled.
+    pub bundles: u64,
+    /// Conflicts found in these bundles, before any of them were resolved.
+    pub conflicts: u64,
+    /// Conflicts by the name of the data type.
+    pub by_type: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    fn load() -> Self {
+        let path = crate::paths::stats();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|err| {
+                warn!("[stats] {:?} is malformed, starting anew: {}", path, err);
+                Self::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                warn!("[stats] Unable to read {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let path = crate::paths::stats();
+        let result = serde_json::to_string_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|text| std::fs::write(&path, text));
+        if let Err(err) = result {
+            warn!("[stats] Unable to save {:?}: {}", path, err);
+        }
+    }
+
+    fn add(&mut self, bundle: &Bundle) {
+        self.bundles += 1;
+        self.conflicts += bundle.by_type.values().sum::<u64>();
+        for (name, count) in &bundle.by_type {
+            *self.by_type.entry(name.clone()).or_default() += count;
+        }
+    }
+
+    fn render(&self) -> String {
+        if self.bundles == 0 {
+            return "No bundles were made since the statistics were enabled.".into();
+        }
+        let mut types: Vec<_> = self.by_type.iter().collect();
+        types.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+        let types: Vec<_> = types
+            .into_iter()
+            .take(TOP_TYPES)
+            .map(|(name, count)| format!("- {}: {}", name, count))
+            .collect();
+        format!(
+            "Bundles made: {}\nConflicts per bundle: {:.1}\n\nData types with the most conflicts:\n{}",
+            self.bundles,
+            self.conflicts as f64 / self.bundles as f64,
+            types.join("\n")
+        )
+    }
+}
+
+/// Conflicts of one bundling, counted by the data type.
+pub struct Bundle {
+    by_type: BTreeMap<String, u64>,
+}
+
+impl Bundle {
+    pub fn of(conflicts: &Conflicts) -> Self {
+        let mut by_type = BTreeMap::new();
+        for path in conflicts.keys() {
+            let name = data_type(path).map_or(OTHER_FILES, |data_type| data_type.name);
+            *by_type.entry(name.to_owned()).or_default() += 1;
+        }
+        Self { by_type }
+    }
+
+    /// Adds the deployed bundle to the statistics, if they are enabled.
+    pub fn record(&self) {
+        if !Config::load().usage_stats {
+            return;
+        }
+        let mut stats = Stats::load();
+        stats.add(self);
+        stats.save();
+        info!("[stats] Recorded bundle, {} in total", stats.bundles);
+    }
+}
+
+/// Screen showing the statistics, where they are enabled and reset.
+pub fn screen(cursive: &mut Cursive) {
+    let enabled = Config::load().usage_stats;
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(
+                    LinearLayout::horizontal()
+                        .child(
+                            Checkbox::new()
+                                .with_checked(enabled)
+                                .on_change(|_, checked| {
+                                    info!("Setting collection of usage statistics to {}", checked);
+                                    let mut config = Config::load();
+                                    config.usage_stats = checked;
+                                    config.save();
+                                }),
+                        )
+                        .child(TextView::new(
+                            " Collect statistics (kept on this computer only, never sent anywhere)",
+                        )),
+                )
+                .child(TextView::new(format!(
+                    "\n{}\n\nTo share them, send the file {}.",
+                    Stats::load().render(),
+                    crate::paths::stats().to_string_lossy()
+                ))),
+        )
+        .title("Usage statistics")
+        .button("Reset", |cursive| {
+            info!("[stats] Resetting usage statistics");
+            Stats::default().save();
+            cursive.pop_layer();
+            screen(cursive);
+        })
+        .dismiss_button("Close"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::DiffNode;
+
+    #[test]
+    fn conflicts_are_counted_by_type() {
+        let mut conflicts = Conflicts::new();
+        for path in &[
+            "heroes/hag/hag.info.darkest",
+            "heroes/abomination/abomination.info.darkest",
+            "fx/hag.png",
+        ] {
+            conflicts.insert(
+                path.into(),
+                vec![("A".into(), DiffNode::AddedText("".into()))],
+            );
+        }
+        let mut stats = Stats::default();
+        stats.add(&Bundle::of(&conflicts));
+        stats.add(&Bundle::of(&Conflicts::new()));
+        assert_eq!(stats.bundles, 2);
+        assert_eq!(stats.conflicts, 3);
+        assert!(stats
+            .render()
+            .contains("Conflicts per bundle: 1.5\n\nData types with the most conflicts:\n- Heroes: 2\n- Other files: 1"));
+    }
+}
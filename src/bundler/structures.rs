@@ -1,16 +1,398 @@
-use std::collections::BTreeMap;
+use super::MergePolicy;
+use log::*;
+use std::{collections::BTreeMap, path::Path, sync::RwLock};
+use thiserror::Error;
 
+mod atlas;
+mod cache;
 mod darkest;
 mod json;
-mod localization;
+mod lines;
+pub mod localization;
+mod path;
+mod table;
 
-trait MapPath: Ord + Eq {}
+pub use cache::{clear as clear_parse_cache, load as load_parse_cache, save as save_parse_cache};
+pub use darkest::split_values;
+pub use path::{DataPath, Segment};
+/// Structured file flattened into the list of values.
+pub type DataMap = BTreeMap<DataPath, String>;
+
+#[derive(Debug, Error)]
+pub enum StructureError {
+    #[error("Unable to parse darkest file: {0}")]
+    Darkest(String),
+    #[error("Unable to parse JSON file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("JSON file must contain an object or an array")]
+    JsonPrimitive,
+    #[error("JSON values can't be put together: {0}")]
+    JsonIncompatible(String),
+    #[error("Unable to parse atlas file: {0}")]
+    Atlas(String),
+    #[error("File is not a valid UTF-8 text, unlike the game's own file")]
+    NotText,
+}
+
+trait MapPath: Ord + Eq + Sized {
+    fn to_data_path(&self) -> DataPath;
+    fn from_data_path(path: &[String]) -> Option<Self>;
+}
 
 trait BTreeMappable: Sized {
     type Key: MapPath;
     type Value;
 
     fn map(&self) -> BTreeMap<Self::Key, &Self::Value>;
+    #[allow(dead_code)]
     fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value>;
-    fn clone_with(&self, _: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>)) -> Self;
+    /// Copy of the structure with the values changed by the closure; fails if they can't be put together.
+    fn clone_with(
+        &self,
+        _: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError>;
+}
+
+/// File format which can be parsed into the `BTreeMappable` structure and written back.
+trait Structured: BTreeMappable {
+    fn parse(text: &str) -> Result<Self, StructureError>;
+    fn render(&self) -> String;
+    fn value_to_string(value: &Self::Value) -> String;
+    fn value_from_string(value: String) -> Self::Value;
+}
+
+fn flatten<T: Structured>(parsed: T) -> DataMap {
+    parsed
+        .map()
+        .into_iter()
+        .map(|(key, value)| (key.to_data_path(), T::value_to_string(value)))
+        .collect()
+}
+
+fn rebuild<T: Structured>(original: T, map: DataMap) -> Result<String, StructureError> {
+    let rebuilt = original.clone_with(|inner| {
+        inner.clear();
+        for (path, value) in map {
+            match T::Key::from_data_path(&path) {
+                Some(key) => {
+                    inner.insert(key, T::value_from_string(value));
+                }
+                None => warn!("Dropping value on malformed data path {:?}", path),
+            }
+        }
+    })?;
+    Ok(rebuilt.render())
+}
+
+/// Known structured file formats.
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Darkest,
+    /// JSON with the array items identified by the first of the given fields which tells them apart,
+    /// and by index if there's none.
+    NamedJson(&'static [&'static str]),
+    /// Plain text, where every line is a separate value and their order doesn't matter.
+    LineSet,
+    /// Spine atlas, with the regions identified by their names within the page.
+    Atlas,
+    /// Spreadsheet with the records identified by their ids.
+    Csv,
+}
+
+impl Format {
+    pub fn flatten(self, text: &str) -> Result<DataMap, StructureError> {
+        profile_span!("parse", format = ?self);
+        Ok(match self {
+            Format::Darkest => flatten(darkest::DarkestFile::parse(text)?),
+            Format::NamedJson(id_fields) => {
+                flatten(json::NamedJsonFile::parse_with(text, id_fields)?)
+            }
+            Format::LineSet => flatten(lines::LineSetFile::parse(text)?),
+            Format::Atlas => flatten(atlas::AtlasFile::parse(text)?),
+            Format::Csv => flatten(table::CsvTable::parse(text)?),
+        })
+    }
+
+    /// Same as `flatten`, but reuses the result of parsing the same text earlier, possibly in the previous run.
+    pub fn flatten_cached(self, text: &str) -> Result<DataMap, StructureError> {
+        cache::flatten(self, text)
+    }
+
+    /// Renders the file, which was originally `original`, with the values from `map`.
+    pub fn rebuild(self, original: &str, map: DataMap) -> Result<String, StructureError> {
+        profile_span!("rebuild", format = ?self);
+        match self {
+            Format::Darkest => rebuild(darkest::DarkestFile::parse(original)?, map),
+            Format::NamedJson(id_fields) => {
+                rebuild(json::NamedJsonFile::parse_with(original, id_fields)?, map)
+            }
+            Format::LineSet => rebuild(lines::LineSetFile::parse(original)?, map),
+            Format::Atlas => rebuild(atlas::AtlasFile::parse(original)?, map),
+            Format::Csv => rebuild(table::CsvTable::parse(original)?, map),
+        }
+    }
+}
+
+/// Group of game files sharing the same structure.
+#[derive(Debug)]
+pub struct DataType {
+    pub name: &'static str,
+    /// Glob for the file paths, relative to the game directory.
+    pub glob: &'static str,
+    pub format: Format,
+    /// Policy for the conflicting values, unless user has chosen another one.
+    pub default_policy: MergePolicy,
+}
+
+pub const DUNGEON_PROPS: DataType = DataType {
+    name: "Dungeon props",
+    glob: "dungeons/**/*.props.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Hero classes; the repeated entries without an id (e.g. the extra loot) are identified by their content.
+pub const HEROES: DataType = DataType {
+    name: "Heroes",
+    glob: "heroes/**/*.info.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Parts of the hero definition replaced by mods without replacing the whole info file;
+/// the sections missing from the override are taken from the info file by the game.
+pub const HERO_OVERRIDES: DataType = DataType {
+    name: "Hero overrides",
+    glob: "heroes/**/*.override.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Layouts of the hero sprites on their pages, changed both by the class frameworks and by the skins.
+pub const HERO_SPRITES: DataType = DataType {
+    name: "Hero sprite atlases",
+    glob: "heroes/**/*.atlas",
+    format: Format::Atlas,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Stats, resistances, skills and loot of monsters; skills are told apart by their id and loot by its code.
+pub const MONSTERS: DataType = DataType {
+    name: "Monsters",
+    glob: "monsters/**/*.info.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+pub const LOOT_TABLES: DataType = DataType {
+    name: "Loot tables",
+    glob: "loot/*.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Effects applied by skills, trinkets and quirks, looked up by their names.
+pub const EFFECTS: DataType = DataType {
+    name: "Effects",
+    glob: "effects/**/*.effects.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Inventory items and the inventory limits, identified by the item type and id, so that the mods
+/// adding provisions or supplies don't conflict with the ones changing the stack sizes.
+pub const INVENTORY: DataType = DataType {
+    name: "Inventory",
+    glob: "inventory/*.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+
+pub const OVERLAY_LAYOUTS: DataType = DataType {
+    name: "Overlay layouts",
+    glob: "overlays/**/*.layout.json",
+    format: Format::NamedJson(json::NAME_FIELDS),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+pub const PANEL_LAYOUTS: DataType = DataType {
+    name: "Panel layouts",
+    glob: "panels/**/*.layout.json",
+    format: Format::NamedJson(json::NAME_FIELDS),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+pub const CAMPAIGN_START: DataType = DataType {
+    name: "Campaign start",
+    glob: "campaign/new_game/*.json",
+    format: Format::NamedJson(json::NAME_FIELDS),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Town buildings and their activities, which class mods extend with the entries for their heroes.
+pub const TOWN: DataType = DataType {
+    name: "Town activities",
+    glob: "campaign/town/**/*.json",
+    format: Format::NamedJson(&["name", "id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Trinket entries and rarities, identified by the trinket and rarity ids, so that the mods adding trinkets
+/// don't conflict with each other and with the balance mods.
+pub const TRINKETS: DataType = DataType {
+    name: "Trinkets",
+    glob: "trinkets/**/*.trinkets.json",
+    format: Format::NamedJson(&["name", "id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Buffs shipped by the trinket packs next to their trinkets, often in the files named the same as
+/// in the other packs. They are identified by the buff ids, so that only the changes of the same buff conflict.
+pub const TRINKET_BUFFS: DataType = DataType {
+    name: "Trinket buffs",
+    glob: "trinkets/**/*.buffs.json",
+    format: Format::NamedJson(&["name", "id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Quirk library and the quirk act-outs, identified by the quirk ids, so that the quirks added by different mods
+/// are merged and only the changes of the same quirk conflict.
+pub const QUIRKS: DataType = DataType {
+    name: "Quirks",
+    glob: "shared/quirk/*.json",
+    format: Format::NamedJson(&["name", "id", "quirk_id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Camping skills available in the game, identified by the skill ids.
+pub const CAMPING_SKILLS: DataType = DataType {
+    name: "Camping skills",
+    glob: "raid/camping/*.camping_skills.json",
+    format: Format::NamedJson(&["name", "id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Camping skills assigned to the hero class, which must be defined in the global camping file.
+pub const HERO_CAMPING_SKILLS: DataType = DataType {
+    name: "Hero camping skills",
+    glob: "heroes/**/*.camping_skills.json",
+    format: Format::NamedJson(&["name", "id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Upgrade trees of the town buildings and the estate-wide attributes, e.g. the stagecoach roster size,
+/// identified by the tree ids and the requirement codes, so that the roster size mods merge with the building
+/// overhauls. The conflicting numbers are asked for, since the higher one is a discount as often as a bonus.
+pub const UPGRADES: DataType = DataType {
+    name: "Upgrades",
+    glob: "upgrades/**/*.upgrades.json",
+    format: Format::NamedJson(&["name", "id", "code"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Curio definitions and their interaction results, identified by the curio ids, so that the dungeon overhauls
+/// adding curios and the mods tweaking the results of the existing ones don't conflict as whole files.
+pub const CURIOS: DataType = DataType {
+    name: "Curios",
+    glob: "curios/curio_type_library.csv",
+    format: Format::Csv,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Plot quests, which campaign-sized mods add to and quest tweak mods change, identified by the quest ids.
+pub const PLOT_QUESTS: DataType = DataType {
+    name: "Plot quests",
+    glob: "dungeons/**/*.plot_quests.json",
+    format: Format::NamedJson(&["name", "id"]),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Ambience of the dungeons and town, tweaked both by ambience overhauls and by dungeon mods.
+pub const AMBIENCE: DataType = DataType {
+    name: "Ambience",
+    glob: "campaign/ambience/**/*.json",
+    format: Format::NamedJson(json::NAME_FIELDS),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+pub const AMBIENCE_PARAMS: DataType = DataType {
+    name: "Ambience parameters",
+    glob: "audio/**/*ambience*.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+/// Hero experience and resolve level thresholds, tweaked by level cap and XP curve mods.
+/// Any mod raising a threshold usually means to make leveling slower, so the highest one wins.
+pub const PROGRESSION: DataType = DataType {
+    name: "Hero progression",
+    glob: "shared/progression/**/*.darkest",
+    format: Format::Darkest,
+    default_policy: MergePolicy::HighestNumber,
+};
+pub const TUTORIAL: DataType = DataType {
+    name: "Tutorial",
+    glob: "campaign/tutorial/*.json",
+    format: Format::NamedJson(json::NAME_FIELDS),
+    default_policy: MergePolicy::AlwaysAsk,
+};
+
+pub const DATA_TYPES: &[DataType] = &[
+    DUNGEON_PROPS,
+    HEROES,
+    HERO_OVERRIDES,
+    HERO_SPRITES,
+    MONSTERS,
+    LOOT_TABLES,
+    EFFECTS,
+    INVENTORY,
+    OVERLAY_LAYOUTS,
+    PANEL_LAYOUTS,
+    CAMPAIGN_START,
+    TOWN,
+    TRINKETS,
+    TRINKET_BUFFS,
+    QUIRKS,
+    CAMPING_SKILLS,
+    HERO_CAMPING_SKILLS,
+    UPGRADES,
+    CURIOS,
+    PLOT_QUESTS,
+    AMBIENCE,
+    AMBIENCE_PARAMS,
+    PROGRESSION,
+    TUTORIAL,
+];
+
+/// Types of the files which the game doesn't ship, so that the mods add them instead of changing them;
+/// when several mods add the same file, they are merged value by value against the empty one.
+const ADDED_DATA_TYPES: &[DataType] = &[HERO_OVERRIDES];
+
+/// Files which user has chosen to merge as sets of lines; their globs are configured by `set_line_set_globs`.
+pub const LINE_SETS: DataType = DataType {
+    name: "Line sets",
+    glob: "",
+    format: Format::LineSet,
+    default_policy: MergePolicy::AlwaysAsk,
+};
+
+/// Globs of the files merged as sets of lines.
+static LINE_SET_GLOBS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+impl DataType {
+    pub fn matches(&self, path: &Path) -> bool {
+        // Only the line sets have no glob of their own.
+        if self.glob.is_empty() {
+            LINE_SET_GLOBS
+                .read()
+                .expect("Line sets lock was poisoned")
+                .iter()
+                .any(|glob| crate::glob::matches(glob, path))
+        } else {
+            crate::glob::matches(self.glob, path)
+        }
+    }
+}
+
+/// Replaces the globs of files merged as sets of lines.
+///
+/// Some framework mods keep their configuration as plain lines under `scrolls/` or `scripts/`,
+/// and every mod using the framework adds its own ones.
+pub fn set_line_set_globs(globs: &[String]) {
+    *LINE_SET_GLOBS.write().expect("Line sets lock was poisoned") = globs.to_vec();
+    info!("Files merged as sets of lines: {:?}", globs);
+}
+
+/// Data type for the file on the given path, if it is known.
+pub fn data_type(path: &Path) -> Option<&'static DataType> {
+    DATA_TYPES
+        .iter()
+        .find(|data_type| data_type.matches(path))
+        .or_else(|| Some(&LINE_SETS).filter(|data_type| data_type.matches(path)))
+}
+
+/// Data type for the file on the given path, if it is added by the mods and merged as such.
+pub fn added_data_type(path: &Path) -> Option<&'static DataType> {
+    ADDED_DATA_TYPES
+        .iter()
+        .find(|data_type| data_type.matches(path))
 }
@@ -0,0 +1,375 @@
+//! Spine atlases describing where every part of the sprite is found on its pages, e.g. `hag.sprite.idle.atlas`.
+//!
+//! Every page starts after the blank line with the name of its image, followed by its own fields
+//! and by the regions, each of them being the name followed by the indented fields:
+//!
+//! ```text
+//! hag.sprite.idle.png
+//! size: 1024,512
+//! body
+//!   xy: 2, 2
+//! ```
+
+use super::{BTreeMappable, DataPath, MapPath, StructureError, Structured};
+use std::collections::BTreeMap;
+
+/// Separator for the occurrence index of regions with the same name, e.g. the frames of the same part.
+const DUPLICATE_MARK: char = '~';
+const INDENT: &str = "  ";
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AtlasKey {
+    page: String,
+    /// Region id, or nothing for the fields of the page itself.
+    region: Option<String>,
+    field: String,
+}
+
+impl MapPath for AtlasKey {
+    fn to_data_path(&self) -> DataPath {
+        let mut path = vec![self.page.clone()];
+        path.extend(self.region.clone());
+        path.push(self.field.clone());
+        path.into()
+    }
+    fn from_data_path(path: &[String]) -> Option<Self> {
+        match path {
+            [page, field] => Some(Self {
+                page: page.clone(),
+                region: None,
+                field: field.clone(),
+            }),
+            [page, region, field] => Some(Self {
+                page: page.clone(),
+                region: Some(region.clone()),
+                field: field.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Region {
+    name: String,
+    /// Name with the occurrence index, if the page has several regions with this name.
+    id: String,
+    fields: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug)]
+struct Page {
+    name: String,
+    fields: Vec<(String, String)>,
+    regions: Vec<Region>,
+}
+
+impl Page {
+    fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            fields: vec![],
+            regions: vec![],
+        }
+    }
+}
+
+fn region_name(id: &str) -> &str {
+    match id.rsplit_once(DUPLICATE_MARK) {
+        Some((name, index)) if index.parse::<usize>().is_ok() => name,
+        _ => id,
+    }
+}
+
+fn split_field(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(':')?;
+    Some((key.trim().to_owned(), value.trim().to_owned()))
+}
+
+pub struct AtlasFile {
+    /// Whether the pages are preceded by the blank line, as the Spine exporter writes them.
+    leading_blank: bool,
+    pages: Vec<Page>,
+}
+
+impl BTreeMappable for AtlasFile {
+    type Key = AtlasKey;
+    type Value = String;
+    fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
+        let mut map = BTreeMap::new();
+        for page in &self.pages {
+            for (field, value) in &page.fields {
+                map.insert(
+                    AtlasKey {
+                        page: page.name.clone(),
+                        region: None,
+                        field: field.clone(),
+                    },
+                    value,
+                );
+            }
+            for region in &page.regions {
+                for (field, value) in &region.fields {
+                    map.insert(
+                        AtlasKey {
+                            page: page.name.clone(),
+                            region: Some(region.id.clone()),
+                            field: field.clone(),
+                        },
+                        value,
+                    );
+                }
+            }
+        }
+        map
+    }
+    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
+        let mut map = BTreeMap::new();
+        for page in &mut self.pages {
+            for (field, value) in &mut page.fields {
+                map.insert(
+                    AtlasKey {
+                        page: page.name.clone(),
+                        region: None,
+                        field: field.clone(),
+                    },
+                    value,
+                );
+            }
+            for region in &mut page.regions {
+                for (field, value) in &mut region.fields {
+                    map.insert(
+                        AtlasKey {
+                            page: page.name.clone(),
+                            region: Some(region.id.clone()),
+                            field: field.clone(),
+                        },
+                        value,
+                    );
+                }
+            }
+        }
+        map
+    }
+    fn clone_with(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError> {
+        let mut map: BTreeMap<_, _> = self
+            .map()
+            .into_iter()
+            .map(|(key, value)| (key, value.clone()))
+            .collect();
+        f(&mut map);
+
+        // Existing fields keep their places; the removed ones are dropped.
+        let mut pages: Vec<_> = self
+            .pages
+            .iter()
+            .map(|page| {
+                let mut take = |region: Option<&Region>, fields: &[(String, String)]| {
+                    fields
+                        .iter()
+                        .filter_map(|(field, _)| {
+                            let key = AtlasKey {
+                                page: page.name.clone(),
+                                region: region.map(|region| region.id.clone()),
+                                field: field.clone(),
+                            };
+                            Some((field.clone(), map.remove(&key)?))
+                        })
+                        .collect::<Vec<_>>()
+                };
+                Page {
+                    name: page.name.clone(),
+                    fields: take(None, &page.fields),
+                    regions: page
+                        .regions
+                        .iter()
+                        .map(|region| Region {
+                            fields: take(Some(region), &region.fields),
+                            ..region.clone()
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        // New fields go after the existing ones, new regions and pages - to the end.
+        for (key, value) in map {
+            let page = match pages.iter().position(|page| page.name == key.page) {
+                Some(index) => index,
+                None => {
+                    pages.push(Page::new(key.page.clone()));
+                    pages.len() - 1
+                }
+            };
+            let page = &mut pages[page];
+            let fields = match key.region {
+                None => &mut page.fields,
+                Some(id) => {
+                    let region = match page.regions.iter().position(|region| region.id == id) {
+                        Some(index) => index,
+                        None => {
+                            page.regions.push(Region {
+                                name: region_name(&id).to_owned(),
+                                id,
+                                fields: vec![],
+                            });
+                            page.regions.len() - 1
+                        }
+                    };
+                    &mut page.regions[region].fields
+                }
+            };
+            fields.push((key.field, value));
+        }
+        for page in &mut pages {
+            page.regions.retain(|region| !region.fields.is_empty());
+        }
+        pages.retain(|page| !page.fields.is_empty() || !page.regions.is_empty());
+        Ok(Self {
+            leading_blank: self.leading_blank,
+            pages,
+        })
+    }
+}
+
+impl Structured for AtlasFile {
+    fn parse(text: &str) -> Result<Self, StructureError> {
+        let mut pages: Vec<Page> = vec![];
+        let mut page_starts = true;
+        let leading_blank = text
+            .lines()
+            .next()
+            .is_some_and(|line| line.trim().is_empty());
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                page_starts = true;
+                continue;
+            }
+            let page = match pages.last_mut() {
+                Some(page) if !page_starts => page,
+                _ => {
+                    pages.push(Page::new(trimmed));
+                    page_starts = false;
+                    continue;
+                }
+            };
+            let indented = line.starts_with(char::is_whitespace);
+            match split_field(trimmed) {
+                // Fields after the first region belong to the regions, even if they aren't indented.
+                Some(field) if indented || !page.regions.is_empty() => page
+                    .regions
+                    .last_mut()
+                    .ok_or_else(|| {
+                        StructureError::Atlas(format!(
+                            "line {}: region field before any region",
+                            number + 1
+                        ))
+                    })?
+                    .fields
+                    .push(field),
+                Some(field) => page.fields.push(field),
+                None => {
+                    let count = page
+                        .regions
+                        .iter()
+                        .filter(|region| region.name == trimmed)
+                        .count();
+                    let id = match count {
+                        0 => trimmed.to_owned(),
+                        count => format!("{}{}{}", trimmed, DUPLICATE_MARK, count),
+                    };
+                    page.regions.push(Region {
+                        name: trimmed.to_owned(),
+                        id,
+                        fields: vec![],
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            leading_blank,
+            pages,
+        })
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for (index, page) in self.pages.iter().enumerate() {
+            if index > 0 || self.leading_blank {
+                text.push('\n');
+            }
+            text.push_str(&format!("{}\n", page.name));
+            for (field, value) in &page.fields {
+                text.push_str(&format!("{}: {}\n", field, value));
+            }
+            for region in &page.regions {
+                text.push_str(&format!("{}\n", region.name));
+                for (field, value) in &region.fields {
+                    text.push_str(&format!("{}{}: {}\n", INDENT, field, value));
+                }
+            }
+        }
+        text
+    }
+
+    fn value_to_string(value: &Self::Value) -> String {
+        value.clone()
+    }
+
+    fn value_from_string(value: String) -> Self::Value {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bundler::structures::Format;
+
+    const ATLAS: &str = "
+hag.sprite.idle.png
+size: 1024,512
+format: RGBA8888
+body
+  rotate: false
+  xy: 2, 2
+frame
+  xy: 10, 2
+  index: 0
+frame
+  xy: 20, 2
+  index: 1
+";
+
+    #[test]
+    fn regions_from_several_mods() {
+        let format = Format::Atlas;
+        let original = format.flatten(ATLAS).unwrap();
+        assert_eq!(
+            original[&["hag.sprite.idle.png", "frame~1", "xy"][..].into()],
+            "20, 2"
+        );
+        assert_eq!(format.rebuild(ATLAS, original.clone()).unwrap(), ATLAS);
+
+        let mut map = original;
+        // One mod moves the body, another one adds the region for its accessory.
+        map.insert(
+            ["hag.sprite.idle.png", "body", "xy"][..].into(),
+            "4, 4".into(),
+        );
+        map.insert(
+            ["hag.sprite.idle.png", "hat", "xy"][..].into(),
+            "30, 2".into(),
+        );
+        map.remove(&["hag.sprite.idle.png", "format"][..].into());
+        assert_eq!(
+            format.rebuild(ATLAS, map).unwrap(),
+            ATLAS
+                .replace("  xy: 2, 2", "  xy: 4, 4")
+                .replace("format: RGBA8888\n", "")
+                + "hat\n  xy: 30, 2\n"
+        );
+    }
+}
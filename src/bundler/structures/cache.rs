@@ -0,0 +1,189 @@
+//! On-disk cache of the parsed game files, keyed by their content hash,
+//! so that the unchanged vanilla and DLC files are not parsed again on every run.
+
+use super::{DataMap, DataPath, Format, StructureError};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+/// Version of the parsed representation, bumped whenever a parser changes which values it produces
+/// or how they are keyed, so that the entries written by the older parsers are not reused.
+const PARSER_VERSION: u32 = 7;
+
+/// Cache loaded for the current run; files are parsed without caching while there's none.
+static CACHE: Mutex<Option<ParseCache>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Default)]
+struct ParseCache {
+    /// Version of the program which has written the cache, since the parsers might change between them.
+    version: String,
+    /// Caches written before the parser version was stored have none, and are never reused.
+    #[serde(default)]
+    parser_version: u32,
+    entries: HashMap<String, Vec<(DataPath, String)>>,
+    #[serde(skip)]
+    used: HashSet<String>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl ParseCache {
+    fn new() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            parser_version: PARSER_VERSION,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the entries were produced by the same parsers as the ones of this run.
+    fn is_current(&self) -> bool {
+        self.version == env!("CARGO_PKG_VERSION") && self.parser_version == PARSER_VERSION
+    }
+
+    fn flatten(&mut self, format: Format, text: &str) -> Result<DataMap, StructureError> {
+        let key = format!(
+            "{:?}:{}",
+            format,
+            crate::bundler::lock::hash_bytes(text.as_bytes())
+        );
+        if let Some(entry) = self.entries.get(&key) {
+            self.used.insert(key);
+            return Ok(entry.iter().cloned().collect());
+        }
+        let map = format.flatten(text)?;
+        self.entries.insert(
+            key.clone(),
+            map.iter()
+                .map(|(path, value)| (path.clone(), value.clone()))
+                .collect(),
+        );
+        self.used.insert(key);
+        self.changed = true;
+        Ok(map)
+    }
+}
+
+/// Loads the cache written by the previous run, if it is there and was written by the same version and parsers.
+pub fn load() {
+    let path = crate::paths::parse_cache();
+    let cache = match std::fs::read_to_string(&path) {
+        Ok(text) => match serde_json::from_str::<ParseCache>(&text) {
+            Ok(cache) if cache.is_current() => {
+                info!(
+                    "[cache] Loaded {} parsed files from {:?}",
+                    cache.entries.len(),
+                    path
+                );
+                cache
+            }
+            Ok(_) => {
+                info!(
+                    "[cache] {:?} was written by another version or parsers, ignoring it",
+                    path
+                );
+                ParseCache::new()
+            }
+            Err(err) => {
+                warn!("[cache] {:?} is malformed, ignoring it: {}", path, err);
+                ParseCache::new()
+            }
+        },
+        Err(err) => {
+            debug!("[cache] No parse cache loaded: {}", err);
+            ParseCache::new()
+        }
+    };
+    *CACHE.lock().expect("Parse cache lock was poisoned") = Some(cache);
+}
+
+/// Writes the files parsed during this run back to disk, dropping the ones which were not needed.
+pub fn save() {
+    let mut guard = CACHE.lock().expect("Parse cache lock was poisoned");
+    let mut cache = match guard.take() {
+        Some(cache) => cache,
+        None => return,
+    };
+    let used = std::mem::take(&mut cache.used);
+    let before = cache.entries.len();
+    cache.entries.retain(|key, _| used.contains(key));
+    if !cache.changed && cache.entries.len() == before {
+        debug!("[cache] Parse cache is up to date");
+        return;
+    }
+    let path = crate::paths::parse_cache();
+    let text = serde_json::to_string(&cache).expect("Parse cache can always be serialized");
+    match std::fs::write(&path, text) {
+        Ok(()) => info!(
+            "[cache] Saved {} parsed files to {:?}",
+            cache.entries.len(),
+            path
+        ),
+        Err(err) => warn!("[cache] Unable to save parse cache to {:?}: {}", path, err),
+    }
+}
+
+/// Removes the cache from disk, so that every file is parsed anew on the next bundling.
+///
+/// Returns `false` if there was no cache.
+pub fn clear() -> std::io::Result<bool> {
+    let path = crate::paths::parse_cache();
+    match std::fs::remove_file(&path) {
+        Ok(()) => {
+            info!("[cache] Removed parse cache {:?}", path);
+            Ok(true)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Flattens the file, reusing the result of the earlier parsing of the same content if possible.
+pub fn flatten(format: Format, text: &str) -> Result<DataMap, StructureError> {
+    match CACHE
+        .lock()
+        .expect("Parse cache lock was poisoned")
+        .as_mut()
+    {
+        Some(cache) => cache.flatten(format, text),
+        None => format.flatten(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_parsed_files() {
+        let mut cache = ParseCache::new();
+        let text = "hp: .id \"base\" .value 10\n";
+        let parsed = cache.flatten(Format::Darkest, text).unwrap();
+        assert!(cache.changed);
+
+        let mut cache: ParseCache =
+            serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+        assert_eq!(cache.flatten(Format::Darkest, text).unwrap(), parsed);
+        assert!(!cache.changed);
+        assert_eq!(cache.used.len(), 1);
+        assert!(cache.is_current());
+    }
+
+    #[test]
+    fn entries_of_other_parsers_are_not_reused() {
+        let mut cache = ParseCache::new();
+        cache.parser_version = PARSER_VERSION - 1;
+        assert!(!cache.is_current());
+
+        // Written before the parser version was stored.
+        let old: ParseCache = serde_json::from_str(&format!(
+            "{{\"version\": \"{}\", \"entries\": {{}}}}",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .unwrap();
+        assert!(!old.is_current());
+    }
+}
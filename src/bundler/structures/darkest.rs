@@ -1,10 +1,155 @@
-use super::BTreeMappable;
+use super::{BTreeMappable, DataPath, MapPath, StructureError, Structured};
+use combine::{eof, EasyParser, Parser};
+use log::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Fields which values identify the entry, if it has them.
+const ID_FIELDS: &[&str] = &["id", "name"];
+/// Field which distinguishes several entries with the same identifying field, e.g. skill levels.
+const LEVEL_FIELD: &str = "level";
+/// Identifying fields of the entries which are repeated without any id, e.g. the loot dropped by hero,
+/// so that the entries added by different mods are kept together instead of conflicting by their position.
+const KEYED_ENTRIES: &[(&str, &[&str])] = &[
+    ("extra_battle_loot", &["code"]),
+    ("extra_curio_loot", &["code"]),
+    ("loot", &["code"]),
+    ("death_reaction", &["effects", "effect"]),
+    ("extra_stack_limit", &["id"]),
+];
+/// Entries identified by the values of several fields together, e.g. the inventory items,
+/// where the provision and the gold both have the empty id and only the type tells them apart.
+const COMPOSITE_ENTRIES: &[(&str, &[&str])] = &[
+    ("inventory_item", &["type", "id"]),
+    ("inventory_system_config", &["type"]),
+];
+/// Separator between the values of the fields identifying the entry together.
+const COMPOSITE_MARK: char = ':';
+/// Separator for the occurrence index of duplicates.
+const DUPLICATE_MARK: char = '~';
+
+/// Fields which may identify the entry with the given key.
+fn id_fields(key: &str) -> &'static [&'static str] {
+    KEYED_ENTRIES
+        .iter()
+        .chain(COMPOSITE_ENTRIES)
+        .find(|(keyed, _)| *keyed == key)
+        .map_or(ID_FIELDS, |(_, fields)| fields)
+}
+
+fn is_composite(key: &str) -> bool {
+    COMPOSITE_ENTRIES.iter().any(|(composite, _)| *composite == key)
+}
+
+/// Position of the field in the entry which didn't have it before: identifying fields go first, in their order
+/// as usual in the game files, and the rest keep their alphabetical order after them.
+fn new_field_position(key: &str, subkey: &str) -> usize {
+    let name = original_name(subkey);
+    let fields = id_fields(key);
+    if let Some(position) = fields.iter().position(|field| *field == name) {
+        position
+    } else if name == LEVEL_FIELD {
+        fields.len()
+    } else {
+        usize::MAX
+    }
+}
 
 #[derive(Clone, Debug, Default)]
-struct DarkestEntry(Vec<(String, Vec<String>)>);
+pub struct DarkestEntry(Vec<(String, Vec<String>)>);
+
+/// Identity of the entry inside the file.
+///
+/// Entries are identified by their key together with the values of identifying fields,
+/// or by their position among the entries with the same key, if there are no such fields.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryId {
+    key: String,
+    id: String,
+}
 
 #[derive(Clone, Debug, Default)]
-struct DarkestFile(Vec<(String, DarkestEntry)>);
+pub struct DarkestFile(Vec<(EntryId, DarkestEntry)>);
+
+/// Appends the occurrence index to the name, if it was already seen.
+fn index_duplicate(seen: &mut HashMap<String, usize>, name: String, context: &str) -> String {
+    let count = seen.entry(name.clone()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        name
+    } else {
+        warn!(
+            "Duplicate {} \"{}\", keeping occurrence {} as a separate one",
+            context, name, count
+        );
+        format!("{}{}{}", name, DUPLICATE_MARK, *count - 1)
+    }
+}
+
+/// Strips the occurrence index added by `index_duplicate`.
+fn original_name(name: &str) -> &str {
+    name.split(DUPLICATE_MARK).next().unwrap_or(name)
+}
+
+impl DarkestEntry {
+    fn field(&self, name: &str) -> Option<String> {
+        self.0
+            .iter()
+            .find(|(subkey, _)| subkey == name)
+            .map(|(_, values)| values.join(" ").trim_matches('"').to_string())
+    }
+
+    fn identity(&self, key: &str) -> Option<String> {
+        let id = if is_composite(key) {
+            let values: Vec<_> = id_fields(key)
+                .iter()
+                .filter_map(|field| self.field(field))
+                .filter(|value| !value.is_empty())
+                .collect();
+            Some(values.join(&COMPOSITE_MARK.to_string())).filter(|id| !id.is_empty())
+        } else {
+            id_fields(key).iter().find_map(|field| self.field(field))
+        }?;
+        Some(match self.field(LEVEL_FIELD) {
+            Some(level) => format!("{}/{}", id, level),
+            None => id,
+        })
+    }
+
+    fn with_indexed_duplicates(self) -> Self {
+        let mut seen = HashMap::new();
+        Self(
+            self.0
+                .into_iter()
+                .map(|(subkey, values)| (index_duplicate(&mut seen, subkey, "field"), values))
+                .collect(),
+        )
+    }
+}
+
+impl DarkestFile {
+    /// Assigns identities to the parsed entries.
+    ///
+    /// Duplicates (both entries with the same identity and repeated fields inside one entry)
+    /// are never dropped: they are kept in place and addressed by their occurrence index.
+    fn from_entries(entries: Vec<(String, DarkestEntry)>) -> Self {
+        let mut positions = HashMap::new();
+        let mut seen = HashMap::<_, HashMap<_, _>>::new();
+        Self(
+            entries
+                .into_iter()
+                .map(|(key, entry)| {
+                    let position = positions.entry(key.clone()).or_insert(0);
+                    let id = match entry.identity(&key) {
+                        Some(id) => index_duplicate(seen.entry(key.clone()).or_default(), id, &key),
+                        None => format!("#{}", position),
+                    };
+                    *position += 1;
+                    (EntryId { key, id }, entry.with_indexed_duplicates())
+                })
+                .collect(),
+        )
+    }
+}
 
 macro_rules! explode {
     ($with:ident) => {
@@ -17,23 +162,40 @@ macro_rules! explode {
     };
 }
 
-impl super::MapPath for (String, String) {}
+impl MapPath for (EntryId, String) {
+    fn to_data_path(&self) -> DataPath {
+        let (id, subkey) = self;
+        vec![id.key.clone(), id.id.clone(), subkey.clone()].into()
+    }
+    fn from_data_path(path: &[String]) -> Option<Self> {
+        match path {
+            [key, id, subkey] => Some((
+                EntryId {
+                    key: key.clone(),
+                    id: id.clone(),
+                },
+                subkey.clone(),
+            )),
+            _ => None,
+        }
+    }
+}
 
 impl BTreeMappable for DarkestFile {
-    type Key = (String, String);
+    type Key = (EntryId, String);
     type Value = Vec<String>;
-    fn map(&self) -> std::collections::BTreeMap<Self::Key, &Self::Value> {
+    fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
         self.0.iter().flat_map(explode!(iter)).collect()
     }
 
-    fn map_mut(&mut self) -> std::collections::BTreeMap<Self::Key, &mut Self::Value> {
+    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
         self.0.iter_mut().flat_map(explode!(iter_mut)).collect()
     }
 
     fn clone_with(
         &self,
-        f: impl FnOnce(&mut std::collections::BTreeMap<Self::Key, Self::Value>),
-    ) -> Self {
+        f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError> {
         let mut map = self
             .0
             .iter()
@@ -41,19 +203,98 @@ impl BTreeMappable for DarkestFile {
             .flat_map(explode!(into_iter))
             .collect();
         f(&mut map);
-        let mut v = vec![];
-        let mut last = None;
-        for ((key, subkey), value) in map {
-            if last.as_ref() != Some(&key) {
-                last = Some(key.clone());
-                v.push((key, DarkestEntry(vec![])))
+
+        // Keep the original order of entries and fields; new entries go after the last one with the same key,
+        // or to the end, if there's none.
+        let mut entry_positions = HashMap::new();
+        let mut key_positions = HashMap::new();
+        let mut field_positions = HashMap::new();
+        for (index, (id, entry)) in self.0.iter().enumerate() {
+            entry_positions.insert(id.clone(), index);
+            key_positions.insert(id.key.clone(), index);
+            for (field_index, (subkey, _)) in entry.0.iter().enumerate() {
+                field_positions.insert((id.clone(), subkey.clone()), field_index);
+            }
+        }
+        let mut items: Vec<_> = map.into_iter().collect();
+        items.sort_by_cached_key(|(key, _)| {
+            let (id, subkey) = key;
+            let (position, new_id) = match entry_positions.get(id) {
+                Some(position) => (*position, None),
+                None => (
+                    key_positions.get(&id.key).copied().unwrap_or(usize::MAX),
+                    Some(id.clone()),
+                ),
+            };
+            (
+                position,
+                new_id,
+                field_positions
+                    .get(key)
+                    .copied()
+                    .unwrap_or_else(|| new_field_position(&id.key, subkey)),
+            )
+        });
+
+        let mut v: Vec<(EntryId, DarkestEntry)> = vec![];
+        for ((id, subkey), value) in items {
+            if v.last().map(|(last, _)| last) != Some(&id) {
+                v.push((id, DarkestEntry(vec![])))
             }
             (v.last_mut().unwrap().1).0.push((subkey, value));
         }
-        Self(v)
+        Ok(Self(v))
     }
 }
 
+impl Structured for DarkestFile {
+    fn parse(text: &str) -> Result<Self, StructureError> {
+        // Empty file is the base for the files added by several mods.
+        if text.trim().is_empty() {
+            return Ok(Self(vec![]));
+        }
+        (Self::parser(), eof())
+            .map(|(file, _)| file)
+            .easy_parse(text)
+            .map(|(file, _)| file)
+            .map_err(|err| {
+                let position = err.position.translate_position(text);
+                StructureError::Darkest(err.map_position(|_| position).to_string())
+            })
+    }
+
+    fn render(&self) -> String {
+        self.0
+            .iter()
+            .map(|(id, entry)| {
+                let fields: Vec<_> = entry
+                    .0
+                    .iter()
+                    .map(|(subkey, values)| {
+                        format!(".{} {}", original_name(subkey), values.join(" "))
+                    })
+                    .collect();
+                format!("{}: {}\n", id.key, fields.join(" "))
+            })
+            .collect()
+    }
+
+    fn value_to_string(value: &Self::Value) -> String {
+        value.join(" ")
+    }
+
+    fn value_from_string(value: String) -> Self::Value {
+        vec![value]
+    }
+}
+
+/// Separate values of the field, as they are joined in the flattened file; the quoted strings are kept whole.
+///
+/// Returns `None` if the text isn't a list of darkest values.
+pub fn split_values(text: &str) -> Option<Vec<String>> {
+    parser::values(text)
+}
+
 mod parser {
     use super::{DarkestEntry, DarkestFile};
     use combine::{
@@ -149,7 +390,10 @@ mod parser {
                 parse_and_do!(input with eof() => break);
                 // If we can parse the next entry - we're also done.
                 // TODO: find more idiomatic way!
-                if let Err(_) = not_followed_by(DarkestEntry::key().map(|_| "next")).parse(&mut *input) {
+                if not_followed_by(DarkestEntry::key().map(|_| "next"))
+                    .parse(&mut *input)
+                    .is_err()
+                {
                     break;
                 }
                 // Now, we should try to get the next item.
@@ -236,7 +480,7 @@ mod parser {
                 take_until(exact_char('"')),
                 exact_char('"'),
             )
-                .map(|(_, s, _)| s);
+                .map(|(_, s, _): (_, String, _)| format!("\"{}\"", s));
             let number = || {
                 (
                     optional(exact_char('-')),
@@ -268,6 +512,16 @@ mod parser {
         }
     }
 
+    pub fn values(text: &str) -> Option<Vec<String>> {
+        let mut parser = (
+            skip_many(space()),
+            many(DarkestEntry::value().skip(skip_many(space()))),
+            eof(),
+        )
+            .map(|(_, values, _): (_, Vec<String>, _)| values);
+        parser.parse(text).ok().map(|(values, _)| values)
+    }
+
     impl DarkestFile {
         pub fn parser<Input>() -> impl Parser<Input, Output = Self>
         where
@@ -280,12 +534,13 @@ mod parser {
                     DarkestEntry::parser().message("Entry parser failed in file parser"),
                     skip_many(skipped()),
                 ))
-                .map(Self)
+                .map(Self::from_entries)
         }
     }
 
     #[cfg(test)]
     mod test {
+        use super::super::BTreeMappable;
         use super::{DarkestEntry, DarkestFile, ItemsParser};
         use combine::{easy::Errors, stream::PointerOffset, EasyParser};
 
@@ -364,5 +619,112 @@ mod parser {
                 .easy_parse(slice)
                 .unwrap_or_else(|err| bail(err, slice));
         }
+
+        #[test]
+        fn duplicates_are_indexed() {
+            let slice = r#"
+            effect: .name "same" .chance 1
+            effect: .name "same" .chance 2
+            combat_skill: .id "skill" .level 0
+            combat_skill: .id "skill" .level 1
+            row: .value 1 .value 2
+            row: .other 3
+            "#;
+            let (file, _) = DarkestFile::parser()
+                .easy_parse(slice)
+                .unwrap_or_else(|err| bail(err, slice));
+            let ids: Vec<_> = file.0.iter().map(|(id, _)| id.id.as_str()).collect();
+            assert_eq!(ids, ["same", "same~1", "skill/0", "skill/1", "#0", "#1"]);
+            // Every field of every entry must be kept.
+            assert_eq!(file.map().len(), 11);
+        }
+
+        #[test]
+        fn render_round_trip() {
+            use super::super::{Structured, DataPath};
+            let slice = r#"
+            // Comments are not preserved
+            effect: .name "first" .target "target" .chance 100%
+            effect: .name "second" .dotPoison 2 .duration 3
+            "#;
+            let file = DarkestFile::parse(slice).unwrap();
+            let rendered = file.render();
+            assert_eq!(
+                rendered,
+                "effect: .name \"first\" .target \"target\" .chance 100%\n\
+                 effect: .name \"second\" .dotPoison 2 .duration 3\n"
+            );
+            let changed = file.clone_with(|map| {
+                let path = DataPath::from(&["effect", "second", "duration"][..]);
+                let key = super::super::MapPath::from_data_path(&path).unwrap();
+                map.insert(key, vec!["4".into()]);
+            }).unwrap();
+            assert!(changed.render().ends_with(".duration 4\n"));
+        }
+
+        #[test]
+        fn loot_is_keyed_by_code() {
+            use super::super::{DataPath, MapPath, Structured};
+            let slice = r#"
+            extra_battle_loot: .code "hag_a" .count 1
+            death_reaction: .target_allies_of_type "hag" .effects "mourn"
+            extra_battle_loot: .code "hag_b" .count 2
+            "#;
+            let file = DarkestFile::parse(slice).unwrap();
+            let ids: Vec<_> = file.0.iter().map(|(id, _)| id.id.as_str()).collect();
+            assert_eq!(ids, ["hag_a", "mourn", "hag_b"]);
+            let changed = file.clone_with(|map| {
+                for (field, value) in &[("code", "\"hag_c\""), ("count", "3")] {
+                    let path = DataPath::from(&["extra_battle_loot", "hag_c", field][..]);
+                    map.insert(MapPath::from_data_path(&path).unwrap(), vec![value.to_string()]);
+                }
+            }).unwrap();
+            assert_eq!(
+                changed.render(),
+                "extra_battle_loot: .code \"hag_a\" .count 1\n\
+                 death_reaction: .target_allies_of_type \"hag\" .effects \"mourn\"\n\
+                 extra_battle_loot: .code \"hag_b\" .count 2\n\
+                 extra_battle_loot: .code \"hag_c\" .count 3\n"
+            );
+        }
+
+        #[test]
+        fn monster_entries_are_identified() {
+            use super::super::Structured;
+            let slice = r#"
+            resistances: .stun 25% .poison 20% .bleed 20% .disease 10% .move 25% .debuff 20%
+            stats: .hp 8 .def 7.5% .prot 0 .spd 4
+            skill: .id "claw" .type "melee" .atk 72.5% .dmg 2 4
+            skill: .id "howl" .type "ranged" .atk 82.5% .dmg 1 2
+            loot: .code "A" .count 1
+            loot: .code "J" .count 2
+            "#;
+            let file = DarkestFile::parse(slice).unwrap();
+            let ids: Vec<_> = file.0.iter().map(|(id, _)| id.id.as_str()).collect();
+            assert_eq!(ids, ["#0", "#0", "claw", "howl", "A", "J"]);
+        }
+
+        #[test]
+        fn inventory_items_are_keyed_by_type_and_id() {
+            use super::super::{DataPath, MapPath, Structured};
+            let slice = r#"
+            inventory_item: .type "provision" .id "" .base_stack_limit 12
+            inventory_item: .type "supply" .id "torch" .base_stack_limit 8
+            inventory_item: .type "gold" .id "" .base_stack_limit 1750
+            inventory_system_config: .type "raid" .num_slots 16
+            "#;
+            let file = DarkestFile::parse(slice).unwrap();
+            let ids: Vec<_> = file.0.iter().map(|(id, _)| id.id.as_str()).collect();
+            assert_eq!(ids, ["provision", "supply:torch", "gold", "raid"]);
+            let changed = file.clone_with(|map| {
+                for (field, value) in &[("type", "\"supply\""), ("id", "\"rope\""), ("base_stack_limit", "4")] {
+                    let path = DataPath::from(&["inventory_item", "supply:rope", field][..]);
+                    map.insert(MapPath::from_data_path(&path).unwrap(), vec![value.to_string()]);
+                }
+            }).unwrap();
+            assert!(changed
+                .render()
+                .contains("inventory_item: .type \"supply\" .id \"rope\" .base_stack_limit 4\n"));
+        }
     }
 }
@@ -1,9 +1,24 @@
-use super::BTreeMappable;
+use super::{BTreeMappable, Segment, StructureError, Structured};
 use serde_json::{Map, Value};
-use std::{collections::BTreeMap, iter::once};
+use std::{
+    collections::{BTreeMap, HashSet},
+    iter::once,
+};
+
+/// Fields identifying the objects inside arrays of `NamedJsonFile`, unless its data type has its own ones,
+/// e.g. the `id` of the town activities or the `code` of the upgrade requirements.
+pub const NAME_FIELDS: &[&str] = &["name"];
+/// Separator between the identifying field and its value in the keys which replace array indices.
+const ID_MARK: char = '=';
+/// Arrays of strings which are sets of ids, e.g. the hero classes able to use the camping skill.
+/// Their items are identified by themselves, so that the classes added by different mods don't conflict
+/// by their position in the array.
+const SET_FIELDS: &[&str] = &["hero_classes"];
+/// Identifying "field" of the items of the set arrays, i.e. the item itself.
+const SET_ITEM: &str = "value";
 
 #[derive(Clone, PartialOrd, PartialEq, Ord, Eq, Debug)]
-enum JsonPathPart {
+pub enum JsonPathPart {
     Index(usize),
     Key(String),
 }
@@ -28,16 +43,45 @@ impl From<String> for JsonPathPart {
     }
 }
 
-type JsonPath = Vec<JsonPathPart>;
-impl super::MapPath for JsonPath {}
+pub type JsonPath = Vec<JsonPathPart>;
+impl super::MapPath for JsonPath {
+    fn to_data_path(&self) -> super::DataPath {
+        self.iter()
+            .map(|part| match part {
+                JsonPathPart::Index(index) => Segment::Index(*index).to_string(),
+                JsonPathPart::Key(key) => key.clone(),
+            })
+            .collect()
+    }
+    fn from_data_path(path: &[String]) -> Option<Self> {
+        Some(
+            path.iter()
+                .map(|part| match Segment::from(part.as_str()) {
+                    Segment::Index(index) => JsonPathPart::Index(index),
+                    Segment::Key(key) => JsonPathPart::Key(key.to_owned()),
+                })
+                .collect(),
+        )
+    }
+}
 
 struct JsonFile(Value);
 
+/// Empty containers have no values inside, but they still must survive the round trip, so they are leaves.
+fn is_empty_container(value: &Value) -> bool {
+    match value {
+        Value::Array(arr) => arr.is_empty(),
+        Value::Object(obj) => obj.is_empty(),
+        _ => false,
+    }
+}
+
 fn flatten(prefix: JsonPath, value: &Value) -> Vec<(JsonPath, &Value)> {
     match value {
         v @ Value::Null | v @ Value::Bool(_) | v @ Value::Number(_) | v @ Value::String(_) => {
             vec![(prefix, v)]
         }
+        v if is_empty_container(v) => vec![(prefix, v)],
         Value::Array(arr) => arr
             .iter()
             .enumerate()
@@ -69,6 +113,7 @@ fn flatten_mut(prefix: JsonPath, value: &mut Value) -> Vec<(JsonPath, &mut Value
         v @ Value::Null | v @ Value::Bool(_) | v @ Value::Number(_) | v @ Value::String(_) => {
             vec![(prefix, v)]
         }
+        v if is_empty_container(v) => vec![(prefix, v)],
         Value::Array(arr) => arr
             .iter_mut()
             .enumerate()
@@ -100,6 +145,7 @@ fn flatten_owned(prefix: JsonPath, value: Value) -> Vec<(JsonPath, Value)> {
         v @ Value::Null | v @ Value::Bool(_) | v @ Value::Number(_) | v @ Value::String(_) => {
             vec![(prefix, v)]
         }
+        v if is_empty_container(&v) => vec![(prefix, v)],
         Value::Array(arr) => arr
             .into_iter()
             .enumerate()
@@ -135,7 +181,10 @@ impl BTreeMappable for JsonFile {
     fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
         flatten_mut(vec![], &mut self.0).into_iter().collect()
     }
-    fn clone_with(&self, f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>)) -> Self {
+    fn clone_with(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError> {
         let mut map = flatten_owned(vec![], self.0.clone()).into_iter().collect();
         f(&mut map);
 
@@ -143,7 +192,7 @@ impl BTreeMappable for JsonFile {
         let mut root = match self.0 {
             Value::Array(_) => Value::Array(vec![]),
             Value::Object(_) => Value::Object(Map::new()),
-            _ => panic!("We're not supposed to have JSON files with the lonely primitive, are we?"),
+            _ => return Err(StructureError::JsonPrimitive),
         };
 
         for (path, mut value) in map {
@@ -199,13 +248,221 @@ impl BTreeMappable for JsonFile {
                 (Some(JsonPathPart::Key(ref key)), Some(Value::Object(obj))) => {
                     obj.insert(key.clone(), value);
                 }
-                (key, value) => panic!(
-                    "JSON was modified incompatibly: key {:?} is going to be inserted into {:?}",
-                    key, value
-                ),
+                (key, value) => {
+                    return Err(StructureError::JsonIncompatible(format!(
+                        "key {:?} is going to be inserted into {:?}",
+                        key, value
+                    )))
+                }
             }
         }
-        Self(root)
+        Ok(Self(root))
+    }
+}
+
+/// First of the fields which every item of the array has, with the unique value, if there is such one.
+fn identifying_field(arr: &[Value], id_fields: &[&'static str]) -> Option<&'static str> {
+    id_fields.iter().copied().find(|field| {
+        let ids: Option<Vec<_>> = arr
+            .iter()
+            .map(|item| item.get(*field).and_then(Value::as_str))
+            .collect();
+        match ids {
+            Some(ids) if !ids.is_empty() => ids.iter().collect::<HashSet<_>>().len() == ids.len(),
+            _ => false,
+        }
+    })
+}
+
+/// Identifying field and its value, if the key replaces an array index.
+fn parse_key<'a>(key: &'a str, id_fields: &[&str]) -> Option<(&'a str, &'a str)> {
+    key.split_once(ID_MARK)
+        .filter(|(field, _)| id_fields.contains(field) || *field == SET_ITEM)
+}
+
+/// Replaces the array of strings with the object keyed by them, or leaves it as is, if it has anything else.
+///
+/// Every set is keyed, even the empty one or the one with the repeated items, which are dropped, so that
+/// the same field of every file is represented the same way and their changes can be merged.
+fn to_set(arr: Vec<Value>) -> Value {
+    let items: Option<Vec<_>> = arr.iter().map(Value::as_str).collect();
+    match items {
+        Some(items) => Value::Object(
+            items
+                .into_iter()
+                .map(|item| (format!("{}{}{}", SET_ITEM, ID_MARK, item), item.into()))
+                .collect(),
+        ),
+        None => Value::Array(arr),
+    }
+}
+
+/// Replaces every array of identified objects with the object keyed by their identities.
+fn to_named(value: Value, id_fields: &[&'static str]) -> Value {
+    match value {
+        Value::Array(arr) => match identifying_field(&arr, id_fields) {
+            Some(field) => Value::Object(
+                arr.into_iter()
+                    .map(|item| {
+                        let key = format!("{}{}{}", field, ID_MARK, item[field].as_str().unwrap());
+                        (key, to_named(item, id_fields))
+                    })
+                    .collect(),
+            ),
+            None => Value::Array(
+                arr.into_iter()
+                    .map(|item| to_named(item, id_fields))
+                    .collect(),
+            ),
+        },
+        Value::Object(obj) => Value::Object(
+            obj.into_iter()
+                .map(|(key, value)| match value {
+                    Value::Array(arr) if SET_FIELDS.contains(&key.as_str()) => (key, to_set(arr)),
+                    value => (key, to_named(value, id_fields)),
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn find_named<'a>(
+    items: Option<&'a Vec<Value>>,
+    field: &str,
+    id: &str,
+) -> Option<(usize, &'a Value)> {
+    items?.iter().enumerate().find(|(_, item)| {
+        let item_id = if field == SET_ITEM {
+            item.as_str()
+        } else {
+            item.get(field).and_then(Value::as_str)
+        };
+        item_id == Some(id)
+    })
+}
+
+/// Reverts `to_named`, keeping the named objects in the same order as in `original`.
+///
+/// Objects which weren't in the original array are placed at its end.
+fn from_named(value: Value, original: Option<&Value>, id_fields: &[&str]) -> Value {
+    match value {
+        Value::Object(obj)
+            if !obj.is_empty() && obj.keys().all(|key| parse_key(key, id_fields).is_some()) =>
+        {
+            let original_items = original.and_then(Value::as_array);
+            let mut items: Vec<_> = obj
+                .into_iter()
+                .map(|(key, item)| {
+                    let found = parse_key(&key, id_fields)
+                        .and_then(|(field, id)| find_named(original_items, field, id));
+                    (
+                        found.map(|(index, _)| index),
+                        item,
+                        found.map(|(_, orig)| orig),
+                    )
+                })
+                .collect();
+            // Stable sort, so that the new items are kept in order too.
+            items.sort_by_key(|(index, _, _)| index.unwrap_or(usize::MAX));
+            Value::Array(
+                items
+                    .into_iter()
+                    .map(|(_, item, orig)| from_named(item, orig, id_fields))
+                    .collect(),
+            )
+        }
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    from_named(item, original.and_then(|orig| orig.get(index)), id_fields)
+                })
+                .collect(),
+        ),
+        Value::Object(obj) => Value::Object(
+            obj.into_iter()
+                .map(|(key, value)| match value {
+                    // Empty set is still an array, not the object it is keyed as.
+                    Value::Object(set) if set.is_empty() && SET_FIELDS.contains(&key.as_str()) => {
+                        (key, Value::Array(vec![]))
+                    }
+                    value => {
+                        let orig = original.and_then(|orig| orig.get(&key));
+                        (key, from_named(value, orig, id_fields))
+                    }
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// JSON file where the objects in arrays are identified by their `name` or the other field known for
+/// the data type, if they all have one, so that moving or adding the UI elements or town activities
+/// doesn't shift every other one.
+pub struct NamedJsonFile {
+    named: JsonFile,
+    original: Value,
+    id_fields: &'static [&'static str],
+}
+
+impl NamedJsonFile {
+    /// Parses the file, identifying the array items by the first of `id_fields` which tells them apart.
+    pub fn parse_with(
+        text: &str,
+        id_fields: &'static [&'static str],
+    ) -> Result<Self, StructureError> {
+        let original: Value = serde_json::from_str(text)?;
+        if !(original.is_array() || original.is_object()) {
+            return Err(StructureError::JsonPrimitive);
+        }
+        Ok(Self {
+            named: JsonFile(to_named(original.clone(), id_fields)),
+            original,
+            id_fields,
+        })
+    }
+}
+
+impl BTreeMappable for NamedJsonFile {
+    type Key = JsonPath;
+    type Value = Value;
+    fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
+        self.named.map()
+    }
+    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
+        self.named.map_mut()
+    }
+    fn clone_with(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError> {
+        Ok(Self {
+            named: self.named.clone_with(f)?,
+            original: self.original.clone(),
+            id_fields: self.id_fields,
+        })
+    }
+}
+
+impl Structured for NamedJsonFile {
+    fn parse(text: &str) -> Result<Self, StructureError> {
+        Self::parse_with(text, NAME_FIELDS)
+    }
+
+    fn render(&self) -> String {
+        let value = from_named(self.named.0.clone(), Some(&self.original), self.id_fields);
+        serde_json::to_string_pretty(&value).expect("JSON value can always be serialized")
+    }
+
+    fn value_to_string(value: &Self::Value) -> String {
+        value.to_string()
+    }
+
+    fn value_from_string(value: String) -> Self::Value {
+        // Manually entered values might be not quoted.
+        serde_json::from_str(&value).unwrap_or(Value::String(value))
     }
 }
 
@@ -232,7 +489,7 @@ mod tests {
         }"#;
         let value: Value = json.parse().unwrap();
         let file = JsonFile(value.clone());
-        let file = file.clone_with(|_| {});
+        let file = file.clone_with(|_| {}).unwrap();
         assert_eq!(value, file.0);
     }
 
@@ -243,11 +500,181 @@ mod tests {
         let source_value = source.parse().unwrap();
         let target_value: Value = target.parse().unwrap();
         let file = JsonFile(source_value);
-        let file = file.clone_with(|map| {
-            map.remove(&vec!["root".into(), "number".into()]);
-            map.insert(vec!["root".into(), "bool".into()], true.into());
-            map.entry(vec!["root".into(), "string".into()]).and_modify(|e| *e = "new".into());
-        });
+        let file = file
+            .clone_with(|map| {
+                map.remove(&vec!["root".into(), "number".into()]);
+                map.insert(vec!["root".into(), "bool".into()], true.into());
+                map.entry(vec!["root".into(), "string".into()])
+                    .and_modify(|e| *e = "new".into());
+            })
+            .unwrap();
         assert_eq!(file.0, target_value);
     }
+
+    #[test]
+    fn named_objects() {
+        let source = r#"{"widgets": [
+            {"name": "first", "x": 1},
+            {"name": "second", "x": 2},
+            {"name": "third", "x": 3}
+        ], "empty": []}"#;
+        let file = NamedJsonFile::parse(source).unwrap();
+        let map = file.map();
+        let second_x = vec!["widgets".into(), "name=second".into(), "x".into()];
+        assert_eq!(map.get(&second_x), Some(&&Value::from(2)));
+
+        let changed = file
+            .clone_with(|map| {
+                map.retain(|path, _| path.get(1) != Some(&JsonPathPart::Key("name=first".into())));
+                map.insert(second_x.clone(), 20.into());
+                map.insert(
+                    vec!["widgets".into(), "name=a_new".into(), "name".into()],
+                    "a_new".into(),
+                );
+            })
+            .unwrap();
+        let rendered: Value = changed.render().parse().unwrap();
+        let expected: Value = r#"{"widgets": [
+            {"name": "second", "x": 20},
+            {"name": "third", "x": 3},
+            {"name": "a_new"}
+        ], "empty": []}"#
+            .parse()
+            .unwrap();
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn incompatible_values_are_an_error() {
+        let file = JsonFile(r#"{"root": {"value": 1}}"#.parse().unwrap());
+        let changed = file.clone_with(|map| {
+            map.insert(vec!["root".into()], 1.into());
+            map.insert(
+                vec!["root".into(), "inner".into(), "value".into()],
+                2.into(),
+            );
+        });
+        assert!(matches!(changed, Err(StructureError::JsonIncompatible(_))));
+        assert!(matches!(
+            JsonFile(Value::from(1)).clone_with(|_| {}),
+            Err(StructureError::JsonPrimitive)
+        ));
+    }
+
+    #[test]
+    fn objects_identified_by_id() {
+        let source = r#"{"activities": [
+            {"id": "meditation", "cost": 1000},
+            {"id": "flagellation", "cost": 1200}
+        ]}"#;
+        let file = NamedJsonFile::parse_with(source, &["name", "id"]).unwrap();
+        let cost = vec!["activities".into(), "id=flagellation".into(), "cost".into()];
+        assert_eq!(file.map().get(&cost), Some(&&Value::from(1200)));
+
+        let changed = file
+            .clone_with(|map| {
+                map.insert(cost, 800.into());
+                map.insert(
+                    vec!["activities".into(), "id=hag_brew".into(), "id".into()],
+                    "hag_brew".into(),
+                );
+            })
+            .unwrap();
+        let rendered: Value = changed.render().parse().unwrap();
+        let expected: Value = r#"{"activities": [
+            {"id": "meditation", "cost": 1000},
+            {"id": "flagellation", "cost": 800},
+            {"id": "hag_brew"}
+        ]}"#
+        .parse()
+        .unwrap();
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn ids_are_used_only_by_the_types_declaring_them() {
+        let source = r#"{"widgets": [{"id": "a", "x": 1}, {"id": "b", "x": 2}]}"#;
+        let file = NamedJsonFile::parse_with(source, NAME_FIELDS).unwrap();
+        let x = vec!["widgets".into(), 1.into(), "x".into()];
+        assert_eq!(file.map().get(&x), Some(&&Value::from(2)));
+        let file = NamedJsonFile::parse_with(source, &["name", "id"]).unwrap();
+        let x = vec!["widgets".into(), "id=b".into(), "x".into()];
+        assert_eq!(file.map().get(&x), Some(&&Value::from(2)));
+    }
+
+    #[test]
+    fn act_outs_identified_by_quirk_id() {
+        let source = r#"{"quirk_act_outs": [
+            {"quirk_id": "kleptomaniac", "act_outs": [{"type": "steal", "chance": 0.1}]},
+            {"quirk_id": "night_owl", "act_outs": []}
+        ]}"#;
+        let file = NamedJsonFile::parse_with(source, &["name", "id", "quirk_id"]).unwrap();
+        let chance = vec![
+            "quirk_act_outs".into(),
+            "quirk_id=kleptomaniac".into(),
+            "act_outs".into(),
+            0.into(),
+            "chance".into(),
+        ];
+        assert_eq!(file.map().get(&chance), Some(&&Value::from(0.1)));
+    }
+
+    #[test]
+    fn upgrade_requirements_identified_by_code() {
+        let source = r#"{"trees": [
+            {"id": "stage_coach.numrecruits", "requirements": [
+                {"code": "a", "amount": 2, "prerequisite_requirement_codes": []},
+                {"code": "b", "amount": 3, "prerequisite_requirement_codes": ["a"]}
+            ]}
+        ]}"#;
+        let file = NamedJsonFile::parse_with(source, &["name", "id", "code"]).unwrap();
+        let amount = vec![
+            "trees".into(),
+            "id=stage_coach.numrecruits".into(),
+            "requirements".into(),
+            "code=b".into(),
+            "amount".into(),
+        ];
+        assert_eq!(file.map().get(&amount), Some(&&Value::from(3)));
+    }
+
+    #[test]
+    fn hero_classes_are_sets() {
+        let source = r#"{"skills": [
+            {"id": "encourage", "hero_classes": ["crusader", "vestal"], "effects": ["a", "a"]}
+        ]}"#;
+        let file = NamedJsonFile::parse_with(source, &["name", "id"]).unwrap();
+        let class = |name: &str| -> JsonPath {
+            vec![
+                "skills".into(),
+                "id=encourage".into(),
+                "hero_classes".into(),
+                format!("value={}", name).into(),
+            ]
+        };
+        assert_eq!(
+            file.map().get(&class("vestal")),
+            Some(&&Value::from("vestal"))
+        );
+        // Only the sets are keyed by their items, the other arrays are still indexed.
+        let effect: JsonPath = vec![
+            "skills".into(),
+            "id=encourage".into(),
+            "effects".into(),
+            1.into(),
+        ];
+        assert!(file.map().contains_key(&effect));
+
+        let changed = file
+            .clone_with(|map| {
+                map.remove(&class("crusader"));
+                map.insert(class("hag"), "hag".into());
+            })
+            .unwrap();
+        let rendered: Value = serde_json::from_str(&changed.render()).unwrap();
+        assert_eq!(
+            rendered["skills"][0]["hero_classes"],
+            serde_json::json!(["vestal", "hag"])
+        );
+    }
 }
@@ -0,0 +1,109 @@
+use super::{BTreeMappable, DataPath, MapPath, StructureError, Structured};
+use std::collections::BTreeMap;
+
+impl MapPath for String {
+    fn to_data_path(&self) -> DataPath {
+        vec![self.clone()].into()
+    }
+    fn from_data_path(path: &[String]) -> Option<Self> {
+        match path {
+            [line] => Some(line.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Line identity, ignoring the surrounding whitespace; blank lines have none.
+fn key(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_owned())
+    }
+}
+
+/// Text file treated as an unordered set of lines, so that lines added by different mods don't conflict.
+///
+/// Lines are rendered in their original order, with the new ones appended to the end.
+pub struct LineSetFile {
+    /// Every line of the file; blank ones are kept in place, but don't take part in merging.
+    lines: Vec<String>,
+}
+
+impl BTreeMappable for LineSetFile {
+    type Key = String;
+    type Value = String;
+    fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
+        self.lines
+            .iter()
+            .filter_map(|line| Some((key(line)?, line)))
+            .collect()
+    }
+    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
+        self.lines
+            .iter_mut()
+            .filter_map(|line| Some((key(line)?, line)))
+            .collect()
+    }
+    fn clone_with(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError> {
+        let mut map = self
+            .map()
+            .into_iter()
+            .map(|(key, line)| (key, line.clone()))
+            .collect();
+        f(&mut map);
+        let mut lines = vec![];
+        for line in &self.lines {
+            match key(line) {
+                None => lines.push(line.clone()),
+                // Removing the line from map also drops its duplicates.
+                Some(key) => lines.extend(map.remove(&key)),
+            }
+        }
+        lines.extend(map.into_values());
+        Ok(Self { lines })
+    }
+}
+
+impl Structured for LineSetFile {
+    fn parse(text: &str) -> Result<Self, StructureError> {
+        Ok(Self {
+            lines: text.lines().map(String::from).collect(),
+        })
+    }
+
+    fn render(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    fn value_to_string(value: &Self::Value) -> String {
+        value.clone()
+    }
+
+    fn value_from_string(value: String) -> Self::Value {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bundler::structures::Format;
+
+    #[test]
+    fn lines_from_several_mods() {
+        let original = "first\n\nsecond\nthird";
+        let format = Format::LineSet;
+        let original_map = format.flatten(original).unwrap();
+        let mut map = format.flatten("first\n\nthird\nfrom_a").unwrap();
+        map.extend(format.flatten("  second\nfrom_b").unwrap());
+        assert_eq!(original_map.len(), 3);
+        assert_eq!(
+            format.rebuild(original, map).unwrap(),
+            "first\n\n  second\nthird\nfrom_a\nfrom_b"
+        );
+    }
+}
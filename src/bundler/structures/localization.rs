@@ -1 +1,227 @@
+use log::*;
+use std::collections::BTreeMap;
+use thiserror::Error;
+use xml::reader::{EventReader, XmlEvent};
 
+/// The language which is expected to contain every string used by the game.
+pub const PRIMARY_LANGUAGE: &str = "english";
+/// Languages supported by the game, by their ids in the string tables.
+pub const LANGUAGES: &[&str] = &[
+    "english",
+    "french",
+    "german",
+    "spanish",
+    "brazilian",
+    "russian",
+    "polish",
+    "czech",
+    "italian",
+    "schinese",
+    "tchinese",
+    "japanese",
+    "koreana",
+];
+
+#[derive(Debug, Error)]
+pub enum LocalizationError {
+    #[error("Malformed XML in string table")]
+    Xml(#[from] xml::reader::Error),
+    #[error("Entry found outside of any <language> element")]
+    NoLanguage,
+    #[error("Element <{0}> has no \"id\" attribute")]
+    NoId(String),
+}
+
+/// Contents of one or several `*.string_table.xml` files, grouped by language.
+#[derive(Clone, Debug, Default)]
+pub struct StringsTable(BTreeMap<String, BTreeMap<String, String>>);
+
+impl StringsTable {
+    pub fn parse(text: &str) -> Result<Self, LocalizationError> {
+        let mut table = BTreeMap::<_, BTreeMap<_, _>>::new();
+        let mut language: Option<String> = None;
+        let mut entry: Option<(String, String)> = None;
+        for event in EventReader::from_str(text) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let id = attributes
+                        .into_iter()
+                        .find(|attr| attr.name.local_name == "id")
+                        .map(|attr| attr.value);
+                    match name.local_name.as_str() {
+                        "language" => {
+                            language = Some(id.ok_or(LocalizationError::NoId(name.local_name))?)
+                        }
+                        "entry" => {
+                            if language.is_none() {
+                                return Err(LocalizationError::NoLanguage);
+                            }
+                            let id = id.ok_or(LocalizationError::NoId(name.local_name))?;
+                            entry = Some((id, String::new()));
+                        }
+                        _ => {}
+                    }
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some((_, value)) = &mut entry {
+                        value.push_str(&text);
+                    }
+                }
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "entry" => {
+                        if let (Some(language), Some((id, value))) = (&language, entry.take()) {
+                            // The game uses the last one of duplicated entries.
+                            let entries = table.entry(language.clone()).or_default();
+                            if entries.contains_key(&id) {
+                                warn!(
+                                    "Duplicate string \"{}\" for language {}, using the last one",
+                                    id, language
+                                );
+                            }
+                            entries.insert(id, value);
+                        }
+                    }
+                    "language" => language = None,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(Self(table))
+    }
+
+    /// Merges another table into this one; entries from `other` take precedence.
+    pub fn extend(&mut self, other: Self) {
+        for (language, entries) in other.0 {
+            self.0.entry(language).or_default().extend(entries);
+        }
+    }
+
+    pub fn get(&self, language: &str, id: &str) -> Option<&str> {
+        self.0
+            .get(language)
+            .and_then(|entries| entries.get(id))
+            .map(String::as_str)
+    }
+
+    pub fn contains(&self, language: &str, id: &str) -> bool {
+        self.get(language, id).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.values().all(BTreeMap::is_empty)
+    }
+
+    /// Table with only the given language, where the strings missing in it are copied from the primary one.
+    ///
+    /// Returns the number of strings copied this way.
+    pub fn only_language(&self, language: &str) -> (Self, usize) {
+        let mut entries = self.0.get(language).cloned().unwrap_or_default();
+        let mut fallbacks = 0;
+        if let Some(primary) = self.0.get(PRIMARY_LANGUAGE) {
+            for (id, value) in primary {
+                if !entries.contains_key(id) {
+                    entries.insert(id.clone(), value.clone());
+                    fallbacks += 1;
+                }
+            }
+        }
+        let mut table = BTreeMap::new();
+        table.insert(language.to_owned(), entries);
+        (Self(table), fallbacks)
+    }
+
+    pub fn render(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<root>\n");
+        for (language, entries) in &self.0 {
+            xml.push_str(&format!(
+                "    <language id=\"{}\">\n",
+                escape_attribute(language)
+            ));
+            for (id, value) in entries {
+                xml.push_str(&format!(
+                    "        <entry id=\"{}\"><![CDATA[{}]]></entry>\n",
+                    escape_attribute(id),
+                    // CDATA can't contain its own terminator, so it is split in two sections.
+                    value.replace("]]>", "]]]]><![CDATA[>")
+                ));
+            }
+            xml.push_str("    </language>\n");
+        }
+        xml.push_str("</root>\n");
+        xml
+    }
+}
+
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_table() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <root>
+                <language id="english">
+                    <entry id="plain">Plain text</entry>
+                    <entry id="cdata"><![CDATA[Text with <tags>]]></entry>
+                </language>
+                <language id="russian">
+                    <entry id="plain">Текст</entry>
+                </language>
+            </root>"#;
+        let table = StringsTable::parse(xml).unwrap();
+        assert_eq!(table.get("english", "plain"), Some("Plain text"));
+        assert_eq!(table.get("english", "cdata"), Some("Text with <tags>"));
+        assert_eq!(table.get("russian", "plain"), Some("Текст"));
+        assert!(!table.contains("russian", "cdata"));
+    }
+
+    #[test]
+    fn duplicate_entry() {
+        let xml = r#"<root><language id="english">
+                <entry id="dup">First</entry>
+                <entry id="dup">Second</entry>
+            </language></root>"#;
+        let table = StringsTable::parse(xml).unwrap();
+        assert_eq!(table.get("english", "dup"), Some("Second"));
+    }
+
+    #[test]
+    fn only_language_with_fallback() {
+        let xml = r#"<root>
+                <language id="english">
+                    <entry id="translated">Text</entry>
+                    <entry id="untranslated"><![CDATA[Only <b>english</b> ]]]]><![CDATA[> here]]></entry>
+                </language>
+                <language id="russian"><entry id="translated">Текст</entry></language>
+                <language id="french"><entry id="translated">Texte</entry></language>
+            </root>"#;
+        let (table, fallbacks) = StringsTable::parse(xml).unwrap().only_language("russian");
+        assert_eq!(fallbacks, 1);
+        let rendered = StringsTable::parse(&table.render()).unwrap();
+        assert_eq!(rendered.get("russian", "translated"), Some("Текст"));
+        assert_eq!(
+            rendered.get("russian", "untranslated"),
+            Some("Only <b>english</b> ]]> here")
+        );
+        assert!(!rendered.contains("english", "translated"));
+        assert!(!rendered.contains("french", "translated"));
+    }
+
+    #[test]
+    fn entry_without_language() {
+        let xml = r#"<root><entry id="orphan">Text</entry></root>"#;
+        assert!(matches!(
+            StringsTable::parse(xml),
+            Err(LocalizationError::NoLanguage)
+        ));
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, iter::FromIterator, ops::Deref};
+
+/// Path to the single value inside the structured file, e.g. `skill/slice/effect` in the darkest files
+/// (entry key, entry id and subkey) or `buffs/[2]/amount` in the JSON ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct DataPath(Vec<String>);
+
+/// Single part of the data path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// Position in the list, written as `[index]`.
+    Index(usize),
+    Key(&'a str),
+}
+
+impl<'a> From<&'a str> for Segment<'a> {
+    fn from(segment: &'a str) -> Self {
+        match segment
+            .strip_prefix('[')
+            .and_then(|segment| segment.strip_suffix(']'))
+            .and_then(|index| index.parse().ok())
+        {
+            Some(index) => Segment::Index(index),
+            None => Segment::Key(segment),
+        }
+    }
+}
+
+impl fmt::Display for Segment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Segment::Index(index) => write!(f, "[{}]", index),
+            Segment::Key(key) => f.write_str(key),
+        }
+    }
+}
+
+impl DataPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, segment: impl Into<String>) {
+        self.0.push(segment.into());
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        self.0.pop()
+    }
+
+    /// Path to the value nested under this one.
+    pub fn child(&self, segment: impl Into<String>) -> Self {
+        let mut child = self.clone();
+        child.push(segment);
+        child
+    }
+
+    /// First segment, i.e. the top-level entry the value belongs to.
+    pub fn root(&self) -> Option<&str> {
+        self.0.first().map(String::as_str)
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = Segment<'_>> {
+        self.0.iter().map(|segment| Segment::from(segment.as_str()))
+    }
+}
+
+impl Deref for DataPath {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl From<Vec<String>> for DataPath {
+    fn from(segments: Vec<String>) -> Self {
+        Self(segments)
+    }
+}
+
+impl From<&[&str]> for DataPath {
+    fn from(segments: &[&str]) -> Self {
+        segments.iter().map(|segment| segment.to_string()).collect()
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for DataPath {
+    fn from_iter<I: IntoIterator<Item = S>>(segments: I) -> Self {
+        Self(segments.into_iter().map(Into::into).collect())
+    }
+}
+
+impl fmt::Display for DataPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_and_display() {
+        let mut path = DataPath::from(&["buffs", "[2]"][..]);
+        path.push("amount");
+        assert_eq!(path.to_string(), "buffs/[2]/amount");
+        assert_eq!(path.root(), Some("buffs"));
+        assert_eq!(
+            path.segments().collect::<Vec<_>>(),
+            [
+                Segment::Key("buffs"),
+                Segment::Index(2),
+                Segment::Key("amount")
+            ]
+        );
+        assert!(path.starts_with(&["buffs".to_string()]));
+        assert_eq!(path.pop().as_deref(), Some("amount"));
+        assert_eq!(path.child("id").to_string(), "buffs/[2]/id");
+        assert_eq!(serde_json::to_string(&path).unwrap(), r#"["buffs","[2]"]"#);
+    }
+}
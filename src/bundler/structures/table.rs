@@ -0,0 +1,258 @@
+//! Spreadsheets exported by the game designers, e.g. `curios/curio_type_library.csv`.
+//!
+//! The rows before the first record are the column titles. Every record starts with the row having its id
+//! in the second column, the first one being its number, and goes on with the rows of its details, where
+//! the id column is left empty:
+//!
+//! ```text
+//! ID,Full ID,Region Found,Tags
+//! 1,discarded_pack,all,Haunted
+//! ,,,,nothing,0.2,,
+//! ,,,,loot,0.8,,
+//! ```
+//!
+//! Every non-empty cell is the value, identified by the record id, the row within the record and the column.
+
+use super::{BTreeMappable, DataPath, MapPath, StructureError, Structured};
+use crate::bundler::csv;
+use std::collections::BTreeMap;
+
+const ID_COLUMN: usize = 1;
+/// Separator for the occurrence index of records with the same id.
+const DUPLICATE_MARK: char = '~';
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CellKey {
+    record: String,
+    row: usize,
+    column: usize,
+}
+
+impl MapPath for CellKey {
+    fn to_data_path(&self) -> DataPath {
+        vec![
+            self.record.clone(),
+            self.row.to_string(),
+            self.column.to_string(),
+        ]
+        .into()
+    }
+    fn from_data_path(path: &[String]) -> Option<Self> {
+        match path {
+            [record, row, column] => Some(Self {
+                record: record.clone(),
+                row: row.parse().ok()?,
+                column: column.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Record {
+    /// Id with the occurrence index, if the table has several records with this id.
+    id: String,
+    rows: Vec<Vec<String>>,
+}
+
+fn record_id(id: &str) -> &str {
+    match id.rsplit_once(DUPLICATE_MARK) {
+        Some((id, index)) if index.parse::<usize>().is_ok() => id,
+        _ => id,
+    }
+}
+
+pub struct CsvTable {
+    titles: Vec<Vec<String>>,
+    records: Vec<Record>,
+}
+
+impl BTreeMappable for CsvTable {
+    type Key = CellKey;
+    type Value = String;
+    fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
+        let mut map = BTreeMap::new();
+        for record in &self.records {
+            for (row, cells) in record.rows.iter().enumerate() {
+                for (column, value) in cells.iter().enumerate() {
+                    if !value.is_empty() {
+                        let key = CellKey {
+                            record: record.id.clone(),
+                            row,
+                            column,
+                        };
+                        map.insert(key, value);
+                    }
+                }
+            }
+        }
+        map
+    }
+    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
+        let mut map = BTreeMap::new();
+        for record in &mut self.records {
+            for (row, cells) in record.rows.iter_mut().enumerate() {
+                for (column, value) in cells.iter_mut().enumerate() {
+                    if !value.is_empty() {
+                        let key = CellKey {
+                            record: record.id.clone(),
+                            row,
+                            column,
+                        };
+                        map.insert(key, value);
+                    }
+                }
+            }
+        }
+        map
+    }
+    fn clone_with(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>),
+    ) -> Result<Self, StructureError> {
+        let mut map: BTreeMap<_, _> = self
+            .map()
+            .into_iter()
+            .map(|(key, value)| (key, value.clone()))
+            .collect();
+        f(&mut map);
+
+        let mut cells = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for (key, value) in map {
+            cells
+                .entry(key.record)
+                .or_default()
+                .insert((key.row, key.column), value);
+        }
+        // Existing records keep their places and the widths of their rows; new ones go to the end.
+        let mut records = vec![];
+        for record in &self.records {
+            if let Some(values) = cells.remove(&record.id) {
+                records.push(Record {
+                    id: record.id.clone(),
+                    rows: fill(&record.rows, values),
+                });
+            }
+        }
+        for (id, mut values) in cells {
+            values
+                .entry((0, ID_COLUMN))
+                .or_insert_with(|| record_id(&id).to_owned());
+            records.push(Record {
+                rows: fill(&[], values),
+                id,
+            });
+        }
+        Ok(Self {
+            titles: self.titles.clone(),
+            records,
+        })
+    }
+}
+
+/// Rows of the record with the given values, laid out like the `original` rows and widened to fit the new ones.
+fn fill(original: &[Vec<String>], values: BTreeMap<(usize, usize), String>) -> Vec<Vec<String>> {
+    let count = values
+        .keys()
+        .map(|(row, _)| row + 1)
+        .max()
+        .unwrap_or(0)
+        .max(original.len());
+    let mut rows: Vec<_> = (0..count)
+        .map(|row| vec![String::new(); original.get(row).map_or(0, Vec::len)])
+        .collect();
+    for ((row, column), value) in values {
+        let row = &mut rows[row];
+        if row.len() <= column {
+            row.resize(column + 1, String::new());
+        }
+        row[column] = value;
+    }
+    rows
+}
+
+impl Structured for CsvTable {
+    fn parse(text: &str) -> Result<Self, StructureError> {
+        let mut titles = vec![];
+        let mut records: Vec<Record> = vec![];
+        for row in csv::parse(text) {
+            match row.get(ID_COLUMN).map(|id| id.trim()) {
+                Some(id) if !id.is_empty() => {
+                    let count = records
+                        .iter()
+                        .filter(|record| record_id(&record.id) == id)
+                        .count();
+                    let id = match count {
+                        0 => id.to_owned(),
+                        count => format!("{}{}{}", id, DUPLICATE_MARK, count),
+                    };
+                    records.push(Record {
+                        id,
+                        rows: vec![row],
+                    });
+                }
+                _ => match records.last_mut() {
+                    Some(record) => record.rows.push(row),
+                    None => titles.push(row),
+                },
+            }
+        }
+        Ok(Self { titles, records })
+    }
+
+    fn render(&self) -> String {
+        let rows = self
+            .titles
+            .iter()
+            .chain(self.records.iter().flat_map(|record| &record.rows));
+        rows.map(|row| {
+            let fields: Vec<_> = row.iter().map(|value| csv::field(value)).collect();
+            fields.join(",") + "\n"
+        })
+        .collect()
+    }
+
+    fn value_to_string(value: &Self::Value) -> String {
+        value.clone()
+    }
+
+    fn value_from_string(value: String) -> Self::Value {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bundler::structures::Format;
+
+    /// Layout of the game's `curios/curio_type_library.csv`, cut down to two curios.
+    const CURIOS: &str = "ID,Full ID,Region Found,Tags,Results,,\n\
+                          1,discarded_pack,all,Haunted,,,\n\
+                          ,,,,nothing,0.2,\n\
+                          ,,,,loot,0.8,\"str_curio_content_loot, maybe\"\n\
+                          2,altar_of_light,ruins,Religious,,,\n\
+                          ,,,,purge,1,\n";
+
+    #[test]
+    fn curios_are_identified_by_their_ids() {
+        let format = Format::Csv;
+        let original = format.flatten(CURIOS).unwrap();
+        assert_eq!(
+            original[&["discarded_pack", "2", "6"][..].into()],
+            "str_curio_content_loot, maybe"
+        );
+        assert_eq!(format.rebuild(CURIOS, original.clone()).unwrap(), CURIOS);
+
+        let mut map = original;
+        // One mod tweaks the loot chance, another one adds the curio of its own.
+        map.insert(["discarded_pack", "2", "5"][..].into(), "0.5".into());
+        map.insert(["bone_altar", "0", "0"][..].into(), "3".into());
+        map.insert(["bone_altar", "0", "2"][..].into(), "crypts".into());
+        map.insert(["bone_altar", "1", "4"][..].into(), "effect".into());
+        assert_eq!(
+            format.rebuild(CURIOS, map).unwrap(),
+            CURIOS.replace(",loot,0.8,", ",loot,0.5,") + "3,bone_altar,crypts\n,,,,effect\n"
+        );
+    }
+}
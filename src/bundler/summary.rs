@@ -0,0 +1,158 @@
+//! Outcome of the bundling, reported through the exit code and, if requested, as a JSON file,
+//! so that the scripts building modpacks can check whether the bundle is healthy.
+
+use log::*;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Bundling has failed, or the program was closed before it finished.
+    NotBundled,
+    Success,
+    ConflictsRemaining,
+    ParseErrors,
+    DeployErrors,
+}
+
+impl Outcome {
+    /// Exit code of the program: 0 for success, 1 if nothing was bundled, 2 if some conflicts remain unresolved,
+    /// 3 if some files couldn't be parsed and 4 if the bundle couldn't be deployed.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::NotBundled => 1,
+            Outcome::ConflictsRemaining => 2,
+            Outcome::ParseErrors => 3,
+            Outcome::DeployErrors => 4,
+        }
+    }
+}
+
+/// Summary of the bundling attempt, filled while bundling and handed back to the main thread when it ends.
+#[derive(Serialize, Debug)]
+pub struct Summary {
+    outcome: Outcome,
+    exit_code: i32,
+    mods: Vec<String>,
+    conflicts: usize,
+    conflicts_remaining: Vec<PathBuf>,
+    /// Files which couldn't be parsed and were merged line by line or not checked.
+    parse_errors: Vec<String>,
+    /// Problems found after merging, which didn't prevent the deployment.
+    issues: Vec<String>,
+    error: Option<String>,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+impl Summary {
+    /// Starts the summary of the new bundling attempt.
+    pub fn new(mods: Vec<String>) -> Self {
+        Self {
+            outcome: Outcome::NotBundled,
+            exit_code: Outcome::NotBundled.exit_code(),
+            mods,
+            conflicts: 0,
+            conflicts_remaining: vec![],
+            parse_errors: vec![],
+            issues: vec![],
+            error: None,
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome) {
+        // A bundle built from partially unparsed data is deployed, but isn't healthy.
+        self.outcome = match outcome {
+            Outcome::Success if !self.parse_errors.is_empty() => Outcome::ParseErrors,
+            outcome => outcome,
+        };
+        self.exit_code = self.outcome.exit_code();
+    }
+
+    pub fn conflicts(&mut self, count: usize) {
+        self.conflicts = count;
+    }
+
+    pub fn parse_error(&mut self, path: &Path, err: impl std::fmt::Display) {
+        self.parse_errors
+            .push(format!("{}: {}", path.to_string_lossy(), err));
+    }
+
+    pub fn postponed(&mut self, paths: Vec<PathBuf>) {
+        self.conflicts_remaining = paths;
+        self.finish(Outcome::ConflictsRemaining);
+    }
+
+    pub fn deployed(&mut self, issues: Vec<String>) {
+        self.issues = issues;
+        self.finish(Outcome::Success);
+    }
+
+    pub fn failed(&mut self, outcome: Outcome, err: &dyn std::error::Error) {
+        self.error = Some(err.to_string());
+        self.finish(outcome);
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Summary can always be serialized")
+    }
+
+    /// Writes the summary to the given file, if any, and returns the exit code for it.
+    pub fn report(&self, json: Option<&Path>) -> i32 {
+        info!(
+            "[summary] Finishing with {:?}, exit code {}",
+            self.outcome, self.exit_code
+        );
+        if let Some(path) = json {
+            if let Err(err) = std::fs::write(path, self.to_json()) {
+                error!("[summary] Unable to write summary to {:?}: {}", path, err);
+            }
+        }
+        self.exit_code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors_make_deployed_bundle_unhealthy() {
+        let mut summary = Summary::new(vec!["Hag Class".into()]);
+        summary.deployed(vec![]);
+        assert_eq!(summary.exit_code, 0);
+
+        summary.parse_error(Path::new("loot/loot.darkest"), "broken");
+        summary.deployed(vec![]);
+        assert_eq!(summary.outcome, Outcome::ParseErrors);
+        assert_eq!(summary.exit_code, 3);
+
+        let err = std::io::Error::other("disk is full");
+        summary.failed(Outcome::DeployErrors, &err);
+        assert_eq!(summary.exit_code, 4);
+    }
+
+    #[test]
+    fn exit_code_is_reported_for_every_outcome() {
+        let json = crate::fixtures::temp_dir("summary").join("summary.json");
+        assert_eq!(Summary::default().report(Some(&json)), 1);
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(written["outcome"], "not_bundled");
+        assert_eq!(written["exit_code"], 1);
+        std::fs::remove_dir_all(json.parent().unwrap()).unwrap();
+
+        let mut summary = Summary::new(vec![]);
+        summary.postponed(vec!["loot/loot.darkest".into()]);
+        assert_eq!(summary.report(None), 2);
+        let mut summary = Summary::new(vec![]);
+        summary.deployed(vec!["Unable to write lock file".into()]);
+        assert_eq!(summary.report(None), 0);
+    }
+}
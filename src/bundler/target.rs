@@ -0,0 +1,242 @@
+//! Places where the deployed bundle is delivered to.
+//!
+//! The bundle is always deployed to the game's mods folder first, since its lock file and the rollback live there;
+//! the other targets deliver a copy of it. New targets only need to implement `DeploymentTarget`
+//! and be listed in `TARGETS`, to be selectable in the deployment options.
+
+use super::{deploy::files, error::DeploymentError};
+use crate::config::Config;
+use cursive::views::{Dialog, TextView};
+use log::*;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+pub trait DeploymentTarget: Sync {
+    /// Name shown in the deployment options and stored in the config.
+    fn name(&self) -> &'static str;
+    /// Delivers the bundle deployed to `bundle`, returning where the copy was made, if it was.
+    fn deliver(
+        &self,
+        sink: &mut cursive::CbSink,
+        bundle: &Path,
+        config: &Config,
+    ) -> Result<Option<PathBuf>, DeploymentError>;
+}
+
+/// Leaves the bundle in the mods folder, where the game picks it up.
+pub struct ModsFolder;
+
+impl DeploymentTarget for ModsFolder {
+    fn name(&self) -> &'static str {
+        "Game mods folder"
+    }
+
+    fn deliver(
+        &self,
+        _: &mut cursive::CbSink,
+        _: &Path,
+        _: &Config,
+    ) -> Result<Option<PathBuf>, DeploymentError> {
+        Ok(None)
+    }
+}
+
+/// Packs the bundle into the zip archive, to be shared or moved to another computer.
+pub struct Archive;
+
+impl DeploymentTarget for Archive {
+    fn name(&self) -> &'static str {
+        "Zip archive"
+    }
+
+    fn deliver(
+        &self,
+        _: &mut cursive::CbSink,
+        bundle: &Path,
+        _: &Config,
+    ) -> Result<Option<PathBuf>, DeploymentError> {
+        let archive = crate::paths::bundle_archive();
+        pack(bundle, &archive)?;
+        Ok(Some(archive))
+    }
+}
+
+/// Copies the bundle into the directory chosen by user, e.g. the mods folder of another installation.
+pub struct Directory;
+
+impl DeploymentTarget for Directory {
+    fn name(&self) -> &'static str {
+        "Another directory"
+    }
+
+    fn deliver(
+        &self,
+        sink: &mut cursive::CbSink,
+        bundle: &Path,
+        config: &Config,
+    ) -> Result<Option<PathBuf>, DeploymentError> {
+        let dir = config
+            .deploy_directory
+            .as_deref()
+            .filter(|dir| !dir.trim().is_empty())
+            .ok_or(DeploymentError::NoTargetDirectory)?;
+        let target = Path::new(dir.trim()).join(bundle.file_name().unwrap_or_default());
+        // E.g. the game's mods folder entered as the target: removing it would remove the bundle itself.
+        if target.exists() && target.canonicalize().ok() == bundle.canonicalize().ok() {
+            return Err(DeploymentError::TargetIsBundle(target));
+        }
+        if target.exists() {
+            if !ask_for_overwrite(sink, &target) {
+                return Err(DeploymentError::AlreadyExists);
+            }
+            std::fs::remove_dir_all(&target).map_err(DeploymentError::from_io(&target))?;
+        }
+        for file in files(bundle).map_err(DeploymentError::from_io(bundle))? {
+            let to = target.join(&file);
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent).map_err(DeploymentError::from_io(parent))?;
+            }
+            std::fs::copy(bundle.join(&file), &to).map_err(DeploymentError::from_io(&to))?;
+        }
+        Ok(Some(target))
+    }
+}
+
+/// Asks whether the existing copy in the target directory may be replaced.
+fn ask_for_overwrite(sink: &mut cursive::CbSink, target: &Path) -> bool {
+    let target = target.to_owned();
+    crate::prompt::ask_or(sink, false, move |cursive, reply| {
+        let cancel = reply.clone();
+        crate::push_screen(
+            cursive,
+            Dialog::around(TextView::new(format!(
+                "Directory {} already exists.
+Overwrite it with the copy of the bundle, or cancel delivering it (the bundle stays deployed to the mods folder)?",
+                target.to_string_lossy()
+            )))
+            .title("Target directory exists")
+            .button("Overwrite", move |cursive| {
+                cursive.pop_layer();
+                reply.send(true);
+            })
+            .button("Cancel", move |cursive| {
+                cursive.pop_layer();
+                cancel.send(false);
+            }),
+        );
+    })
+}
+
+/// Every known target; the first one is used when none is chosen.
+pub static TARGETS: &[&dyn DeploymentTarget] = &[&ModsFolder, &Archive, &Directory];
+
+/// Target with the given name, falling back to the mods folder if there's no such target.
+pub fn by_name(name: Option<&str>) -> &'static dyn DeploymentTarget {
+    TARGETS
+        .iter()
+        .find(|target| Some(target.name()) == name)
+        .copied()
+        .unwrap_or(TARGETS[0])
+}
+
+/// Delivers the deployed bundle to the target chosen in the config.
+pub fn deliver(
+    sink: &mut cursive::CbSink,
+    bundle: &Path,
+    config: &Config,
+) -> Result<Option<PathBuf>, DeploymentError> {
+    let target = by_name(config.deploy_target.as_deref());
+    info!("[target] Delivering bundle to {}", target.name());
+    let delivered = target.deliver(sink, bundle, config)?;
+    if let Some(path) = &delivered {
+        info!("[target] Bundle delivered to {:?}", path);
+    }
+    Ok(delivered)
+}
+
+fn pack(bundle: &Path, archive: &Path) -> Result<(), DeploymentError> {
+    let file = std::fs::File::create(archive).map_err(DeploymentError::from_io(archive))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let root = Path::new(bundle.file_name().unwrap_or_default());
+    for file in files(bundle).map_err(DeploymentError::from_io(bundle))? {
+        let source = bundle.join(&file);
+        let content = std::fs::read(&source).map_err(DeploymentError::from_io(&source))?;
+        // Zip entries always use the forward slashes.
+        let name = root.join(&file).to_string_lossy().replace('\\', "/");
+        zip.start_file(name, options)?;
+        zip.write_all(&content)
+            .map_err(DeploymentError::from_io(archive))?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::puppet::drive;
+    use crate::fixtures::{temp_dir, write_files};
+    use cursive::event::{Event, Key};
+
+    #[test]
+    fn directory_target_copies_bundle() {
+        let root = temp_dir("target");
+        let bundle = root.join("mods/generated_bundle");
+        write_files(
+            &bundle,
+            &[
+                ("heroes/hag/hag.info.darkest", "hag"),
+                ("project.xml", "<project/>"),
+            ],
+        );
+        let (mut sink, _receiver) = crossbeam_channel::unbounded();
+
+        let mut config = Config::default();
+        assert!(matches!(
+            Directory.deliver(&mut sink, &bundle, &config),
+            Err(DeploymentError::NoTargetDirectory)
+        ));
+        config.deploy_directory = Some(root.join("mods").to_string_lossy().into_owned());
+        assert!(matches!(
+            Directory.deliver(&mut sink, &bundle, &config),
+            Err(DeploymentError::TargetIsBundle(_))
+        ));
+        assert!(bundle.join("project.xml").exists());
+
+        config.deploy_directory = Some(root.join("share").to_string_lossy().into_owned());
+        let copied = Directory
+            .deliver(&mut sink, &bundle, &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(copied, root.join("share/generated_bundle"));
+        assert_eq!(files(&copied).unwrap(), files(&bundle).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(copied.join("heroes/hag/hag.info.darkest")).unwrap(),
+            "hag"
+        );
+        assert_eq!(by_name(Some("Unknown")).name(), ModsFolder.name());
+
+        // The existing copy is only replaced when user agrees.
+        std::fs::write(copied.join("stale.txt"), "stale").unwrap();
+        let answers = |keys: Vec<Event>| {
+            let (bundle, config) = (bundle.clone(), config.clone());
+            drive(
+                move |sink| Directory.deliver(sink, &bundle, &config),
+                vec![keys],
+            )
+            .0
+        };
+        assert!(matches!(
+            answers(vec![Event::Key(Key::Right), Event::Key(Key::Enter)]),
+            Err(DeploymentError::AlreadyExists)
+        ));
+        assert!(copied.join("stale.txt").exists());
+        answers(vec![Event::Key(Key::Enter)]).unwrap();
+        assert!(!copied.join("stale.txt").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+use log::*;
+use std::time::{Duration, Instant};
+
+/// Time spent in every phase of the bundling, in order of their first appearance.
+#[derive(Debug, Default)]
+pub struct Timings(Vec<(&'static str, Duration)>);
+
+impl Timings {
+    /// Runs the closure, adding the time it took to the given phase.
+    pub fn measure<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(phase, start.elapsed());
+        result
+    }
+
+    /// Adds time to the phase; phases which are run several times (e.g. once per mod) are summed up.
+    pub fn add(&mut self, phase: &'static str, duration: Duration) {
+        debug!("[timing] {}: +{}", phase, format_duration(duration));
+        match self.0.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.0.push((phase, duration)),
+        }
+    }
+
+    /// Human-readable breakdown, one phase per line.
+    pub fn report(&self, elapsed: Duration) -> String {
+        self.0
+            .iter()
+            .map(|(phase, duration)| format!("{}: {}", phase, format_duration(*duration)))
+            .chain(std::iter::once(format!(
+                "Total: {}",
+                format_duration(elapsed)
+            )))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_phases_are_summed() {
+        let mut timings = Timings::default();
+        timings.add("Loading mods", Duration::from_millis(1500));
+        timings.add("Diffing", Duration::from_millis(250));
+        timings.add("Loading mods", Duration::from_millis(500));
+        assert_eq!(
+            timings.report(Duration::from_secs(61)),
+            "Loading mods: 2.00s\nDiffing: 0.25s\nTotal: 1m 1s"
+        );
+    }
+}
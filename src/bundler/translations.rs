@@ -0,0 +1,197 @@
+use super::{
+    diff::{Conflicts, DiffTree},
+    lock::LockedMod,
+    validate::is_string_table,
+};
+use log::*;
+use std::path::Path;
+
+/// Words which mark the mod title as the one of a translation, in lowercase.
+const TRANSLATION_WORDS: &[&str] = &[
+    "translation",
+    "localization",
+    "localisation",
+    "перевод",
+    "русификатор",
+    "traduction",
+    "traducción",
+    "tradução",
+    "traduzione",
+    "übersetzung",
+    "tłumaczenie",
+    "překlad",
+    "翻译",
+    "汉化",
+];
+/// Titles shorter than this are too likely to be found inside another title by chance.
+const MIN_TARGET_TITLE: usize = 4;
+
+/// Selected mod which only translates another selected mod.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Translation {
+    pub translation: String,
+    pub target: String,
+    /// Whether the translation is bundled before its target, i.e. is overridden by it.
+    pub misordered: bool,
+}
+
+/// Files which are not the part of the mod data, i.e. `project.xml` and preview.
+fn is_metadata(path: &Path) -> bool {
+    path.parent() == Some(Path::new(""))
+}
+
+fn looks_like_translation(the_mod: &LockedMod) -> bool {
+    let title = the_mod.title.to_lowercase();
+    if TRANSLATION_WORDS.iter().any(|word| title.contains(word)) {
+        return true;
+    }
+    let content: Vec<_> = the_mod
+        .files
+        .keys()
+        .filter(|path| !is_metadata(path))
+        .collect();
+    !content.is_empty() && content.iter().all(|path| is_string_table(path))
+}
+
+/// Finds the mods which look like translations and the mods they translate, by their titles:
+/// e.g. "Some Mod - RU translation" translates "Some Mod", and so does "Перевод 1234567890" for the Workshop ID.
+pub fn detect(mods: &[LockedMod]) -> Vec<Translation> {
+    mods.iter()
+        .enumerate()
+        .filter(|(_, the_mod)| looks_like_translation(the_mod))
+        .filter_map(|(index, the_mod)| {
+            let title = the_mod.title.to_lowercase();
+            let (target_index, target) = mods
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .filter(|(_, target)| {
+                    (target.title.chars().count() >= MIN_TARGET_TITLE
+                        && title.contains(&target.title.to_lowercase()))
+                        || (!target.directory.is_empty() && title.contains(&target.directory))
+                })
+                // The most specific title wins, e.g. "Some Mod Redux" over "Some Mod".
+                .max_by_key(|(_, target)| target.title.len())?;
+            Some(Translation {
+                translation: the_mod.title.clone(),
+                target: target.title.clone(),
+                misordered: index < target_index,
+            })
+        })
+        .collect()
+}
+
+/// Resolves the string table conflicts between the translations and their targets in favor of translations.
+///
+/// Conflicts where some other mod is involved too stay unresolved, but without the target's variant.
+pub fn prefer(translations: &[Translation], conflicts: &mut Conflicts) -> DiffTree {
+    let mut resolved = DiffTree::new();
+    for (path, conflict) in conflicts.iter_mut() {
+        if !is_string_table(path) {
+            continue;
+        }
+        for translation in translations {
+            let involved = |name: &str| conflict.iter().any(|(mod_name, _)| mod_name == name);
+            if involved(&translation.translation) && involved(&translation.target) {
+                info!(
+                    "[translations] {:?}: preferring {} over {}",
+                    path, translation.translation, translation.target
+                );
+                conflict.retain(|(name, _)| *name != translation.target);
+            }
+        }
+    }
+    conflicts.retain(|path, conflict| {
+        if conflict.len() > 1 {
+            return true;
+        }
+        if let Some((_, node)) = conflict.pop() {
+            resolved.insert(path.clone(), node);
+        }
+        false
+    });
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::DiffNode;
+
+    fn locked(title: &str, directory: &str, files: &[&str]) -> LockedMod {
+        LockedMod {
+            title: title.into(),
+            directory: directory.into(),
+            version: None,
+            hash: String::new(),
+            files: files
+                .iter()
+                .map(|file| (file.into(), String::new()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn detects_translations_by_title_and_content() {
+        let mods = [
+            locked("Перевод 111", "3", &["localization/ru.string_table.xml"]),
+            locked(
+                "Marvin Seo's Lamia",
+                "111",
+                &["heroes/lamia/lamia.info.darkest"],
+            ),
+            locked(
+                "Lamia strings",
+                "4",
+                &["project.xml", "localization/lamia.string_table.xml"],
+            ),
+            locked("Extra Lamia skins", "5", &["heroes/lamia/lamia_A.png"]),
+        ];
+        assert_eq!(
+            detect(&mods),
+            vec![Translation {
+                translation: "Перевод 111".into(),
+                target: "Marvin Seo's Lamia".into(),
+                misordered: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn translation_wins_string_tables_only() {
+        let translations = [Translation {
+            translation: "Mod RU".into(),
+            target: "Mod".into(),
+            misordered: false,
+        }];
+        let text = |text: &str| DiffNode::AddedText(text.into());
+        let mut conflicts = Conflicts::new();
+        conflicts.insert(
+            "localization/mod.string_table.xml".into(),
+            vec![("Mod".into(), text("en")), ("Mod RU".into(), text("ru"))],
+        );
+        conflicts.insert(
+            "localization/other.string_table.xml".into(),
+            vec![
+                ("Mod".into(), text("en")),
+                ("Mod RU".into(), text("ru")),
+                ("Other".into(), text("other")),
+            ],
+        );
+        conflicts.insert(
+            "heroes/mod/mod.info.darkest".into(),
+            vec![("Mod".into(), text("en")), ("Mod RU".into(), text("ru"))],
+        );
+        let resolved = prefer(&translations, &mut conflicts);
+        assert_eq!(resolved.len(), 1);
+        assert!(matches!(
+            &resolved[Path::new("localization/mod.string_table.xml")],
+            DiffNode::AddedText(text) if text == "ru"
+        ));
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(
+            conflicts[Path::new("localization/other.string_table.xml")].len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,629 @@
+use super::{
+    diff::{DataNode, DataTree},
+    lock::LockedMod,
+    structures::{
+        localization::{StringsTable, PRIMARY_LANGUAGE},
+        DataType, Format, CAMPING_SKILLS, EFFECTS, HERO_CAMPING_SKILLS, LOOT_TABLES, MONSTERS,
+    },
+};
+use log::*;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Start of the message of every issue about the file which can't be parsed.
+pub const UNPARSED: &str = "Unable to parse";
+
+/// Problem found in the game data after all the patches were applied.
+#[derive(Debug)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl Issue {
+    /// Whether the file couldn't be checked, since it can't be parsed.
+    pub fn is_unparsed(&self) -> bool {
+        self.message.starts_with(UNPARSED)
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.to_string_lossy(), self.message)
+    }
+}
+
+/// Game data as the game will see it with the bundle installed.
+pub struct MergedView<'a> {
+    original: &'a DataTree,
+    modded: &'a DataTree,
+    mods: &'a [LockedMod],
+}
+
+impl<'a> MergedView<'a> {
+    pub fn new(original: &'a DataTree, modded: &'a DataTree, mods: &'a [LockedMod]) -> Self {
+        Self {
+            original,
+            modded,
+            mods,
+        }
+    }
+
+    /// Every file in the merged data, with the bundled ones shadowing the originals.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a PathBuf, &'a DataNode)> + '_ {
+        let modded = self.modded;
+        self.original
+            .iter()
+            .filter(move |(path, _)| !modded.contains_key(*path))
+            .chain(self.modded.iter())
+    }
+
+    /// Files which are written into the bundle.
+    pub fn modded(&self) -> impl Iterator<Item = (&'a PathBuf, &'a DataNode)> {
+        self.modded.iter()
+    }
+
+    /// Titles of the bundled mods which contain the file, or the vanilla game if none of them does.
+    pub fn sources(&self, path: &Path) -> String {
+        let titles: Vec<_> = self
+            .mods
+            .iter()
+            .filter(|the_mod| the_mod.files.contains_key(path))
+            .map(|the_mod| the_mod.title.as_str())
+            .collect();
+        if titles.is_empty() {
+            "vanilla game".into()
+        } else {
+            titles.join(", ")
+        }
+    }
+}
+
+type Check = fn(&MergedView) -> Vec<Issue>;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("localization", check_localization),
+    ("loot tables", check_loot_tables),
+    ("effects", check_effects),
+    ("camping skills", check_camping_skills),
+    ("buffs", check_buffs),
+];
+
+/// Hero class files, which define the hero skills.
+const HERO_INFO: &str = "heroes/**/*.info.darkest";
+/// Buff files, both the shared ones and the ones shipped with trinkets, which all define buffs in one namespace.
+const BUFFS: &str = "**/*.buffs.json";
+
+pub fn validate(original: &DataTree, modded: &DataTree, mods: &[LockedMod]) -> Vec<Issue> {
+    let view = MergedView::new(original, modded, mods);
+    CHECKS
+        .iter()
+        .flat_map(|(name, check)| {
+            profile_span!("validate", check = name);
+            info!("[validate] Running check: {}", name);
+            let issues = check(&view);
+            info!("[validate] Check {} found {} issues", name, issues.len());
+            issues
+        })
+        .collect()
+}
+
+/// Issue for the file which can't be checked, also recorded in the bundling summary.
+fn unparsed(path: &Path, what: impl std::fmt::Display) -> Issue {
+    Issue {
+        path: path.to_owned(),
+        message: format!("{} {}", UNPARSED, what),
+    }
+}
+
+/// Path components as strings, for matching against the known game layout.
+pub fn segments(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
+
+fn parse_json(node: &DataNode) -> Option<Value> {
+    node.text().and_then(|text| serde_json::from_str(text).ok())
+}
+
+/// Collects the `id` fields of every object in the array under `key`.
+fn json_ids(value: &Value, key: &str) -> Vec<String> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.get("id").and_then(Value::as_str))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Values of the given field in every entry with the given key, with quotes stripped.
+///
+/// Returns `None` if the file can't be parsed as the data of the given type.
+fn entry_fields(
+    data_type: &DataType,
+    node: &DataNode,
+    key: &str,
+    field: &str,
+) -> Option<Vec<String>> {
+    let map = data_type.format.flatten(node.text()?).ok()?;
+    Some(
+        map.into_iter()
+            .filter(|(path, _)| {
+                path.first().map(String::as_str) == Some(key)
+                    && path.last().map(String::as_str) == Some(field)
+            })
+            .map(|(_, value)| value.trim_matches('"').to_string())
+            .collect(),
+    )
+}
+
+/// String ids which the game will look up for the data defined in this file.
+fn string_references(path: &Path, node: &DataNode) -> Vec<String> {
+    let segments = segments(path);
+    let segments: Vec<_> = segments.iter().map(String::as_str).collect();
+    match segments.as_slice() {
+        ["heroes", hero, file] if *file == format!("{}.info.darkest", hero) => {
+            vec![format!("hero_class_name_{}", hero)]
+        }
+        ["trinkets", file] if file.ends_with(".trinkets.json") => parse_json(node)
+            .map(|value| json_ids(&value, "entries"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| format!("str_inventory_title_trinket{}", id))
+            .collect(),
+        ["shared", "quirk", "quirk_library.json"] => parse_json(node)
+            .map(|value| json_ids(&value, "quirks"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| format!("str_quirk_name_{}", id))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+pub fn is_string_table(path: &Path) -> bool {
+    let segments = segments(path);
+    segments.first().map(String::as_str) == Some("localization")
+        && segments
+            .last()
+            .is_some_and(|name| name.ends_with(".string_table.xml"))
+}
+
+pub fn strings_table(view: &MergedView, issues: &mut Vec<Issue>) -> StringsTable {
+    let mut table = StringsTable::default();
+    for (path, node) in view.iter().filter(|(path, _)| is_string_table(path)) {
+        match node.text().map(StringsTable::parse) {
+            Some(Ok(parsed)) => table.extend(parsed),
+            Some(Err(err)) => issues.push(unparsed(path, format!("string table: {}", err))),
+            None => warn!("[validate] String table {:?} is not a text file", path),
+        }
+    }
+    table
+}
+
+fn check_localization(view: &MergedView) -> Vec<Issue> {
+    let mut issues = vec![];
+    let table = strings_table(view, &mut issues);
+    if table.is_empty() {
+        info!("[validate] No string tables found, skipping localization check");
+        return issues;
+    }
+    for (path, node) in view.modded() {
+        let missing: BTreeSet<_> = string_references(path, node)
+            .into_iter()
+            .filter(|id| !table.contains(PRIMARY_LANGUAGE, id))
+            .collect();
+        issues.extend(missing.into_iter().map(|id| Issue {
+            path: path.clone(),
+            message: format!(
+                "String \"{}\" is missing for language {}",
+                id, PRIMARY_LANGUAGE
+            ),
+        }));
+    }
+    issues
+}
+
+fn check_loot_tables(view: &MergedView) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut tables = BTreeSet::new();
+    for (path, node) in view.iter().filter(|(path, _)| LOOT_TABLES.matches(path)) {
+        match entry_fields(&LOOT_TABLES, node, "table", "id") {
+            Some(ids) => tables.extend(ids),
+            None => issues.push(unparsed(path, "loot tables")),
+        }
+    }
+    if tables.is_empty() {
+        info!("[validate] No loot tables found, skipping loot tables check");
+        return issues;
+    }
+    // If the tables themselves were changed, even the vanilla monsters might be affected.
+    let tables_changed = view.modded().any(|(path, _)| LOOT_TABLES.matches(path));
+    let monsters: Vec<_> = if tables_changed {
+        view.iter().collect()
+    } else {
+        view.modded().collect()
+    };
+    for (path, node) in monsters
+        .into_iter()
+        .filter(|(path, _)| MONSTERS.matches(path))
+    {
+        let codes = match entry_fields(&MONSTERS, node, "loot", "code") {
+            Some(codes) => codes,
+            None => {
+                warn!("[validate] Unable to parse monster file {:?}", path);
+                continue;
+            }
+        };
+        let missing: BTreeSet<_> = codes
+            .into_iter()
+            .filter(|code| !tables.contains(code))
+            .collect();
+        issues.extend(missing.into_iter().map(|code| Issue {
+            path: path.clone(),
+            message: format!("Loot table \"{}\" is not defined", code),
+        }));
+    }
+    issues
+}
+
+/// Names of the effects applied by every skill in the hero or monster file.
+///
+/// Returns `None` if the file can't be parsed.
+fn skill_effects(node: &DataNode) -> Option<Vec<String>> {
+    let map = Format::Darkest.flatten(node.text()?).ok()?;
+    Some(
+        map.into_iter()
+            .filter(|(path, _)| match &path[..] {
+                [key, _, field] => {
+                    key.ends_with("skill") && field.split('~').next() == Some("effect")
+                }
+                _ => false,
+            })
+            .flat_map(|(_, value)| {
+                // Names with spaces are quoted; several effects are listed one after another.
+                if value.contains('"') {
+                    value
+                        .split('"')
+                        .skip(1)
+                        .step_by(2)
+                        .map(String::from)
+                        .collect::<Vec<_>>()
+                } else {
+                    value.split_whitespace().map(String::from).collect()
+                }
+            })
+            .filter(|name| !name.is_empty())
+            .collect(),
+    )
+}
+
+fn check_effects(view: &MergedView) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut effects = BTreeSet::new();
+    for (path, node) in view.iter().filter(|(path, _)| EFFECTS.matches(path)) {
+        match entry_fields(&EFFECTS, node, "effect", "name") {
+            Some(names) => effects.extend(names),
+            None => issues.push(unparsed(path, "effects")),
+        }
+    }
+    if effects.is_empty() {
+        info!("[validate] No effects found, skipping effects check");
+        return issues;
+    }
+    // If the effects were changed, even the vanilla skills might lose theirs.
+    let effects_changed = view.modded().any(|(path, _)| EFFECTS.matches(path));
+    let skills: Vec<_> = if effects_changed {
+        view.iter().collect()
+    } else {
+        view.modded().collect()
+    };
+    for (path, node) in skills
+        .into_iter()
+        .filter(|(path, _)| MONSTERS.matches(path) || crate::glob::matches(HERO_INFO, path))
+    {
+        let referenced = match skill_effects(node) {
+            Some(referenced) => referenced,
+            None => {
+                warn!("[validate] Unable to parse skills in {:?}", path);
+                continue;
+            }
+        };
+        let missing: BTreeSet<_> = referenced
+            .into_iter()
+            .filter(|name| !effects.contains(name))
+            .collect();
+        issues.extend(missing.into_iter().map(|name| Issue {
+            path: path.clone(),
+            message: format!(
+                "Effect \"{}\" is not defined (skill from {})",
+                name,
+                view.sources(path)
+            ),
+        }));
+    }
+    issues
+}
+
+/// Ids of the camping skills defined in the file: every skill of the global camping files, and the skills
+/// with their effects listed in the class files, where the class mods often define the skills of their class.
+fn camping_definitions(path: &Path, value: &Value) -> Vec<String> {
+    let global = CAMPING_SKILLS.matches(path);
+    value
+        .get("skills")
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter(|item| global || item.get("effects").is_some())
+                .filter_map(|item| item.get("id").and_then(Value::as_str))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn check_camping_skills(view: &MergedView) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut parsed = vec![];
+    for (path, node) in view
+        .iter()
+        .filter(|(path, _)| CAMPING_SKILLS.matches(path) || HERO_CAMPING_SKILLS.matches(path))
+    {
+        match parse_json(node) {
+            Some(value) => parsed.push((path, value)),
+            None => issues.push(unparsed(path, "camping skills")),
+        }
+    }
+    // Skills are looked up across the whole bundle, wherever they are defined.
+    let defined: BTreeSet<_> = parsed
+        .iter()
+        .flat_map(|(path, value)| camping_definitions(path, value))
+        .collect();
+    if defined.is_empty() {
+        info!("[validate] No camping skills found, skipping camping skills check");
+        return issues;
+    }
+    // If any camping file was changed, even the vanilla heroes might lose their skills.
+    if !view
+        .modded()
+        .any(|(path, _)| CAMPING_SKILLS.matches(path) || HERO_CAMPING_SKILLS.matches(path))
+    {
+        return issues;
+    }
+    for (path, value) in parsed
+        .iter()
+        .filter(|(path, _)| HERO_CAMPING_SKILLS.matches(path))
+    {
+        let missing: BTreeSet<_> = json_ids(value, "skills")
+            .into_iter()
+            .filter(|id| !defined.contains(id))
+            .collect();
+        issues.extend(missing.into_iter().map(|id| Issue {
+            path: (*path).clone(),
+            message: format!(
+                "Camping skill \"{}\" is not defined (assigned by {})",
+                id,
+                view.sources(path)
+            ),
+        }));
+    }
+    issues
+}
+
+fn check_buffs(view: &MergedView) -> Vec<Issue> {
+    let mut issues = vec![];
+    let modded: BTreeSet<_> = view.modded().map(|(path, _)| path).collect();
+    let mut definitions = BTreeMap::<String, Vec<(&PathBuf, Value)>>::new();
+    for (path, node) in view
+        .iter()
+        .filter(|(path, _)| crate::glob::matches(BUFFS, path))
+    {
+        let value = match parse_json(node) {
+            Some(value) => value,
+            None => {
+                issues.push(unparsed(path, "buffs"));
+                continue;
+            }
+        };
+        let buffs = value.get("buffs").and_then(Value::as_array).cloned();
+        for buff in buffs.unwrap_or_default() {
+            if let Some(id) = buff.get("id").and_then(Value::as_str) {
+                definitions
+                    .entry(id.to_owned())
+                    .or_default()
+                    .push((path, buff.clone()));
+            }
+        }
+    }
+    for (id, defined) in definitions {
+        let (first_path, first) = &defined[0];
+        // The game keeps only one of the definitions, so the trinkets using the other one silently change.
+        let different = defined.iter().skip(1).filter(|(path, buff)| {
+            buff != first && (modded.contains(path) || modded.contains(first_path))
+        });
+        issues.extend(different.map(|(path, _)| Issue {
+            path: (*path).clone(),
+            message: format!(
+                "Buff \"{}\" is defined differently in {} (from {}) and {} (from {})",
+                id,
+                first_path.to_string_lossy(),
+                view.sources(first_path),
+                path.to_string_lossy(),
+                view.sources(path)
+            ),
+        }));
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(files: &[(&str, &str)]) -> DataTree {
+        files
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), DataNode::new("", text.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn missing_effects_are_reported_with_their_mod() {
+        let original = tree(&[(
+            "effects/base.effects.darkest",
+            "effect: .name \"Bleed 1\" .dotBleed 1\neffect: .name \"Stun 1\" .stun 1\n",
+        )]);
+        let modded = tree(&[(
+            "heroes/hag/hag.info.darkest",
+            "combat_skill: .id \"curse\" .level 0 .effect \"Bleed 1\" \"Hex 1\"\n\
+             combat_skill: .id \"curse\" .level 1 .effect \"Stun 1\"\n",
+        )]);
+        let mods = [LockedMod {
+            title: "Hag Class".into(),
+            directory: "123".into(),
+            version: None,
+            hash: String::new(),
+            files: vec![("heroes/hag/hag.info.darkest".into(), String::new())]
+                .into_iter()
+                .collect(),
+        }];
+        let issues = check_effects(&MergedView::new(&original, &modded, &mods));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "heroes/hag/hag.info.darkest: Effect \"Hex 1\" is not defined (skill from Hag Class)"
+        );
+    }
+
+    #[test]
+    fn missing_strings_are_reported_for_modded_files() {
+        let original = tree(&[(
+            "localization/heroes.string_table.xml",
+            r#"<root><language id="english"><entry id="hero_class_name_crusader">Crusader</entry></language></root>"#,
+        )]);
+        let modded = tree(&[
+            (
+                "localization/hag.string_table.xml",
+                r#"<root><language id="english"><entry id="hero_class_name_hag">Hag</entry></language></root>"#,
+            ),
+            ("heroes/hag/hag.info.darkest", "hero: .id \"hag\"\n"),
+        ]);
+        let view = MergedView::new(&original, &modded, &[]);
+        assert!(check_localization(&view).is_empty());
+
+        let modded = tree(&[("heroes/hag/hag.info.darkest", "hero: .id \"hag\"\n")]);
+        let issues = check_localization(&MergedView::new(&original, &modded, &[]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "heroes/hag/hag.info.darkest: String \"hero_class_name_hag\" is missing for language english"
+        );
+    }
+
+    #[test]
+    fn dangling_loot_tables_are_reported() {
+        let original = tree(&[
+            (
+                "loot/loot.darkest",
+                "table: .id \"cultist\" .difficulty 1\n",
+            ),
+            (
+                "monsters/cultist_brawler/cultist_brawler_A.info.darkest",
+                "loot: .code \"cultist\" .count 1\n",
+            ),
+        ]);
+        let modded = tree(&[
+            ("loot/hag.darkest", "table: .id \"hag\" .difficulty 1\n"),
+            (
+                "monsters/hag/hag_A.info.darkest",
+                "loot: .code \"hag\" .count 1\n",
+            ),
+        ]);
+        assert!(check_loot_tables(&MergedView::new(&original, &modded, &[])).is_empty());
+
+        let modded = tree(&[(
+            "monsters/hag/hag_A.info.darkest",
+            "loot: .code \"cultist\" .count 1\nloot: .code \"hag\" .count 1\n",
+        )]);
+        let issues = check_loot_tables(&MergedView::new(&original, &modded, &[]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "monsters/hag/hag_A.info.darkest: Loot table \"hag\" is not defined"
+        );
+    }
+
+    #[test]
+    fn camping_skills_are_found_in_every_camping_file() {
+        let original = tree(&[
+            (
+                "raid/camping/default.camping_skills.json",
+                r#"{"skills": [{"id": "encourage", "effects": []}]}"#,
+            ),
+            (
+                "heroes/crusader/crusader.camping_skills.json",
+                r#"{"skills": [{"id": "encourage"}]}"#,
+            ),
+        ]);
+        // Class mod defines one skill in its class file and another one in its own global file.
+        let modded = tree(&[
+            (
+                "heroes/hag/hag.camping_skills.json",
+                r#"{"skills": [{"id": "brew", "effects": []}, {"id": "encourage"}, {"id": "stir"}]}"#,
+            ),
+            (
+                "raid/camping/hag.camping_skills.json",
+                r#"{"skills": [{"id": "stir", "effects": []}]}"#,
+            ),
+        ]);
+        let view = MergedView::new(&original, &modded, &[]);
+        assert!(check_camping_skills(&view).is_empty());
+
+        let modded = tree(&[(
+            "heroes/hag/hag.camping_skills.json",
+            r#"{"skills": [{"id": "brew", "effects": []}, {"id": "stir"}]}"#,
+        )]);
+        let issues = check_camping_skills(&MergedView::new(&original, &modded, &[]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "heroes/hag/hag.camping_skills.json: Camping skill \"stir\" is not defined (assigned by vanilla game)"
+        );
+    }
+
+    #[test]
+    fn buffs_redefined_by_trinket_packs_are_reported() {
+        let original = tree(&[(
+            "shared/buffs/base.buffs.json",
+            r#"{"buffs": [{"id": "ACC+5", "stat_type": "combat_stat_add", "amount": 5}]}"#,
+        )]);
+        let modded = tree(&[
+            (
+                "trinkets/pack_a.buffs.json",
+                r#"{"buffs": [{"id": "ACC+5", "stat_type": "combat_stat_add", "amount": 5},
+                              {"id": "pack_crit", "stat_type": "combat_stat_add", "amount": 0.05}]}"#,
+            ),
+            (
+                "trinkets/pack_b.buffs.json",
+                r#"{"buffs": [{"id": "pack_crit", "stat_type": "combat_stat_add", "amount": 0.1}]}"#,
+            ),
+        ]);
+        let issues = check_buffs(&MergedView::new(&original, &modded, &[]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "trinkets/pack_b.buffs.json: Buff \"pack_crit\" is defined differently in \
+             trinkets/pack_a.buffs.json (from vanilla game) and trinkets/pack_b.buffs.json (from vanilla game)"
+        );
+    }
+}
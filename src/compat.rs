@@ -0,0 +1,186 @@
+//! Database of the known problems with the Workshop mods, which the bundler can't detect by itself.
+
+use crate::loader::Mod;
+use log::*;
+use serde::Deserialize;
+
+/// Database shipped with the program.
+const BUILTIN: &str = include_str!("compat.toml");
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Database {
+    #[serde(default, rename = "mod")]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Entry {
+    id: Option<String>,
+    title: Option<String>,
+    note: Option<String>,
+    #[serde(default)]
+    requires: Vec<String>,
+    #[serde(default)]
+    incompatible: Vec<String>,
+    #[serde(default)]
+    load_after: Vec<String>,
+}
+
+/// What the database needs to know about the mod.
+#[derive(Debug, Clone, Copy)]
+struct ModKey<'a> {
+    /// Workshop ID, i.e. the mod directory name.
+    id: &'a str,
+    title: &'a str,
+}
+
+impl ModKey<'_> {
+    /// Whether the mod is referred by the given Workshop ID or title.
+    fn is(&self, reference: &str) -> bool {
+        self.id == reference || self.title.eq_ignore_ascii_case(reference)
+    }
+}
+
+impl Entry {
+    fn describes(&self, the_mod: ModKey) -> bool {
+        self.id.as_deref() == Some(the_mod.id)
+            || self
+                .title
+                .as_deref()
+                .is_some_and(|title| title.eq_ignore_ascii_case(the_mod.title))
+    }
+}
+
+impl Database {
+    fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Loads the shipped database, extended by the user one, if it exists.
+    pub fn load() -> Self {
+        let mut database =
+            Self::parse(BUILTIN).expect("Shipped compatibility database is malformed");
+        let path = crate::paths::compat();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => match Self::parse(&text) {
+                Ok(user) => {
+                    info!(
+                        "Loaded {} entries from compatibility database {:?}",
+                        user.entries.len(),
+                        path
+                    );
+                    database.entries.extend(user.entries);
+                }
+                Err(err) => warn!(
+                    "Compatibility database {:?} is malformed, ignoring it: {}",
+                    path, err
+                ),
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => warn!("Unable to read compatibility database {:?}: {}", path, err),
+        }
+        database
+    }
+
+    /// Known problems of the selected mods, listed in the order they are bundled, as pairs of mod title and message.
+    pub fn warnings<'a>(
+        &self,
+        selected: impl IntoIterator<Item = &'a Mod>,
+    ) -> Vec<(String, String)> {
+        let keys: Vec<_> = selected
+            .into_iter()
+            .map(|the_mod| ModKey {
+                id: the_mod
+                    .path
+                    .file_name()
+                    .and_then(std::ffi::OsStr::to_str)
+                    .unwrap_or_default(),
+                title: the_mod.name(),
+            })
+            .collect();
+        self.check(&keys)
+    }
+
+    fn check(&self, selected: &[ModKey]) -> Vec<(String, String)> {
+        let find = |reference: &str| selected.iter().position(|the_mod| the_mod.is(reference));
+        let mut warnings = vec![];
+        for (index, the_mod) in selected.iter().enumerate() {
+            for entry in self
+                .entries
+                .iter()
+                .filter(|entry| entry.describes(*the_mod))
+            {
+                let mut warn = |message: String| warnings.push((the_mod.title.to_owned(), message));
+                if let Some(note) = &entry.note {
+                    warn(note.clone());
+                }
+                for required in &entry.requires {
+                    if find(required).is_none() {
+                        warn(format!("requires {}, which is not selected", required));
+                    }
+                }
+                for other in &entry.incompatible {
+                    if let Some(other) = find(other) {
+                        warn(format!("is incompatible with {}", selected[other].title));
+                    }
+                }
+                for other in &entry.load_after {
+                    match find(other) {
+                        Some(other) if other > index => warn(format!(
+                            "must be loaded after {}, but is bundled before it",
+                            selected[other].title
+                        )),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_database_is_valid() {
+        assert!(Database::parse(BUILTIN).is_ok());
+    }
+
+    #[test]
+    fn problems_of_selected_mods() {
+        let database = Database::parse(
+            r#"
+            [[mod]]
+            id = "1"
+            requires = ["Framework"]
+            incompatible = ["3"]
+            load_after = ["Base"]
+
+            [[mod]]
+            title = "base"
+            note = "Outdated"
+            "#,
+        )
+        .unwrap();
+        let key = |id, title| ModKey { id, title };
+        let selected = [key("1", "First"), key("2", "Base"), key("3", "Third")];
+        assert_eq!(
+            database.check(&selected),
+            vec![
+                (
+                    "First".to_string(),
+                    "requires Framework, which is not selected".to_string()
+                ),
+                ("First".into(), "is incompatible with Third".into()),
+                (
+                    "First".into(),
+                    "must be loaded after Base, but is bundled before it".into()
+                ),
+                ("Base".into(), "Outdated".into()),
+            ]
+        );
+        assert!(database.check(&[key("2", "Other")]).is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::loader::Mod;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MAX_RECENT_PATHS: usize = 10;
+/// Name of the profile created when user hasn't chosen another one.
+pub const DEFAULT_PROFILE: &str = "Default";
+
+/// Named group of settings, so that several setups can live side by side.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Profile {
+    /// Steam library containing the game and the Workshop mods.
+    pub library_path: String,
+    /// Globs of files which are ignored in the particular mod, by the mod directory name.
+    pub mod_excludes: BTreeMap<String, Vec<String>>,
+    /// Values set directly by user, applied on top of the bundled mods.
+    pub value_edits: Vec<crate::bundler::ValueEdit>,
+    /// Ids renamed in the particular mod before merging (old id to new id), by the mod directory name.
+    pub id_renames: BTreeMap<String, BTreeMap<String, String>>,
+    /// DLC directories left out of the baseline, e.g. for the mods made for the game without DLC.
+    pub excluded_dlc: Vec<String>,
+}
+
+/// Layout of the screens, restored on the next launch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UiState {
+    /// Width of the available mods list on the mods selection screen, in percents.
+    pub available_width: u8,
+    /// Whether the options below the mods lists are hidden.
+    pub options_hidden: bool,
+    /// Screen opened from the mods selection when the program was closed.
+    pub last_screen: Option<String>,
+}
+
+impl Default for UiState {
+    fn default() -> Self {
+        Self {
+            available_width: 50,
+            options_hidden: false,
+            last_screen: None,
+        }
+    }
+}
+
+/// Settings persisted between runs.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Steam library paths which were successfully loaded, most recent first.
+    pub recent_paths: Vec<String>,
+    /// Whether to put the information about bundled mods into the bundle.
+    pub include_attribution: bool,
+    /// The only language left in the bundled string tables; all of them are kept if not set.
+    pub bundle_language: Option<String>,
+    /// Merge policy for every structured data type, by its name; missing ones default to asking.
+    pub merge_policies: BTreeMap<String, crate::bundler::MergePolicy>,
+    /// Directory of the mod which wins the conflicts in the data types with the "prefer the chosen mod" policy.
+    pub preferred_mod: Option<String>,
+    /// Globs of text files (e.g. `scrolls/**/*`) to be merged as sets of lines, whatever their extension is.
+    pub line_set_globs: Vec<String>,
+    /// Globs of game files which mods may never change, e.g. the ones affecting saves.
+    pub protected_paths: Vec<String>,
+    /// Whether conflicts changing only art, colours, fonts or strings are resolved in favor of the mod selected last.
+    pub auto_resolve_cosmetic: bool,
+    /// Whether the hero and monster sprites chosen in conflicts are checked against the sizes the game expects.
+    pub validate_sprites: bool,
+    /// Whether to deploy the compatibility patch (only the files changed by several mods) next to the bundle.
+    pub compat_patch: bool,
+    /// Whether to prepare the deployed bundle for the Workshop uploader shipped with the game.
+    pub workshop_upload: bool,
+    /// Whether the executable files some mods ship (libraries, programs, shaders) are bundled; they are left out otherwise.
+    pub bundle_executables: bool,
+    /// Comments put into the deployed files.
+    pub deploy_comments: crate::bundler::CommentVerbosity,
+    /// Name of the deployment target the bundle is delivered to, besides the game mods folder.
+    pub deploy_target: Option<String>,
+    /// Directory the bundle is copied to by the "Another directory" target.
+    pub deploy_directory: Option<String>,
+    /// Whether to write the report of what the DLCs change in the vanilla data on bundling.
+    pub dlc_report: bool,
+    /// Directory for the temporary files, if not the default one; they are kept in its `dd_bundler` subdirectory.
+    pub temp_dir: Option<String>,
+    /// Limit of the space taken by the temporary files, in MiB.
+    pub temp_size_limit_mib: Option<u64>,
+    /// Whether to count the bundles and their conflicts, only locally.
+    pub usage_stats: bool,
+    /// Bundle sizes which are worth a warning before deploying.
+    pub size_limits: crate::bundler::SizeLimits,
+    pub profiles: BTreeMap<String, Profile>,
+    /// Name of the profile in use, if any was created.
+    pub active_profile: Option<String>,
+    pub ui: UiState,
+}
+
+impl Config {
+    /// Whether the config was ever saved, i.e. the program is not run for the first time.
+    pub fn exists() -> bool {
+        crate::paths::config().is_file()
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profiles.get(self.active_profile.as_ref()?)
+    }
+
+    /// Active profile; if there's none yet, the default one is created.
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        let name = self
+            .active_profile
+            .get_or_insert_with(|| DEFAULT_PROFILE.into());
+        self.profiles.entry(name.clone()).or_default()
+    }
+
+    /// Globs of files excluded from the mod in the active profile.
+    pub fn mod_excludes(&self, the_mod: &Mod) -> &[String] {
+        self.profile()
+            .and_then(|profile| profile.mod_excludes.get(&the_mod.directory()))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Ids renamed in the mod in the active profile.
+    pub fn id_renames(&self, the_mod: &Mod) -> Option<&BTreeMap<String, String>> {
+        self.profile()?.id_renames.get(&the_mod.directory())
+    }
+
+    /// Whether the DLC directory is left out of the baseline in the active profile.
+    pub fn dlc_excluded(&self, dlc: &str) -> bool {
+        self.profile()
+            .is_some_and(|profile| profile.excluded_dlc.iter().any(|excluded| excluded == dlc))
+    }
+
+    /// Loads config from disk; any failure results in default config.
+    pub fn load() -> Self {
+        let path = crate::paths::config();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(config) => {
+                    info!("Loaded config from {:?}", path);
+                    config
+                }
+                Err(err) => {
+                    warn!("Config file {:?} is malformed, ignoring it: {}", path, err);
+                    Self::default()
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                info!("No config file found, using defaults");
+                Self::default()
+            }
+            Err(err) => {
+                warn!("Unable to read config file {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Writes config to disk. Failure is not critical, so it is only logged.
+    pub fn save(&self) {
+        let path = crate::paths::config();
+        let result = serde_json::to_string_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|text| std::fs::write(&path, text));
+        match result {
+            Ok(()) => info!("Config saved to {:?}", path),
+            Err(err) => warn!("Unable to save config to {:?}: {}", path, err),
+        }
+    }
+
+    pub fn push_recent_path(&mut self, path: &str) {
+        self.recent_paths.retain(|recent| recent != path);
+        self.recent_paths.insert(0, path.to_owned());
+        self.recent_paths.truncate(MAX_RECENT_PATHS);
+    }
+}
@@ -0,0 +1,244 @@
+//! Settings screen for the files ignored in the particular mods.
+
+use crate::{
+    config::Config,
+    loader::{mods_list, Mod},
+};
+use cursive::{
+    traits::{Nameable, Resizable, Scrollable},
+    views::{Dialog, LinearLayout, SelectView, TextArea, TextView},
+    Cursive,
+};
+use log::*;
+use std::path::{Path, PathBuf};
+
+fn label(config: &Config, the_mod: &Mod) -> String {
+    match config.mod_excludes(the_mod).len() {
+        0 => the_mod.name().to_owned(),
+        count => format!("{} ({} excluded)", the_mod.name(), count),
+    }
+}
+
+/// Screen listing every mod, where the excluded globs can be edited for each one of them.
+pub fn settings(cursive: &mut Cursive) {
+    let config = Config::load();
+    let mut list = SelectView::new()
+        .with_all(
+            mods_list(cursive)
+                .iter()
+                .map(|the_mod| (label(&config, the_mod), the_mod.clone())),
+        )
+        .on_submit(edit);
+    list.sort_by_label();
+    cursive.add_layer(
+        Dialog::around(list.with_name("Excludes list").scrollable())
+            .title(format!(
+                "Excluded files (profile \"{}\")",
+                config
+                    .active_profile
+                    .as_deref()
+                    .unwrap_or(crate::config::DEFAULT_PROFILE)
+            ))
+            .dismiss_button("Close"),
+    );
+}
+
+fn edit(cursive: &mut Cursive, the_mod: &Mod) {
+    let current = Config::load().mod_excludes(the_mod).join("\n");
+    let the_mod = the_mod.clone();
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "Files of this mod matching any of these globs (one per line, e.g. `shared/rules.darkest` \
+                     or `trinkets/*`) are ignored while bundling. \
+                     \"Browse files...\" lets you pick the files and directories to exclude instead.",
+                ))
+                .child(
+                    TextArea::new()
+                        .content(current)
+                        .with_name("Excluded globs")
+                        .min_height(5),
+                ),
+        )
+        .title(the_mod.name())
+        .button("Browse files...", {
+            let the_mod = the_mod.clone();
+            move |cursive| browse(cursive, &the_mod, PathBuf::new())
+        })
+        .button("Save", move |cursive| {
+            let globs: Vec<String> = cursive
+                .call_on_name("Excluded globs", |area: &mut TextArea| {
+                    area.get_content()
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default();
+            info!("Excluding {:?} from mod {}", globs, the_mod.name());
+            let mut config = Config::load();
+            let excludes = &mut config.profile_mut().mod_excludes;
+            if globs.is_empty() {
+                excludes.remove(&the_mod.directory());
+            } else {
+                excludes.insert(the_mod.directory(), globs);
+            }
+            config.save();
+            cursive.pop_layer();
+            cursive.call_on_name("Excludes list", |list: &mut SelectView<Mod>| {
+                if let Some(index) = list.selected_id() {
+                    if let Some((text, _)) = list.get_item_mut(index) {
+                        *text = label(&config, &the_mod).into();
+                    }
+                }
+            });
+        })
+        .dismiss_button("Cancel")
+        .full_width(),
+    );
+}
+
+/// Glob excluding the file, or everything in the directory.
+fn glob_for(path: &Path, dir: bool) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if dir {
+        format!("{}/**", path)
+    } else {
+        path
+    }
+}
+
+/// Adds the glob to the lines of globs, or removes it if it's already there.
+fn toggle(globs: &str, glob: &str) -> String {
+    let mut lines: Vec<_> = globs
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.contains(&glob) {
+        lines.retain(|line| *line != glob);
+    } else {
+        lines.push(glob);
+    }
+    lines.join("\n")
+}
+
+fn current_globs(cursive: &mut Cursive) -> String {
+    cursive
+        .call_on_name("Excluded globs", |area: &mut TextArea| {
+            area.get_content().to_owned()
+        })
+        .unwrap_or_default()
+}
+
+/// Directories and files in the directory of the mod, directories first, relative to the mod's root.
+fn entries(the_mod: &Mod, dir: &Path) -> Vec<(PathBuf, bool)> {
+    let mut entries: Vec<_> = std::fs::read_dir(the_mod.path.join(dir))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| (dir.join(entry.file_name()), entry.path().is_dir()))
+        // Files in the root of the mod are never bundled.
+        .filter(|(_, is_dir)| *is_dir || !dir.as_os_str().is_empty())
+        .collect();
+    entries.sort_by(|(first, first_dir), (second, second_dir)| {
+        second_dir.cmp(first_dir).then_with(|| first.cmp(second))
+    });
+    entries
+}
+
+/// Item of the mod files list.
+#[derive(Clone)]
+enum Entry {
+    /// Parent of the shown directory.
+    Up(PathBuf),
+    Item {
+        path: PathBuf,
+        is_dir: bool,
+    },
+}
+
+/// Files of the mod, one directory at a time, where the entries can be excluded or included back.
+fn browse(cursive: &mut Cursive, the_mod: &Mod, dir: PathBuf) {
+    let globs = current_globs(cursive);
+    let excluded = |path: &Path, is_dir: bool| {
+        let own = glob_for(path, is_dir);
+        globs
+            .lines()
+            .map(str::trim)
+            .any(|glob| glob == own || crate::glob::matches(glob, path))
+    };
+    let mut list = SelectView::new();
+    if let Some(parent) = dir.parent() {
+        list.add_item("../", Entry::Up(parent.to_owned()));
+    }
+    for (path, is_dir) in entries(the_mod, &dir) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut label = if is_dir {
+            format!("{}/", name)
+        } else {
+            name.into_owned()
+        };
+        if excluded(&path, is_dir) {
+            label.push_str(" (excluded)");
+        }
+        list.add_item(label, Entry::Item { path, is_dir });
+    }
+    let opened = the_mod.clone();
+    let list = list.on_submit(move |cursive, entry: &Entry| {
+        let target = match entry {
+            Entry::Up(path) => path,
+            Entry::Item { path, is_dir: true } => path,
+            Entry::Item { is_dir: false, .. } => return,
+        };
+        cursive.pop_layer();
+        browse(cursive, &opened, target.clone());
+    });
+    let toggled = the_mod.clone();
+    let current = dir.clone();
+    cursive.add_layer(
+        Dialog::around(list.with_name("Mod files").scrollable())
+            .title(format!(
+                "{}/{}",
+                the_mod.name(),
+                dir.to_string_lossy().replace('\\', "/")
+            ))
+            .button("Exclude / include", move |cursive| {
+                let selected = cursive
+                    .call_on_name("Mod files", |list: &mut SelectView<Entry>| list.selection())
+                    .flatten();
+                let glob = match selected.as_deref() {
+                    Some(Entry::Item { path, is_dir }) => glob_for(path, *is_dir),
+                    _ => return,
+                };
+                let globs = toggle(&current_globs(cursive), &glob);
+                info!("Mod {}: excluded globs are now {:?}", toggled.name(), globs);
+                cursive.call_on_name("Excluded globs", |area: &mut TextArea| {
+                    area.set_content(globs)
+                });
+                cursive.pop_layer();
+                browse(cursive, &toggled, current.clone());
+            })
+            .dismiss_button("Done")
+            .min_width(50)
+            .max_height(30),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_are_toggled() {
+        let file = glob_for(Path::new("localization/hag.string_table.xml"), false);
+        let dir = glob_for(Path::new("localization"), true);
+        assert_eq!(dir, "localization/**");
+        let globs = toggle("trinkets/*\n\n", &dir);
+        assert_eq!(globs, "trinkets/*\nlocalization/**");
+        assert_eq!(toggle(&toggle(&globs, &file), &file), globs);
+        assert_eq!(toggle(&globs, &dir), "trinkets/*");
+    }
+}
@@ -0,0 +1,23 @@
+//! Files on disk for the tests.
+//!
+//! Every test gets its own directory, named after it and the process id, so that the tests of parallel runs
+//! don't step on each other.
+
+use std::path::{Path, PathBuf};
+
+/// Empty directory for the test with the given name, cleared of whatever the previous run left in it.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dd_bundler_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes the files, given by their paths relative to `root`, creating the directories on the way.
+pub fn write_files(root: &Path, files: &[(&str, &str)]) {
+    for (path, text) in files {
+        let file = root.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, text).unwrap();
+    }
+}
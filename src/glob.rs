@@ -0,0 +1,84 @@
+//! Minimal glob matching for the relative game paths.
+//!
+//! Supported syntax: `*` matches any part of one path segment, `**` matches any number of segments.
+//! Matching is case-insensitive, since the game is mostly run on Windows.
+
+use std::path::Path;
+
+fn segment_matches(pattern: &[char], segment: &[char]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some(('*', rest)) => {
+            (0..=segment.len()).any(|skip| segment_matches(rest, &segment[skip..]))
+        }
+        Some((c, rest)) => match segment.split_first() {
+            Some((s, segment_rest)) => {
+                c.to_lowercase().eq(s.to_lowercase()) && segment_matches(rest, segment_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn segments_match(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first.iter().collect::<String>() == "**" => {
+            (0..=path.len()).any(|skip| segments_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                segment_matches(first, segment) && segments_match(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+pub fn matches(pattern: &str, path: &Path) -> bool {
+    let pattern: Vec<Vec<char>> = pattern
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.chars().collect())
+        .collect();
+    let path: Vec<Vec<char>> = path
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().chars().collect())
+        .collect();
+    segments_match(&pattern, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+    use std::path::Path;
+
+    #[test]
+    fn simple() {
+        assert!(matches(
+            "trinkets/*.json",
+            Path::new("trinkets/base.entries.json")
+        ));
+        assert!(!matches(
+            "trinkets/*.json",
+            Path::new("trinkets/sub/base.json")
+        ));
+        assert!(matches("Heroes/*", Path::new("heroes/x.png")));
+    }
+
+    #[test]
+    fn recursive() {
+        let pattern = "dungeons/**/*.props.darkest";
+        assert!(matches(
+            pattern,
+            Path::new("dungeons/crypts/crypts.props.darkest")
+        ));
+        assert!(matches(pattern, Path::new("dungeons/a/b/c.props.darkest")));
+        assert!(matches(pattern, Path::new("dungeons/c.props.darkest")));
+        assert!(!matches(
+            pattern,
+            Path::new("dungeons/crypts/crypts.darkest")
+        ));
+        assert!(matches("**", Path::new("anything/at/all")));
+    }
+}
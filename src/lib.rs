@@ -1,12 +1,33 @@
+/// Enters the profiling span until the end of the current scope.
+///
+/// Does nothing unless built with the `profile` feature, so that the spans cost nothing in the usual builds.
+macro_rules! profile_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "profile")]
+        let _span = tracing::info_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
+mod browse;
 mod bundler;
+mod compat;
+mod config;
+mod excludes;
+#[cfg(test)]
+mod fixtures;
+mod glob;
 mod loader;
 mod paths;
+mod prompt;
+mod report;
 mod select;
+mod temp;
+mod wizard;
 
 use cursive::{
     event::{Event, Key},
     traits::{Nameable, Resizable},
-    views::{Dialog, EditView, PaddedView, TextView},
+    views::{Dialog, EditView, LinearLayout, PaddedView, Panel, SelectView, TextView},
     Cursive, View,
 };
 use log::*;
@@ -39,18 +60,84 @@ fn run_update<F: FnOnce(&mut Cursive) + 'static + Send>(sink: &mut cursive::CbSi
         .expect("Cursive sink was unexpectedly dropped, this is probably a bug");
 }
 
-pub fn run() {
+fn update_library_status(cursive: &mut Cursive, path: &str, _: usize) {
+    let status = match loader::check_library(path) {
+        Ok(status) => status,
+        Err(problem) => format!("Warning: {}", problem),
+    };
+    cursive.call_on_name("Library status", |text: &mut TextView| {
+        text.set_content(status)
+    });
+}
+
+/// Runs the program and returns its exit code for the outcome of the bundling, writing its summary
+/// to the given JSON file, if any.
+///
+/// Codes are 0 for success, 1 if nothing was bundled, 2 if some conflicts remain unresolved,
+/// 3 if some files couldn't be parsed and 4 if the bundle couldn't be deployed.
+pub fn run(summary_json: Option<&std::path::Path>) -> i32 {
+    // Leftovers of the run which crashed before cleaning up.
+    temp::clean();
     let mut cursive: Cursive = cursive::default();
 
+    if !config::Config::exists() {
+        wizard::run(&mut cursive);
+        info!("Starting Cursive");
+        cursive.run();
+        select::remember_screen(&mut cursive);
+        temp::clean();
+        return exit_code(&mut cursive, summary_json);
+    }
+
     info!("Creating initial dialog");
+    let config = config::Config::load();
+    let mut known_paths = config.recent_paths.clone();
+    if let Some(profile) = config.profile() {
+        info!("Active profile library: {}", profile.library_path);
+        known_paths.retain(|path| path != &profile.library_path);
+        known_paths.insert(0, profile.library_path.clone());
+    }
+    for detected in paths::detect_libraries() {
+        let detected = detected.to_string_lossy().into_owned();
+        if !known_paths.contains(&detected) {
+            info!("Detected Steam library: {}", detected);
+            known_paths.push(detected);
+        }
+    }
+
+    let mut layout = LinearLayout::vertical();
+    if !known_paths.is_empty() {
+        layout.add_child(TextView::new("Recently used and detected libraries:"));
+        layout.add_child(Panel::new(
+            SelectView::new()
+                .with_all_str(known_paths)
+                .on_submit(|cursive, path: &String| {
+                    info!("Known library path chosen: {}", path);
+                    cursive.call_on_name("Library path", |view: &mut EditView| {
+                        view.set_content(path.clone())
+                    });
+                    loader::load_path(cursive, path);
+                }),
+        ));
+        layout.add_child(TextView::new("Or enter another one:"));
+    }
+    layout.add_child(
+        EditView::new()
+            .on_edit(update_library_status)
+            .on_submit_mut(loader::load_path)
+            .with_name("Library path")
+            .full_width(),
+    );
+    layout.add_child(TextView::new(" ").with_name("Library status"));
+
     let dialog = cursive::views::Dialog::new()
-        .content(
-            EditView::new()
-                .on_submit_mut(loader::load_path)
-                .with_name("Library path")
-                .full_width(),
-        )
+        .content(layout)
         .title("Steam library path:")
+        .button("Browse...", |cursive| {
+            browse::browse(cursive, "Library path", false, |cursive, path| {
+                update_library_status(cursive, path, 0)
+            })
+        })
         .button("List mods", |cursive| {
             info!("List mods button click");
             cursive.call_on_name("Library path", |view: &mut EditView| {
@@ -62,4 +149,56 @@ pub fn run() {
 
     info!("Starting Cursive");
     cursive.run();
+    select::remember_screen(&mut cursive);
+    temp::clean();
+    exit_code(&mut cursive, summary_json)
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("No Steam library is chosen yet; run the program once to choose it")]
+struct NoLibrary;
+
+/// Rebuilds the deployed bundle from its lock file without any UI, e.g. in CI, and returns the exit code
+/// the same way as `run` does. Nothing is asked: the conflicts which the lock file doesn't resolve are left
+/// unresolved, so that the code is 2.
+///
+/// The library is the one of the active profile, or the last used one.
+pub fn run_headless(summary_json: Option<&std::path::Path>) -> i32 {
+    temp::clean();
+    let config = config::Config::load();
+    let library = config
+        .profile()
+        .map(|profile| profile.library_path.clone())
+        .filter(|path| !path.is_empty())
+        .or_else(|| config.recent_paths.first().cloned());
+    let mut summary = bundler::Summary::default();
+    match library {
+        None => {
+            error!("{}", NoLibrary);
+            summary.failed(bundler::Outcome::NotBundled, &NoLibrary);
+        }
+        Some(library) => {
+            info!("Headless run on the Steam library {}", library);
+            let base_path = std::path::PathBuf::from(library);
+            match loader::load_library(&base_path) {
+                Ok(mods) => {
+                    summary = bundler::rebuild_headless(loader::GlobalData { base_path, mods })
+                }
+                Err(err) => {
+                    error!("Unable to load the library: {}", err);
+                    summary.failed(bundler::Outcome::NotBundled, &err);
+                }
+            }
+        }
+    }
+    temp::clean();
+    summary.report(summary_json)
+}
+
+/// Exit code for the summary left by the bundling when it's over; if it hasn't run or finished, nothing was bundled.
+fn exit_code(cursive: &mut Cursive, summary_json: Option<&std::path::Path>) -> i32 {
+    cursive
+        .take_user_data::<bundler::Summary>()
+        .unwrap_or_default()
+        .report(summary_json)
 }
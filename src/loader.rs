@@ -1,18 +1,145 @@
+use crate::config::Config;
 use cursive::Cursive;
 use log::*;
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
+use xml::reader::{EventReader, ParserConfig2, XmlEvent};
 
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+/// Top-level directories of the game data; every real mod has at least one of them.
+const GAME_DATA_DIRS: &[&str] = &[
+    "activity_log",
+    "audio",
+    "campaign",
+    "colours",
+    "curios",
+    "cursors",
+    "dungeons",
+    "effects",
+    "fe_flow",
+    "fonts",
+    "fx",
+    "game_over",
+    "heroes",
+    "inventory",
+    "loading_screen",
+    "localization",
+    "loot",
+    "maps",
+    "modes",
+    "monsters",
+    "overlays",
+    "panels",
+    "props",
+    "raid",
+    "raid_results",
+    "scripts",
+    "scrolls",
+    "shaders",
+    "shared",
+    "trinkets",
+    "upgrades",
+    "user_information",
+    "video",
+];
+
+/// Whether any of the directory entries looks like the game data.
+fn has_game_data<'a>(names: impl IntoIterator<Item = &'a str>) -> bool {
+    names.into_iter().any(|name| {
+        GAME_DATA_DIRS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(name))
+    })
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct Project {
-    #[serde(rename = "Title")]
     pub title: String,
+    /// Every other top-level element of `project.xml`, by its local name.
+    pub fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ProjectError {
+    #[error("Malformed XML")]
+    Xml(#[from] xml::reader::Error),
+    #[error("No <Title> element found")]
+    NoTitle,
+}
+
+impl Project {
+    /// Parses `project.xml`, ignoring namespaces and unknown elements.
+    ///
+    /// Encoding is detected from BOM, if any; UTF-16 files without BOM are detected
+    /// by the zero bytes in the leading `<`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ProjectError> {
+        let mut config = ParserConfig2::new().ignore_invalid_encoding_declarations(true);
+        match bytes {
+            [b'<', 0, ..] => config = config.override_encoding(Some(xml::Encoding::Utf16Le)),
+            [0, b'<', ..] => config = config.override_encoding(Some(xml::Encoding::Utf16Be)),
+            _ => {}
+        }
+
+        let mut fields = BTreeMap::new();
+        let mut depth = 0;
+        let mut current: Option<(String, String)> = None;
+        for event in EventReader::new_with_config(bytes, config) {
+            match event? {
+                XmlEvent::StartElement { name, .. } => {
+                    depth += 1;
+                    // Only the direct children of the root element are interesting.
+                    current = if depth == 2 {
+                        Some((name.local_name, String::new()))
+                    } else {
+                        None
+                    };
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some((_, value)) = &mut current {
+                        value.push_str(&text);
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    if let Some((name, value)) = current.take() {
+                        fields.insert(name, value.trim().to_string());
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        let title_key = fields
+            .keys()
+            .find(|key| key.eq_ignore_ascii_case("title"))
+            .cloned()
+            .ok_or(ProjectError::NoTitle)?;
+        let title = fields.remove(&title_key).unwrap_or_default();
+        if title.is_empty() {
+            return Err(ProjectError::NoTitle);
+        }
+        Ok(Self { title, fields })
+    }
+
+    /// Project for the mod which `project.xml` couldn't be parsed.
+    fn fallback(path: &Path) -> Self {
+        Self {
+            title: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "<unknown mod>".into()),
+            fields: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct Mod {
     pub selected: bool,
+    /// Whether the directory contains any game data, as opposed to leftovers of unsubscribed items and such.
+    pub recognized: bool,
     pub path: PathBuf,
     project: Project,
 }
@@ -20,6 +147,45 @@ impl Mod {
     pub fn name(&self) -> &str {
         &self.project.title
     }
+    /// Name of the mod directory, i.e. Workshop ID.
+    pub fn directory(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+    /// Value of the top-level element of `project.xml`, other than the title.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.project.fields.get(key).map(String::as_str)
+    }
+    /// Version from `project.xml`, if the author has set it.
+    pub fn version(&self) -> Option<String> {
+        let parts: Vec<_> = ["VersionMajor", "VersionMinor", "VersionBuild"]
+            .iter()
+            .filter_map(|key| self.field(key))
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("."))
+        }
+    }
+}
+
+#[cfg(test)]
+impl Mod {
+    /// Selected mod with the given title, without reading anything from its directory.
+    pub fn selected(path: impl Into<PathBuf>, title: &str) -> Self {
+        Self {
+            selected: true,
+            recognized: true,
+            path: path.into(),
+            project: Project {
+                title: title.into(),
+                fields: BTreeMap::new(),
+            },
+        }
+    }
 }
 
 pub struct GlobalData {
@@ -35,54 +201,178 @@ pub fn mods_list(cursive: &mut Cursive) -> &mut [Mod] {
 }
 
 #[derive(Debug, Error)]
-enum LoadModsError {
+pub enum LoadModsError {
     #[error("Failed to load mods data due to IO error")]
     Io(#[from] std::io::Error),
-    #[error("Broken XML in mod directory {1}")]
-    XML(#[source] serde_xml_rs::Error, PathBuf),
 }
 
-pub fn load_path(cursive: &mut Cursive, base_path: &str) {
-    info!("Loading Steam library from path: {}", base_path);
-    let base_path = base_path.into();
-    let path = crate::paths::workshop(&base_path);
-    let dir = match std::fs::read_dir(path) {
-        Ok(dir) => dir,
-        Err(error) => {
-            crate::error(cursive, &error);
-            return;
-        }
-    };
-    let mods = match dir
-        .map(|item| {
-            item.map_err(LoadModsError::Io).and_then(|entry| {
-                let path = entry.path();
-                let file = std::fs::File::open(path.join("project.xml"))?;
-                match serde_xml_rs::from_reader::<_, Project>(file) {
-                    Ok(project) => {
-                        info!(
-                            "Successfully parsed mod \"{}\" from directory {}",
-                            project.title,
-                            path.to_string_lossy()
-                        );
-                        Ok(Mod {
-                            selected: false,
-                            path,
-                            project,
-                        })
-                    }
-                    Err(error) => Err(LoadModsError::XML(error, path)),
-                }
-            })
+/// Quick sanity check of the Steam library path, to give feedback before loading it.
+pub fn check_library(base_path: &str) -> Result<String, String> {
+    if base_path.is_empty() {
+        return Err("Enter the path to the Steam library".into());
+    }
+    let base = Path::new(base_path);
+    if !base.is_dir() {
+        return Err("Directory doesn't exist".into());
+    }
+    if !crate::paths::game(base).is_dir() {
+        return Err("Darkest Dungeon is not installed in this library".into());
+    }
+    match std::fs::read_dir(crate::paths::workshop(base)) {
+        Ok(dir) => Ok(format!(
+            "Found Darkest Dungeon and {} workshop items",
+            dir.count()
+        )),
+        Err(_) => Err("No workshop content for Darkest Dungeon in this library".into()),
+    }
+}
+
+/// Every Workshop mod of the Steam library, in the directory order.
+pub fn load_library(base_path: &Path) -> Result<Vec<Mod>, LoadModsError> {
+    std::fs::read_dir(crate::paths::workshop(base_path))?
+        .filter_map(|item| {
+            let entry = match item {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(LoadModsError::Io(err))),
+            };
+            let path = entry.path();
+            if !path.is_dir() {
+                info!(
+                    "Skipping {}, since it is not a directory",
+                    path.to_string_lossy()
+                );
+                return None;
+            }
+            Some(load_mod(path))
         })
-        .collect::<Result<Vec<_>, _>>()
-    {
+        .collect()
+}
+
+pub fn load_path(cursive: &mut Cursive, path_str: &str) {
+    info!("Loading Steam library from path: {}", path_str);
+    let base_path: PathBuf = path_str.into();
+    let mods = match load_library(&base_path) {
         Ok(mods) => mods,
         Err(error) => {
             crate::error(cursive, &error);
             return;
         }
     };
+    let mut config = Config::load();
+    config.push_recent_path(path_str);
+    config.save();
+    crate::bundler::recover_deployment(&base_path);
     cursive.set_user_data(GlobalData { base_path, mods });
     crate::select::render_lists(cursive);
+    crate::bundler::offer_resume(cursive);
+}
+
+pub fn load_mod(path: PathBuf) -> Result<Mod, LoadModsError> {
+    let names: Vec<_> = std::fs::read_dir(&path)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    let recognized = has_game_data(names.iter().map(String::as_str));
+    if !recognized {
+        info!(
+            "Directory {} has no game data, treating it as unrecognized",
+            path.to_string_lossy()
+        );
+    }
+    let project = match std::fs::read(path.join("project.xml")) {
+        Ok(bytes) => match Project::parse(&bytes) {
+            Ok(project) => {
+                info!(
+                    "Successfully parsed mod \"{}\" from directory {}",
+                    project.title,
+                    path.to_string_lossy()
+                );
+                debug!("Other project fields: {:?}", project.fields);
+                project
+            }
+            Err(error) => {
+                let project = Project::fallback(&path);
+                warn!(
+                    "Unable to parse project.xml in directory {} ({}), using \"{}\" as title",
+                    path.to_string_lossy(),
+                    error,
+                    project.title
+                );
+                project
+            }
+        },
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            let project = Project::fallback(&path);
+            warn!(
+                "No project.xml in directory {}, using \"{}\" as title",
+                path.to_string_lossy(),
+                project.title
+            );
+            project
+        }
+        Err(error) => return Err(error.into()),
+    };
+    Ok(Mod {
+        selected: false,
+        recognized,
+        path,
+        project,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_game_data, Project};
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let bom = if bom { vec![0xFF, 0xFE] } else { vec![] };
+        bom.into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect()
+    }
+
+    #[test]
+    fn plain() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+            <project><Title>Plain</Title><PublishedFileId>123</PublishedFileId></project>"#;
+        let project = Project::parse(xml.as_bytes()).unwrap();
+        assert_eq!(project.title, "Plain");
+        assert_eq!(project.fields["PublishedFileId"], "123");
+    }
+
+    #[test]
+    fn utf8_bom() {
+        let xml = "\u{feff}<?xml version=\"1.0\"?><project><Title>Bom</Title></project>";
+        assert_eq!(Project::parse(xml.as_bytes()).unwrap().title, "Bom");
+    }
+
+    #[test]
+    fn utf16() {
+        let xml = r#"<?xml version="1.0" encoding="utf-16"?><project><Title>Мод</Title></project>"#;
+        assert_eq!(Project::parse(&utf16le(xml, true)).unwrap().title, "Мод");
+        assert_eq!(Project::parse(&utf16le(xml, false)).unwrap().title, "Мод");
+    }
+
+    #[test]
+    fn namespaces_and_unknown_fields() {
+        let xml = r#"<project xmlns:ws="urn:workshop">
+                <ws:Title>Namespaced</ws:Title>
+                <TagsList><Tags>Gameplay</Tags></TagsList>
+            </project>"#;
+        let project = Project::parse(xml.as_bytes()).unwrap();
+        assert_eq!(project.title, "Namespaced");
+    }
+
+    #[test]
+    fn no_title() {
+        assert!(Project::parse(b"<project><Language>english</Language></project>").is_err());
+    }
+
+    #[test]
+    fn game_data_directories() {
+        assert!(has_game_data(vec!["preview_icon", "Heroes"]));
+        assert!(!has_game_data(vec!["preview_icon", "backup"]));
+        assert!(!has_game_data(vec![]));
+    }
 }
@@ -1,11 +1,40 @@
 use log::LevelFilter;
 use simplelog::{ConfigBuilder, WriteLogger};
-use std::fs::File;
+use std::{fs::File, path::PathBuf};
+
+/// Exit code for the wrong command line, apart from the ones telling how the bundling went.
+const USAGE_ERROR: i32 = 64;
+
+/// File with the collected spans, in the folded stacks format accepted by the flamegraph tools.
+#[cfg(feature = "profile")]
+const PROFILE_FILE: &str = "profile.folded";
+
+#[cfg(feature = "profile")]
+fn init_profiling() -> tracing_flame::FlushGuard<std::io::BufWriter<File>> {
+    use tracing_subscriber::prelude::*;
+    let (layer, guard) =
+        tracing_flame::FlameLayer::with_file(PROFILE_FILE).expect("Unable to create profile file");
+    tracing_subscriber::registry().with(layer).init();
+    guard
+}
 
 fn main() {
-    let log_level = match std::env::args().nth(1).as_deref() {
-        Some("--debug") => LevelFilter::Debug,
-        _ => LevelFilter::Error,
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let summary_json = match args.iter().position(|arg| arg == "--summary-json") {
+        None => None,
+        Some(index) => match args.get(index + 1).filter(|value| !value.starts_with("--")) {
+            Some(value) => Some(PathBuf::from(value)),
+            None => {
+                eprintln!("--summary-json requires the file to write the summary to");
+                std::process::exit(USAGE_ERROR);
+            }
+        },
+    };
+    let headless = args.iter().any(|arg| arg == "--headless");
+    let log_level = if args.iter().any(|arg| arg == "--debug") {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Error
     };
 
     WriteLogger::init(
@@ -20,5 +49,29 @@ fn main() {
         File::create("log").unwrap(),
     )
     .unwrap();
-    darkest_dungeon_mod_bundler::run();
+
+    let profile = args.iter().any(|arg| arg == "--profile");
+    // The guard writes the collected spans when dropped, i.e. after the program exits normally.
+    #[cfg(feature = "profile")]
+    let profile_guard = if profile {
+        Some(init_profiling())
+    } else {
+        None
+    };
+    #[cfg(not(feature = "profile"))]
+    if profile {
+        log::error!(
+            "Profiling is not available, the program must be built with the \"profile\" feature"
+        );
+    }
+
+    let code = if headless {
+        darkest_dungeon_mod_bundler::run_headless(summary_json.as_deref())
+    } else {
+        darkest_dungeon_mod_bundler::run(summary_json.as_deref())
+    };
+    // Exiting skips the destructors, so the profile must be written before.
+    #[cfg(feature = "profile")]
+    drop(profile_guard);
+    std::process::exit(code);
 }
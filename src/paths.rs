@@ -7,3 +7,89 @@ pub fn workshop(base: impl AsRef<Path>) -> PathBuf {
 pub fn game(base: impl AsRef<Path>) -> PathBuf {
     base.as_ref().join("steamapps/common/DarkestDungeon")
 }
+
+pub fn config() -> PathBuf {
+    "config.json".into()
+}
+
+/// Conflict resolutions made so far, when the bundling was stopped with some of them left for later.
+pub fn session() -> PathBuf {
+    "session.json".into()
+}
+
+/// Temporary files, unless another location is configured.
+pub fn temp() -> PathBuf {
+    "temp".into()
+}
+
+/// Log of the current run, written by the logger set up on start.
+pub fn log() -> PathBuf {
+    "log".into()
+}
+
+/// Archive with the context for a bug report, made on user's request.
+pub fn bug_report() -> PathBuf {
+    "bug_report.zip".into()
+}
+
+/// Archive with the deployed bundle, made when it is chosen as the deployment target.
+pub fn bundle_archive() -> PathBuf {
+    "bundle.zip".into()
+}
+
+/// Conflict resolutions made by hand, replayed while the conflicts stay the same.
+pub fn decisions() -> PathBuf {
+    "decisions.json".into()
+}
+
+/// Report of the merge made without deploying, requested by user.
+pub fn conflict_report() -> PathBuf {
+    "conflict_report.md".into()
+}
+
+/// Conflicts of the same merge as a spreadsheet, to be filled with the decisions and imported into the preset.
+pub fn conflict_csv() -> PathBuf {
+    "conflicts.csv".into()
+}
+
+/// Local usage statistics, collected when enabled by user.
+pub fn stats() -> PathBuf {
+    "stats.json".into()
+}
+
+/// Report of the DLC changes to the vanilla data, written when enabled in the config.
+pub fn dlc_report() -> PathBuf {
+    "dlc_report.txt".into()
+}
+
+/// Parsed vanilla and DLC files, kept between runs.
+pub fn parse_cache() -> PathBuf {
+    "parse_cache.json".into()
+}
+
+/// Resolution preset imported by the user, applied when bundling.
+pub fn preset() -> PathBuf {
+    "preset.json".into()
+}
+
+/// User additions to the compatibility database.
+pub fn compat() -> PathBuf {
+    "compat.toml".into()
+}
+
+/// Steam libraries in the default install locations, which contain the game.
+pub fn detect_libraries() -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = vec![
+        "C:\\Program Files (x86)\\Steam".into(),
+        "C:\\Program Files\\Steam".into(),
+    ];
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        candidates.push(home.join(".steam/steam"));
+        candidates.push(home.join(".local/share/Steam"));
+        candidates.push(home.join("Library/Application Support/Steam"));
+    }
+    candidates
+        .into_iter()
+        .filter(|candidate| game(candidate).is_dir())
+        .collect()
+}
@@ -0,0 +1,116 @@
+//! Questions asked to user from the background threads, which wait for the answer.
+//!
+//! Only one prompt is shown at a time: the threads asking while another prompt is open wait for their turn,
+//! so that the prompts don't stack over each other and the named views of one prompt are never confused
+//! with the ones of another. The UI thread never blocks on the answer, even if the asking thread is gone.
+//!
+//! In the headless run there's nobody to answer, so the prompts which may be met there take their unattended
+//! answers at once, and any other prompt is a bug.
+
+use crossbeam_channel::{bounded, Sender};
+use cursive::Cursive;
+use log::*;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, PoisonError,
+};
+
+/// Held by the thread whose prompt is shown.
+static TURN: Mutex<()> = Mutex::new(());
+/// Set for the whole headless run.
+static HEADLESS: AtomicBool = AtomicBool::new(false);
+
+/// Makes every prompt take its unattended answer, since the program is run without the user, e.g. in CI.
+pub fn set_headless() {
+    HEADLESS.store(true, Ordering::Relaxed);
+}
+
+pub fn is_headless() -> bool {
+    HEADLESS.load(Ordering::Relaxed)
+}
+
+/// Sends the answer back to the thread which asked.
+pub struct Reply<T>(Sender<T>);
+
+impl<T> Clone for Reply<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Reply<T> {
+    /// Only the first answer counts; the later ones, e.g. from the buttons pressed twice, are ignored.
+    pub fn send(&self, answer: T) {
+        let _ = self.0.try_send(answer);
+    }
+}
+
+/// Shows the prompt built by `show` on the UI thread and blocks until it is answered through the `Reply`.
+pub fn ask<T: Send + 'static>(
+    sink: &mut cursive::CbSink,
+    show: impl FnOnce(&mut Cursive, Reply<T>) + Send + 'static,
+) -> T {
+    assert!(
+        !is_headless(),
+        "Prompt can't be answered in the headless run"
+    );
+    // Panic of another asking thread doesn't make the prompts any less usable.
+    let _turn = TURN.lock().unwrap_or_else(PoisonError::into_inner);
+    let (sender, receiver) = bounded(1);
+    crate::run_update(sink, move |cursive| show(cursive, Reply(sender)));
+    receiver
+        .recv()
+        .expect("Prompt was closed without an answer")
+}
+
+/// Same as `ask`, but takes the `unattended` answer at once in the headless run.
+pub fn ask_or<T: Send + 'static>(
+    sink: &mut cursive::CbSink,
+    unattended: T,
+    show: impl FnOnce(&mut Cursive, Reply<T>) + Send + 'static,
+) -> T {
+    if is_headless() {
+        info!("[prompt] Headless run, taking the unattended answer");
+        return unattended;
+    }
+    ask(sink, show)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn prompts_are_shown_one_at_a_time() {
+        let (sink, callbacks) = crossbeam_channel::unbounded();
+        let askers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut sink = sink.clone();
+                std::thread::spawn(move || {
+                    ask(&mut sink, |cursive: &mut Cursive, reply: Reply<usize>| {
+                        cursive.set_user_data(reply)
+                    })
+                })
+            })
+            .collect();
+
+        let mut cursive = Cursive::dummy();
+        for answer in 0..4 {
+            let callback: Box<dyn FnOnce(&mut Cursive) + Send> = callbacks.recv().unwrap();
+            callback(&mut cursive);
+            // Other threads are still waiting for their turn, not showing their prompts.
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(callbacks.is_empty());
+            let reply: Reply<usize> = cursive.take_user_data().unwrap();
+            reply.send(answer);
+            reply.send(answer + 10);
+        }
+        let mut answers: Vec<_> = askers
+            .into_iter()
+            .map(|asker| asker.join().unwrap())
+            .collect();
+        answers.sort_unstable();
+        assert_eq!(answers, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,356 @@
+//! Archive with the context needed to reproduce the problem, to be attached to the bug report.
+//!
+//! Paths to the Steam library and the home directory are masked, and the local mods are only numbered
+//! in every file of the archive, so that it can be shared publicly.
+
+use crate::loader::{GlobalData, Mod};
+use cursive::{
+    traits::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, TextView},
+    Cursive,
+};
+use log::*;
+use std::{
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// At most this many lines of the offending file are included, which is enough to show the parser failure.
+const SNIPPET_LINES: usize = 200;
+
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("IO error encountered on path {1}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("Unable to write the report archive")]
+    Zip(#[from] zip::result::ZipError),
+}
+
+impl ReportError {
+    pub fn from_io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        |err| Self::Io(err, path)
+    }
+}
+
+fn is_workshop(the_mod: &Mod) -> bool {
+    let directory = the_mod.directory();
+    !directory.is_empty() && directory.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Text which may identify the user, with the placeholder to replace it with.
+struct Mask {
+    text: String,
+    placeholder: String,
+    /// Whether the text is masked only as a whole, not as a part of the longer name or path component.
+    whole: bool,
+}
+
+impl Mask {
+    fn new(text: impl Into<String>, placeholder: impl Into<String>, whole: bool) -> Self {
+        Self {
+            text: text.into(),
+            placeholder: placeholder.into(),
+            whole,
+        }
+    }
+}
+
+/// Texts which may identify the user: paths which may contain the user name, and paths and names of the local mods.
+///
+/// Names of the local mods may well be common words, e.g. `ui` or `heroes`, so they are masked only in the log
+/// prefixes and as the mod directory together with its parent, and not wherever they happen to occur.
+fn masks(base_path: &Path, mods: &[Mod]) -> Vec<Mask> {
+    let mut masks = vec![Mask::new(
+        base_path.to_string_lossy(),
+        "<steam library>",
+        false,
+    )];
+    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+        masks.push(Mask::new(home.to_string_lossy(), "<home>", false));
+    }
+    let mut selected = 0;
+    for the_mod in mods {
+        let label = if the_mod.selected {
+            selected += 1;
+            format!("Local mod #{}", selected)
+        } else {
+            "<local mod>".to_owned()
+        };
+        if is_workshop(the_mod) {
+            continue;
+        }
+        masks.push(Mask::new(the_mod.path.to_string_lossy(), &*label, false));
+        masks.push(Mask::new(
+            format!("Mod {}:", the_mod.name()),
+            format!("Mod {}:", label),
+            false,
+        ));
+        let parent = the_mod
+            .path
+            .parent()
+            .and_then(Path::file_name)
+            .map(|parent| parent.to_string_lossy());
+        if let Some(parent) = parent {
+            for separator in &['/', '\\'] {
+                let relative = format!("{}{}{}", parent, separator, the_mod.directory());
+                masks.push(Mask::new(relative, &*label, true));
+            }
+        }
+    }
+    masks.retain(|mask| !mask.text.is_empty());
+    // Longer texts go first, so that they aren't broken by the masks of their parts; the full mod paths are
+    // masked before their last components.
+    masks.sort_by_key(|mask| std::cmp::Reverse(mask.text.len()));
+    masks
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Replaces the occurrences of `from` which aren't surrounded by other characters of the name.
+fn replace_whole(text: &str, from: &str, to: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(index) = rest.find(from) {
+        let before = rest[..index]
+            .chars()
+            .next_back()
+            .or_else(|| result.chars().next_back());
+        let after = rest[index + from.len()..].chars().next();
+        result.push_str(&rest[..index]);
+        if before.is_some_and(is_name_char) || after.is_some_and(is_name_char) {
+            result.push_str(from);
+        } else {
+            result.push_str(to);
+        }
+        rest = &rest[index + from.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn anonymize(text: &str, masks: &[Mask]) -> String {
+    masks.iter().fold(text.to_owned(), |text, mask| {
+        if mask.whole {
+            replace_whole(&text, &mask.text, &mask.placeholder)
+        } else {
+            text.replace(&mask.text, &mask.placeholder)
+        }
+    })
+}
+
+/// Selected mods in the bundling order; the Workshop ones are public, the local ones are only numbered.
+fn mods_list(mods: &[Mod]) -> String {
+    mods.iter()
+        .filter(|the_mod| the_mod.selected)
+        .enumerate()
+        .map(|(index, the_mod)| {
+            if is_workshop(the_mod) {
+                format!(
+                    "- {}: {} (version {})",
+                    the_mod.directory(),
+                    the_mod.name(),
+                    the_mod.version().as_deref().unwrap_or("unknown")
+                )
+            } else {
+                format!("- Local mod #{}", index + 1)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn info(mods: &[Mod]) -> String {
+    format!(
+        "Bundler version: {}\nOS: {} ({}, {})\n\nSelected mods:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::FAMILY,
+        std::env::consts::ARCH,
+        mods_list(mods)
+    )
+}
+
+fn snippet(path: &Path) -> Result<String, ReportError> {
+    let bytes = std::fs::read(path).map_err(ReportError::from_io(path))?;
+    Ok(String::from_utf8_lossy(&bytes)
+        .lines()
+        .take(SNIPPET_LINES)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Writes the archive with every file anonymized.
+fn archive(
+    global_data: &GlobalData,
+    log: &str,
+    offending: Option<&Path>,
+    writer: impl Write + Seek,
+) -> Result<(), ReportError> {
+    let mut files = vec![
+        ("info.txt".to_owned(), info(&global_data.mods)),
+        ("log.txt".to_owned(), log.to_owned()),
+    ];
+    if let Some(path) = offending {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        files.push((format!("snippet/{}", name), snippet(path)?));
+    }
+
+    let masks = masks(&global_data.base_path, &global_data.mods);
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in files {
+        zip.start_file(anonymize(&name, &masks), options)?;
+        zip.write_all(anonymize(&content, &masks).as_bytes())
+            .map_err(ReportError::from_io(crate::paths::bug_report()))?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Writes the report archive and returns its path.
+pub fn write(global_data: &GlobalData, offending: Option<&Path>) -> Result<PathBuf, ReportError> {
+    let log_path = crate::paths::log();
+    let log = std::fs::read(&log_path).map_err(ReportError::from_io(&log_path))?;
+    let report_path = crate::paths::bug_report();
+    let file = std::fs::File::create(&report_path).map_err(ReportError::from_io(&report_path))?;
+    archive(global_data, &String::from_utf8_lossy(&log), offending, file)?;
+    info!("[report] Bug report written to {:?}", report_path);
+    Ok(report_path)
+}
+
+fn create(cursive: &mut Cursive) {
+    let offending = cursive
+        .call_on_name("Offending file", |view: &mut EditView| view.get_content())
+        .map(|content| content.trim().to_owned())
+        .filter(|content| !content.is_empty())
+        .map(PathBuf::from);
+    let global_data = cursive.user_data::<GlobalData>().expect("No data was set");
+    let text = match write(global_data, offending.as_deref()) {
+        Ok(path) => format!(
+            "Report is written to {}.\n\
+             Please attach it to the issue on GitHub, describing what you did and what went wrong.",
+            std::fs::canonicalize(&path).unwrap_or(path).display()
+        ),
+        Err(err) => {
+            warn!("[report] Unable to write bug report: {}", err);
+            format!("Unable to write the report: {}", err)
+        }
+    };
+    cursive.pop_layer();
+    cursive.add_layer(Dialog::info(text));
+}
+
+/// Screen collecting the bug report archive.
+pub fn screen(cursive: &mut Cursive) {
+    cursive.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "The report includes the log (run with --debug for the detailed one), the bundler version, \
+                     the OS and the selected mods. Paths to your Steam library and home directory are masked, \
+                     and the local mods are not named.\n\n\
+                     File which fails to be parsed or merged, to include its beginning (optional):",
+                ))
+                .child(
+                    EditView::new()
+                        .on_submit(|cursive, _| create(cursive))
+                        .with_name("Offending file")
+                        .full_width(),
+                ),
+        )
+        .title("Report a bug")
+        .button("Browse...", |cursive| {
+            crate::browse::browse(cursive, "Offending file", true, |_, _| {})
+        })
+        .button("Create report", create)
+        .dismiss_button("Cancel"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_path_is_masked() {
+        let text =
+            "Reading file: \"/games/steam/steamapps/common/DarkestDungeon/heroes/a.darkest\"";
+        assert_eq!(
+            anonymize(text, &masks(Path::new("/games/steam"), &[])),
+            "Reading file: \"<steam library>/steamapps/common/DarkestDungeon/heroes/a.darkest\""
+        );
+    }
+
+    #[test]
+    fn local_mod_named_as_common_word_is_masked_only_as_itself() {
+        let game = "/games/steam/steamapps/common/DarkestDungeon";
+        let mods = [Mod::selected(format!("{}/mods/heroes", game), "a")];
+        let log = format!(
+            "Mod a: Reading file \"{game}/mods/heroes/heroes/hag/hag.info.darkest\"\n\
+             Copied mods/heroes/project.xml\n\
+             Reading a file: \"{game}/heroes/a.darkest\"\n\
+             Copied oldmods/heroes_extra/skin.png",
+            game = game
+        );
+        assert_eq!(
+            anonymize(&log, &masks(Path::new("/games/steam"), &mods)),
+            "Mod Local mod #1: Reading file \"Local mod #1/heroes/hag/hag.info.darkest\"\n\
+             Copied Local mod #1/project.xml\n\
+             Reading a file: \"<steam library>/steamapps/common/DarkestDungeon/heroes/a.darkest\"\n\
+             Copied oldmods/heroes_extra/skin.png"
+        );
+    }
+
+    #[test]
+    fn local_mods_are_masked_in_every_file() {
+        let global_data = GlobalData {
+            base_path: "/games/steam".into(),
+            mods: vec![
+                Mod::selected(
+                    "/games/steam/steamapps/workshop/content/262060/123",
+                    "Public Mod",
+                ),
+                Mod::selected("/mods/secret_stash", "Secret Project of Jane"),
+            ],
+        };
+        let log =
+            "Mod Secret Project of Jane: Reading file \"/mods/secret_stash/heroes/a.darkest\"\n\
+                   Mod Public Mod: Data successfully extracted";
+        let mut buffer = std::io::Cursor::new(vec![]);
+        archive(&global_data, log, None, &mut buffer).unwrap();
+
+        let mut zip = zip::ZipArchive::new(buffer).unwrap();
+        assert_eq!(zip.len(), 2);
+        for index in 0..zip.len() {
+            let mut file = zip.by_index(index).unwrap();
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut file, &mut content).unwrap();
+            assert!(!content.contains("Secret"), "{}: {}", file.name(), content);
+            assert!(
+                !content.contains("secret_stash"),
+                "{}: {}",
+                file.name(),
+                content
+            );
+            if file.name() != "info.txt" {
+                assert!(
+                    content.contains("Local mod #2"),
+                    "{}: {}",
+                    file.name(),
+                    content
+                );
+                assert!(
+                    content.contains("Public Mod"),
+                    "{}: {}",
+                    file.name(),
+                    content
+                );
+            }
+        }
+    }
+}
@@ -1,47 +1,154 @@
-use crate::loader::{mods_list, Mod};
+use crate::{
+    bundler::CommentVerbosity,
+    config::Config,
+    loader::{mods_list, Mod},
+};
 use cursive::{
-    traits::{Finder, Nameable, Resizable, Scrollable},
+    event::{Event, Key},
+    traits::{Finder, Nameable, Resizable, Scrollable, With},
     view::ViewWrapper,
-    views::{Dialog, LinearLayout, Panel, SelectView},
+    views::{
+        BoxedView, Button, Checkbox, Dialog, EditView, HideableView, LinearLayout, OnEventView,
+        Panel, SelectView, TextView,
+    },
     Cursive, Vec2, View,
 };
 use log::*;
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Width of the available mods list, in percents; the selected mods list takes the rest.
+static AVAILABLE_WIDTH: AtomicU8 = AtomicU8::new(50);
+const MIN_WIDTH: u8 = 20;
+const MAX_WIDTH: u8 = 80;
+const WIDTH_STEP: u8 = 5;
 
-struct Half<V: View>(V);
+type Screen = fn(&mut Cursive);
+
+/// Screens opened from the mods selection, which are reopened on the next launch if they were left open.
+const SCREENS: &[(&str, Screen)] = &[
+    ("Merge settings", crate::bundler::merge_settings),
+    ("Excluded files", crate::excludes::settings),
+    ("DLC baseline", crate::bundler::dlc_baseline),
+    ("Search data", crate::bundler::search_data),
+    ("Presets", crate::bundler::presets),
+    ("Edit values", crate::bundler::value_edits),
+    ("Rename ids", crate::bundler::rename_ids),
+    ("Usage statistics", crate::bundler::usage_stats),
+];
+
+/// One of the mods lists, taking its share of the screen width.
+struct Pane<V: View> {
+    available: bool,
+    view: V,
+}
 
-impl<V: View> ViewWrapper for Half<V> {
+impl<V: View> ViewWrapper for Pane<V> {
     type V = V;
     fn with_view<F, R>(&self, f: F) -> Option<R>
     where
         F: FnOnce(&Self::V) -> R,
     {
-        Some(f(&self.0))
+        Some(f(&self.view))
     }
     fn with_view_mut<F, R>(&mut self, f: F) -> Option<R>
     where
         F: FnOnce(&mut Self::V) -> R,
     {
-        Some(f(&mut self.0))
+        Some(f(&mut self.view))
     }
     fn wrap_required_size(&mut self, req: Vec2) -> Vec2 {
         debug!(
-            "Half-width view asked for required size with constraints {:?}",
+            "Mods list asked for required size with constraints {:?}",
             req
         );
-        (req.x / 2, req.y).into()
+        let available = usize::from(AVAILABLE_WIDTH.load(Ordering::Relaxed));
+        let percent = if self.available {
+            available
+        } else {
+            100 - available
+        };
+        (req.x * percent / 100, req.y).into()
+    }
+}
+
+fn screen_name(title: &str) -> String {
+    format!("Screen {}", title)
+}
+
+/// Opens the screen, marking it so that it can be found when the program is closed.
+fn open_screen(cursive: &mut Cursive, title: &'static str, open: Screen) {
+    let layers = cursive.screen().len();
+    open(cursive);
+    if cursive.screen().len() > layers {
+        if let Some(layer) = cursive.pop_layer() {
+            cursive.add_layer(BoxedView::new(layer).with_name(screen_name(title)));
+        }
     }
 }
 
+/// Stores the screen open over the mods selection, if the program is closed there.
+pub fn remember_screen(cursive: &mut Cursive) {
+    let screen = cursive.screen_mut();
+    if screen.find_layer_from_name("Mods selection").is_none() {
+        return;
+    }
+    let last_screen = SCREENS
+        .iter()
+        .map(|(title, _)| *title)
+        .find(|title| screen.find_layer_from_name(&screen_name(title)).is_some());
+    info!("Closing with screen {:?} open", last_screen);
+    let mut config = Config::load();
+    config.ui.last_screen = last_screen.map(String::from);
+    config.save();
+}
+
+fn resize_lists(cursive: &mut Cursive, wider: bool) {
+    let current = AVAILABLE_WIDTH.load(Ordering::Relaxed);
+    let width = if wider {
+        current.saturating_add(WIDTH_STEP).min(MAX_WIDTH)
+    } else {
+        current.saturating_sub(WIDTH_STEP).max(MIN_WIDTH)
+    };
+    AVAILABLE_WIDTH.store(width, Ordering::Relaxed);
+    let mut config = Config::load();
+    config.ui.available_width = width;
+    config.save();
+    // Sizes are only asked for on layout, which happens after the event is processed.
+    cursive.clear();
+}
+
+fn hide_options(cursive: &mut Cursive, hidden: bool) {
+    cursive.call_on_name("Options", |view: &mut HideableView<LinearLayout>| {
+        view.set_visible(!hidden)
+    });
+    let mut config = Config::load();
+    config.ui.options_hidden = hidden;
+    config.save();
+}
+
 pub fn render_lists(cursive: &mut Cursive) {
+    let unrecognized = mods_list(cursive)
+        .iter()
+        .filter(|the_mod| !the_mod.recognized)
+        .count();
     let mut available = SelectView::new()
-        .with_all(mods_list(cursive).iter().cloned().map(|the_mod| {
-            info!(
-                "Adding mod {} (dir {}) to \"available\" list",
-                the_mod.name(),
-                the_mod.path.to_string_lossy()
-            );
-            (the_mod.name().to_owned(), the_mod)
-        }))
+        .with_all(
+            mods_list(cursive)
+                .iter()
+                .filter(|the_mod| the_mod.recognized)
+                .cloned()
+                .map(|the_mod| {
+                    info!(
+                        "Adding mod {} (dir {}) to \"available\" list",
+                        the_mod.name(),
+                        the_mod.path.to_string_lossy()
+                    );
+                    (the_mod.name().to_owned(), the_mod)
+                }),
+        )
         .on_submit(do_select)
         .with_name("Available")
         .scrollable();
@@ -51,30 +158,415 @@ pub fn render_lists(cursive: &mut Cursive) {
         .with_name("Selected")
         .scrollable();
 
+    let ui = Config::load().ui;
+    AVAILABLE_WIDTH.store(
+        ui.available_width.clamp(MIN_WIDTH, MAX_WIDTH),
+        Ordering::Relaxed,
+    );
+
     info!("Rendering lists of available and selected mods for the first time");
+    let mut dialog = Dialog::new()
+        .title(
+            "Select mods from the list to be bundled \
+             (Ctrl+Left/Right to resize the lists, Ctrl+Up/Down to reorder the selected mods)",
+        )
+        .content(
+            LinearLayout::vertical()
+                .child(
+                    LinearLayout::horizontal()
+                        .child(Pane {
+                            available: true,
+                            view: Panel::new(available).title("Available"),
+                        })
+                        .child(Pane {
+                            available: false,
+                            view: Panel::new(selected).title("Selected"),
+                        })
+                        .full_height(),
+                )
+                .child(TextView::new("").with_name("Compatibility"))
+                .child(
+                    LinearLayout::horizontal()
+                        .child(
+                            Checkbox::new()
+                                .with_checked(ui.options_hidden)
+                                .on_change(hide_options),
+                        )
+                        .child(TextView::new(" Hide options")),
+                )
+                .child(
+                    HideableView::new(options(unrecognized))
+                        .with(|view| view.set_visible(!ui.options_hidden))
+                        .with_name("Options"),
+                ),
+        )
+        .button("Make bundle!", |cursive| {
+            save_deploy_directory(cursive);
+            crate::bundler::bundle(cursive)
+        })
+        .button("Report only", crate::bundler::dry_run)
+        .button("Rebuild from lock", |cursive| {
+            save_deploy_directory(cursive);
+            crate::bundler::rebuild_from_lock(cursive)
+        })
+        .button(
+            "Rollback last deployment",
+            crate::bundler::rollback_deployment,
+        )
+        .button("Clear parse cache", crate::bundler::clear_parse_cache)
+        .button("Report a bug", crate::report::screen);
+    for (title, open) in SCREENS {
+        dialog.add_button(*title, move |cursive| open_screen(cursive, title, *open));
+    }
     crate::screen(
         cursive,
-        Dialog::new()
-            .title("Select mods from the list to be bundled")
-            .content(
-                LinearLayout::horizontal()
-                    .child(Half(Panel::new(available).title("Available")))
-                    .child(Half(Panel::new(selected).title("Selected"))),
-            )
-            .button("Make bundle!", crate::bundler::bundle)
-            .h_align(cursive::align::HAlign::Center)
-            .with_name("Mods selection")
-            .full_screen(),
+        OnEventView::new(
+            dialog
+                .h_align(cursive::align::HAlign::Center)
+                .with_name("Mods selection"),
+        )
+        .on_event(Event::Ctrl(Key::Left), |cursive| {
+            resize_lists(cursive, false)
+        })
+        .on_event(Event::Ctrl(Key::Right), |cursive| {
+            resize_lists(cursive, true)
+        })
+        .on_event(Event::Ctrl(Key::Up), |cursive| move_selected(cursive, true))
+        .on_event(Event::Ctrl(Key::Down), |cursive| {
+            move_selected(cursive, false)
+        })
+        .full_screen(),
+    );
+
+    if let Some((title, open)) = SCREENS
+        .iter()
+        .find(|(title, _)| ui.last_screen.as_deref() == Some(*title))
+    {
+        info!("Reopening screen {} left open in the previous run", title);
+        open_screen(cursive, title, *open);
+    }
+}
+
+/// Settings shown below the mods lists.
+fn options(unrecognized: usize) -> LinearLayout {
+    LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(Checkbox::new().on_change(show_unrecognized))
+                .child(TextView::new(format!(
+                    " Show unrecognized folders ({} without any game data)",
+                    unrecognized
+                ))),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(Config::load().include_attribution)
+                        .on_change(set_include_attribution),
+                )
+                .child(TextView::new(
+                    " Include titles and previews of bundled mods, for attribution",
+                )),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(Config::load().workshop_upload)
+                        .on_change(set_workshop_upload),
+                )
+                .child(TextView::new(
+                    " Prepare the bundle for uploading to the Steam Workshop",
+                )),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(Config::load().compat_patch)
+                        .on_change(set_compat_patch),
+                )
+                .child(TextView::new(
+                    " Also make a compatibility patch, to be loaded after the original mods",
+                )),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(Config::load().bundle_executables)
+                        .on_change(set_bundle_executables)
+                        .with_name("Bundle executables"),
+                )
+                .child(TextView::new(
+                    " Bundle executable files shipped with the mods (libraries, programs, shaders)",
+                )),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Languages in the bundle: "))
+                .child(language_select()),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Comments in the deployed files: "))
+                .child(comments_select()),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Deploy to: "))
+                .child(target_select()),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Another directory: "))
+                .child(
+                    EditView::new()
+                        .content(Config::load().deploy_directory.unwrap_or_default())
+                        .on_submit(set_deploy_directory)
+                        .with_name("Deploy directory")
+                        .full_width(),
+                )
+                .child(Button::new("Browse...", |cursive| {
+                    crate::browse::browse(cursive, "Deploy directory", false, set_deploy_directory)
+                })),
+        )
+}
+
+/// Shows the known problems of the selected mods, if there are any.
+fn update_compatibility(cursive: &mut Cursive) {
+    let warnings = crate::compat::Database::load()
+        .warnings(mods_list(cursive).iter().filter(|the_mod| the_mod.selected));
+    let text = warnings
+        .iter()
+        .map(|(title, message)| format!("Warning: {} {}", title, message))
+        .collect::<Vec<_>>()
+        .join("\n");
+    cursive.call_on_name("Compatibility", |view: &mut TextView| {
+        view.set_content(text)
+    });
+}
+
+fn language_select() -> SelectView<Option<String>> {
+    let current = crate::config::Config::load().bundle_language;
+    let mut select = SelectView::new()
+        .popup()
+        .item("All", None)
+        .with_all(
+            crate::bundler::LANGUAGES
+                .iter()
+                .map(|language| (format!("Only {}", language), Some(language.to_string()))),
+        )
+        .on_submit(set_bundle_language);
+    let index = select
+        .iter()
+        .position(|(_, language)| *language == current)
+        .unwrap_or_default();
+    select.set_selection(index);
+    select
+}
+
+fn comments_select() -> SelectView<CommentVerbosity> {
+    let current = Config::load().deploy_comments;
+    let mut select = SelectView::new()
+        .popup()
+        .with_all(
+            CommentVerbosity::ALL
+                .iter()
+                .map(|verbosity| (verbosity.description(), *verbosity)),
+        )
+        .on_submit(set_deploy_comments);
+    let index = select
+        .iter()
+        .position(|(_, verbosity)| *verbosity == current)
+        .unwrap_or_default();
+    select.set_selection(index);
+    select
+}
+
+fn set_deploy_comments(_: &mut Cursive, verbosity: &CommentVerbosity) {
+    info!("Setting comments in the deployed files to {:?}", verbosity);
+    let mut config = Config::load();
+    config.deploy_comments = *verbosity;
+    config.save();
+}
+
+fn target_select() -> SelectView<&'static str> {
+    let current = Config::load().deploy_target;
+    let mut select = SelectView::new()
+        .popup()
+        .with_all(
+            crate::bundler::DEPLOYMENT_TARGETS
+                .iter()
+                .map(|target| (target.name(), target.name())),
+        )
+        .on_submit(set_deploy_target);
+    let index = select
+        .iter()
+        .position(|(_, name)| current.as_deref() == Some(*name))
+        .unwrap_or_default();
+    select.set_selection(index);
+    select
+}
+
+fn set_deploy_target(_: &mut Cursive, name: &&'static str) {
+    info!("Setting deployment target to {}", name);
+    let mut config = Config::load();
+    config.deploy_target = Some(name.to_string());
+    config.save();
+}
+
+fn set_deploy_directory(_: &mut Cursive, directory: &str) {
+    let directory = Some(directory.to_owned()).filter(|directory| !directory.is_empty());
+    let mut config = Config::load();
+    if config.deploy_directory != directory {
+        info!("Setting deployment directory to {:?}", directory);
+        config.deploy_directory = directory;
+        config.save();
+    }
+}
+
+/// Saves the typed deployment directory, which is otherwise saved only when the input is submitted.
+fn save_deploy_directory(cursive: &mut Cursive) {
+    if let Some(directory) =
+        cursive.call_on_name("Deploy directory", |view: &mut EditView| view.get_content())
+    {
+        set_deploy_directory(cursive, &directory);
+    }
+}
+
+fn set_bundle_language(_: &mut Cursive, language: &Option<String>) {
+    info!("Setting bundle language to {:?}", language);
+    let mut config = crate::config::Config::load();
+    config.bundle_language = language.clone();
+    config.save();
+}
+
+/// Adds the folders without game data to the available mods, or removes them back.
+fn show_unrecognized(cursive: &mut Cursive, checked: bool) {
+    info!("Showing unrecognized folders: {}", checked);
+    let unrecognized: Vec<_> = mods_list(cursive)
+        .iter()
+        .filter(|the_mod| !the_mod.recognized && !the_mod.selected)
+        .cloned()
+        .collect();
+    cursive.call_on_name("Available", |list: &mut SelectView<Mod>| {
+        if checked {
+            for the_mod in unrecognized {
+                list.add_item(the_mod.name().to_owned(), the_mod);
+            }
+            list.sort_by_label();
+        } else {
+            let hidden: Vec<_> = list
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, the_mod))| !the_mod.recognized)
+                .map(|(index, _)| index)
+                .collect();
+            // Removing from the end, so that the remaining indices stay valid.
+            for index in hidden.into_iter().rev() {
+                list.remove_item(index);
+            }
+        }
+    });
+}
+
+fn set_include_attribution(_: &mut Cursive, checked: bool) {
+    info!("Setting attribution inclusion to {}", checked);
+    let mut config = crate::config::Config::load();
+    config.include_attribution = checked;
+    config.save();
+}
+
+fn set_workshop_upload(_: &mut Cursive, checked: bool) {
+    info!("Setting Workshop upload preparation to {}", checked);
+    let mut config = crate::config::Config::load();
+    config.workshop_upload = checked;
+    config.save();
+}
+
+fn set_compat_patch(_: &mut Cursive, checked: bool) {
+    info!("Setting compatibility patch output to {}", checked);
+    let mut config = crate::config::Config::load();
+    config.compat_patch = checked;
+    config.save();
+}
+
+/// Executable files are only allowed after the warning, since they are run outside of the game's data.
+fn set_bundle_executables(cursive: &mut Cursive, checked: bool) {
+    let save = |checked: bool| {
+        info!("Setting bundling of executable files to {}", checked);
+        let mut config = crate::config::Config::load();
+        config.bundle_executables = checked;
+        config.save();
+    };
+    if !checked {
+        save(false);
+        return;
+    }
+    cursive.add_layer(
+        Dialog::around(TextView::new(
+            "Some mods ship files which are not game data: libraries, programs, scripts or shaders.
+They are run together with the game, so a malicious or broken one can harm your system or installation.
+Bundle them only if you trust every selected mod which ships them.",
+        ))
+        .title("Bundle executable files?")
+        .button("Allow", move |cursive| {
+            save(true);
+            cursive.pop_layer();
+        })
+        .button("Cancel", |cursive| {
+            cursive.pop_layer();
+            cursive.call_on_name("Bundle executables", |checkbox: &mut Checkbox| {
+                checkbox.set_checked(false)
+            });
+        }),
+    );
+}
+
+/// Moves the mod focused in the selected list one place up or down, changing the order the mods are bundled in.
+///
+/// Mods later in the list are merged later, so their changes win where the conflicts are resolved automatically.
+fn move_selected(cursive: &mut Cursive, up: bool) {
+    let swapped = cursive
+        .call_on_name("Selected", |list: &mut SelectView<Mod>| {
+            let index = list.selected_id()?;
+            let target = if up { index.checked_sub(1)? } else { index + 1 };
+            let other = list.get_item(target)?.1.path.clone();
+            let (label, the_mod) = list
+                .get_item(index)
+                .map(|(label, the_mod)| (label.to_owned(), the_mod.clone()))?;
+            list.remove_item(index);
+            list.insert_item(target, label, the_mod.clone());
+            list.set_selection(target);
+            Some((the_mod, other))
+        })
+        .flatten();
+    let (moved, other) = match swapped {
+        Some(swapped) => swapped,
+        None => return,
+    };
+    info!(
+        "Moving mod {} {}",
+        moved.name(),
+        if up { "up" } else { "down" }
     );
+    // Selected mods are kept in the global list in the same order as in the selected list.
+    let mods = mods_list(cursive);
+    let position = |path: &PathBuf| mods.iter().position(|the_mod| the_mod.path == *path);
+    if let (Some(first), Some(second)) = (position(&moved.path), position(&other)) {
+        mods.swap(first, second);
+    }
 }
 
 fn do_select(cursive: &mut Cursive, item: &Mod) {
     info!("Selecting mod: {}", item.name());
-    if let Some(the_mod) = mods_list(cursive)
-        .iter_mut()
-        .find(|the_mod| the_mod.path == item.path)
-    {
-        the_mod.selected = true;
+    let mods = mods_list(cursive);
+    if let Some(index) = mods.iter().position(|the_mod| the_mod.path == item.path) {
+        mods[index].selected = true;
+        // Mods are bundled in the order they were selected in, until they are reordered.
+        mods[index..].rotate_left(1);
     } else {
         warn!(
             "Attempted to select mod {}, but it wasn't found in loaded list",
@@ -108,6 +600,7 @@ fn do_select(cursive: &mut Cursive, item: &Mod) {
     } else {
         warn!("Failed to select mod - something went wrong!");
     }
+    update_compatibility(cursive);
 }
 
 fn do_deselect(cursive: &mut Cursive, item: &Mod) {
@@ -150,4 +643,5 @@ fn do_deselect(cursive: &mut Cursive, item: &Mod) {
     } else {
         warn!("Failed to deselect mod - something went wrong!");
     }
+    update_compatibility(cursive);
 }
@@ -0,0 +1,279 @@
+//! Managed space for the temporary files, e.g. the unpacked mods or the files passed to the external tools.
+//!
+//! Everything is kept in the `dd_bundler` subdirectory of one directory (`temp` next to the config, unless another
+//! one is configured), which is limited in size. Every entry is prefixed with the id of the process which created it,
+//! and the process holds the lock file with the same prefix while it runs. Own entries are cleaned on exit, and
+//! the ones whose lock is no longer held are left by the crashed runs and are cleaned on start; the entries of other
+//! running instances of the bundler and anything not made by the bundler are never touched.
+
+use crate::config::Config;
+use log::*;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+use thiserror::Error;
+
+/// Limit of the temporary space, unless another one is configured.
+const DEFAULT_LIMIT_MIB: u64 = 4096;
+const MEBIBYTE: u64 = 1024 * 1024;
+
+/// Distinguishes the directories created for the same purpose during one run.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+/// Lock files held by this process, by the temporary space they are in.
+static LOCKS: Mutex<BTreeMap<PathBuf, File>> = Mutex::new(BTreeMap::new());
+/// Name of the lock file after the process prefix; entries always have the counter after their purpose,
+/// so it can't be taken by any of them.
+const LOCK_NAME: &str = "lock";
+
+#[derive(Debug, Error)]
+pub enum TempError {
+    #[error("IO error encountered on path {1}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("Temporary files take {used} MiB, more than the limit of {limit} MiB")]
+    Full { used: u64, limit: u64 },
+}
+
+impl TempError {
+    pub fn from_io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        |err| Self::Io(err, path)
+    }
+}
+
+/// Directory in the temporary space, removed with everything inside when dropped.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps the directory until the temporary space is cleaned, e.g. when it is used by another program.
+    pub fn keep(self) -> PathBuf {
+        let path = self.path.clone();
+        std::mem::forget(self);
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            warn!("[temp] Unable to remove {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// Subdirectory of the configured directory, so that nothing else in it is ever touched.
+const SUBDIRECTORY: &str = "dd_bundler";
+
+fn root(config: &Config) -> PathBuf {
+    config
+        .temp_dir
+        .as_ref()
+        .map_or_else(crate::paths::temp, PathBuf::from)
+        .join(SUBDIRECTORY)
+}
+
+/// Prefix of the entries created by this process.
+fn own_prefix() -> String {
+    format!("{}_", std::process::id())
+}
+
+/// Process id which the entry is prefixed with, if it was created by the bundler.
+fn entry_pid(name: &str) -> Option<&str> {
+    let (pid, _) = name.split_once('_')?;
+    Some(pid).filter(|pid| !pid.is_empty() && pid.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+fn lock_path(root: &Path, pid: &str) -> PathBuf {
+    root.join(format!("{}_{}", pid, LOCK_NAME))
+}
+
+/// Takes the lock of this process in the temporary space, unless it is already held.
+fn hold_lock(root: &Path) -> Result<(), TempError> {
+    let mut locks = LOCKS.lock().unwrap();
+    if locks.contains_key(root) {
+        return Ok(());
+    }
+    let path = lock_path(root, &std::process::id().to_string());
+    loop {
+        let file = File::create(&path).map_err(TempError::from_io(&path))?;
+        file.lock().map_err(TempError::from_io(&path))?;
+        // Another process could have taken the lock file for abandoned and removed it just before it was locked.
+        if path.exists() {
+            locks.insert(root.to_owned(), file);
+            return Ok(());
+        }
+    }
+}
+
+/// Whether the process with the given id has left its entries, i.e. doesn't hold its lock any more.
+///
+/// The lock file of such a process is removed while its lock is taken, so that no process can hold it afterwards.
+fn take_abandoned(root: &Path, pid: &str) -> bool {
+    let path = lock_path(root, pid);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) => return err.kind() == std::io::ErrorKind::NotFound,
+    };
+    if file.try_lock().is_err() {
+        return false;
+    }
+    match std::fs::remove_file(&path) {
+        Ok(()) => true,
+        Err(err) => {
+            warn!("[temp] Unable to remove {:?}: {}", path, err);
+            false
+        }
+    }
+}
+
+/// Total size of the files in the directory, in bytes.
+fn used(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => used(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn create_in(root: &Path, limit_mib: u64, purpose: &str) -> Result<TempDir, TempError> {
+    let used_mib = used(root) / MEBIBYTE;
+    if used_mib >= limit_mib {
+        return Err(TempError::Full {
+            used: used_mib,
+            limit: limit_mib,
+        });
+    }
+    std::fs::create_dir_all(root).map_err(TempError::from_io(root))?;
+    hold_lock(root)?;
+    let path = root.join(format!(
+        "{}{}_{}",
+        own_prefix(),
+        purpose,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&path).map_err(TempError::from_io(&path))?;
+    debug!("[temp] Created {:?}", path);
+    Ok(TempDir { path })
+}
+
+/// Creates the new temporary directory, named after its purpose for easier debugging.
+pub fn create(purpose: &str) -> Result<TempDir, TempError> {
+    let config = Config::load();
+    let limit = config.temp_size_limit_mib.unwrap_or(DEFAULT_LIMIT_MIB);
+    create_in(&root(&config), limit, purpose)
+}
+
+fn clean_in(root: &Path) {
+    let entries: Vec<_> = match std::fs::read_dir(root) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+        Err(err) => return warn!("[temp] Unable to read {:?}: {}", root, err),
+    };
+    // Own lock is released, so that its file can be removed with the rest.
+    LOCKS.lock().unwrap().remove(root);
+    let own = own_prefix();
+    let mut abandoned: BTreeSet<String> = entries
+        .iter()
+        .filter_map(|entry| entry_pid(&entry.file_name().to_string_lossy()).map(String::from))
+        .filter(|pid| format!("{}_", pid) != own)
+        .collect();
+    abandoned.retain(|pid| take_abandoned(root, pid));
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let removable =
+            name.starts_with(&own) || entry_pid(&name).is_some_and(|pid| abandoned.contains(pid));
+        if !removable {
+            continue;
+        }
+        let path = entry.path();
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match removed {
+            Ok(()) => info!("[temp] Removed temporary files in {:?}", path),
+            Err(err) => warn!("[temp] Unable to remove {:?}: {}", path, err),
+        }
+    }
+}
+
+/// Removes the temporary files of this process and the ones left by the crashed runs;
+/// failure is not critical, so it is only logged.
+pub fn clean() {
+    clean_in(&root(&Config::load()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::temp_dir;
+
+    #[test]
+    fn directories_are_removed_and_limited() {
+        let root = temp_dir("temp");
+        let first = create_in(&root, 1, "extract").unwrap();
+        let second = create_in(&root, 1, "extract").unwrap();
+        assert_ne!(first.path(), second.path());
+        let first_path = first.path().to_owned();
+        drop(first);
+        assert!(!first_path.exists());
+
+        std::fs::write(second.path().join("big"), vec![0; MEBIBYTE as usize]).unwrap();
+        assert!(matches!(
+            create_in(&root, 1, "extract"),
+            Err(TempError::Full { used: 1, limit: 1 })
+        ));
+        drop(second);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn only_own_and_abandoned_files_are_cleaned() {
+        let root = temp_dir("clean");
+        let own = create_in(&root, 1, "extract").unwrap().keep();
+        // Another running instance holds its lock.
+        let running = (std::process::id() + 1).to_string();
+        let foreign = root.join(format!("{}_extract_0", running));
+        std::fs::create_dir_all(&foreign).unwrap();
+        let foreign_lock = File::create(lock_path(&root, &running)).unwrap();
+        foreign_lock.lock().unwrap();
+        // Instances which crashed left their entries, with or without the lock file.
+        let crashed = (std::process::id() + 2).to_string();
+        let leftover = root.join(format!("{}_open_3", crashed));
+        std::fs::create_dir_all(&leftover).unwrap();
+        File::create(lock_path(&root, &crashed)).unwrap();
+        let unlocked = root.join(format!("{}_extract_1", std::process::id() + 3));
+        std::fs::create_dir_all(&unlocked).unwrap();
+        let unrelated = root.join("notes.txt");
+        std::fs::write(&unrelated, "kept").unwrap();
+
+        clean_in(&root);
+        assert!(!own.exists());
+        assert!(foreign.exists());
+        assert!(lock_path(&root, &running).exists());
+        assert!(!leftover.exists());
+        assert!(!lock_path(&root, &crashed).exists());
+        assert!(!unlocked.exists());
+        assert!(unrelated.exists());
+        drop(foreign_lock);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
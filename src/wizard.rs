@@ -0,0 +1,173 @@
+//! First-time setup, shown instead of the bare library path prompt when there is no config yet.
+
+use crate::{
+    bundler::MergePolicy,
+    config::{Config, Profile, DEFAULT_PROFILE},
+};
+use cursive::{
+    traits::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, Panel, SelectView, TextView},
+    Cursive,
+};
+use log::*;
+
+pub fn run(cursive: &mut Cursive) {
+    info!("No config found, starting setup wizard");
+    library_step(cursive, Config::default(), String::new());
+}
+
+fn edit_content(cursive: &mut Cursive, name: &str) -> String {
+    cursive
+        .call_on_name(name, |view: &mut EditView| view.get_content())
+        .map(|content| content.trim().to_owned())
+        .unwrap_or_default()
+}
+
+fn library_step(cursive: &mut Cursive, config: Config, library_path: String) {
+    let detected: Vec<_> = crate::paths::detect_libraries()
+        .into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    let initial = match (library_path.is_empty(), detected.first()) {
+        (true, Some(first)) => first.clone(),
+        _ => library_path,
+    };
+
+    let mut layout = LinearLayout::vertical().child(TextView::new(
+        "Where is the Steam library with Darkest Dungeon installed?",
+    ));
+    if detected.is_empty() {
+        layout.add_child(TextView::new(
+            "No library was found in the default locations, please enter the path manually.",
+        ));
+    } else {
+        layout.add_child(TextView::new("Detected libraries:"));
+        layout.add_child(Panel::new(
+            SelectView::new()
+                .with_all_str(detected)
+                .on_submit(|cursive, path: &String| {
+                    cursive.call_on_name("Library path", |view: &mut EditView| {
+                        view.set_content(path.clone())
+                    });
+                    crate::update_library_status(cursive, path, 0);
+                }),
+        ));
+        layout.add_child(TextView::new("Or enter another one:"));
+    }
+    layout.add_child(
+        EditView::new()
+            .content(initial.clone())
+            .on_edit(crate::update_library_status)
+            .with_name("Library path")
+            .full_width(),
+    );
+    layout.add_child(TextView::new(" ").with_name("Library status"));
+
+    crate::screen(
+        cursive,
+        Dialog::around(layout)
+            .title("First-time setup (1/3): Steam library")
+            .button("Browse...", |cursive| {
+                crate::browse::browse(cursive, "Library path", false, |cursive, path| {
+                    crate::update_library_status(cursive, path, 0)
+                })
+            })
+            .button("Next", move |cursive| {
+                let path = edit_content(cursive, "Library path");
+                match crate::loader::check_library(&path) {
+                    Ok(_) => {
+                        info!("[wizard] Library chosen: {}", path);
+                        policy_step(cursive, config.clone(), path);
+                    }
+                    Err(problem) => {
+                        cursive.call_on_name("Library status", |text: &mut TextView| {
+                            text.set_content(format!("Can't continue: {}", problem))
+                        });
+                    }
+                }
+            })
+            .button("Quit", Cursive::quit)
+            .full_width(),
+    );
+    crate::update_library_status(cursive, &initial, 0);
+}
+
+fn policy_step(cursive: &mut Cursive, config: Config, library_path: String) {
+    let back_config = config.clone();
+    let back_path = library_path.clone();
+    let select = SelectView::new()
+        .with_all(
+            MergePolicy::ALL
+                .iter()
+                .map(|policy| (policy.description(), *policy)),
+        )
+        .with_name("Default policy");
+    crate::screen(
+        cursive,
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(
+                    "What to do when several mods change the same value differently?\n\
+                     This can be changed later for every kind of data with \"Merge settings\".",
+                ))
+                .child(Panel::new(select)),
+        )
+        .title("First-time setup (2/3): conflicts")
+        .button("Back", move |cursive| {
+            library_step(cursive, back_config.clone(), back_path.clone())
+        })
+        .button("Next", move |cursive| {
+            let policy = cursive
+                .call_on_name("Default policy", |view: &mut SelectView<MergePolicy>| {
+                    view.selection()
+                })
+                .flatten()
+                .map(|policy| *policy)
+                .unwrap_or_default();
+            info!("[wizard] Default merge policy: {:?}", policy);
+            let mut config = config.clone();
+            crate::bundler::set_default_merge_policy(&mut config, policy);
+            profile_step(cursive, config, library_path.clone());
+        }),
+    );
+}
+
+fn profile_step(cursive: &mut Cursive, config: Config, library_path: String) {
+    let back_config = config.clone();
+    let back_path = library_path.clone();
+    crate::screen(
+        cursive,
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new("Name of the settings profile:"))
+                .child(
+                    EditView::new()
+                        .content(DEFAULT_PROFILE)
+                        .with_name("Profile name")
+                        .full_width(),
+                ),
+        )
+        .title("First-time setup (3/3): profile")
+        .button("Back", move |cursive| {
+            policy_step(cursive, back_config.clone(), back_path.clone())
+        })
+        .button("Finish", move |cursive| {
+            let mut name = edit_content(cursive, "Profile name");
+            if name.is_empty() {
+                name = DEFAULT_PROFILE.into();
+            }
+            info!("[wizard] Creating profile \"{}\"", name);
+            let mut config = config.clone();
+            config.profiles.insert(
+                name.clone(),
+                Profile {
+                    library_path: library_path.clone(),
+                    ..Profile::default()
+                },
+            );
+            config.active_profile = Some(name);
+            config.save();
+            crate::loader::load_path(cursive, &library_path);
+        }),
+    );
+}